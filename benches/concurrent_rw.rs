@@ -0,0 +1,97 @@
+//! Mixed read/write throughput with N writer and M reader threads.
+//!
+//! Run with `cargo bench --bench concurrent_rw`. Each measurement opens a fresh
+//! engine, preloads `PRELOAD_KEYS`, then times the writers and readers running
+//! together; criterion reports the combined operations per second.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lsm_kv_store::{LsmConfig, LsmEngine};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PRELOAD_KEYS: usize = 1_000;
+const WRITES_PER_THREAD: usize = 200;
+const READS_PER_THREAD: usize = 2_000;
+
+fn open_engine(dir: &std::path::Path) -> LsmEngine {
+    let config = LsmConfig::builder()
+        .dir_path(dir.to_path_buf())
+        .memtable_max_size(64 * 1024 * 1024)
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config).unwrap();
+
+    let preload = (0..PRELOAD_KEYS)
+        .map(|i| (format!("key_{i:06}"), vec![b'v'; 64]))
+        .collect();
+    engine.set_batch(preload).unwrap();
+    engine
+}
+
+fn run_workload(engine: Arc<LsmEngine>, writers: usize, readers: usize) -> Duration {
+    let barrier = Arc::new(Barrier::new(writers + readers + 1));
+    let mut handles = Vec::with_capacity(writers + readers);
+
+    for w in 0..writers {
+        let engine = Arc::clone(&engine);
+        let barrier = Arc::clone(&barrier);
+        handles.push(thread::spawn(move || {
+            barrier.wait();
+            for i in 0..WRITES_PER_THREAD {
+                engine
+                    .set(format!("writer_{w}_{i:06}"), vec![b'w'; 64])
+                    .unwrap();
+            }
+        }));
+    }
+
+    for r in 0..readers {
+        let engine = Arc::clone(&engine);
+        let barrier = Arc::clone(&barrier);
+        handles.push(thread::spawn(move || {
+            barrier.wait();
+            for i in 0..READS_PER_THREAD {
+                let key = format!("key_{:06}", (i * 7 + r) % PRELOAD_KEYS);
+                criterion::black_box(engine.get(&key).unwrap());
+            }
+        }));
+    }
+
+    barrier.wait();
+    let start = Instant::now();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    start.elapsed()
+}
+
+fn bench_concurrent_rw(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent_rw");
+    group.sample_size(10);
+
+    for &(writers, readers) in &[(1, 0), (4, 0), (0, 4), (1, 4), (4, 4), (4, 16)] {
+        let ops = (writers * WRITES_PER_THREAD + readers * READS_PER_THREAD) as u64;
+        group.throughput(Throughput::Elements(ops));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{writers}w_{readers}r")),
+            &(writers, readers),
+            |b, &(writers, readers)| {
+                b.iter_custom(|iters| {
+                    let mut total = Duration::ZERO;
+                    for _ in 0..iters {
+                        let dir = tempfile::tempdir().unwrap();
+                        let engine = Arc::new(open_engine(dir.path()));
+                        total += run_workload(engine, writers, readers);
+                    }
+                    total
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_concurrent_rw);
+criterion_main!(benches);
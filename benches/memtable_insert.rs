@@ -0,0 +1,60 @@
+//! Concurrent MemTable inserts, `BTree` vs `SkipList`.
+//!
+//! Run with `cargo bench --bench memtable_insert`. Each measurement inserts
+//! `KEYS_PER_THREAD` keys from each of `THREADS` threads into a fresh MemTable.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lsm_kv_store::core::memtable::MemTable;
+use lsm_kv_store::{LogRecord, MemTableImpl};
+use std::sync::Barrier;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const THREADS: usize = 4;
+const KEYS_PER_THREAD: usize = 10_000;
+
+fn insert_concurrently(memtable_impl: MemTableImpl) -> Duration {
+    let memtable = MemTable::with_impl(usize::MAX, memtable_impl);
+    let barrier = Barrier::new(THREADS + 1);
+
+    thread::scope(|scope| {
+        for t in 0..THREADS {
+            let (memtable, barrier) = (&memtable, &barrier);
+            scope.spawn(move || {
+                barrier.wait();
+                for i in 0..KEYS_PER_THREAD {
+                    let mut record = LogRecord::new(format!("key_{t}_{i:06}"), vec![b'v'; 64]);
+                    record.seq = (i * THREADS + t) as u64;
+                    memtable.insert(record);
+                }
+            });
+        }
+
+        barrier.wait();
+        let start = Instant::now();
+        // Leaving the scope joins every inserter
+        start
+    })
+    .elapsed()
+}
+
+fn bench_memtable_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("memtable_insert");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((THREADS * KEYS_PER_THREAD) as u64));
+
+    for memtable_impl in [MemTableImpl::BTree, MemTableImpl::SkipList] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{memtable_impl:?}_{THREADS}t")),
+            &memtable_impl,
+            |b, &memtable_impl| {
+                b.iter_custom(|iters| (0..iters).map(|_| insert_concurrently(memtable_impl)).sum())
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_memtable_insert);
+criterion_main!(benches);
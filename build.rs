@@ -0,0 +1,42 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "grpc")]
+    grpc_service();
+}
+
+/// Client and server stubs for `proto/lsm.proto`. The messages are written by
+/// hand in `src/grpc/proto.rs`, so building doesn't need `protoc`.
+#[cfg(feature = "grpc")]
+fn grpc_service() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("super::{input}"))
+            .output_type(format!("super::{output}"))
+            .codec_path("tonic::codec::ProstCodec")
+    };
+
+    let service = Service::builder()
+        .name("Lsm")
+        .package("lsm.v1")
+        .method(method("get", "Get", "GetRequest", "GetResponse").build())
+        .method(method("set", "Set", "SetRequest", "SetResponse").build())
+        .method(method("delete", "Delete", "DeleteRequest", "DeleteResponse").build())
+        .method(
+            method("scan", "Scan", "ScanRequest", "Record")
+                .server_streaming()
+                .build(),
+        )
+        .method(
+            method("watch", "Watch", "WatchRequest", "ChangeEvent")
+                .server_streaming()
+                .build(),
+        )
+        .build();
+
+    Builder::new().compile(&[service]);
+}
@@ -0,0 +1,55 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+
+use super::{ApiResponse, AppState};
+
+/// Require `Authorization: Bearer <api_token>` when a token is configured:
+/// on every request that can change data, and on reads too if `auth_reads`
+pub(super) async fn require_token(
+    data: web::Data<AppState>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let allowed = match data.api_token.as_deref() {
+        None => true,
+        Some(_) if !data.auth_reads && is_read(req.method()) => true,
+        Some(token) => req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|presented| constant_time_eq(presented, token)),
+    };
+    if allowed {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+
+    let response = HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+        .json(ApiResponse {
+            success: false,
+            message: "Missing or invalid API token".to_string(),
+            data: None,
+        });
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+fn is_read(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Compare without returning at the first difference, so response timing
+/// doesn't reveal how much of the token was right
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
@@ -1,3 +1,5 @@
+use super::LogLevelHandle;
+use crate::core::subscription::OverflowPolicy;
 use serde::{Deserialize, Serialize};
 use std::env;
 
@@ -8,6 +10,21 @@ pub struct ServerConfig {
     pub max_json_payload_size: usize,
     pub max_raw_payload_size: usize,
     pub feature_cache_ttl_secs: u64,
+    /// How long a graceful shutdown waits for in-flight requests
+    pub shutdown_timeout_secs: u64,
+    /// Events buffered per `/watch` client before the overflow policy applies
+    pub watch_channel_capacity: usize,
+    pub watch_overflow_policy: OverflowPolicy,
+    /// Key prefixes hidden from listings, scans and exports
+    pub hidden_key_prefixes: Vec<String>,
+    /// Bearer token required on POST/DELETE requests; unset means no auth
+    #[serde(skip_serializing)]
+    pub api_token: Option<String>,
+    /// Require the token on GET requests too
+    pub auth_reads: bool,
+    /// Lets `POST /loglevel` change the level set up by `install_tracing`
+    #[serde(skip)]
+    pub log_level: Option<LogLevelHandle>,
 }
 
 impl Default for ServerConfig {
@@ -18,6 +35,13 @@ impl Default for ServerConfig {
             max_json_payload_size: 50 * 1024 * 1024,  // 50MB
             max_raw_payload_size: 50 * 1024 * 1024,   // 50MB
             feature_cache_ttl_secs: 10,
+            shutdown_timeout_secs: 30,
+            watch_channel_capacity: 1024,
+            watch_overflow_policy: OverflowPolicy::DropOldest,
+            hidden_key_prefixes: vec!["feature:".to_string()],
+            api_token: None,
+            auth_reads: false,
+            log_level: None,
         }
     }
 }
@@ -46,12 +70,49 @@ impl ServerConfig {
             .parse::<u64>()
             .unwrap_or(10);
 
+        let shutdown_timeout_secs = env::var("SERVER_SHUTDOWN_TIMEOUT")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()
+            .unwrap_or(30);
+
+        let watch_channel_capacity = env::var("WATCH_CHANNEL_CAPACITY")
+            .unwrap_or_else(|_| "1024".to_string())
+            .parse::<usize>()
+            .unwrap_or(1024);
+
+        let watch_overflow_policy = match env::var("WATCH_OVERFLOW_POLICY").as_deref() {
+            Ok("block") => OverflowPolicy::Block,
+            _ => OverflowPolicy::DropOldest,
+        };
+
+        // Comma-separated; set it empty to hide nothing
+        let hidden_key_prefixes = env::var("HIDDEN_KEY_PREFIXES")
+            .unwrap_or_else(|_| "feature:".to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|prefix| !prefix.is_empty())
+            .map(String::from)
+            .collect();
+
+        let api_token = env::var("API_TOKEN").ok().filter(|token| !token.is_empty());
+
+        let auth_reads = env::var("API_AUTH_READS")
+            .map(|v| v == "true")
+            .unwrap_or(false);
+
         Self {
             host,
             port,
             max_json_payload_size,
             max_raw_payload_size,
             feature_cache_ttl_secs,
+            shutdown_timeout_secs,
+            watch_channel_capacity,
+            watch_overflow_policy,
+            hidden_key_prefixes,
+            api_token,
+            auth_reads,
+            log_level: None,
         }
     }
 
@@ -62,6 +123,20 @@ impl ServerConfig {
         println!("   JSON Payload Limit: {} MB", self.max_json_payload_size / 1024 / 1024);
         println!("   Raw Payload Limit: {} MB", self.max_raw_payload_size / 1024 / 1024);
         println!("   Feature Cache TTL: {}s", self.feature_cache_ttl_secs);
+        println!("   Shutdown Timeout: {}s", self.shutdown_timeout_secs);
+        println!(
+            "   Watch Channel: {} events ({:?})",
+            self.watch_channel_capacity, self.watch_overflow_policy
+        );
+        println!("   Hidden Key Prefixes: {:?}", self.hidden_key_prefixes);
+        println!(
+            "   API Token: {}",
+            match (&self.api_token, self.auth_reads) {
+                (None, _) => "disabled",
+                (Some(_), false) => "required for writes",
+                (Some(_), true) => "required for all requests",
+            }
+        );
         println!();
     }
 }
@@ -1,25 +1,119 @@
+mod auth;
 mod config;
 
 use actix_cors::Cors;
+use actix_web::middleware::from_fn;
 use actix_web::{delete, get, post, web, App, HttpResponse, HttpServer, Responder};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine as _;
+use crossbeam_channel::RecvTimeoutError;
 use serde::{Deserialize, Serialize};
+use std::ops::Bound;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, Registry};
 
+use crate::core::batch::WriteBatch;
+use crate::core::dump::DumpRecord;
 use crate::core::engine::LsmEngine;
-use crate::features::FeatureClient;
+use crate::core::iterator::{Direction, KeyValueIterator};
+use crate::core::subscription::{OverflowPolicy, Subscription};
+use crate::features::{FeatureClient, FeatureFlag};
+use crate::infra::error::LsmError;
 
 pub use config::ServerConfig;
 
+/// Changes the level of the subscriber set up by [`install_tracing`]
+pub type LogLevelHandle = reload::Handle<LevelFilter, Registry>;
+
+/// Install the global log output, filtered at `level` until changed through
+/// the returned handle (e.g. by `POST /loglevel`)
+pub fn install_tracing(level: LevelFilter) -> LogLevelHandle {
+    let (filter, handle) = reload::Layer::new(level);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_level(true),
+        )
+        .init();
+    handle
+}
+
 pub struct AppState {
     pub engine: Arc<LsmEngine>,
     pub features: Arc<FeatureClient>,
+    pub watch_capacity: usize,
+    pub watch_policy: OverflowPolicy,
+    /// Keys starting with any of these are left out of `/keys`, `/scan`,
+    /// `/namespaces` and `/export`
+    pub hidden_prefixes: Vec<String>,
+    /// Bearer token required to change data; `None` leaves the API open
+    pub api_token: Option<String>,
+    /// Require the token on reads as well
+    pub auth_reads: bool,
+    /// Backs `/loglevel`; without it the level can't be changed at runtime
+    pub log_level: Option<LogLevelHandle>,
+}
+
+impl AppState {
+    fn is_hidden(&self, key: &str) -> bool {
+        self.hidden_prefixes
+            .iter()
+            .any(|prefix| key.starts_with(prefix.as_str()))
+    }
+}
+
+/// How often an idle `/watch` stream sends a comment frame. Besides keeping
+/// proxies from timing out, this is how a disconnected client is noticed.
+const WATCH_KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// Page size of `/scan` when a cursor is given without a limit
+const DEFAULT_SCAN_LIMIT: usize = 1000;
+/// Largest page `/scan` returns, whatever the limit asks for
+const MAX_SCAN_LIMIT: usize = 10_000;
+
+/// Roughly how much of `/export` or `/scan/stream` is buffered before being
+/// sent
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// `/scan` cursor for continuing after `key`: the key, base64url-encoded
+fn encode_cursor(key: &str) -> String {
+    URL_SAFE_NO_PAD.encode(key)
+}
+
+fn decode_cursor(cursor: &str) -> Option<String> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|key| String::from_utf8(key).ok())
 }
 
 #[derive(Deserialize)]
 pub struct SetRequest {
     pub key: String,
+    #[serde(default)]
     pub value: String,
+    /// The value as standard base64, for bytes that aren't valid UTF-8; takes
+    /// precedence over `value`
+    #[serde(default)]
+    pub value_b64: Option<String>,
+}
+
+impl SetRequest {
+    fn value_bytes(&self) -> Result<Vec<u8>, String> {
+        match &self.value_b64 {
+            Some(b64) => STANDARD
+                .decode(b64)
+                .map_err(|e| format!("Invalid value_b64 for key '{}': {}", self.key, e)),
+            None => Ok(self.value.as_bytes().to_vec()),
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -39,6 +133,38 @@ pub struct SearchQuery {
     pub prefix: bool,
 }
 
+/// Window and pagination for `/scan`: keys in `[start_key, end_key)`, either
+/// bound optional, at most `limit` at a time after the key encoded in
+/// `cursor`. Without `cursor` or `limit`, the whole window is returned in one
+/// response.
+#[derive(Deserialize)]
+pub struct ScanQuery {
+    pub start_key: Option<String>,
+    pub end_key: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct MemtableSizeRequest {
+    pub bytes: usize,
+}
+
+/// Either `keys`, or a `[start, end)` range
+#[derive(Deserialize)]
+pub struct WarmupRequest {
+    #[serde(default)]
+    pub keys: Vec<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct WatchQuery {
+    #[serde(default)]
+    pub prefix: String,
+}
+
 #[derive(Serialize)]
 pub struct ApiResponse {
     pub success: bool,
@@ -47,11 +173,18 @@ pub struct ApiResponse {
     pub data: Option<serde_json::Value>,
 }
 
+#[derive(Deserialize)]
+pub struct LogLevelRequest {
+    pub level: String,
+}
+
 #[derive(Deserialize)]
 pub struct SetFeatureRequest {
     pub enabled: bool,
     #[serde(default)]
     pub description: String,
+    #[serde(default)]
+    pub rollout_percent: Option<u8>,
 }
 
 #[derive(Serialize)]
@@ -59,17 +192,98 @@ pub struct FeatureResponse {
     pub name: String,
     pub enabled: bool,
     pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollout_percent: Option<u8>,
 }
 
+/// Readiness: 503 once the engine can no longer serve, e.g. after a panic
+/// poisoned one of its locks
 #[get("/health")]
-async fn health() -> impl Responder {
+async fn health(data: web::Data<AppState>) -> impl Responder {
+    match data.engine.stats_all() {
+        Ok(_) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: "LSM-Tree API is running".to_string(),
+            data: None,
+        }),
+        Err(e) => HttpResponse::ServiceUnavailable().json(ApiResponse {
+            success: false,
+            message: format!("Not ready: {}", e),
+            data: None,
+        }),
+    }
+}
+
+/// Liveness: 200 for as long as the process answers at all
+#[get("/live")]
+async fn live() -> impl Responder {
     HttpResponse::Ok().json(ApiResponse {
         success: true,
-        message: "LSM-Tree API is running".to_string(),
+        message: "alive".to_string(),
         data: None,
     })
 }
 
+#[get("/loglevel")]
+async fn get_log_level(data: web::Data<AppState>) -> impl Responder {
+    let current = data
+        .log_level
+        .as_ref()
+        .and_then(|handle| handle.with_current(|level| level.to_string()).ok());
+    match current {
+        Some(level) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: format!("Log level is {}", level),
+            data: Some(serde_json::json!({ "level": level })),
+        }),
+        None => HttpResponse::NotImplemented().json(ApiResponse {
+            success: false,
+            message: "Log level can't be changed at runtime".to_string(),
+            data: None,
+        }),
+    }
+}
+
+#[post("/loglevel")]
+async fn set_log_level(
+    req: web::Json<LogLevelRequest>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let Some(handle) = &data.log_level else {
+        return HttpResponse::NotImplemented().json(ApiResponse {
+            success: false,
+            message: "Log level can't be changed at runtime".to_string(),
+            data: None,
+        });
+    };
+    let level = match req.level.parse::<LevelFilter>() {
+        Ok(level) => level,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ApiResponse {
+                success: false,
+                message: format!(
+                    "Unknown log level '{}', expected off, error, warn, info, debug or trace",
+                    req.level
+                ),
+                data: None,
+            })
+        }
+    };
+
+    match handle.reload(level) {
+        Ok(()) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: format!("Log level set to {}", level),
+            data: Some(serde_json::json!({ "level": level.to_string() })),
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Error: {}", e),
+            data: None,
+        }),
+    }
+}
+
 #[get("/stats")]
 async fn get_stats(data: web::Data<AppState>) -> impl Responder {
     let stats = data.engine.stats();
@@ -96,6 +310,149 @@ async fn get_stats_all(data: web::Data<AppState>) -> impl Responder {
     }
 }
 
+#[get("/stats/wal")]
+async fn get_stats_wal(data: web::Data<AppState>) -> impl Responder {
+    let stats = data.engine.wal_stats();
+    HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "WAL stats retrieved".to_string(),
+        data: Some(serde_json::to_value(stats).unwrap_or_default()),
+    })
+}
+
+#[get("/config")]
+async fn get_config(data: web::Data<AppState>) -> impl Responder {
+    let config = data.engine.effective_config();
+    HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "Configuration retrieved".to_string(),
+        data: Some(serde_json::json!({
+            "core": config.core,
+            "storage": config.storage,
+            "derived": {
+                "block_cache_capacity_blocks": data.engine.block_cache_capacity_blocks(),
+            },
+        })),
+    })
+}
+
+#[post("/config/memtable_max_size")]
+async fn set_memtable_max_size(
+    req: web::Json<MemtableSizeRequest>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    match data.engine.set_memtable_max_size(req.bytes) {
+        Ok(_) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: format!("MemTable max size set to {} bytes", req.bytes),
+            data: None,
+        }),
+        Err(e @ LsmError::InvalidMemtableSize(_)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: e.to_string(),
+            data: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Error: {}", e),
+            data: None,
+        }),
+    }
+}
+
+#[get("/admin/quarantine")]
+async fn list_quarantine(data: web::Data<AppState>) -> impl Responder {
+    match data.engine.quarantined_files() {
+        Ok(files) => {
+            let files: Vec<String> = files.iter().map(|p| p.display().to_string()).collect();
+            HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                message: format!("{} quarantined files", files.len()),
+                data: Some(serde_json::json!({ "files": files })),
+            })
+        }
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Error: {}", e),
+            data: None,
+        }),
+    }
+}
+
+#[post("/admin/warmup")]
+async fn warmup(req: web::Json<WarmupRequest>, data: web::Data<AppState>) -> impl Responder {
+    let result = match (&req.start, &req.end) {
+        (Some(start), Some(end)) => data.engine.warmup_range(start, end),
+        (None, None) => data.engine.warmup(&req.keys),
+        _ => {
+            return HttpResponse::BadRequest().json(ApiResponse {
+                success: false,
+                message: "Both start and end are required for a range warmup".to_string(),
+                data: None,
+            })
+        }
+    };
+
+    match result {
+        Ok(blocks) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: format!("{} blocks loaded into cache", blocks),
+            data: Some(serde_json::json!({ "blocks": blocks })),
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Error: {}", e),
+            data: None,
+        }),
+    }
+}
+
+#[post("/flush")]
+async fn flush(data: web::Data<AppState>) -> impl Responder {
+    // Writing SSTables blocks, so keep it off the async workers
+    let engine = Arc::clone(&data.engine);
+    let result = web::block(move || engine.flush())
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|flushed| flushed.map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: "MemTable flushed".to_string(),
+            data: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Error: {}", e),
+            data: None,
+        }),
+    }
+}
+
+#[post("/compact")]
+async fn compact(data: web::Data<AppState>) -> impl Responder {
+    let engine = Arc::clone(&data.engine);
+    let result = web::block(move || engine.compact_now())
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|compacted| compacted.map_err(|e| e.to_string()));
+    match result {
+        Ok(summary) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: format!(
+                "Compacted {} sstables into {}",
+                summary.tables_before, summary.tables_after
+            ),
+            data: Some(serde_json::json!(summary)),
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Error: {}", e),
+            data: None,
+        }),
+    }
+}
+
 #[get("/keys/{key}")]
 async fn get_key(path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
     let key = path.into_inner();
@@ -108,7 +465,8 @@ async fn get_key(path: web::Path<String>, data: web::Data<AppState>) -> impl Res
                 message: "Key found".to_string(),
                 data: Some(serde_json::json!({
                     "key": key,
-                    "value": value_str
+                    "value": value_str,
+                    "value_b64": STANDARD.encode(&value),
                 })),
             })
         }
@@ -127,7 +485,16 @@ async fn get_key(path: web::Path<String>, data: web::Data<AppState>) -> impl Res
 
 #[post("/keys")]
 async fn set_key(req: web::Json<SetRequest>, data: web::Data<AppState>) -> impl Responder {
-    let value_bytes = req.value.as_bytes().to_vec();
+    let value_bytes = match req.value_bytes() {
+        Ok(value) => value,
+        Err(message) => {
+            return HttpResponse::BadRequest().json(ApiResponse {
+                success: false,
+                message,
+                data: None,
+            })
+        }
+    };
 
     match data.engine.set(req.key.clone(), value_bytes) {
         Ok(_) => HttpResponse::Ok().json(ApiResponse {
@@ -135,6 +502,16 @@ async fn set_key(req: web::Json<SetRequest>, data: web::Data<AppState>) -> impl
             message: format!("Key '{}' set successfully", req.key),
             data: Some(serde_json::json!({ "key": req.key })),
         }),
+        Err(LsmError::ValidationRejected(msg)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: format!("Validation rejected: {}", msg),
+            data: None,
+        }),
+        Err(e @ LsmError::ValueTooLarge(_)) => HttpResponse::PayloadTooLarge().json(ApiResponse {
+            success: false,
+            message: e.to_string(),
+            data: None,
+        }),
         Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
             success: false,
             message: format!("Error: {}", e),
@@ -145,11 +522,21 @@ async fn set_key(req: web::Json<SetRequest>, data: web::Data<AppState>) -> impl
 
 #[post("/keys/batch")]
 async fn set_batch(req: web::Json<BatchSetRequest>, data: web::Data<AppState>) -> impl Responder {
-    let records: Vec<(String, Vec<u8>)> = req
+    let records: Result<Vec<(String, Vec<u8>)>, String> = req
         .records
         .iter()
-        .map(|r| (r.key.clone(), r.value.as_bytes().to_vec()))
+        .map(|r| Ok((r.key.clone(), r.value_bytes()?)))
         .collect();
+    let records = match records {
+        Ok(records) => records,
+        Err(message) => {
+            return HttpResponse::BadRequest().json(ApiResponse {
+                success: false,
+                message,
+                data: None,
+            })
+        }
+    };
 
     match data.engine.set_batch(records) {
         Ok(count) => HttpResponse::Ok().json(ApiResponse {
@@ -157,6 +544,16 @@ async fn set_batch(req: web::Json<BatchSetRequest>, data: web::Data<AppState>) -
             message: format!("{} keys inserted successfully", count),
             data: Some(serde_json::json!({ "count": count })),
         }),
+        Err(LsmError::ValidationRejected(msg)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: format!("Validation rejected: {}", msg),
+            data: None,
+        }),
+        Err(e @ LsmError::ValueTooLarge(_)) => HttpResponse::PayloadTooLarge().json(ApiResponse {
+            success: false,
+            message: e.to_string(),
+            data: None,
+        }),
         Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
             success: false,
             message: format!("Error: {}", e),
@@ -183,13 +580,37 @@ async fn delete_key(path: web::Path<String>, data: web::Data<AppState>) -> impl
     }
 }
 
+#[delete("/keys/batch")]
+async fn delete_batch(
+    req: web::Json<BatchDeleteRequest>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    match data.engine.delete_batch(req.into_inner().keys) {
+        Ok(count) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: format!("{} keys deleted successfully", count),
+            data: Some(serde_json::json!({ "count": count })),
+        }),
+        Err(e @ LsmError::ValueTooLarge(_)) => HttpResponse::PayloadTooLarge().json(ApiResponse {
+            success: false,
+            message: e.to_string(),
+            data: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Error: {}", e),
+            data: None,
+        }),
+    }
+}
+
 #[get("/keys")]
 async fn list_keys(data: web::Data<AppState>) -> impl Responder {
     match data.engine.keys() {
         Ok(keys) => {
             let filtered_keys: Vec<String> = keys
                 .into_iter()
-                .filter(|k: &String| !k.starts_with("feature:"))
+                .filter(|k: &String| !data.is_hidden(k))
                 .collect();
 
             HttpResponse::Ok().json(ApiResponse {
@@ -221,7 +642,8 @@ async fn search_keys(query: web::Query<SearchQuery>, data: web::Data<AppState>)
                 .map(|(k, v): (String, Vec<u8>)| {
                     serde_json::json!({
                         "key": k,
-                        "value": String::from_utf8_lossy(&v).to_string()
+                        "value": String::from_utf8_lossy(&v).to_string(),
+                        "value_b64": STANDARD.encode(&v),
                     })
                 })
                 .collect();
@@ -241,16 +663,71 @@ async fn search_keys(query: web::Query<SearchQuery>, data: web::Data<AppState>)
 }
 
 #[get("/scan")]
-async fn scan_all(data: web::Data<AppState>) -> impl Responder {
-    match data.engine.scan() {
-        Ok(records) => {
+async fn scan_all(query: web::Query<ScanQuery>, data: web::Data<AppState>) -> impl Responder {
+    let paginated = query.cursor.is_some() || query.limit.is_some();
+    let limit = if paginated {
+        query
+            .limit
+            .unwrap_or(DEFAULT_SCAN_LIMIT)
+            .clamp(1, MAX_SCAN_LIMIT)
+    } else {
+        usize::MAX
+    };
+
+    // Resume strictly after the last key of the previous page, which is
+    // already past `start_key`
+    let after = match query.cursor.as_deref().filter(|cursor| !cursor.is_empty()) {
+        Some(cursor) => match decode_cursor(cursor) {
+            Some(key) => Some(key),
+            None => {
+                return HttpResponse::BadRequest().json(ApiResponse {
+                    success: false,
+                    message: "Invalid cursor".to_string(),
+                    data: None,
+                })
+            }
+        },
+        None => None,
+    };
+    let start = match (&after, &query.start_key) {
+        (Some(key), _) => Bound::Excluded(key.as_str()),
+        (None, Some(key)) => Bound::Included(key.as_str()),
+        (None, None) => Bound::Unbounded,
+    };
+    let end = match &query.end_key {
+        Some(key) => Bound::Excluded(key.as_bytes()),
+        None => Bound::Unbounded,
+    };
+
+    let page: Result<Vec<(String, Vec<u8>)>, LsmError> = data
+        .engine
+        .merged_iter(start, end, Direction::Ascending)
+        .and_then(|records| {
+            records
+                .filter(|record| !matches!(record, Ok((k, _)) if data.is_hidden(k)))
+                // One extra record tells whether there is a next page
+                .take(limit.saturating_add(1))
+                .collect()
+        });
+
+    match page {
+        Ok(mut records) => {
+            let next_cursor = if records.len() > limit {
+                records.truncate(limit);
+                records
+                    .last()
+                    .map(|(k, _)| encode_cursor(k))
+                    .unwrap_or_default()
+            } else {
+                String::new()
+            };
             let records_json: Vec<serde_json::Value> = records
                 .into_iter()
-                .filter(|(k, _): &(String, Vec<u8>)| !k.starts_with("feature:"))
                 .map(|(k, v): (String, Vec<u8>)| {
                     serde_json::json!({
                         "key": k,
-                        "value": String::from_utf8_lossy(&v).to_string()
+                        "value": String::from_utf8_lossy(&v).to_string(),
+                        "value_b64": STANDARD.encode(&v),
                     })
                 })
                 .collect();
@@ -258,7 +735,10 @@ async fn scan_all(data: web::Data<AppState>) -> impl Responder {
             HttpResponse::Ok().json(ApiResponse {
                 success: true,
                 message: format!("{} records found", records_json.len()),
-                data: Some(serde_json::json!({ "records": records_json })),
+                data: Some(serde_json::json!({
+                    "records": records_json,
+                    "next_cursor": next_cursor,
+                })),
             })
         }
         Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
@@ -269,6 +749,40 @@ async fn scan_all(data: web::Data<AppState>) -> impl Responder {
     }
 }
 
+/// Records in `[start_key, end_key)` as newline-delimited
+/// `{"key": ..., "value": ..., "value_b64": ...}`, streamed in key order without a page limit
+#[get("/scan/stream")]
+async fn scan_stream(query: web::Query<ScanQuery>, data: web::Data<AppState>) -> impl Responder {
+    let start = match &query.start_key {
+        Some(key) => Bound::Included(key.as_str()),
+        None => Bound::Unbounded,
+    };
+    let end = match &query.end_key {
+        Some(key) => Bound::Excluded(key.as_bytes()),
+        None => Bound::Unbounded,
+    };
+
+    let records = match data.engine.merged_iter(start, end, Direction::Ascending) {
+        Ok(records) => records,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ApiResponse {
+                success: false,
+                message: format!("Error: {}", e),
+                data: None,
+            })
+        }
+    };
+
+    stream_ndjson(records, data.into_inner(), |key, value| {
+        let record = serde_json::json!({
+            "key": key,
+            "value": String::from_utf8_lossy(value),
+            "value_b64": STANDARD.encode(value),
+        });
+        Some(format!("{}\n", record))
+    })
+}
+
 #[get("/features")]
 async fn list_features(data: web::Data<AppState>) -> impl Responder {
     match data.features.list_all() {
@@ -280,6 +794,7 @@ async fn list_features(data: web::Data<AppState>) -> impl Responder {
                     name: name.clone(),
                     enabled: flag.enabled,
                     description: flag.description.clone(),
+                    rollout_percent: flag.rollout_percent,
                 })
                 .collect();
 
@@ -307,15 +822,23 @@ async fn set_feature(
     data: web::Data<AppState>,
 ) -> impl Responder {
     let name = path.into_inner();
-    match data
-        .features
-        .set_flag(name.clone(), req.enabled, Some(req.description.clone()))
-    {
+    let req = req.into_inner();
+    let flag = FeatureFlag {
+        enabled: req.enabled,
+        description: req.description,
+        rollout_percent: req.rollout_percent,
+    };
+    match data.features.put_flag(name.clone(), flag) {
         Ok(_) => HttpResponse::Ok().json(ApiResponse {
             success: true,
             message: format!("Feature '{}' updated", name),
             data: None,
         }),
+        Err(LsmError::ValidationRejected(msg)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: format!("Validation rejected: {}", msg),
+            data: None,
+        }),
         Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
             success: false,
             message: format!("Error: {}", e),
@@ -324,10 +847,285 @@ async fn set_feature(
     }
 }
 
-pub async fn start_server(
-    engine: LsmEngine,
-    server_config: ServerConfig,
-) -> std::io::Result<()> {
+#[get("/features/{name}")]
+async fn get_feature(path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let name = path.into_inner();
+    match data.features.get_flag(&name) {
+        Ok(flag) => match flag {
+            Some(flag) => HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                message: format!("Feature '{}' found", name),
+                data: Some(serde_json::json!(FeatureResponse {
+                    name,
+                    enabled: flag.enabled,
+                    description: flag.description.clone(),
+                    rollout_percent: flag.rollout_percent,
+                })),
+            }),
+            None => HttpResponse::NotFound().json(ApiResponse {
+                success: false,
+                message: format!("Feature '{}' not found", name),
+                data: None,
+            }),
+        },
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Error: {}", e),
+            data: None,
+        }),
+    }
+}
+
+#[delete("/features/{name}")]
+async fn delete_feature(path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let name = path.into_inner();
+    match data.features.remove_flag(&name) {
+        Ok(true) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: format!("Feature '{}' deleted", name),
+            data: None,
+        }),
+        Ok(false) => HttpResponse::NotFound().json(ApiResponse {
+            success: false,
+            message: format!("Feature '{}' not found", name),
+            data: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Error: {}", e),
+            data: None,
+        }),
+    }
+}
+
+#[get("/namespaces")]
+async fn list_namespaces(data: web::Data<AppState>) -> impl Responder {
+    match data.engine.list_namespaces() {
+        Ok(namespaces) => {
+            // Hidden like in /keys, e.g. the feature flags under "feature:"
+            let namespaces: Vec<String> = namespaces
+                .into_iter()
+                .filter(|ns| !data.is_hidden(&format!("{ns}:")))
+                .collect();
+
+            HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                message: format!("{} namespaces found", namespaces.len()),
+                data: Some(serde_json::json!({ "namespaces": namespaces })),
+            })
+        }
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Error: {}", e),
+            data: None,
+        }),
+    }
+}
+
+/// Every visible record as newline-delimited [`DumpRecord`]s, streamed in
+/// key order
+#[get("/export")]
+async fn export(data: web::Data<AppState>) -> impl Responder {
+    let records =
+        match data
+            .engine
+            .merged_iter(Bound::Unbounded, Bound::Unbounded, Direction::Ascending)
+        {
+            Ok(records) => records,
+            Err(e) => {
+                return HttpResponse::InternalServerError().json(ApiResponse {
+                    success: false,
+                    message: format!("Error: {}", e),
+                    data: None,
+                })
+            }
+        };
+
+    stream_ndjson(records, data.into_inner(), |key, value| {
+        DumpRecord::new(key, value).to_line().ok()
+    })
+}
+
+/// Stream the visible `records` as newline-delimited lines from `to_line`.
+/// The iterator runs on its own thread and hands over chunks of about
+/// [`STREAM_CHUNK_SIZE`], so memory stays flat however many records there are.
+fn stream_ndjson(
+    records: KeyValueIterator,
+    state: Arc<AppState>,
+    to_line: fn(String, &[u8]) -> Option<String>,
+) -> HttpResponse {
+    let (tx, rx) = mpsc::channel::<std::io::Result<web::Bytes>>(16);
+    std::thread::spawn(move || {
+        let mut chunk = String::new();
+        for record in records {
+            let (key, value) = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    // Cuts the response short so a partial stream can't
+                    // pass for a complete one
+                    let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+                    return;
+                }
+            };
+            if state.is_hidden(&key) {
+                continue;
+            }
+
+            if let Some(line) = to_line(key, &value) {
+                chunk.push_str(&line);
+            }
+            if chunk.len() >= STREAM_CHUNK_SIZE
+                && tx
+                    .blocking_send(Ok(web::Bytes::from(std::mem::take(&mut chunk))))
+                    .is_err()
+            {
+                return;
+            }
+        }
+        if !chunk.is_empty() {
+            let _ = tx.blocking_send(Ok(web::Bytes::from(chunk)));
+        }
+    });
+
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(stream)
+}
+
+/// Load the output of `/export`, all lines committed as one batch
+#[post("/import")]
+async fn import(body: web::Bytes, data: web::Data<AppState>) -> impl Responder {
+    let mut batch = WriteBatch::new();
+    for (i, line) in body.split(|&b| b == b'\n').enumerate() {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        match DumpRecord::parse(line) {
+            Ok((key, value)) => {
+                batch.set(key, value);
+            }
+            Err(e) => {
+                return HttpResponse::BadRequest().json(ApiResponse {
+                    success: false,
+                    message: format!("Invalid record on line {}: {}", i + 1, e),
+                    data: None,
+                })
+            }
+        }
+    }
+
+    let count = batch.len();
+    match data.engine.commit(batch) {
+        Ok(()) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: format!("{} records imported", count),
+            data: Some(serde_json::json!({ "count": count })),
+        }),
+        Err(LsmError::ValidationRejected(msg)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: format!("Validation rejected: {}", msg),
+            data: None,
+        }),
+        Err(e @ LsmError::ValueTooLarge(_)) => HttpResponse::PayloadTooLarge().json(ApiResponse {
+            success: false,
+            message: e.to_string(),
+            data: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Error: {}", e),
+            data: None,
+        }),
+    }
+}
+
+#[get("/watch")]
+async fn watch(query: web::Query<WatchQuery>, data: web::Data<AppState>) -> impl Responder {
+    let subscription = data
+        .engine
+        .subscribe(&query.prefix, data.watch_capacity, data.watch_policy);
+
+    let (tx, rx) = mpsc::channel::<String>(16);
+    std::thread::spawn(move || forward_events(subscription, tx));
+
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv()
+            .await
+            .map(|frame| (Ok::<_, actix_web::Error>(web::Bytes::from(frame)), rx))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
+
+/// Bridge a blocking subscription to the async response stream. Returns (and
+/// drops the subscription) once the client goes away and `tx` is closed.
+fn forward_events(subscription: Subscription, tx: mpsc::Sender<String>) {
+    loop {
+        let frame = match subscription.recv_timeout(WATCH_KEEPALIVE) {
+            Ok(event) => match serde_json::to_string(&event) {
+                Ok(json) => format!("id: {}\ndata: {}\n\n", event.seq, json),
+                Err(_) => continue,
+            },
+            Err(RecvTimeoutError::Timeout) => ": keep-alive\n\n".to_string(),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        if tx.blocking_send(frame).is_err() {
+            return;
+        }
+    }
+}
+
+/// Register every endpoint behind the token check; the app must provide
+/// `web::Data<AppState>`
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("")
+            .wrap(from_fn(auth::require_token))
+            .service(health)
+            .service(live)
+            .service(get_log_level)
+            .service(set_log_level)
+            .service(get_stats)
+            .service(get_stats_all)
+            .service(get_stats_wal)
+            .service(get_config)
+            .service(set_memtable_max_size)
+            .service(list_quarantine)
+            .service(warmup)
+            .service(flush)
+            .service(compact)
+            // Before `/keys/{key}`, which would otherwise take "search" as a key
+            .service(search_keys)
+            .service(get_key)
+            .service(set_key)
+            .service(set_batch)
+            // Before `delete_key`, which would otherwise take "batch" for a key
+            .service(delete_batch)
+            .service(delete_key)
+            .service(list_keys)
+            .service(scan_all)
+            .service(scan_stream)
+            .service(list_features)
+            .service(set_feature)
+            .service(get_feature)
+            .service(delete_feature)
+            .service(list_namespaces)
+            .service(export)
+            .service(import)
+            .service(watch),
+    );
+}
+
+pub async fn start_server(engine: LsmEngine, server_config: ServerConfig) -> std::io::Result<()> {
     let engine = Arc::new(engine);
     let features = Arc::new(FeatureClient::new(
         Arc::clone(&engine),
@@ -338,11 +1136,18 @@ pub async fn start_server(
     println!("🚀 Starting server at {}:{}\n", server_config.host, server_config.port);
 
     let max_json = server_config.max_json_payload_size;
+    let watch_capacity = server_config.watch_channel_capacity;
+    let watch_policy = server_config.watch_overflow_policy;
+    let hidden_prefixes = server_config.hidden_key_prefixes.clone();
+    let api_token = server_config.api_token.clone();
+    let auth_reads = server_config.auth_reads;
+    let log_level = server_config.log_level.clone();
     let max_raw = server_config.max_raw_payload_size;
     let host = server_config.host.clone();
     let port = server_config.port;
 
-    HttpServer::new(move || {
+    let server_engine = Arc::clone(&engine);
+    let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
@@ -351,24 +1156,113 @@ pub async fn start_server(
         App::new()
             .wrap(cors)
             .app_data(web::Data::new(AppState {
-                engine: Arc::clone(&engine),
+                engine: Arc::clone(&server_engine),
                 features: Arc::clone(&features),
+                watch_capacity,
+                watch_policy,
+                hidden_prefixes: hidden_prefixes.clone(),
+                api_token: api_token.clone(),
+                auth_reads,
+                log_level: log_level.clone(),
             }))
             .app_data(web::JsonConfig::default().limit(max_json))
             .app_data(web::PayloadConfig::default().limit(max_raw))
-            .service(health)
-            .service(get_stats)
-            .service(get_stats_all)
-            .service(get_key)
-            .service(set_key)
-            .service(set_batch)
-            .service(list_keys)
-            .service(search_keys)
-            .service(scan_all)
-            .service(list_features)
-            .service(set_feature)
+            .configure(routes)
     })
+    // Signals are handled below so the engine can be closed after draining
+    .disable_signals()
+    .shutdown_timeout(server_config.shutdown_timeout_secs)
     .bind((host.as_str(), port))?
-    .run()
-    .await
+    .run();
+
+    let handle = server.handle();
+    tokio::spawn(async move {
+        wait_for_shutdown_signal().await;
+        println!("\n🛑 Shutdown signal received, finishing in-flight requests...");
+        handle.stop(true).await;
+    });
+
+    server.await?;
+
+    println!("💾 Flushing MemTable before exit...");
+    engine
+        .close()
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    println!("✓ Shutdown complete");
+    Ok(())
+}
+
+/// Resolve on SIGTERM (e.g. `docker stop`) or SIGINT / Ctrl+C
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = sigterm.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::config::LsmConfig;
+    use actix_web::test;
+    use tempfile::tempdir;
+
+    #[actix_web::test]
+    async fn test_health_turns_unavailable_once_a_lock_is_poisoned() {
+        let dir = tempdir().unwrap();
+        let config = LsmConfig::builder()
+            .dir_path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let engine = Arc::new(LsmEngine::new(config).unwrap());
+        let state = web::Data::new(AppState {
+            features: Arc::new(FeatureClient::new(
+                Arc::clone(&engine),
+                Duration::from_secs(10),
+            )),
+            engine: Arc::clone(&engine),
+            watch_capacity: 16,
+            watch_policy: OverflowPolicy::DropOldest,
+            hidden_prefixes: Vec::new(),
+            api_token: None,
+            auth_reads: false,
+            log_level: None,
+        });
+        let app = test::init_service(App::new().app_data(state).configure(routes)).await;
+
+        for uri in ["/health", "/live"] {
+            let request = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&app, request).await.status(), 200);
+        }
+
+        let poisoner = Arc::clone(&engine);
+        std::thread::spawn(move || {
+            let _guard = poisoner.memtables.write().unwrap();
+            panic!("poisoning the memtable lock");
+        })
+        .join()
+        .unwrap_err();
+
+        let request = test::TestRequest::get().uri("/health").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 503);
+        let request = test::TestRequest::get().uri("/live").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 200);
+    }
 }
@@ -1,7 +1,10 @@
-use lsm_kv_store::{LsmConfig, LsmEngine};
+use lsm_kv_store::{
+    Codec, CompactionStrategy, Compression, LsmConfig, LsmEngine, MemTableImpl, WalSyncPolicy,
+};
 use std::env;
 use std::io;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -11,19 +14,109 @@ async fn main() -> std::io::Result<()> {
         let _ = dotenvy::dotenv();
     }
 
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .with_level(true)
-        .init();
+    // Only a plain level here, which POST /loglevel can change later
+    let log_level = env::var("RUST_LOG")
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(tracing_subscriber::filter::LevelFilter::INFO);
+    let log_level = lsm_kv_store::api::install_tracing(log_level);
 
     println!("╔═══════════════════════════════════════════════════════╗");
     println!("║         LSM-Tree REST API Server                      ║");
     println!("╚═══════════════════════════════════════════════════════╝\n");
 
     // Load server configuration from environment
-    let server_config = lsm_kv_store::api::ServerConfig::from_env();
+    let server_config = lsm_kv_store::api::ServerConfig {
+        log_level: Some(log_level),
+        ..lsm_kv_store::api::ServerConfig::from_env()
+    };
+
+    // A --config file replaces the engine settings from the environment
+    let config = match config_path()? {
+        Some(path) => {
+            println!("📄 Loading engine configuration from {}", path.display());
+            LsmConfig::from_toml_path(&path)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?
+        }
+        None => config_from_env()?,
+    };
+    let core = &config.core;
+    let storage = &config.storage;
+
+    // Print LSM configuration
+    println!("📋 LSM Engine Configuration:");
+    match core.dir_path.canonicalize() {
+        Ok(abs_path) => println!("   Data Directory: {}", abs_path.display()),
+        Err(_) => println!(
+            "   Data Directory: {} (will be created)",
+            core.dir_path.display()
+        ),
+    }
+    println!(
+        "   MemTable Max Size: {} MB",
+        core.memtable_max_size / 1024 / 1024
+    );
+    println!("   MemTable Implementation: {:?}", storage.memtable_impl);
+    println!("   WAL Sync Policy: {:?}", core.wal_sync_policy);
+    if core.wal_group_commit_us > 0 {
+        println!("   WAL Group Commit: {} µs", core.wal_group_commit_us);
+    }
+    println!("   Block Size: {} bytes", storage.block_size);
+    println!("   Block Cache: {} MB", storage.block_cache_size_mb);
+    println!("   Block Compression: {:?}", storage.compression);
+    println!(
+        "   Sparse Index Interval: {}",
+        storage.sparse_index_interval
+    );
+    println!(
+        "   Bloom Filter FP Rate: {}",
+        storage.bloom_false_positive_rate
+    );
+    println!("   Record Codec: {:?}", storage.codec);
+    println!("   Compaction Strategy: {:?}", storage.compaction_strategy);
+    if let Some(separator) = storage.namespace_separator {
+        println!("   Namespace Separator: '{}'", separator);
+    }
+    println!();
+
+    let engine = match LsmEngine::new(config) {
+        Ok(engine) => engine,
+        Err(e) => {
+            eprintln!("❌ Error initializing LSM Engine: {}", e);
+            eprintln!("💡 Tip: if you don't need to recover unflushed writes, move the wal-*.log segments out of the data directory and try again.");
+            return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+        }
+    };
+
+    println!("✓ Engine initialized successfully!\n");
+
+    lsm_kv_store::api::start_server(engine, server_config).await
+}
 
-    // Load LSM engine configuration from environment
+/// The file given as `--config <file>` (or `--config=<file>`), if any
+fn config_path() -> io::Result<Option<PathBuf>> {
+    let mut args = env::args().skip(1);
+    let arg = match args.next() {
+        Some(arg) => arg,
+        None => return Ok(None),
+    };
+    let path = match arg.strip_prefix("--config=") {
+        Some(path) => Some(path.to_string()),
+        None if arg == "--config" => args.next(),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown argument '{}' (expected --config <file>)", arg),
+            ))
+        }
+    };
+    path.map(|path| Some(PathBuf::from(path)))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "--config needs a file path"))
+}
+
+/// Engine settings from `DATA_DIR`, `MEMTABLE_MAX_SIZE` and the other
+/// variables in `.env.example`
+fn config_from_env() -> io::Result<LsmConfig> {
     let data_dir = env::var("DATA_DIR").unwrap_or_else(|_| "./.lsm_data".to_string());
 
     let memtable_max_size = env::var("MEMTABLE_MAX_SIZE")
@@ -31,6 +124,32 @@ async fn main() -> std::io::Result<()> {
         .parse::<usize>()
         .unwrap_or(4 * 1024 * 1024);
 
+    let wal_group_commit_us = env::var("WAL_GROUP_COMMIT_US")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse::<u64>()
+        .unwrap_or(0);
+
+    let memtable_impl = match env::var("MEMTABLE_IMPL").as_deref() {
+        Ok("skiplist") => MemTableImpl::SkipList,
+        _ => MemTableImpl::BTree,
+    };
+
+    let wal_sync_policy = match env::var("WAL_SYNC_MODE").as_deref() {
+        Ok("every_second") => WalSyncPolicy::Interval(Duration::from_secs(1)),
+        Ok("manual") => WalSyncPolicy::Never,
+        _ => WalSyncPolicy::Always,
+    };
+
+    let wal_segment_size = env::var("WAL_SEGMENT_SIZE")
+        .unwrap_or_else(|_| (64 * 1024 * 1024).to_string())
+        .parse::<usize>()
+        .unwrap_or(64 * 1024 * 1024);
+
+    let wal_max_size = env::var("WAL_MAX_SIZE")
+        .unwrap_or_else(|_| (256 * 1024 * 1024).to_string())
+        .parse::<usize>()
+        .unwrap_or(256 * 1024 * 1024);
+
     let block_size = env::var("BLOCK_SIZE")
         .unwrap_or_else(|_| "4096".to_string())
         .parse::<usize>()
@@ -51,39 +170,76 @@ async fn main() -> std::io::Result<()> {
         .parse::<f64>()
         .unwrap_or(0.01);
 
-    let config = LsmConfig::builder()
+    let namespace_separator = env::var("NAMESPACE_SEPARATOR")
+        .ok()
+        .and_then(|s| s.chars().next());
+
+    let compaction_strategy = match env::var("COMPACTION_STRATEGY").as_deref() {
+        Ok("leveled") => CompactionStrategy::Leveled,
+        _ => CompactionStrategy::SizeTiered,
+    };
+
+    let level0_compaction_threshold = env::var("LEVEL0_COMPACTION_THRESHOLD")
+        .unwrap_or_else(|_| "4".to_string())
+        .parse::<usize>()
+        .unwrap_or(4);
+
+    let target_file_size = env::var("TARGET_FILE_SIZE")
+        .unwrap_or_else(|_| (2 * 1024 * 1024).to_string())
+        .parse::<usize>()
+        .unwrap_or(2 * 1024 * 1024);
+
+    let max_sstable_size = env::var("MAX_SSTABLE_SIZE")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok());
+
+    let max_key_size = env::var("MAX_KEY_SIZE")
+        .unwrap_or_else(|_| "1024".to_string())
+        .parse::<usize>()
+        .unwrap_or(1024);
+
+    let max_value_size = env::var("MAX_VALUE_SIZE")
+        .unwrap_or_else(|_| (60 * 1024).to_string())
+        .parse::<usize>()
+        .unwrap_or(60 * 1024);
+
+    let codec = match env::var("RECORD_CODEC").as_deref() {
+        Ok("varint") => Codec::Varint,
+        _ => Codec::Fixint,
+    };
+
+    let compression = match env::var("BLOCK_COMPRESSION").as_deref() {
+        Ok("none") => Compression::None,
+        Ok("zstd") => Compression::Zstd,
+        _ => Compression::Lz4,
+    };
+
+    let mut builder = LsmConfig::builder()
         .dir_path(PathBuf::from(&data_dir))
         .memtable_max_size(memtable_max_size)
+        .memtable_impl(memtable_impl)
+        .wal_group_commit_us(wal_group_commit_us)
+        .wal_segment_size(wal_segment_size)
+        .wal_max_size(wal_max_size)
+        .wal_sync_policy(wal_sync_policy)
         .block_size(block_size)
         .block_cache_size_mb(block_cache_size_mb)
         .sparse_index_interval(sparse_index_interval)
         .bloom_false_positive_rate(bloom_false_positive_rate)
-        .build()
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
-
-    // Print LSM configuration
-    println!("📋 LSM Engine Configuration:");
-    match PathBuf::from(&data_dir).canonicalize() {
-        Ok(abs_path) => println!("   Data Directory: {}", abs_path.display()),
-        Err(_) => println!("   Data Directory: {} (will be created)", data_dir),
+        .compaction_strategy(compaction_strategy)
+        .level0_compaction_threshold(level0_compaction_threshold)
+        .target_file_size(target_file_size)
+        .max_key_size(max_key_size)
+        .max_value_size(max_value_size)
+        .codec(codec)
+        .compression(compression);
+    if let Some(separator) = namespace_separator {
+        builder = builder.namespace_separator(separator);
     }
-    println!("   MemTable Max Size: {} MB", memtable_max_size / 1024 / 1024);
-    println!("   Block Size: {} bytes", block_size);
-    println!("   Block Cache: {} MB", block_cache_size_mb);
-    println!("   Sparse Index Interval: {}", sparse_index_interval);
-    println!("   Bloom Filter FP Rate: {}", bloom_false_positive_rate);
-    println!();
-
-    let engine = match LsmEngine::new(config) {
-        Ok(engine) => engine,
-        Err(e) => {
-            eprintln!("❌ Error initializing LSM Engine: {}", e);
-            eprintln!("💡 Tip: if you don't need to recover unflushed writes, rename/delete wal.log and try again.");
-            return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
-        }
-    };
-
-    println!("✓ Engine initialized successfully!\n");
-
-    lsm_kv_store::api::start_server(engine, server_config).await
+    if let Some(max) = max_sstable_size {
+        builder = builder.max_sstable_size(max);
+    }
+    builder
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
 }
@@ -1,7 +1,12 @@
 use lsm_kv_store::{LsmConfig, LsmEngine};
-use std::io::{self, Write};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::ops::Bound;
 use std::path::PathBuf;
 
+/// Rows `LOADCSV` inserts per `set_batch`
+const CSV_BATCH: usize = 1000;
+
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Configurar tracing
     tracing_subscriber::fmt()
@@ -15,21 +20,22 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("╚═══════════════════════════════════════════════════════╝\n");
 
     // Configuração
-    let config = LsmConfig {
-        memtable_max_size: 4 * 1024, // 4KB para testes
-        data_dir: PathBuf::from("./.lsm_data"),
-    };
+    let data_dir = PathBuf::from("./.lsm_data");
+    let config = LsmConfig::builder()
+        .memtable_max_size(4 * 1024) // 4KB para testes
+        .dir_path(data_dir.clone())
+        .build()?;
+
+    println!("Inicializando engine em: {}", data_dir.display());
+    let comparator = config.storage.key_comparator;
+    let engine = LsmEngine::new(config)?;
+    println!("✓ Engine inicializado com sucesso!\n");
 
-    // ADICIONAR ESTA LINHA:
     println!(
         "📂 Diretório de dados: {}",
-        config.data_dir.canonicalize()?.display()
+        data_dir.canonicalize()?.display()
     );
 
-    println!("Inicializando engine em: {}", config.data_dir.display());
-    let engine = LsmEngine::new(config)?;
-    println!("✓ Engine inicializado com sucesso!\n");
-
     print_help();
     println!();
 
@@ -98,6 +104,21 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("{}", engine.stats());
             }
 
+            "FLUSH" => match engine.flush() {
+                Ok(()) => println!("✓ MemTable gravada em SSTable"),
+                Err(e) => println!("❌ Erro: {}", e),
+            },
+
+            "COMPACT" => match engine.compact_now() {
+                Ok(summary) => {
+                    println!("✓ Compactação concluída");
+                    println!("  SSTables antes:   {}", summary.tables_before);
+                    println!("  SSTables depois:  {}", summary.tables_after);
+                    println!("  Bytes liberados:  {}", summary.bytes_reclaimed);
+                }
+                Err(e) => println!("❌ Erro: {}", e),
+            },
+
             "HELP" | "?" => {
                 print_help();
             }
@@ -148,49 +169,153 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             "SCAN" => {
                 if parts.len() < 2 {
-                    println!("❌ Uso: SCAN <prefix>");
+                    println!("❌ Uso: SCAN <prefix> [limit]");
                     continue;
                 }
                 let prefix = parts[1];
-                println!(
-                    "⚠ SCAN não implementado na Fase 1 (requer iterador sobre MemTable + SSTables)"
-                );
-                println!("  Prefix procurado: '{}'", prefix);
+                let limit = match parts.get(2).map(|n| n.trim().parse::<usize>()) {
+                    Some(Ok(n)) => Some(n),
+                    Some(Err(_)) => {
+                        println!("❌ Limite inválido");
+                        continue;
+                    }
+                    None => None,
+                };
+
+                match engine.prefix_scan(prefix, limit) {
+                    Ok(records) if records.is_empty() => {
+                        println!("⚠ Nenhum registro com o prefixo '{}'", prefix)
+                    }
+                    Ok(records) => print_records(records),
+                    Err(e) => println!("❌ Erro ao escanear: {}", e),
+                }
+            }
+
+            "RANGE" => {
+                let args: Vec<&str> = input.split_whitespace().skip(1).collect();
+                let (start, end) = match parse_range(&args) {
+                    Ok(bounds) => bounds,
+                    Err(msg) => {
+                        println!("❌ {}", msg);
+                        println!(
+                            "   Uso: RANGE <início> <fim> [--exclusive-start] [--exclusive-end]"
+                        );
+                        continue;
+                    }
+                };
+                if let (
+                    Bound::Included(s) | Bound::Excluded(s),
+                    Bound::Included(e) | Bound::Excluded(e),
+                ) = (&start, &end)
+                {
+                    if comparator.compare(s.as_bytes(), e.as_bytes()).is_gt() {
+                        println!(
+                            "⚠ Início '{}' é maior que o fim '{}': intervalo vazio",
+                            s, e
+                        );
+                        continue;
+                    }
+                }
+
+                match engine.range(start, end) {
+                    Ok(records) if records.is_empty() => println!("⚠ Nenhum registro no intervalo"),
+                    Ok(records) => print_records(records),
+                    Err(e) => println!("❌ Erro ao escanear: {}", e),
+                }
             }
 
             "ALL" => {
                 println!("Listando todos os registros...\n");
                 match engine.scan() {
-                    Ok(records) => {
-                        if records.is_empty() {
-                            println!("⚠ Banco de dados vazio");
-                        } else {
-                            println!("┌─────────────────────────────────────────────────┐");
-                            println!("│  Chave                │  Valor                 │");
-                            println!("├─────────────────────────────────────────────────┤");
-
-                            for (key, value) in records {
-                                let value_str = String::from_utf8_lossy(&value);
-                                let key_display = if key.len() > 20 {
-                                    format!("{}...", &key[..17])
-                                } else {
-                                    key
-                                };
-                                let value_display = if value_str.len() > 20 {
-                                    format!("{}...", &value_str[..17])
-                                } else {
-                                    value_str.to_string()
-                                };
-                                println!("│  {:<20} │  {:<20} │", key_display, value_display);
-                            }
-
-                            println!("└─────────────────────────────────────────────────┘");
-                        }
-                    }
+                    Ok(records) if records.is_empty() => println!("⚠ Banco de dados vazio"),
+                    Ok(records) => print_records(records),
                     Err(e) => println!("❌ Erro ao escanear: {}", e),
                 }
             }
 
+            "EXPORT" => {
+                if parts.len() < 2 {
+                    println!("❌ Uso: EXPORT <arquivo>");
+                    continue;
+                }
+                let path = parts[1];
+
+                let file = match File::create(path) {
+                    Ok(file) => file,
+                    Err(e) => {
+                        println!("❌ Não foi possível criar '{}': {}", path, e);
+                        continue;
+                    }
+                };
+                match engine.export_ndjson(BufWriter::new(file)) {
+                    Ok(count) => println!("✓ {} registros exportados para '{}'", count, path),
+                    Err(e) => println!("❌ Erro ao exportar: {}", e),
+                }
+            }
+
+            "IMPORT" => {
+                if parts.len() < 2 {
+                    println!("❌ Uso: IMPORT <arquivo>");
+                    continue;
+                }
+                let path = parts[1];
+
+                let file = match File::open(path) {
+                    Ok(file) => file,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        println!("❌ Arquivo '{}' não encontrado", path);
+                        continue;
+                    }
+                    Err(e) => {
+                        println!("❌ Não foi possível abrir '{}': {}", path, e);
+                        continue;
+                    }
+                };
+                match engine.import_ndjson(BufReader::new(file)) {
+                    Ok(count) => println!("✓ {} registros importados de '{}'", count, path),
+                    Err(e) => println!("❌ Erro ao importar: {}", e),
+                }
+            }
+
+            "LOADCSV" => {
+                if parts.len() < 2 {
+                    println!("❌ Uso: LOADCSV <arquivo> [delimitador]");
+                    continue;
+                }
+                let path = parts[1];
+                let delimiter = match parts.get(2).map(|d| d.trim()) {
+                    None => ',',
+                    Some("\\t") | Some("tab") => '\t',
+                    Some(d) if d.chars().count() == 1 => d.chars().next().unwrap(),
+                    Some(d) => {
+                        println!("❌ Delimitador inválido: '{}'", d);
+                        continue;
+                    }
+                };
+
+                let file = match File::open(path) {
+                    Ok(file) => file,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        println!("❌ Arquivo '{}' não encontrado", path);
+                        continue;
+                    }
+                    Err(e) => {
+                        println!("❌ Não foi possível abrir '{}': {}", path, e);
+                        continue;
+                    }
+                };
+
+                let start = std::time::Instant::now();
+                match load_csv(&engine, BufReader::new(file), delimiter) {
+                    Ok(count) => {
+                        let elapsed = start.elapsed();
+                        println!("✓ {} registros carregados em {:.2?}", count, elapsed);
+                        println!("  Taxa: {:.0} ops/s", count as f64 / elapsed.as_secs_f64());
+                    }
+                    Err(e) => println!("❌ Erro ao carregar CSV: {}", e),
+                }
+            }
+
             "KEYS" => match engine.keys() {
                 Ok(keys) => {
                     if keys.is_empty() {
@@ -220,17 +345,135 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Insert every `key<delimiter>value` row of `reader`, a line at a time and
+/// `CSV_BATCH` rows per batch. A first row whose key is `key` is taken for a
+/// header. Fields may be double-quoted (`""` for a literal quote) to contain
+/// the delimiter, but not line breaks.
+fn load_csv(
+    engine: &LsmEngine,
+    reader: impl BufRead,
+    delimiter: char,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut batch = Vec::with_capacity(CSV_BATCH);
+    let mut count = 0;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+
+        let (key, value) = split_row(line, delimiter)
+            .ok_or_else(|| format!("linha {}: esperado <chave>{}<valor>", i + 1, delimiter))?;
+        if i == 0 && key == "key" {
+            continue;
+        }
+        batch.push((key, value.into_bytes()));
+        if batch.len() == CSV_BATCH {
+            count += engine.set_batch(std::mem::take(&mut batch))?;
+        }
+    }
+    count += engine.set_batch(batch)?;
+    Ok(count)
+}
+
+/// The two fields of a CSV row, unquoted; `None` unless there are exactly two
+fn split_row(line: &str, delimiter: char) -> Option<(String, String)> {
+    let mut fields = Vec::with_capacity(2);
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    match <[String; 2]>::try_from(fields) {
+        Ok([key, value]) if !quoted => Some((key, value)),
+        _ => None,
+    }
+}
+
+/// Bounds for `RANGE <start> <end>`: both inclusive unless flagged with
+/// `--exclusive-start` / `--exclusive-end`
+fn parse_range(args: &[&str]) -> Result<(Bound<String>, Bound<String>), String> {
+    let (mut exclusive_start, mut exclusive_end) = (false, false);
+    let mut keys = Vec::with_capacity(2);
+    for &arg in args {
+        match arg {
+            "--exclusive-start" => exclusive_start = true,
+            "--exclusive-end" => exclusive_end = true,
+            flag if flag.starts_with("--") => return Err(format!("Opção desconhecida: {flag}")),
+            key => keys.push(key.to_string()),
+        }
+    }
+
+    let [start, end] = <[String; 2]>::try_from(keys)
+        .map_err(|_| "RANGE precisa de exatamente duas chaves".to_string())?;
+    let bound = |key, exclusive| {
+        if exclusive {
+            Bound::Excluded(key)
+        } else {
+            Bound::Included(key)
+        }
+    };
+    Ok((bound(start, exclusive_start), bound(end, exclusive_end)))
+}
+
+/// Key/value table, both columns cut to 20 characters
+fn print_records(records: Vec<(String, Vec<u8>)>) {
+    println!("┌─────────────────────────────────────────────────┐");
+    println!("│  Chave                │  Valor                 │");
+    println!("├─────────────────────────────────────────────────┤");
+
+    for (key, value) in records {
+        let value_str = String::from_utf8_lossy(&value);
+        println!(
+            "│  {:<20} │  {:<20} │",
+            truncate(&key),
+            truncate(&value_str)
+        );
+    }
+
+    println!("└─────────────────────────────────────────────────┘");
+}
+
+fn truncate(s: &str) -> String {
+    if s.chars().count() > 20 {
+        format!("{}...", s.chars().take(17).collect::<String>())
+    } else {
+        s.to_string()
+    }
+}
+
 fn print_help() {
     println!("Comandos disponíveis:");
     println!("  SET <key> <value>      - Insere ou atualiza um par chave-valor");
     println!("  GET <key>              - Recupera o valor de uma chave");
     println!("  DELETE <key>           - Remove uma chave (cria tombstone)");
-    println!("  SCAN <prefix>          - Lista todos os registros do banco com o prefixo");
+    println!("  SCAN <prefix> [limit]  - Lista os registros com o prefixo, em ordem");
+    println!("  RANGE <início> <fim>   - Lista as chaves no intervalo [início, fim]");
+    println!("        [--exclusive-start] [--exclusive-end]  (exclui o limite indicado)");
     println!("  ALL                    - Lista todos os registros do banco");
+    println!("  EXPORT <arquivo>       - Exporta todos os registros (NDJSON, valores em base64)");
+    println!("  IMPORT <arquivo>       - Importa registros de um arquivo gerado por EXPORT");
+    println!("  LOADCSV <arquivo> [d]  - Carrega pares chave,valor de um CSV (delimitador d)");
     println!("  KEYS                   - Lista apenas as chaves");
     println!("  COUNT                  - Conta registros ativos");
     println!("  STATS                  - Exibe estatísticas do engine");
     println!("  BATCH <count>          - Insere N registros de teste");
+    println!("  FLUSH                  - Grava a MemTable em uma SSTable");
+    println!("  COMPACT                - Executa uma compactação completa");
     println!("  DEMO                   - Executa demonstração de features");
     println!("  CLEAR                  - Limpa a tela");
     println!("  HELP ou ?              - Exibe esta ajuda");
@@ -297,3 +540,109 @@ fn run_demo(engine: &LsmEngine) -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_load_csv_fixture() {
+        let dir = tempdir().unwrap();
+        let config = LsmConfig::builder()
+            .memtable_max_size(4 * 1024)
+            .dir_path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let engine = LsmEngine::new(config).unwrap();
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/users.csv");
+        let file = BufReader::new(File::open(path).unwrap());
+        assert_eq!(load_csv(&engine, file, ',').unwrap(), 5);
+
+        assert_eq!(engine.get("key").unwrap(), None);
+        assert_eq!(engine.get("user:1").unwrap(), Some(b"Alice".to_vec()));
+        assert_eq!(
+            engine.get("user:3").unwrap(),
+            Some(b"Carol, \"CJ\" Jones".to_vec())
+        );
+        assert_eq!(engine.get("user:5").unwrap(), Some(Vec::new()));
+        assert_eq!(engine.count().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_load_csv_streams_in_batches() {
+        let dir = tempdir().unwrap();
+        let config = LsmConfig::builder()
+            .memtable_max_size(16 * 1024)
+            .dir_path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let engine = LsmEngine::new(config).unwrap();
+
+        let csv: String = (0..2500).map(|i| format!("k{i};v{i}\r\n")).collect();
+        assert_eq!(load_csv(&engine, csv.as_bytes(), ';').unwrap(), 2500);
+        assert_eq!(engine.get("k2499").unwrap(), Some(b"v2499".to_vec()));
+
+        let err = load_csv(&engine, "a,b\nonly-one-field\n".as_bytes(), ',').unwrap_err();
+        assert!(err.to_string().starts_with("linha 2"), "{err}");
+    }
+
+    #[test]
+    fn test_split_row() {
+        assert_eq!(split_row("a,b", ','), Some(("a".into(), "b".into())));
+        assert_eq!(
+            split_row("\"a,1\",\"x\"\"y\"", ','),
+            Some(("a,1".into(), "x\"y".into()))
+        );
+        assert_eq!(split_row("a\tb", '\t'), Some(("a".into(), "b".into())));
+        assert_eq!(split_row("a,b,c", ','), None);
+        assert_eq!(split_row("a", ','), None);
+        assert_eq!(split_row("\"a,b", ','), None);
+    }
+
+    #[test]
+    fn test_range_bound_styles_with_unicode_keys() {
+        let dir = tempdir().unwrap();
+        let config = LsmConfig::builder()
+            .memtable_max_size(4 * 1024)
+            .dir_path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let engine = LsmEngine::new(config).unwrap();
+        for key in ["água", "café", "ação", "zebra", "ñandú", "日本"] {
+            engine
+                .set(key.to_string(), key.as_bytes().to_vec())
+                .unwrap();
+        }
+        engine.flush().unwrap();
+        engine.set("maçã".to_string(), b"m".to_vec()).unwrap();
+
+        let keys = |args: &[&str]| -> Vec<String> {
+            let (start, end) = parse_range(args).unwrap();
+            engine
+                .range(start, end)
+                .unwrap()
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect()
+        };
+
+        // Byte order: ASCII first, so "água" sorts after "zebra", then CJK
+        assert_eq!(keys(&["café", "zebra"]), ["café", "maçã", "zebra"]);
+        assert_eq!(
+            keys(&["café", "zebra", "--exclusive-start", "--exclusive-end"]),
+            ["maçã"]
+        );
+        assert_eq!(
+            keys(&["zebra", "日本", "--exclusive-end"]),
+            ["zebra", "água", "ñandú"]
+        );
+        assert_eq!(keys(&["água", "日本"]), ["água", "ñandú", "日本"]);
+        assert!(keys(&["zebra", "café"]).is_empty());
+
+        assert!(parse_range(&["a"]).is_err());
+        assert!(parse_range(&["a", "b", "c"]).is_err());
+        assert!(parse_range(&["a", "b", "--inclusive"]).is_err());
+    }
+}
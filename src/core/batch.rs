@@ -0,0 +1,34 @@
+use crate::core::log_record::LogRecord;
+
+/// Writes applied together by [`LsmEngine::commit`](crate::LsmEngine::commit):
+/// after a crash either all of them are recovered or none.
+///
+/// Later operations on the same key win, as if applied one by one.
+#[derive(Debug, Clone, Default)]
+pub struct WriteBatch {
+    pub(crate) records: Vec<LogRecord>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, value: Vec<u8>) -> &mut Self {
+        self.records.push(LogRecord::new(key.into(), value));
+        self
+    }
+
+    pub fn delete(&mut self, key: impl Into<String>) -> &mut Self {
+        self.records.push(LogRecord::tombstone(key.into()));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
@@ -0,0 +1,408 @@
+use crate::core::iterator::{Direction, HeapEntry};
+use crate::core::log_record::LogRecord;
+use crate::infra::config::{KeyComparator, StorageConfig};
+use crate::infra::error::{LsmError, Result};
+use crate::storage::blob::BlobRef;
+use crate::storage::builder::SstableBuilder;
+use crate::storage::reader::{SstableIter, SstableReader};
+use std::borrow::Borrow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Order tables the way reads walk them: L0 newest first (tables sharing a
+/// timestamp by their newest record), then the deeper levels. Tables within a
+/// level >= 1 never overlap, so their relative order doesn't matter.
+pub(crate) fn sort_for_reads(tables: &mut [Arc<SstableReader>]) {
+    tables.sort_by_key(|sst| {
+        let meta = sst.metadata();
+        (sst.level(), Reverse(meta.timestamp), Reverse(meta.max_seq))
+    });
+}
+
+/// Whether the key ranges of `table` and `[min_key, max_key]` intersect
+pub(crate) fn overlaps(table: &SstableReader, min_key: &[u8], max_key: &[u8]) -> bool {
+    let meta = table.metadata();
+    let cmp = table.comparator();
+    cmp.compare(&meta.min_key, max_key).is_le() && cmp.compare(&meta.max_key, min_key).is_ge()
+}
+
+/// Merge `tables` (ordered newest first, as the engine keeps them) into a
+/// single sorted run holding the newest version of every key.
+///
+/// Tombstones are only safe to drop when every older version of the key is
+/// part of the merge; callers pass `drop_tombstones = true` only in that case.
+/// Expired records shadow older versions just like tombstones, so they are
+/// dropped under the same condition.
+///
+/// Out-of-line values are not read: kept records carry their blob pointer.
+/// Records come out in the order of the tables' comparator, streamed a block
+/// per table at a time.
+pub(crate) fn merge_tables(
+    tables: &[Arc<SstableReader>],
+    drop_tombstones: bool,
+) -> Result<MergedTables> {
+    let mut merged = MergedTables {
+        sources: tables.iter().map(|table| table.iter().raw()).collect(),
+        heap: BinaryHeap::new(),
+        comparator: tables
+            .first()
+            .map(|table| table.comparator())
+            .unwrap_or_default(),
+        drop_tombstones,
+        dropped_blobs: Vec::new(),
+        failed: false,
+    };
+    for source in 0..merged.sources.len() {
+        merged.advance(source)?;
+    }
+    Ok(merged)
+}
+
+/// K-way merge returned by [`merge_tables`], yielding the records to keep
+pub(crate) struct MergedTables {
+    sources: Vec<SstableIter>,
+    heap: BinaryHeap<Reverse<HeapEntry>>,
+    comparator: KeyComparator,
+    drop_tombstones: bool,
+    /// Blobs of the versions left out so far. Each blob belongs to exactly
+    /// one record version, so nothing references these once the inputs are
+    /// gone.
+    dropped_blobs: Vec<BlobRef>,
+    /// Set once a source failed, after which nothing more is yielded
+    failed: bool,
+}
+
+impl MergedTables {
+    /// Blobs of every version the merge left out; only complete once the
+    /// merge has run to the end
+    pub(crate) fn into_dropped_blobs(self) -> Vec<BlobRef> {
+        self.dropped_blobs
+    }
+
+    /// Push the next record of `source` onto the heap
+    fn advance(&mut self, source: usize) -> Result<()> {
+        let Some(next) = self.sources[source].next() else {
+            return Ok(());
+        };
+        let (key_bytes, record) = next?;
+        let key =
+            String::from_utf8(key_bytes).map_err(|e| LsmError::CorruptedData(e.to_string()))?;
+        self.heap.push(Reverse(HeapEntry {
+            key,
+            source,
+            record,
+            direction: Direction::Ascending,
+            comparator: self.comparator,
+        }));
+        Ok(())
+    }
+
+    /// Pop the newest version of the next key, dropping the older ones
+    fn pop_newest(&mut self) -> Result<Option<HeapEntry>> {
+        let Some(Reverse(newest)) = self.heap.pop() else {
+            return Ok(None);
+        };
+        self.advance(newest.source)?;
+
+        // Older versions of the same key sit right behind it in the heap
+        while self
+            .heap
+            .peek()
+            .is_some_and(|Reverse(next)| next.key == newest.key)
+        {
+            if let Some(Reverse(older)) = self.heap.pop() {
+                self.dropped_blobs.extend(older.record.blob);
+                self.advance(older.source)?;
+            }
+        }
+        Ok(Some(newest))
+    }
+}
+
+impl Iterator for MergedTables {
+    type Item = Result<(String, LogRecord)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            match self.pop_newest() {
+                Ok(Some(newest)) if newest.record.is_live() || !self.drop_tombstones => {
+                    return Some(Ok((newest.key, newest.record)));
+                }
+                Ok(Some(dropped)) => self.dropped_blobs.extend(dropped.record.blob),
+                Ok(None) => return None,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Write `records` (sorted, one version per key) into `dir` as consecutive
+/// tables tagged with `level`, starting a new table once the current one holds
+/// `target_size` bytes. Returns the paths in key order; on error, any table
+/// written so far is removed.
+pub(crate) fn write_tables(
+    dir: &Path,
+    config: &StorageConfig,
+    records: &[(String, LogRecord)],
+    level: u32,
+    target_size: u64,
+) -> Result<Vec<PathBuf>> {
+    write_merged_tables(
+        dir,
+        config,
+        records.iter().map(|(key, record)| Ok((key, record))),
+        level,
+        target_size,
+    )
+}
+
+/// [`write_tables`] for records streamed out of a merge, which stop the write
+/// at their first error
+pub(crate) fn write_merged_tables<K: AsRef<str>, R: Borrow<LogRecord>>(
+    dir: &Path,
+    config: &StorageConfig,
+    records: impl Iterator<Item = Result<(K, R)>>,
+    level: u32,
+    target_size: u64,
+) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    let result = write_tables_into(dir, config, records, level, target_size, &mut paths);
+
+    if result.is_err() {
+        for path in &paths {
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("Failed to remove partial SSTable {}: {}", path.display(), e);
+            }
+        }
+    }
+    result.map(|_| paths)
+}
+
+fn write_tables_into<K: AsRef<str>, R: Borrow<LogRecord>>(
+    dir: &Path,
+    config: &StorageConfig,
+    records: impl Iterator<Item = Result<(K, R)>>,
+    level: u32,
+    target_size: u64,
+    paths: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let base_timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    // An unfinished table removes its own file when its builder is dropped
+    let mut builder: Option<SstableBuilder> = None;
+    let mut started = 0u128;
+
+    for entry in records {
+        let (key, record) = entry?;
+        let current = match builder.as_mut() {
+            Some(current) => current,
+            None => {
+                let mut new_builder =
+                    SstableBuilder::create_in(dir, config.clone(), base_timestamp + started)?;
+                started += 1;
+                new_builder.set_level(level);
+                builder.insert(new_builder)
+            }
+        };
+
+        current.add(key.as_ref().as_bytes(), record.borrow())?;
+
+        if current.estimated_size() >= target_size {
+            if let Some(full) = builder.take() {
+                paths.push(full.finish()?);
+            }
+        }
+    }
+
+    if let Some(last) = builder {
+        paths.push(last.finish()?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::cache::GlobalBlockCache;
+
+    fn build_table(
+        dir: &std::path::Path,
+        timestamp: u128,
+        records: &[LogRecord],
+    ) -> Arc<SstableReader> {
+        let config = StorageConfig::default();
+        let path = dir.join(format!("{timestamp}.sst"));
+        let mut builder = SstableBuilder::new(path.clone(), config.clone(), timestamp).unwrap();
+        for record in records {
+            builder.add(record.key.as_bytes(), record).unwrap();
+        }
+        builder.finish().unwrap();
+        Arc::new(SstableReader::open(path, config, GlobalBlockCache::new(1, 4096)).unwrap())
+    }
+
+    #[test]
+    fn test_merge_newest_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = build_table(
+            dir.path(),
+            1,
+            &[
+                LogRecord::new("a".to_string(), b"old".to_vec()),
+                LogRecord::new("b".to_string(), b"old".to_vec()),
+            ],
+        );
+        let new = build_table(
+            dir.path(),
+            2,
+            &[
+                LogRecord::new("a".to_string(), b"new".to_vec()),
+                LogRecord::tombstone("b".to_string()),
+            ],
+        );
+        let tables = vec![new, old];
+
+        let kept = merge_tables(&tables, false)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].1.value, b"new");
+        assert!(kept[1].1.is_deleted);
+
+        let dropped = merge_tables(&tables, true)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].0, "a");
+    }
+
+    #[test]
+    fn test_merged_table_bloom_is_sized_for_the_surviving_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        // Three generations of the same 1000 keys, plus tombstones for 100
+        let mut tables = Vec::new();
+        for generation in 0..3u128 {
+            let records: Vec<LogRecord> = (0..1000)
+                .map(|i| {
+                    let key = format!("key_{:04}", i);
+                    if generation == 2 && i % 10 == 0 {
+                        LogRecord::tombstone(key)
+                    } else {
+                        LogRecord::new(key, format!("g{generation}").into_bytes())
+                    }
+                })
+                .collect();
+            tables.insert(0, build_table(dir.path(), generation + 1, &records));
+        }
+
+        let merged = merge_tables(&tables, true)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(merged.len(), 900);
+
+        let config = StorageConfig::default();
+        let out = dir.path().join("out");
+        std::fs::create_dir(&out).unwrap();
+        let paths = write_tables(&out, &config, &merged, 1, u64::MAX).unwrap();
+        let table = SstableReader::open(
+            paths[0].clone(),
+            config.clone(),
+            GlobalBlockCache::new(1, 4096),
+        )
+        .unwrap();
+
+        let bloom =
+            bloomfilter::Bloom::<[u8]>::from_bytes(table.metadata().bloom_filter_data.clone())
+                .unwrap();
+        let expected_bytes =
+            bloomfilter::Bloom::<[u8]>::compute_bitmap_size(900, config.bloom_false_positive_rate);
+        assert_eq!(table.metadata().record_count, 900);
+        assert_eq!(bloom.len(), expected_bytes as u64 * 8);
+    }
+
+    #[test]
+    fn test_write_tables_splits_at_target_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig::default();
+        let records: Vec<(String, LogRecord)> = (0..200)
+            .map(|i| {
+                let key = format!("key_{:04}", i);
+                (key.clone(), LogRecord::new(key, vec![b'x'; 100]))
+            })
+            .collect();
+
+        let paths = write_tables(dir.path(), &config, &records, 1, 4096).unwrap();
+        assert!(paths.len() > 1);
+
+        let mut previous_max: Option<Vec<u8>> = None;
+        let mut total = 0;
+        for path in paths {
+            let table =
+                SstableReader::open(path, config.clone(), GlobalBlockCache::new(1, 4096)).unwrap();
+            assert_eq!(table.level(), 1);
+            if let Some(max) = &previous_max {
+                assert!(table.metadata().min_key > *max);
+            }
+            previous_max = Some(table.metadata().max_key.clone());
+            total += table.metadata().record_count;
+        }
+        assert_eq!(total, 200);
+    }
+
+    #[test]
+    fn test_merge_streams_in_comparator_order_and_collects_dropped_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig {
+            key_comparator: KeyComparator::NumericSuffix,
+            inline_value_max: Some(16),
+            block_size: 256,
+            ..Default::default()
+        };
+        let build = |timestamp: u128, records: Vec<LogRecord>| {
+            let path = dir.path().join(format!("{timestamp}.sst"));
+            let mut builder = SstableBuilder::new(path.clone(), config.clone(), timestamp).unwrap();
+            for record in &records {
+                builder.add(record.key.as_bytes(), record).unwrap();
+            }
+            builder.finish().unwrap();
+            Arc::new(
+                SstableReader::open(path, config.clone(), GlobalBlockCache::new(1, 4096)).unwrap(),
+            )
+        };
+
+        // key_2 < key_10 numerically; both old values and the deleted one
+        // live in blobs
+        let old = build(
+            1,
+            (1..=12)
+                .map(|i| LogRecord::new(format!("key_{i}"), vec![b'o'; 64]))
+                .collect(),
+        );
+        let new = build(
+            2,
+            vec![
+                LogRecord::new("key_2".to_string(), b"new".to_vec()),
+                LogRecord::tombstone("key_10".to_string()),
+            ],
+        );
+
+        let mut merged = merge_tables(&[new, old], true).unwrap();
+        let keys: Vec<String> = merged.by_ref().map(|entry| entry.unwrap().0).collect();
+        let expected: Vec<String> = (1..=12)
+            .filter(|i| *i != 10)
+            .map(|i| format!("key_{i}"))
+            .collect();
+        assert_eq!(keys, expected);
+        // The shadowed key_2 and key_10 values
+        assert_eq!(merged.into_dropped_blobs().len(), 2);
+    }
+}
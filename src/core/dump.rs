@@ -0,0 +1,45 @@
+//! Line format of exports: one JSON object per line, values base64-encoded so
+//! any bytes survive. Independent of the on-disk layout, so it moves data
+//! between stores of any version or configuration.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+
+use crate::infra::error::{LsmError, Result};
+
+/// Records committed together by `LsmEngine::import_ndjson`
+pub(crate) const IMPORT_BATCH: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpRecord {
+    pub key: String,
+    pub value_b64: String,
+}
+
+impl DumpRecord {
+    pub fn new(key: String, value: &[u8]) -> Self {
+        Self {
+            key,
+            value_b64: STANDARD.encode(value),
+        }
+    }
+
+    /// The line for this record, newline included
+    pub fn to_line(&self) -> Result<String> {
+        let mut line = serde_json::to_string(self)
+            .map_err(|e| LsmError::SerializationFailed(e.to_string()))?;
+        line.push('\n');
+        Ok(line)
+    }
+
+    /// Parse one line back into a key and its value, or say what is wrong
+    /// with it
+    pub fn parse(line: &[u8]) -> std::result::Result<(String, Vec<u8>), String> {
+        let record: Self = serde_json::from_slice(line).map_err(|e| e.to_string())?;
+        let value = STANDARD
+            .decode(&record.value_b64)
+            .map_err(|e| format!("value_b64: {e}"))?;
+        Ok((record.key, value))
+    }
+}
@@ -1,19 +1,74 @@
+use crate::core::batch::WriteBatch;
+use crate::core::compaction::{
+    merge_tables, overlaps, sort_for_reads, write_merged_tables, write_tables,
+};
+use crate::core::dump::{DumpRecord, IMPORT_BATCH};
+use crate::core::iterator::{Direction, KeyValueIterator, RecordSource};
 use crate::core::log_record::LogRecord;
-use crate::core::memtable::MemTable;
-use crate::infra::config::LsmConfig;
+use crate::core::memtable::{MemTable, MemTables};
+use crate::core::snapshot::Snapshot;
+use crate::core::subscription::{ChangeKind, OverflowPolicy, SubscriberRegistry, Subscription};
+use crate::infra::codec;
+use crate::infra::config::{CompactionStrategy, CoreConfig, KeyComparator, LsmConfig};
 use crate::infra::error::{LsmError, Result};
-use crate::storage::builder::SstableBuilder;
-use crate::storage::cache::GlobalBlockCache;
+use crate::storage::blob::{blob_dir_of, blob_ids, blob_path, BlobRef, ObsoleteBlobs, BLOB_DIR};
+use crate::storage::builder::{namespace_of, SstableBuilder, SST_TMP_SUFFIX};
+use crate::storage::cache::{GlobalBlockCache, NegativeCache};
+use crate::storage::manifest::{Manifest, ManifestEdit, MANIFEST_FILE};
 use crate::storage::reader::SstableReader;
-use crate::storage::wal::WriteAheadLog;
+use crate::storage::wal::{WalStats, WriteAheadLog};
 
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex, MutexGuard};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufRead, Write};
+use std::num::NonZeroUsize;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use serde::de::DeserializeOwned;
 use serde::Serialize;
-use tracing::{info, warn};
+use tracing::{debug, error, info, warn};
+
+/// Subdirectory of the data dir that unreadable SSTables are moved into
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// Prefix of the secondary index entries written by
+/// [`LsmEngine::set_indexed`]: `idx:<field>:<value>:<primary_key>`
+pub const INDEX_PREFIX: &str = "idx:";
+
+/// Prefix of the per-key list of index entries, `idxref:<primary_key>`, read
+/// back to remove stale entries when the key is rewritten or deleted
+pub const INDEX_REFS_PREFIX: &str = "idxref:";
+
+/// Hook used to accept or reject a key/value pair before it is written.
+///
+/// The engine is shared across threads (the HTTP server keeps it behind an
+/// `Arc`), so the closure must be `Send + Sync` and may be invoked from several
+/// threads at once. Returning `Err(msg)` makes `set` fail with
+/// [`LsmError::ValidationRejected`] without touching the WAL or the MemTable.
+pub type Validator = Box<dyn Fn(&str, &[u8]) -> std::result::Result<(), String> + Send + Sync>;
+
+/// Result of [`LsmEngine::get_status`]: like `get`, but tells an explicit
+/// delete apart from a key that was never written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyStatus {
+    Present(Vec<u8>),
+    /// The newest version of the key is a tombstone
+    Deleted,
+    Absent,
+}
+
+/// Result of [`LsmEngine::get_record`]: the newest version of a key with its
+/// write metadata. A tombstone has `is_deleted` set and an empty value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordView {
+    pub value: Vec<u8>,
+    /// Nanos since the epoch when the version was written
+    pub timestamp: u128,
+    pub is_deleted: bool,
+}
 
 #[derive(Serialize)]
 pub struct LsmStats {
@@ -25,19 +80,135 @@ pub struct LsmStats {
     pub wal_kb: u64,
     pub total_records: u64,
     pub memtable_max_size: usize,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub cache_evictions: u64,
+    pub cache_hit_ratio: f64,
+    /// `get` calls answered by the negative cache
+    pub negative_cache_hits: u64,
+    /// How long replaying the WAL took when the engine was opened
+    pub last_recovery_ms: u64,
+    /// Records the WAL replay read back when the engine was opened
+    pub recovered_records: u64,
+    /// Every live SSTable, in the order reads consult them
+    pub per_sstable: Vec<SstableStat>,
+    /// Tables bucketed by order of magnitude of their size, smallest first
+    pub size_tiers: Vec<SizeTier>,
+}
+
+/// Size and contents of one SSTable, as reported by [`LsmEngine::stats_all`]
+#[derive(Debug, Clone, Serialize)]
+pub struct SstableStat {
+    pub timestamp: u128,
+    pub level: u32,
+    pub record_count: u64,
+    pub byte_size: u64,
+    pub min_key: String,
+    pub max_key: String,
+}
+
+/// SSTables of `min_bytes` up to ten times that size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SizeTier {
+    /// A power of ten
+    pub min_bytes: u64,
+    pub tables: usize,
+    pub total_bytes: u64,
+}
+
+/// Group table sizes into [`SizeTier`]s, skipping empty tiers
+fn size_tiers(sizes: impl IntoIterator<Item = u64>) -> Vec<SizeTier> {
+    let mut tiers: BTreeMap<u64, SizeTier> = BTreeMap::new();
+    for size in sizes {
+        let min_bytes = 10u64.pow(size.max(1).ilog10());
+        let tier = tiers.entry(min_bytes).or_insert(SizeTier {
+            min_bytes,
+            tables: 0,
+            total_bytes: 0,
+        });
+        tier.tables += 1;
+        tier.total_bytes += size;
+    }
+    tiers.into_values().collect()
+}
+
+/// Summary of one live SSTable, in read order (see [`LsmEngine::sstable_info`])
+#[derive(Debug, Clone, Serialize)]
+pub struct SstableInfo {
+    pub path: PathBuf,
+    pub level: u32,
+    pub min_key: String,
+    pub max_key: String,
+    pub record_count: u64,
+}
+
+/// Outcome of [`LsmEngine::compact_now`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CompactionSummary {
+    pub tables_before: usize,
+    pub tables_after: usize,
+    /// Bytes of SSTable files freed (0 if the output is not smaller)
+    pub bytes_reclaimed: u64,
 }
 
 pub struct LsmEngine {
-    pub(crate) memtable: Mutex<MemTable>,
+    /// Active MemTable plus the frozen ones still being flushed. Reads and
+    /// inserts share the lock; only a freeze or an installed flush takes it
+    /// exclusively.
+    pub(crate) memtables: RwLock<MemTables>,
+    /// Held shared by every write from sequence number to MemTable insert and
+    /// exclusively by a freeze, so a write never straddles one: its WAL record
+    /// and MemTable entry always end up on the same side.
+    write_gate: RwLock<()>,
+    /// Serializes flushes so SSTables are installed in freeze order. Manual
+    /// compactions hold it too, so no table flushed mid-merge ends up read
+    /// after their level-0 output.
+    flush_lock: Mutex<()>,
+    /// Serializes compactions. Each one merges and writes its inputs without
+    /// blocking reads or flushes, then swaps in the output under the
+    /// `sstables` write lock.
+    compaction_lock: Mutex<()>,
+    /// Serializes indexed writes, so reading a key's old index entries and
+    /// replacing them happen as one step
+    index_lock: Mutex<()>,
     pub(crate) wal: WriteAheadLog,
-    pub(crate) sstables: Mutex<Vec<SstableReader>>,
+    /// Durable record of which SSTables are live; appended to before a table
+    /// is installed or retired
+    manifest: Manifest,
+    /// Live tables in read order. Point reads hold the read lock for the
+    /// lookup; iterators clone the `Arc`s and release it straight away. Flush
+    /// and compaction swap entries under the write lock.
+    pub(crate) sstables: RwLock<Vec<Arc<SstableReader>>>,
     pub(crate) block_cache: Arc<GlobalBlockCache>,
+    /// Set when `negative_cache_size` is non-zero
+    negative_cache: Option<NegativeCache>,
     pub(crate) dir_path: PathBuf,
     pub(crate) config: LsmConfig,
+    pub(crate) validator: Option<Validator>,
+    pub(crate) subscribers: Arc<SubscriberRegistry>,
+    /// Sequence number handed to the next write
+    pub(crate) next_seq: AtomicU64,
+    /// Time `open` spent replaying the WAL into the MemTable
+    last_recovery_ms: u64,
+    /// Records that replay read back
+    recovered_records: u64,
 }
 
 impl LsmEngine {
     pub fn new(config: LsmConfig) -> Result<Self> {
+        Self::open(config, None)
+    }
+
+    /// Create an engine that runs `validator` on every key/value before `set`
+    /// writes it.
+    ///
+    /// Closures can't be serialized, so the validator is supplied here rather
+    /// than through `LsmConfig`.
+    pub fn with_validator(config: LsmConfig, validator: Validator) -> Result<Self> {
+        Self::open(config, Some(validator))
+    }
+
+    fn open(config: LsmConfig, validator: Option<Validator>) -> Result<Self> {
         std::fs::create_dir_all(&config.core.dir_path)?;
 
         // Create global shared block cache
@@ -46,133 +217,730 @@ impl LsmEngine {
             config.storage.block_size,
         );
 
-        let wal = WriteAheadLog::new(&config.core.dir_path)?;
-        let wal_records = wal.recover()?;
+        let mut wal =
+            WriteAheadLog::open(&config.core.dir_path, config.core.wal_segment_size as u64)?
+                .with_sync_policy(config.core.wal_sync_policy)?
+                .with_codec(config.storage.codec)?
+                .with_directory_sync(config.storage.sync_directory)?;
+        if config.core.wal_group_commit_us > 0 {
+            wal = wal.with_group_commit(Duration::from_micros(config.core.wal_group_commit_us))?;
+        }
+        let recovery_started = Instant::now();
+        let wal_records = wal.recover_with_progress(|progress| {
+            debug!(
+                "WAL replay: segment {}/{}, {} records, {} bytes",
+                progress.segments_done, progress.segments_total, progress.records, progress.bytes
+            )
+        })?;
+
+        // The manifest says which tables are live; a store from before it
+        // existed takes every table in the directory
+        let dir = &config.core.dir_path;
+        let listed = Manifest::load(dir)?;
+        let names = match &listed {
+            Some(names) => names.clone(),
+            None => sst_file_names(dir)?,
+        };
 
         let mut sstables = Vec::new();
-        for entry in std::fs::read_dir(&config.core.dir_path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.extension().is_some_and(|ext| ext == "sst") {
-                match SstableReader::open(
-                    path.clone(),
-                    config.storage.clone(),
-                    Arc::clone(&block_cache),
-                ) {
-                    Ok(sst) => sstables.push(sst),
-                    Err(e) => warn!("Failed to load SSTable {}: {}", path.display(), e),
+        let mut quarantined = false;
+        for name in &names {
+            let path = dir.join(name);
+            if listed.is_some() && !path.exists() {
+                return Err(LsmError::CorruptedData(format!(
+                    "{} lists {}, which is missing",
+                    MANIFEST_FILE, name
+                )));
+            }
+            match SstableReader::open(
+                path.clone(),
+                config.storage.clone(),
+                Arc::clone(&block_cache),
+            ) {
+                Ok(sst) if sst.comparator() != config.storage.key_comparator => {
+                    return Err(LsmError::ConfigValidation(format!(
+                        "{} is ordered by {:?}, but key_comparator is {:?}",
+                        name,
+                        sst.comparator(),
+                        config.storage.key_comparator
+                    )));
+                }
+                Ok(sst) => sstables.push(Arc::new(sst)),
+                Err(e) if is_corruption(&e) => {
+                    quarantine(dir, &path, &e);
+                    quarantined = true;
                 }
+                Err(e) => return Err(e),
             }
         }
 
-        // Sort by timestamp descending (newest first)
-        sstables.sort_by(|a, b| b.metadata().timestamp.cmp(&a.metadata().timestamp));
+        let live: Vec<String> = sstables.iter().map(|sst| table_name(sst)).collect();
+        let manifest = Manifest::create(dir, &live, config.storage.sync_directory)?;
+        remove_orphans(dir, &names)?;
+        remove_orphaned_blobs(dir, &sstables, quarantined)?;
+
+        sort_for_reads(&mut sstables);
+        preload(&sstables, config.storage.preload_blocks, &block_cache);
+
+        let max_seq = sstables
+            .iter()
+            .map(|sst| sst.metadata().max_seq)
+            .chain(wal_records.iter().map(|record| record.seq))
+            .max()
+            .unwrap_or(0);
 
-        let mut memtable = MemTable::new(config.core.memtable_max_size);
+        let memtables = MemTables::new(
+            MemTable::with_impl(config.core.memtable_max_size, config.storage.memtable_impl)
+                .with_comparator(config.storage.key_comparator),
+        );
+        let recovered_records = wal_records.len() as u64;
         for record in wal_records {
-            memtable.insert(record);
+            memtables.active.insert(record);
         }
+        let last_recovery_ms = recovery_started.elapsed().as_millis() as u64;
 
         info!(
-            "LSM Engine initialized: {} sstables, memtable={} records, cache={}MB",
+            "LSM Engine initialized: {} sstables, memtable={} records, cache={}MB, \
+             replayed {} WAL records in {}ms",
             sstables.len(),
-            memtable.data.len(),
-            config.storage.block_cache_size_mb
+            memtables.len(),
+            config.storage.block_cache_size_mb,
+            recovered_records,
+            last_recovery_ms
         );
 
         Ok(Self {
-            memtable: Mutex::new(memtable),
+            memtables: RwLock::new(memtables),
+            write_gate: RwLock::new(()),
+            flush_lock: Mutex::new(()),
+            compaction_lock: Mutex::new(()),
+            index_lock: Mutex::new(()),
             wal,
-            sstables: Mutex::new(sstables),
+            manifest,
+            sstables: RwLock::new(sstables),
             block_cache,
+            negative_cache: NonZeroUsize::new(config.storage.negative_cache_size)
+                .map(NegativeCache::new),
             dir_path: config.core.dir_path.clone(),
             config,
+            validator,
+            subscribers: SubscriberRegistry::new(),
+            next_seq: AtomicU64::new(max_seq + 1),
+            last_recovery_ms,
+            recovered_records,
         })
     }
 
-    fn memtable_lock(&self) -> Result<MutexGuard<'_, MemTable>> {
-        self.memtable
-            .lock()
+    fn memtables_read(&self) -> Result<RwLockReadGuard<'_, MemTables>> {
+        self.memtables
+            .read()
+            .map_err(|_| LsmError::LockPoisoned("memtable"))
+    }
+
+    fn memtables_write(&self) -> Result<RwLockWriteGuard<'_, MemTables>> {
+        self.memtables
+            .write()
             .map_err(|_| LsmError::LockPoisoned("memtable"))
     }
 
-    fn sstables_lock(&self) -> Result<MutexGuard<'_, Vec<SstableReader>>> {
+    fn sstables_read(&self) -> Result<RwLockReadGuard<'_, Vec<Arc<SstableReader>>>> {
         self.sstables
-            .lock()
+            .read()
             .map_err(|_| LsmError::LockPoisoned("sstables"))
     }
 
+    /// Also empties the negative cache: a table added under the lock (e.g. by
+    /// `ingest`) can hold keys that were absent
+    fn sstables_write(&self) -> Result<RwLockWriteGuard<'_, Vec<Arc<SstableReader>>>> {
+        let sstables = self
+            .sstables
+            .write()
+            .map_err(|_| LsmError::LockPoisoned("sstables"))?;
+        if let Some(negative) = &self.negative_cache {
+            negative.clear();
+        }
+        Ok(sstables)
+    }
+
+    /// Size limits first, then the validator: everything a set has to pass
+    /// before it is written
+    fn check_set(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.config
+            .storage
+            .check_entry_size(key.as_bytes(), value)?;
+        if let Some(validator) = &self.validator {
+            validator(key, value).map_err(LsmError::ValidationRejected)?;
+        }
+        Ok(())
+    }
+
     pub fn set(&self, key: String, value: Vec<u8>) -> Result<()> {
+        self.check_set(&key, &value)?;
+
         let record = LogRecord::new(key, value);
+        self.apply(record, ChangeKind::Set)
+    }
+
+    /// Encode `value` with bincode and `set` it.
+    ///
+    /// Always uses the fixed-width encoding of [`codec::encode`], whatever
+    /// `record_codec` is, so values read back the same after a config change.
+    pub fn set_typed<T: Serialize>(&self, key: String, value: &T) -> Result<()> {
+        let bytes =
+            codec::encode(value).map_err(|e| LsmError::SerializationFailed(e.to_string()))?;
+        self.set(key, bytes)
+    }
+
+    /// Like `set`, but the value reads as absent once `ttl` has passed.
+    ///
+    /// Expired records are not rewritten on read; compaction drops them the
+    /// same way it drops tombstones.
+    pub fn set_with_ttl(&self, key: String, value: Vec<u8>, ttl: Duration) -> Result<()> {
+        self.check_set(&key, &value)?;
+
+        let record = LogRecord::with_ttl(key, value, ttl);
+        self.apply(record, ChangeKind::Set)
+    }
+
+    /// Write path shared by `set` and `delete`.
+    ///
+    /// The WAL append and the MemTable insert are separate critical sections:
+    /// readers only wait for the (short) insert, never for the fsync. Two
+    /// writers can therefore reach the MemTable in a different order than they
+    /// reached the WAL; `MemTable::insert` keeps the higher sequence number,
+    /// which is also what WAL replay converges to.
+    ///
+    /// All of it happens under the shared write gate, so a freeze sees every
+    /// write either fully before it (lower sequence number, sealed WAL segment,
+    /// frozen MemTable) or fully after.
+    fn apply(&self, record: LogRecord, kind: ChangeKind) -> Result<()> {
+        let key = record.key.clone();
+        let (seq, should_flush) = {
+            let _gate = self
+                .write_gate
+                .read()
+                .map_err(|_| LsmError::LockPoisoned("write_gate"))?;
+            self.write_through(record)?
+        };
+
+        self.finish_write(&key, kind, seq, should_flush)
+    }
+
+    /// Sequence, log and insert `record`; the caller holds the write gate.
+    /// Returns the record's sequence number and whether a flush is due.
+    fn write_through(&self, mut record: LogRecord) -> Result<(u64, bool)> {
+        record.seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        let seq = record.seq;
         self.wal.write_record(&record)?;
 
-        let mut memtable = self.memtable_lock()?;
-        memtable.insert(record);
+        // Shared lock: concurrent inserts are up to the MemTable
+        let memtables = self.memtables_read()?;
+        let key = record.key.clone();
+        memtables.active.insert(record);
+        self.forget_absent(&key);
+        Ok((seq, self.flush_due(&memtables.active)))
+    }
+
+    /// Drop `key` from the negative cache once a write to it is visible
+    fn forget_absent(&self, key: &str) {
+        if let Some(negative) = &self.negative_cache {
+            negative.invalidate(key);
+        }
+    }
+
+    /// Whether `active` should be flushed: it is full, or the WAL behind it
+    /// passed `wal_max_size`
+    fn flush_due(&self, active: &MemTable) -> bool {
+        active.should_flush() || self.wal.unsealed_bytes() >= self.config.core.wal_max_size as u64
+    }
+
+    /// Notify subscribers and flush if due, once the write gate is released
+    fn finish_write(
+        &self,
+        key: &str,
+        kind: ChangeKind,
+        seq: u64,
+        should_flush: bool,
+    ) -> Result<()> {
+        self.subscribers.notify(key, kind, seq);
 
-        if memtable.should_flush() {
-            drop(memtable);
+        if should_flush {
             self.flush()?;
+            self.maybe_compact()?;
         }
 
         Ok(())
     }
 
+    /// Write `new` to `key` only if its current value equals `expected`
+    /// (`None` = absent or deleted). Returns whether the write happened.
+    ///
+    /// Holds the write gate exclusively from the read to the write, so no
+    /// other write can land in between; other writers wait for the duration
+    /// of one lookup and one WAL append.
+    pub fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        new: Vec<u8>,
+    ) -> Result<bool> {
+        self.check_set(key, &new)?;
+
+        let (seq, should_flush) = {
+            let _gate = self
+                .write_gate
+                .write()
+                .map_err(|_| LsmError::LockPoisoned("write_gate"))?;
+            if self.get(key)?.as_deref() != expected {
+                return Ok(false);
+            }
+            self.write_through(LogRecord::new(key.to_string(), new))?
+        };
+
+        self.finish_write(key, ChangeKind::Set, seq, should_flush)?;
+        Ok(true)
+    }
+
+    /// Add `delta` to the counter at `key` and return the new value.
+    ///
+    /// Counters are stored as little-endian `i64`s; a missing key counts as 0.
+    /// Like [`compare_and_swap`](Self::compare_and_swap), the read and the
+    /// write happen under the exclusive write gate, so concurrent increments
+    /// never lose an update.
+    pub fn increment(&self, key: &str, delta: i64) -> Result<i64> {
+        let (value, seq, should_flush) = {
+            let _gate = self
+                .write_gate
+                .write()
+                .map_err(|_| LsmError::LockPoisoned("write_gate"))?;
+            let current = match self.get(key)? {
+                None => 0,
+                Some(bytes) => {
+                    let bytes: [u8; 8] = bytes.as_slice().try_into().map_err(|_| {
+                        LsmError::CorruptedData(format!(
+                            "'{key}' holds {} bytes, not an i64 counter",
+                            bytes.len()
+                        ))
+                    })?;
+                    i64::from_le_bytes(bytes)
+                }
+            };
+            let value = current.checked_add(delta).ok_or_else(|| {
+                LsmError::Overflow(format!("'{key}' = {current}, adding {delta}"))
+            })?;
+
+            let bytes = value.to_le_bytes().to_vec();
+            self.check_set(key, &bytes)?;
+            let (seq, should_flush) = self.write_through(LogRecord::new(key.to_string(), bytes))?;
+            (value, seq, should_flush)
+        };
+
+        self.finish_write(key, ChangeKind::Set, seq, should_flush)?;
+        Ok(value)
+    }
+
     pub fn delete(&self, key: String) -> Result<()> {
+        self.config.storage.check_entry_size(key.as_bytes(), &[])?;
         let record = LogRecord::tombstone(key);
-        self.wal.write_record(&record)?;
+        self.apply(record, ChangeKind::Delete)
+    }
+
+    /// `set` the key and index it under each `field:value` in `index_values`,
+    /// so [`find_by_index`](Self::find_by_index) can look it up by value.
+    ///
+    /// Index entries the key had from an earlier `set_indexed` that are not
+    /// in `index_values` are removed. The record, its index entries and the
+    /// list of them are written as one batch. Neither the field nor the value
+    /// may contain `:`, which separates them in the entry key.
+    pub fn set_indexed(&self, key: String, value: Vec<u8>, index_values: &[String]) -> Result<()> {
+        let mut entries = BTreeSet::new();
+        for index_value in index_values {
+            let (field, value) = index_value.split_once(':').ok_or_else(|| {
+                LsmError::ValidationRejected(format!(
+                    "index value '{}' is not of the form field:value",
+                    index_value
+                ))
+            })?;
+            entries.insert(index_entry_prefix(field, value)? + &key);
+        }
 
-        let mut memtable = self.memtable_lock()?;
-        memtable.insert(record);
+        let _guard = self
+            .index_lock
+            .lock()
+            .map_err(|_| LsmError::LockPoisoned("index_lock"))?;
+        let refs_key = format!("{}{}", INDEX_REFS_PREFIX, key);
+        let mut batch = WriteBatch::new();
+        for stale in self.index_refs(&refs_key)? {
+            if !entries.contains(&stale) {
+                batch.delete(stale);
+            }
+        }
+        for entry in &entries {
+            batch.set(entry.clone(), Vec::new());
+        }
+        if entries.is_empty() {
+            batch.delete(refs_key);
+        } else {
+            let refs: Vec<&String> = entries.iter().collect();
+            batch.set(refs_key, codec::encode(&refs)?);
+        }
+        batch.set(key, value);
+        self.commit(batch)
+    }
 
-        if memtable.should_flush() {
-            drop(memtable);
-            self.flush()?;
+    /// `delete` the key together with the index entries `set_indexed` wrote
+    /// for it.
+    pub fn delete_indexed(&self, key: String) -> Result<()> {
+        let _guard = self
+            .index_lock
+            .lock()
+            .map_err(|_| LsmError::LockPoisoned("index_lock"))?;
+        let refs_key = format!("{}{}", INDEX_REFS_PREFIX, key);
+        let mut batch = WriteBatch::new();
+        for stale in self.index_refs(&refs_key)? {
+            batch.delete(stale);
+        }
+        batch.delete(refs_key);
+        batch.delete(key);
+        self.commit(batch)
+    }
+
+    /// Primary keys indexed under `field` = `value`, in ascending order.
+    pub fn find_by_index(&self, field: &str, value: &str) -> Result<Vec<String>> {
+        let prefix = index_entry_prefix(field, value)?;
+        Ok(self
+            .prefix_scan(&prefix, None)?
+            .into_iter()
+            .map(|(entry, _)| entry[prefix.len()..].to_string())
+            .collect())
+    }
+
+    /// Index entry keys recorded under `refs_key`, empty if there are none.
+    fn index_refs(&self, refs_key: &str) -> Result<Vec<String>> {
+        match self.get(refs_key)? {
+            Some(bytes) => codec::decode(&bytes),
+            None => Ok(Vec::new()),
         }
+    }
 
+    /// Flush the MemTable to an SSTable (which also drops the WAL segments it
+    /// covered), so the next open starts without anything to replay, then
+    /// fsync the WAL whatever the sync policy.
+    ///
+    /// The sync covers writes that raced the flush into the fresh segment, and
+    /// everything still in the WAL if the flush itself failed. Meant for
+    /// shutdown; the engine stays usable afterwards.
+    pub fn close(&self) -> Result<()> {
+        let flushed = self.flush();
+        self.wal.sync()?;
+        flushed?;
+        info!("LSM Engine closed cleanly");
         Ok(())
     }
 
+    /// Receive a [`ChangeEvent`](crate::core::subscription::ChangeEvent) for
+    /// every `set`/`delete` whose key starts with `prefix`.
+    ///
+    /// Events are buffered in a channel of `capacity` entries; `policy` decides
+    /// what happens when the subscriber falls behind. Dropping the returned
+    /// [`Subscription`] unregisters it.
+    pub fn subscribe(&self, prefix: &str, capacity: usize, policy: OverflowPolicy) -> Subscription {
+        self.subscribers.subscribe(prefix, capacity, policy)
+    }
+
     pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
-        let memtable = self.memtable_lock()?;
-        if let Some(record) = memtable.get(key) {
-            return Ok(if record.is_deleted {
-                None
-            } else {
-                Some(record.value)
-            });
+        let Some(negative) = &self.negative_cache else {
+            return self.get_live(key);
+        };
+        if negative.contains(key) {
+            return Ok(None);
+        }
+
+        let generation = negative.generation();
+        let value = self.get_live(key)?;
+        if value.is_none() {
+            negative.insert(key, generation);
+        }
+        Ok(value)
+    }
+
+    fn get_live(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .get_record(key)?
+            .filter(|record| !record.is_deleted)
+            .map(|record| record.value))
+    }
+
+    /// `get` a value written by [`set_typed`](Self::set_typed) and decode it.
+    ///
+    /// Bytes that don't decode as `T` are a
+    /// [`DeserializationFailed`](LsmError::DeserializationFailed) error.
+    pub fn get_typed<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.get(key)?
+            .map(|bytes| {
+                codec::decode(&bytes).map_err(|e| LsmError::DeserializationFailed(e.to_string()))
+            })
+            .transpose()
+    }
+
+    /// Whether `key` holds a live value: `false` for a key that was never
+    /// written, deleted or expired.
+    ///
+    /// The MemTables answer without copying the value. An SSTable whose key
+    /// range or Bloom filter excludes the key is skipped without reading a
+    /// block; only the table that may hold it has its block read.
+    pub fn exists(&self, key: &str) -> Result<bool> {
+        if let Some(live) = self.memtables_read()?.is_live(key) {
+            return Ok(live);
+        }
+
+        let sstables = self.sstables_read()?;
+        for sst in sstables.iter() {
+            if let Some(record) = sst.get_raw(key)? {
+                return Ok(record.is_live());
+            }
+        }
+        Ok(false)
+    }
+
+    /// Look up the newest version of `key` with its timestamp, including a
+    /// tombstone, so callers can tell "deleted" from "never written". An
+    /// expired value is `None`, as with `get`.
+    pub fn get_record(&self, key: &str) -> Result<Option<RecordView>> {
+        Ok(self
+            .newest_record(key)?
+            .filter(|record| !record.is_expired())
+            .map(|record| RecordView {
+                value: record.value,
+                timestamp: record.timestamp,
+                is_deleted: record.is_deleted,
+            }))
+    }
+
+    /// Look up every key in `keys`, returning values in the same order.
+    ///
+    /// Each lock is taken once for the whole call. Keys the MemTables don't
+    /// answer are looked up table by table in key order, so keys sharing a
+    /// block cost a single read of it; a key stops at the newest table that
+    /// holds it, as with `get`.
+    pub fn multi_get(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut found: Vec<Option<LogRecord>> = vec![None; keys.len()];
+
+        let memtables = self.memtables_read()?;
+        for (slot, key) in found.iter_mut().zip(keys) {
+            *slot = memtables.get(key);
+        }
+        drop(memtables);
+
+        // Still unresolved, as indices into `keys` in key order
+        let mut pending: Vec<usize> = (0..keys.len()).filter(|&i| found[i].is_none()).collect();
+        let cmp = self.config.storage.key_comparator;
+        pending.sort_by(|&a, &b| cmp.compare(keys[a].as_bytes(), keys[b].as_bytes()));
+
+        let sstables = self.sstables_read()?;
+        for sst in sstables.iter() {
+            if pending.is_empty() {
+                break;
+            }
+            let lookups: Vec<&str> = pending.iter().map(|&i| keys[i].as_str()).collect();
+            let records = sst.multi_get(&lookups)?;
+            pending = pending
+                .into_iter()
+                .zip(records)
+                .filter_map(|(i, record)| match record {
+                    Some(record) => {
+                        found[i] = Some(record);
+                        None
+                    }
+                    None => Some(i),
+                })
+                .collect();
+        }
+        drop(sstables);
+
+        Ok(found
+            .into_iter()
+            .map(|record| record.filter(LogRecord::is_live).map(|record| record.value))
+            .collect())
+    }
+
+    /// Look up `key`, reporting a tombstone as [`KeyStatus::Deleted`] instead
+    /// of folding it into "not found" like `get` does. An expired value is
+    /// [`KeyStatus::Absent`].
+    pub fn get_status(&self, key: &str) -> Result<KeyStatus> {
+        Ok(match self.newest_record(key)? {
+            Some(record) => Self::status_of(record),
+            None => KeyStatus::Absent,
+        })
+    }
+
+    /// Newest version of `key`: the MemTables first, then the SSTables
+    /// newest to oldest
+    fn newest_record(&self, key: &str) -> Result<Option<LogRecord>> {
+        let memtables = self.memtables_read()?;
+        if let Some(record) = memtables.get(key) {
+            return Ok(Some(record));
         }
-        drop(memtable);
+        drop(memtables);
 
-        // 2. Check SSTables (newest to oldest)
-        let mut sstables = self.sstables_lock()?;
-        for sst in sstables.iter_mut() {
-            if let Some(record) = sst.get(key)? {
-                return Ok(if record.is_deleted {
-                    None
-                } else {
-                    Some(record.value)
-                });
+        let sstables = self.sstables_read()?;
+        for sst in sstables.iter() {
+            if let Some(record) = sst.get_raw(key)? {
+                // Callers never see an expired value, so don't fetch its blob
+                if record.is_expired() {
+                    return Ok(Some(record));
+                }
+                return sst.resolve(record).map(Some);
             }
         }
 
         Ok(None)
     }
 
+    fn status_of(record: LogRecord) -> KeyStatus {
+        if record.is_deleted {
+            KeyStatus::Deleted
+        } else if record.is_expired() {
+            KeyStatus::Absent
+        } else {
+            KeyStatus::Present(record.value)
+        }
+    }
+
+    /// Capture a consistent, read-only view of the store as of now.
+    ///
+    /// Briefly holds the write gate exclusively while the active MemTable is
+    /// copied, so the snapshot contains exactly the writes up to
+    /// [`Snapshot::seq`]. See [`Snapshot`] for how long its files are kept.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let (memtables, seq) = {
+            let _gate = self
+                .write_gate
+                .write()
+                .map_err(|_| LsmError::LockPoisoned("write_gate"))?;
+            (self.memtables_read()?.snapshot(), self.last_seq())
+        };
+
+        // A flush finishing in between leaves its records in both a frozen
+        // MemTable and the new SSTable, which reads the same
+        let sstables = self.sstables_read()?.clone();
+
+        Ok(Snapshot {
+            memtables,
+            sstables,
+            seq,
+            comparator: self.config.storage.key_comparator,
+        })
+    }
+
+    /// Sequence number of the most recent write (0 if nothing was written yet)
+    pub fn last_seq(&self) -> u64 {
+        self.next_seq.load(Ordering::SeqCst) - 1
+    }
+
+    /// Whether `key` held a live value as of sequence number `seq`.
+    ///
+    /// Looks for the newest version with a sequence number `<= seq`, which may
+    /// live in an older SSTable than the key's current version. Only retained
+    /// versions can be found: the MemTable keeps every version it has seen,
+    /// but a flush writes just the latest one per key and compaction drops
+    /// superseded versions, so history older than that reads as absent.
+    pub fn exists_at_seq(&self, key: &str, seq: u64) -> Result<bool> {
+        let memtables = self.memtables_read()?;
+        if let Some(live) = memtables.visible_at(key, seq) {
+            return Ok(live);
+        }
+        drop(memtables);
+
+        let sstables = self.sstables_read()?;
+        for sst in sstables.iter() {
+            if let Some(record) = sst.get_raw(key)? {
+                if record.seq <= seq {
+                    return Ok(!record.is_deleted);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Apply every write in `batch` atomically: one WAL append and fsync,
+    /// and one MemTable lock for all the inserts, so readers and recovery see
+    /// either the whole batch or none of it. Nothing is written if the
+    /// validator rejects any value.
+    pub fn commit(&self, batch: WriteBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        for record in &batch.records {
+            if record.is_deleted {
+                self.config
+                    .storage
+                    .check_entry_size(record.key.as_bytes(), &[])?;
+            } else {
+                self.check_set(&record.key, &record.value)?;
+            }
+        }
+
+        let mut records = batch.records;
+        let should_flush = {
+            let _gate = self
+                .write_gate
+                .read()
+                .map_err(|_| LsmError::LockPoisoned("write_gate"))?;
+            let first_seq = self
+                .next_seq
+                .fetch_add(records.len() as u64, Ordering::SeqCst);
+            for (seq, record) in (first_seq..).zip(records.iter_mut()) {
+                record.seq = seq;
+            }
+            self.wal.write_batch(&records)?;
+
+            let memtables = self.memtables_write()?;
+            for record in &records {
+                memtables.active.insert(record.clone());
+                self.forget_absent(&record.key);
+            }
+            self.flush_due(&memtables.active)
+        };
+
+        for record in &records {
+            let kind = if record.is_deleted {
+                ChangeKind::Delete
+            } else {
+                ChangeKind::Set
+            };
+            self.subscribers.notify(&record.key, kind, record.seq);
+        }
+
+        if should_flush {
+            self.flush()?;
+            self.maybe_compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Set every pair as one atomic [`WriteBatch`]
     pub fn set_batch(&self, items: Vec<(String, Vec<u8>)>) -> Result<usize> {
-        let mut count = 0;
+        let mut batch = WriteBatch::new();
         for (key, value) in items {
-            self.set(key, value)?;
-            count += 1;
+            batch.set(key, value);
         }
+        let count = batch.len();
+        self.commit(batch)?;
         Ok(count)
     }
 
+    /// Delete every key as one atomic [`WriteBatch`]
     pub fn delete_batch(&self, keys: Vec<String>) -> Result<usize> {
-        let mut count = 0;
+        let mut batch = WriteBatch::new();
         for key in keys {
-            self.delete(key)?;
-            count += 1;
+            batch.delete(key);
         }
+        let count = batch.len();
+        self.commit(batch)?;
         Ok(count)
     }
 
@@ -185,160 +953,1900 @@ impl LsmEngine {
     }
 
     pub fn search_prefix(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
-        let all_data = self.scan()?;
-        Ok(all_data
-            .into_iter()
-            .filter(|(key, _)| key.starts_with(prefix))
-            .collect())
+        self.prefix_scan(prefix, None)
     }
 
-    fn flush(&self) -> Result<()> {
-        let mut memtable = self.memtable_lock()?;
-        let records: Vec<(String, LogRecord)> = memtable
-            .iter_ordered()
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect();
+    /// Freeze the active MemTable and write every frozen one out as an
+    /// SSTable, oldest first. Writes continue into the fresh active MemTable
+    /// while the SSTables are built; readers see the frozen tables until each
+    /// one's SSTable is installed.
+    ///
+    /// Once it returns, everything written before the call lives in SSTables
+    /// and the WAL segments covering it are gone, e.g. ahead of a file-level
+    /// backup. A no-op when there is nothing to flush.
+    pub fn flush(&self) -> Result<()> {
+        self.freeze_active()?;
 
-        if records.is_empty() {
-            return Ok(());
+        let _flushing = self.lock_flush()?;
+        loop {
+            // Bound first: flush_frozen takes the MemTable write lock
+            let oldest = self.memtables_read()?.oldest_frozen();
+            let Some((table, next_wal_segment)) = oldest else {
+                break;
+            };
+            self.flush_frozen(&table, next_wal_segment)?;
         }
 
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
-        let filename = format!("{}.sst", timestamp);
-        let path = self.dir_path.join(filename);
+        Ok(())
+    }
 
-        // Create new SSTable using Builder (V2)
-        let mut builder = SstableBuilder::new(path, self.config.storage.clone(), timestamp)?;
-        for (key, record) in records {
-            builder.add(key.as_bytes(), &record)?;
+    /// Move the active MemTable to the frozen queue, sealing the WAL segments
+    /// that hold its records
+    fn freeze_active(&self) -> Result<()> {
+        let _gate = self
+            .write_gate
+            .write()
+            .map_err(|_| LsmError::LockPoisoned("write_gate"))?;
+        if self.memtables_read()?.active.is_empty() {
+            return Ok(());
         }
-        let sst_path = builder.finish()?;
 
-        // Open the new SSTable as Reader (V2) with shared cache
-        let reader = SstableReader::open(
-            sst_path,
-            self.config.storage.clone(),
-            Arc::clone(&self.block_cache),
+        // Everything in the active MemTable is now in sealed segments; writes
+        // after the freeze land in the new one and outlive the flush
+        let next_wal_segment = self.wal.rotate()?;
+        self.memtables_write()?.freeze(next_wal_segment);
+        Ok(())
+    }
+
+    /// Write `table` (the oldest frozen MemTable) to SSTables, install them
+    /// and drop the WAL segments before `next_wal_segment`.
+    ///
+    /// The MemTable becomes a single L0 table unless that would outgrow
+    /// `max_sstable_size`, in which case it is split into consecutive,
+    /// non-overlapping L0 tables.
+    fn flush_frozen(&self, table: &MemTable, next_wal_segment: u64) -> Result<()> {
+        let records: Vec<(String, LogRecord)> = table.iter_ordered().collect();
+
+        let paths = write_tables(
+            &self.dir_path,
+            &self.config.storage,
+            &records,
+            0,
+            self.config.storage.table_size_limit(u64::MAX),
         )?;
 
-        let mut sstables = self.sstables_lock()?;
-        sstables.insert(0, reader);
-        let cleared = memtable.clear();
+        // Keep the frozen MemTable and WAL intact if the tables don't read back correctly
+        let readers = self.open_tables(paths.clone()).and_then(|readers| {
+            if self.config.core.verify_after_flush {
+                verify_flushed_split(&readers, &records)?;
+            }
+            Ok(readers)
+        });
+        let readers = match readers {
+            Ok(readers) => readers,
+            Err(e) => {
+                for path in &paths {
+                    if let Err(rm_err) = std::fs::remove_file(path) {
+                        warn!(
+                            "Failed to remove unverified SSTable {}: {}",
+                            path.display(),
+                            rm_err
+                        );
+                    }
+                }
+                return Err(e);
+            }
+        };
+        self.record_tables(&readers, &[])?;
+
+        // Install the tables before retiring the MemTable, so a reader in
+        // between finds the records in one or both
+        let tables_written = readers.len();
+        let mut sstables = self.sstables_write()?;
+        sstables.splice(0..0, readers);
+        let sstables_total = sstables.len();
+        drop(sstables);
+        self.memtables_write()?.remove_oldest_frozen();
 
         info!(
-            "Memtable flushed: {} records, sstables total={}",
-            cleared,
-            sstables.len()
+            "Memtable flushed: {} records into {} sstables, sstables total={}",
+            records.len(),
+            tables_written,
+            sstables_total
         );
 
-        drop(memtable);
-        drop(sstables);
-
-        self.wal.clear()?;
+        self.wal.remove_segments_before(next_wal_segment)?;
 
         Ok(())
     }
 
-    pub fn scan(&self) -> Result<Vec<(String, Vec<u8>)>> {
-        let mut result_map: HashMap<String, (Vec<u8>, u128, bool)> = HashMap::new();
+    /// Collapse the whole store into exactly one SSTable.
+    ///
+    /// The MemTable is flushed first, then every SSTable is merged into a single
+    /// output regardless of size; obsolete versions, tombstones and expired
+    /// records are dropped because no older data remains to be shadowed. The
+    /// inputs are removed once the new table is installed. Handy for producing
+    /// a minimal snapshot. Reads carry on during the merge; flushes wait.
+    pub fn compact_to_single_file(&self) -> Result<PathBuf> {
+        self.flush()?;
 
-        let memtable = self.memtable_lock()?;
-        for (key, record) in memtable.iter_ordered() {
-            result_map.insert(
-                key.clone(),
-                (record.value.clone(), record.timestamp, record.is_deleted),
-            );
+        let _compacting = self.lock_compaction()?;
+        let _flushing = self.lock_flush()?;
+        let sstables = self.sstables_read()?.clone();
+        if sstables.is_empty() {
+            return Err(LsmError::CompactionFailed("store is empty".to_string()));
         }
-        drop(memtable);
 
-        let mut sstables = self.sstables_lock()?;
-        for sst in sstables.iter_mut() {
-            let records = sst.scan()?;
-            for (key_bytes, record) in records {
-                let key = String::from_utf8(key_bytes).map_err(|e| LsmError::CorruptedData(e.to_string()))?;
-                result_map.entry(key).or_insert((
-                    record.value,
-                    record.timestamp,
-                    record.is_deleted,
-                ));
-            }
-        }
-        drop(sstables);
+        // Under leveled compaction the result is a complete, non-overlapping run
+        let level = match self.config.storage.compaction_strategy {
+            CompactionStrategy::SizeTiered => 0,
+            CompactionStrategy::Leveled => 1,
+        };
+        let mut merged = merge_tables(&sstables, true)?;
+        let Some(sst_path) = write_merged_tables(
+            &self.dir_path,
+            &self.config.storage,
+            &mut merged,
+            level,
+            u64::MAX,
+        )?
+        .pop() else {
+            return Err(LsmError::CompactionFailed(
+                "no live records left to compact".to_string(),
+            ));
+        };
+        let dropped_blobs = merged.into_dropped_blobs();
 
-        let mut results: Vec<(String, Vec<u8>)> = result_map
-            .into_iter()
-            .filter_map(|(key, (value, _ts, is_deleted))| {
-                if !is_deleted {
-                    Some((key, value))
-                } else {
-                    None
-                }
-            })
-            .collect();
+        let reader = Arc::new(SstableReader::open(
+            sst_path.clone(),
+            self.config.storage.clone(),
+            Arc::clone(&self.block_cache),
+        )?);
+        self.record_tables(std::slice::from_ref(&reader), &sstables)?;
 
-        results.sort_by(|a, b| a.0.cmp(&b.0));
-        Ok(results)
-    }
+        let record_count = reader.metadata().record_count;
+        self.install_compacted(&sstables, vec![reader])?;
+        let removed = sstables.len();
+        self.remove_compacted(sstables, &dropped_blobs);
 
-    pub fn keys(&self) -> Result<Vec<String>> {
-        let all_data = self.scan()?;
-        Ok(all_data.into_iter().map(|(k, _)| k).collect())
-    }
+        info!(
+            "Compacted {} sstables into {} ({} records)",
+            removed,
+            sst_path.display(),
+            record_count
+        );
 
-    pub fn count(&self) -> Result<usize> {
-        Ok(self.scan()?.len())
+        Ok(sst_path)
     }
 
-    pub fn stats(&self) -> String {
-        let memtable = match self.memtable_lock() {
-            Ok(g) => g,
-            Err(e) => return format!("LSM Stats error: {e}"),
+    /// Merge every SSTable into a fresh set of tables and wait for it to
+    /// finish, dropping superseded versions, tombstones and expired records.
+    ///
+    /// Unlike [`compact_to_single_file`](Self::compact_to_single_file) this
+    /// follows the configured strategy (leveled compaction splits the output
+    /// into `target_file_size` L1 tables) and succeeds on an empty store.
+    /// Reads carry on during the merge; flushes wait until it is installed.
+    pub fn compact_now(&self) -> Result<CompactionSummary> {
+        self.flush()?;
+
+        let _compacting = self.lock_compaction()?;
+        let _flushing = self.lock_flush()?;
+        let sstables = self.sstables_read()?.clone();
+        let tables_before = sstables.len();
+        let bytes_before = total_file_size(&sstables);
+        if tables_before == 0 {
+            return Ok(CompactionSummary {
+                tables_before,
+                tables_after: 0,
+                bytes_reclaimed: 0,
+            });
+        }
+
+        let (level, target_size) = match self.config.storage.compaction_strategy {
+            CompactionStrategy::SizeTiered => (0, u64::MAX),
+            CompactionStrategy::Leveled => (1, self.config.storage.target_file_size as u64),
         };
-        let sstables = match self.sstables_lock() {
-            Ok(g) => g,
-            Err(e) => return format!("LSM Stats error: {e}"),
+        let target_size = self.config.storage.table_size_limit(target_size);
+        let mut merged = merge_tables(&sstables, true)?;
+        let paths = write_merged_tables(
+            &self.dir_path,
+            &self.config.storage,
+            &mut merged,
+            level,
+            target_size,
+        )?;
+        let dropped_blobs = merged.into_dropped_blobs();
+        let outputs = self.open_tables(paths)?;
+        self.record_tables(&outputs, &sstables)?;
+
+        let bytes_after = total_file_size(&outputs);
+        let summary = CompactionSummary {
+            tables_before,
+            tables_after: self.install_compacted(&sstables, outputs)?,
+            bytes_reclaimed: bytes_before.saturating_sub(bytes_after),
         };
+        self.remove_compacted(sstables, &dropped_blobs);
 
-        let cache_stats = self.block_cache.stats();
+        info!(
+            "Manual compaction: {} -> {} sstables, {} bytes reclaimed",
+            summary.tables_before, summary.tables_after, summary.bytes_reclaimed
+        );
 
-        format!(
-            "LSM Stats:\n MemTable: {} records, ~{} KB\n SSTables: {} files\n Cache: {}/{} blocks",
-            memtable.data.len(),
-            memtable.size_bytes / 1024,
-            sstables.len(),
-            cache_stats.len,
-            cache_stats.cap
-        )
+        Ok(summary)
     }
 
-    pub fn stats_all(&self) -> std::result::Result<LsmStats, String> {
-        let memtable = self.memtable_lock().map_err(|e| e.to_string())?;
-        let sstables = self.sstables_lock().map_err(|e| e.to_string())?;
-
-        let mem_records = memtable.data.len();
-        let sst_records_total: u64 = sstables
-            .iter()
-            .map(|s| s.metadata().record_count)
-            .sum();
+    /// Retire tables replaced by a compaction. Each file is deleted as soon
+    /// as no iterator or [`Snapshot`] still holds its reader, and the blobs
+    /// the compaction dropped once none of the inputs is held any more.
+    fn remove_compacted(&self, inputs: Vec<Arc<SstableReader>>, dropped_blobs: &[BlobRef]) {
+        if !dropped_blobs.is_empty() {
+            let blobs = Arc::new(ObsoleteBlobs::new(
+                &self.dir_path.join(BLOB_DIR),
+                dropped_blobs,
+            ));
+            for input in &inputs {
+                input.hold_obsolete_blobs(Arc::clone(&blobs));
+            }
+        }
+        for input in inputs {
+            input.mark_obsolete();
+        }
+    }
 
-        let sst_bytes_total: u64 = sstables
-            .iter()
-            .map(|s| std::fs::metadata(s.path()).map(|m| m.len()).unwrap_or(0))
-            .sum();
+    fn open_tables(&self, paths: Vec<PathBuf>) -> Result<Vec<Arc<SstableReader>>> {
+        paths
+            .into_iter()
+            .map(|path| {
+                SstableReader::open(
+                    path,
+                    self.config.storage.clone(),
+                    Arc::clone(&self.block_cache),
+                )
+                .map(Arc::new)
+            })
+            .collect()
+    }
 
-        let wal_bytes: u64 = std::fs::metadata(&self.wal.path)
-            .map(|m| m.len())
-            .unwrap_or(0);
+    fn lock_flush(&self) -> Result<MutexGuard<'_, ()>> {
+        self.flush_lock
+            .lock()
+            .map_err(|_| LsmError::LockPoisoned("flush"))
+    }
+
+    fn lock_compaction(&self) -> Result<MutexGuard<'_, ()>> {
+        self.compaction_lock
+            .lock()
+            .map_err(|_| LsmError::LockPoisoned("compaction"))
+    }
+
+    /// Replace a compaction's `inputs` with its `outputs`, keeping any table
+    /// flushed meanwhile, and return the new table count. The negative cache
+    /// survives: the outputs hold no key the inputs didn't.
+    fn install_compacted(
+        &self,
+        inputs: &[Arc<SstableReader>],
+        outputs: Vec<Arc<SstableReader>>,
+    ) -> Result<usize> {
+        let mut sstables = self
+            .sstables
+            .write()
+            .map_err(|_| LsmError::LockPoisoned("sstables"))?;
+        sstables.retain(|sst| !inputs.iter().any(|input| Arc::ptr_eq(sst, input)));
+        sstables.extend(outputs);
+        sort_for_reads(&mut sstables);
+        Ok(sstables.len())
+    }
+
+    /// Run a leveled compaction if the configured strategy calls for one
+    fn maybe_compact(&self) -> Result<()> {
+        if self.config.storage.compaction_strategy != CompactionStrategy::Leveled {
+            return Ok(());
+        }
+
+        let _compacting = self.lock_compaction()?;
+        // Most calls have nothing to do, which the read lock is enough to find out
+        let sstables = self.sstables_read()?.clone();
+        let level0 = sstables.iter().filter(|sst| sst.level() == 0).count();
+        if level0 < self.config.storage.level0_compaction_threshold {
+            return Ok(());
+        }
+
+        self.compact_level0(&sstables)
+    }
+
+    /// Merge every L0 table, plus the L1 tables whose key range they overlap,
+    /// into new non-overlapping L1 tables of about `target_file_size` bytes.
+    ///
+    /// All of L0 takes part, so whatever is left in L1 is older than any L0
+    /// table flushed afterwards and the read order (L0 newest first, then L1)
+    /// stays correct. `sstables` is the live set when the compaction started;
+    /// tables flushed since are newer L0 tables and are left alone.
+    fn compact_level0(&self, sstables: &[Arc<SstableReader>]) -> Result<()> {
+        let (mut inputs, rest): (Vec<Arc<SstableReader>>, Vec<Arc<SstableReader>>) =
+            sstables.iter().cloned().partition(|sst| sst.level() == 0);
+
+        let cmp = self.config.storage.key_comparator;
+        let min_key = inputs
+            .iter()
+            .map(|sst| sst.metadata().min_key.clone())
+            .min_by(|a, b| cmp.compare(a, b));
+        let max_key = inputs
+            .iter()
+            .map(|sst| sst.metadata().max_key.clone())
+            .max_by(|a, b| cmp.compare(a, b));
+        let (Some(min_key), Some(max_key)) = (min_key, max_key) else {
+            return Ok(());
+        };
+
+        let (overlapping, untouched): (Vec<Arc<SstableReader>>, Vec<Arc<SstableReader>>) = rest
+            .into_iter()
+            .partition(|sst| sst.level() == 1 && overlaps(sst, &min_key, &max_key));
+        inputs.extend(overlapping);
+
+        // Nothing below L1 can hold an older version of these keys unless
+        // deeper levels exist, so tombstones can go
+        let drop_tombstones = !untouched.iter().any(|sst| sst.level() > 1);
+
+        // On error the live set hasn't changed
+        let mut merged = merge_tables(&inputs, drop_tombstones)?;
+        let paths = write_merged_tables(
+            &self.dir_path,
+            &self.config.storage,
+            &mut merged,
+            1,
+            self.config
+                .storage
+                .table_size_limit(self.config.storage.target_file_size as u64),
+        )?;
+        let outputs = self.open_tables(paths)?;
+        self.record_tables(&outputs, &inputs)?;
+
+        let (input_count, output_count) = (inputs.len(), outputs.len());
+        let sstables_total = self.install_compacted(&inputs, outputs)?;
+        self.remove_compacted(inputs, &merged.into_dropped_blobs());
+
+        info!(
+            "Leveled compaction: {} tables into {} L1 tables, sstables total={}",
+            input_count, output_count, sstables_total
+        );
+
+        Ok(())
+    }
+
+    /// Every live key/value pair in ascending key order.
+    pub fn scan(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        self.iter()?.collect()
+    }
+
+    /// Same result as [`scan`](Self::scan), with each SSTable read and
+    /// decoded on its own rayon worker. The per-table streams are then merged
+    /// on the calling thread, newest version winning, exactly as `scan` does.
+    ///
+    /// Every table is held in memory at once, so this trades memory for
+    /// speed on large analytical scans.
+    #[cfg(feature = "parallel")]
+    pub fn scan_parallel(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        use rayon::prelude::*;
+
+        let memtables = self.memtables_read()?;
+        let memtable_records: Vec<Vec<(String, LogRecord)>> = memtables
+            .tables()
+            .map(|memtable| {
+                memtable.range(Bound::Unbounded, Bound::Unbounded, Direction::Ascending)
+            })
+            .collect();
+        drop(memtables);
+        let sstables: Vec<Arc<SstableReader>> = self.sstables_read()?.clone();
+
+        let table_records: Vec<Vec<(String, LogRecord)>> = sstables
+            .par_iter()
+            .map(|sst| {
+                sst.scan_raw()?
+                    .into_iter()
+                    .map(|(key_bytes, record)| {
+                        let key = String::from_utf8(key_bytes)
+                            .map_err(|e| LsmError::CorruptedData(e.to_string()))?;
+                        Ok((key, record))
+                    })
+                    .collect()
+            })
+            .collect::<Result<_>>()?;
+
+        // Sources in the same newest-first order as `merged_iter`
+        let sources: Vec<RecordSource<'static>> = memtable_records
+            .into_iter()
+            .chain(table_records)
+            .map(|records| Box::new(records.into_iter().map(Ok)) as RecordSource<'static>)
+            .collect();
+        KeyValueIterator::new(
+            sources,
+            Direction::Ascending,
+            self.config.storage.key_comparator,
+        )
+        .with_blob_dir(self.dir_path.join(BLOB_DIR))
+        .collect()
+    }
+
+    /// Write every live pair to `writer` as newline-delimited
+    /// [`DumpRecord`]s, streamed in key order. Returns the number written.
+    pub fn export_ndjson(&self, mut writer: impl Write) -> Result<usize> {
+        let mut count = 0;
+        for record in self.iter()? {
+            let (key, value) = record?;
+            writer.write_all(DumpRecord::new(key, &value).to_line()?.as_bytes())?;
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Load the output of [`export_ndjson`](Self::export_ndjson), committing
+    /// a [`WriteBatch`] every thousand records. Blank lines are skipped; a
+    /// malformed one stops the import with its line number, after the batches
+    /// before it were committed. Returns the number of records imported.
+    pub fn import_ndjson(&self, reader: impl BufRead) -> Result<usize> {
+        let mut batch = WriteBatch::new();
+        let mut count = 0;
+        for (i, line) in reader.split(b'\n').enumerate() {
+            let line = line?;
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            let (key, value) = DumpRecord::parse(&line)
+                .map_err(|e| LsmError::DeserializationFailed(format!("line {}: {}", i + 1, e)))?;
+            batch.set(key, value);
+            if batch.len() == IMPORT_BATCH {
+                count += batch.len();
+                self.commit(std::mem::take(&mut batch))?;
+            }
+        }
+        count += batch.len();
+        self.commit(batch)?;
+        Ok(count)
+    }
+
+    /// Write `records` straight to SSTables, bypassing the WAL and MemTable,
+    /// for imports too large to pay a WAL append per record. Keys must be
+    /// strictly ascending. Returns the number of records loaded.
+    ///
+    /// See [`ingest_sorted`](Self::ingest_sorted), which does the work. Each
+    /// table is fsynced and installed once it is full, so a crash or error
+    /// mid-load keeps the tables already installed and loses the rest: the
+    /// caller has to restart the import (reloading a key just shadows the
+    /// earlier copy).
+    pub fn bulk_load<I>(&self, records: I) -> Result<usize>
+    where
+        I: IntoIterator<Item = (String, Vec<u8>)>,
+    {
+        self.ingest_sorted(records.into_iter())
+    }
+
+    /// Stream records that are already in ascending key order (say, exported
+    /// from another store) through an [`SstableBuilder`] into L0 tables of
+    /// about `target_file_size` bytes, installing each as it is finished.
+    /// Nothing goes through the WAL or the MemTable, so there is no flush
+    /// churn and only one table is held in memory at a time.
+    ///
+    /// A key that is not greater than the one before it stops the ingest with
+    /// `LsmError::ValidationRejected`; the table in progress is discarded and
+    /// the ones installed before it stay.
+    ///
+    /// The MemTable is flushed first so ingested values shadow earlier writes.
+    /// Subscribers are not notified, and writes to the same keys while the
+    /// ingest runs may end up either side of it.
+    pub fn ingest_sorted(&self, records: impl Iterator<Item = (String, Vec<u8>)>) -> Result<usize> {
+        self.flush()?;
+
+        // On error, dropping the builder of the unfinished table removes its file
+        let mut pending: Option<SstableBuilder> = None;
+        let mut previous: Option<String> = None;
+        let mut count = 0;
+        let cmp = self.config.storage.key_comparator;
+        let target_size = self
+            .config
+            .storage
+            .table_size_limit(self.config.storage.target_file_size as u64);
+        for (key, value) in records {
+            self.check_set(&key, &value)?;
+            if let Some(previous) =
+                previous.filter(|previous| cmp.compare(previous.as_bytes(), key.as_bytes()).is_ge())
+            {
+                return Err(LsmError::ValidationRejected(format!(
+                    "keys must be strictly ascending: '{}' after '{}'",
+                    key, previous
+                )));
+            }
+
+            let mut record = LogRecord::new(key, value);
+            record.seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+
+            let builder = match pending.as_mut() {
+                Some(builder) => builder,
+                None => {
+                    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+                    pending.insert(SstableBuilder::create_in(
+                        &self.dir_path,
+                        self.config.storage.clone(),
+                        timestamp,
+                    )?)
+                }
+            };
+            builder.add(record.key.as_bytes(), &record)?;
+            count += 1;
+
+            if builder.estimated_size() >= target_size {
+                if let Some(full) = pending.take() {
+                    self.install_ingested(full)?;
+                }
+            }
+            previous = Some(record.key);
+        }
+
+        if let Some(last) = pending.take() {
+            self.install_ingested(last)?;
+        }
+        self.maybe_compact()?;
+
+        info!("Ingested {} sorted records", count);
+        Ok(count)
+    }
+
+    /// Finish an ingested table and put it in front of the others
+    fn install_ingested(&self, builder: SstableBuilder) -> Result<()> {
+        let path = builder.finish()?;
+        let reader = Arc::new(SstableReader::open(
+            path,
+            self.config.storage.clone(),
+            Arc::clone(&self.block_cache),
+        )?);
+        self.record_tables(std::slice::from_ref(&reader), &[])?;
+        self.sstables_write()?.insert(0, reader);
+        Ok(())
+    }
+
+    /// Record in the manifest that `added` are live and `removed` no longer
+    /// are, before the in-memory list changes. If that fails, `added` are
+    /// retired so their files go away with the readers.
+    fn record_tables(
+        &self,
+        added: &[Arc<SstableReader>],
+        removed: &[Arc<SstableReader>],
+    ) -> Result<()> {
+        let edit = ManifestEdit {
+            added: added.iter().map(|sst| table_name(sst)).collect(),
+            removed: removed.iter().map(|sst| table_name(sst)).collect(),
+        };
+        self.manifest.record(&edit).inspect_err(|_| {
+            for sst in added {
+                sst.mark_obsolete();
+            }
+        })
+    }
+
+    /// Live key/value pairs with keys between `start` and `end`, in ascending
+    /// key order.
+    ///
+    /// The MemTable and SSTables are merged with a [`MergingIterator`]. Each
+    /// SSTable seeks to its first relevant block through the sparse index and
+    /// stops before the first block past `end`, keeping at most one decoded
+    /// block in memory. An empty or inverted range returns no entries.
+    pub fn range(
+        &self,
+        start: Bound<String>,
+        end: Bound<String>,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        self.merged_iter(
+            start.as_ref().map(String::as_str),
+            end.as_ref().map(|key| key.as_bytes()),
+            Direction::Ascending,
+        )?
+        .collect()
+    }
+
+    /// Same entries as [`range`](Self::range), in descending key order.
+    ///
+    /// Useful for "most recent first" queries over keys with a sortable
+    /// suffix: take the first `n` items instead of collecting the whole range.
+    /// SSTables are read from the last block overlapping the range backwards.
+    pub fn range_rev(
+        &self,
+        start: Bound<String>,
+        end: Bound<String>,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        self.merged_iter(
+            start.as_ref().map(String::as_str),
+            end.as_ref().map(|key| key.as_bytes()),
+            Direction::Descending,
+        )?
+        .collect()
+    }
+
+    /// Live key/value pairs whose key starts with `prefix`, in ascending key
+    /// order, stopping after `limit` entries if given.
+    ///
+    /// Runs as a range scan over `[prefix, next_prefix)`, where `next_prefix`
+    /// is `prefix` with its last byte incremented, so only the blocks holding
+    /// matching keys are read. Under [`KeyComparator::NumericSuffix`] the
+    /// matching keys aren't contiguous, so every key is visited instead.
+    pub fn prefix_scan(
+        &self,
+        prefix: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        if self.config.storage.key_comparator != KeyComparator::Lexicographic {
+            return self
+                .merged_iter(Bound::Unbounded, Bound::Unbounded, Direction::Ascending)?
+                .filter(|entry| {
+                    entry
+                        .as_ref()
+                        .map_or(true, |(key, _)| key.starts_with(prefix))
+                })
+                .take(limit.unwrap_or(usize::MAX))
+                .collect();
+        }
+
+        let upper = next_prefix(prefix.as_bytes());
+        let end = match &upper {
+            Some(upper) => Bound::Excluded(upper.as_slice()),
+            None => Bound::Unbounded,
+        };
+        self.merged_iter(Bound::Included(prefix), end, Direction::Ascending)?
+            .take(limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// Streaming merge over `[start, end]` shared by `iter`, `range` and
+    /// `prefix_scan`. The upper bound is raw bytes because the end of a prefix
+    /// range need not be valid UTF-8.
+    pub(crate) fn merged_iter(
+        &self,
+        start: Bound<&str>,
+        end: Bound<&[u8]>,
+        direction: Direction,
+    ) -> Result<KeyValueIterator> {
+        let comparator = self.config.storage.key_comparator;
+        if is_empty_range(comparator, start.map(str::as_bytes), end) {
+            return Ok(KeyValueIterator::new(Vec::new(), direction, comparator));
+        }
+
+        // One source per MemTable, newest first, so the merge prefers them in
+        // that order over each other and over every SSTable
+        let memtables = self.memtables_read()?;
+        let memtable_records: Vec<Vec<(String, LogRecord)>> = memtables
+            .tables()
+            .map(|memtable| memtable.range(start, end, direction))
+            .collect();
+        drop(memtables);
+
+        // Clone the handles so the iterator doesn't pin the lock
+        let sstables: Vec<Arc<SstableReader>> = self.sstables_read()?.clone();
+
+        merge_sources(
+            memtable_records,
+            &sstables,
+            start,
+            end,
+            direction,
+            comparator,
+        )
+    }
+
+    /// Distinct key prefixes (up to `namespace_separator`) present in the store.
+    ///
+    /// SSTables carry their prefix set in metadata, so this costs one pass over
+    /// the MemTable plus O(distinct prefixes) per table. The result is
+    /// approximate: a namespace whose keys were all deleted keeps showing up
+    /// until compaction rewrites the tables that still mention it. Returns an
+    /// empty list when no separator is configured.
+    pub fn list_namespaces(&self) -> Result<Vec<String>> {
+        let Some(separator) = self.config.storage.namespace_separator else {
+            return Ok(Vec::new());
+        };
+
+        let mut namespaces = BTreeSet::new();
+
+        let memtables = self.memtables_read()?;
+        for (key, record) in memtables.tables().flat_map(MemTable::iter_ordered) {
+            if record.is_deleted {
+                continue;
+            }
+            if let Some(namespace) = namespace_of(&key, separator) {
+                namespaces.insert(namespace.to_string());
+            }
+        }
+        drop(memtables);
+
+        let sstables = self.sstables_read()?;
+        for sst in sstables.iter() {
+            namespaces.extend(sst.metadata().namespaces.iter().cloned());
+        }
+
+        Ok(namespaces.into_iter().collect())
+    }
+
+    /// Iterate over all live key/value pairs in key order.
+    ///
+    /// See [`KeyValueIterator`] for the snapshot semantics.
+    pub fn iter(&self) -> Result<KeyValueIterator> {
+        self.merged_iter(Bound::Unbounded, Bound::Unbounded, Direction::Ascending)
+    }
+
+    /// Iterate over all live key/value pairs in descending key order.
+    ///
+    /// Same snapshot semantics as [`iter`](Self::iter).
+    pub fn iter_rev(&self) -> Result<KeyValueIterator> {
+        self.merged_iter(Bound::Unbounded, Bound::Unbounded, Direction::Descending)
+    }
+
+    pub fn keys(&self) -> Result<Vec<String>> {
+        self.iter()?.map(|kv| kv.map(|(key, _)| key)).collect()
+    }
+
+    pub fn count(&self) -> Result<usize> {
+        self.iter()?.try_fold(0, |count, kv| kv.map(|_| count + 1))
+    }
+
+    pub fn stats(&self) -> String {
+        let memtables = match self.memtables_read() {
+            Ok(g) => g,
+            Err(e) => return format!("LSM Stats error: {e}"),
+        };
+        let sstables = match self.sstables_read() {
+            Ok(g) => g,
+            Err(e) => return format!("LSM Stats error: {e}"),
+        };
+
+        let cache_stats = self.block_cache.stats();
+
+        format!(
+            "LSM Stats:\n MemTable: {} records, ~{} KB\n SSTables: {} files\n Cache: {}/{} blocks, {:.1}% hits",
+            memtables.len(),
+            memtables.size_bytes() / 1024,
+            sstables.len(),
+            cache_stats.len,
+            cache_stats.cap,
+            cache_stats.hit_ratio() * 100.0
+        )
+    }
+
+    /// Read the blocks holding `keys` into the block cache ahead of traffic.
+    ///
+    /// Best effort: values aren't returned, and loading stops once as many
+    /// blocks as the cache can hold have been read (anything more would only
+    /// evict what was just loaded). Returns the number of blocks read.
+    pub fn warmup(&self, keys: &[String]) -> Result<usize> {
+        let budget = self.block_cache_capacity_blocks();
+        let mut loaded = 0;
+
+        let sstables = self.sstables_read()?;
+        'tables: for sst in sstables.iter() {
+            for key in keys {
+                if loaded >= budget {
+                    break 'tables;
+                }
+                if sst.warm_key(key)? {
+                    loaded += 1;
+                }
+            }
+        }
+
+        Ok(loaded)
+    }
+
+    /// Like [`warmup`](Self::warmup), but loads every block covering keys in
+    /// `[start, end)`, newest tables first.
+    pub fn warmup_range(&self, start: &str, end: &str) -> Result<usize> {
+        let budget = self.block_cache_capacity_blocks();
+        let mut loaded = 0;
+
+        let sstables = self.sstables_read()?;
+        for sst in sstables.iter() {
+            if loaded >= budget {
+                break;
+            }
+            loaded += sst.warm_range(start.as_bytes(), end.as_bytes(), budget - loaded)?;
+        }
+
+        Ok(loaded)
+    }
+
+    /// Level, key range and size of every live SSTable, in the order reads
+    /// consult them
+    pub fn sstable_info(&self) -> Result<Vec<SstableInfo>> {
+        let sstables = self.sstables_read()?;
+        Ok(sstables
+            .iter()
+            .map(|sst| {
+                let meta = sst.metadata();
+                SstableInfo {
+                    path: sst.path().clone(),
+                    level: sst.level(),
+                    min_key: String::from_utf8_lossy(&meta.min_key).into_owned(),
+                    max_key: String::from_utf8_lossy(&meta.max_key).into_owned(),
+                    record_count: meta.record_count,
+                }
+            })
+            .collect())
+    }
+
+    /// SSTables found corrupted on open and moved to the quarantine directory
+    pub fn quarantined_files(&self) -> Result<Vec<PathBuf>> {
+        let dir = self.dir_path.join(QUARANTINE_DIR);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            files.push(entry?.path());
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Configuration the engine is actually running with, including values
+    /// changed at runtime
+    pub fn effective_config(&self) -> LsmConfig {
+        let mut config = self.config.clone();
+        if let Ok(memtables) = self.memtables_read() {
+            config.core.memtable_max_size = memtables.active.max_size_bytes;
+        }
+        config
+    }
+
+    /// Change the MemTable flush threshold without restarting.
+    ///
+    /// The new size is validated like `CoreConfig::memtable_max_size` and
+    /// swapped in under the MemTable write lock, so every `set` sees either the
+    /// old or the new threshold. If the MemTable is already over the new limit
+    /// it is flushed right away.
+    pub fn set_memtable_max_size(&self, bytes: usize) -> Result<()> {
+        CoreConfig {
+            memtable_max_size: bytes,
+            ..self.config.core.clone()
+        }
+        .validate()?;
+
+        let should_flush = {
+            let mut memtables = self.memtables_write()?;
+            memtables.active.max_size_bytes = bytes;
+            memtables.active.should_flush()
+        };
+
+        info!("MemTable max size set to {} bytes", bytes);
+
+        if should_flush {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Number of blocks the shared block cache can hold, derived from
+    /// `block_cache_size_mb` and `block_size`
+    pub fn block_cache_capacity_blocks(&self) -> usize {
+        self.block_cache.stats().cap
+    }
+
+    /// WAL fsync latency statistics since the engine was opened
+    pub fn wal_stats(&self) -> WalStats {
+        self.wal.stats()
+    }
+
+    pub fn stats_all(&self) -> std::result::Result<LsmStats, String> {
+        let memtables = self.memtables_read().map_err(|e| e.to_string())?;
+        let sstables = self.sstables_read().map_err(|e| e.to_string())?;
+
+        let mem_records = memtables.len();
+        let sst_records_total: u64 = sstables.iter().map(|s| s.metadata().record_count).sum();
+
+        let per_sstable: Vec<SstableStat> = sstables
+            .iter()
+            .map(|sst| {
+                let meta = sst.metadata();
+                SstableStat {
+                    timestamp: meta.timestamp,
+                    level: sst.level(),
+                    record_count: meta.record_count,
+                    byte_size: std::fs::metadata(sst.path()).map(|m| m.len()).unwrap_or(0),
+                    min_key: String::from_utf8_lossy(&meta.min_key).into_owned(),
+                    max_key: String::from_utf8_lossy(&meta.max_key).into_owned(),
+                }
+            })
+            .collect();
+        let sst_bytes_total: u64 = per_sstable.iter().map(|s| s.byte_size).sum();
+
+        let wal_bytes = self.wal.size_bytes();
+        let cache_stats = self.block_cache.stats();
 
         Ok(LsmStats {
             mem_records,
-            mem_kb: memtable.size_bytes / 1024,
+            mem_kb: memtables.size_bytes() / 1024,
             sst_files: sstables.len(),
             sst_records: sst_records_total,
             sst_kb: sst_bytes_total / 1024,
             wal_kb: wal_bytes / 1024,
             total_records: (mem_records as u64) + sst_records_total,
-            memtable_max_size: self.config.core.memtable_max_size / 1024,
+            memtable_max_size: memtables.active.max_size_bytes / 1024,
+            cache_hits: cache_stats.hits,
+            cache_misses: cache_stats.misses,
+            cache_evictions: cache_stats.evictions,
+            cache_hit_ratio: cache_stats.hit_ratio(),
+            negative_cache_hits: self.negative_cache.as_ref().map_or(0, NegativeCache::hits),
+            last_recovery_ms: self.last_recovery_ms,
+            recovered_records: self.recovered_records,
+            size_tiers: size_tiers(per_sstable.iter().map(|s| s.byte_size)),
+            per_sstable,
         })
     }
 }
+
+/// Merge MemTable records already collected over `[start, end]` (one `Vec`
+/// per table, newest first) with `sstables`, in read order. Shared by the
+/// engine's iterators and [`Snapshot`].
+pub(crate) fn merge_sources(
+    memtable_records: Vec<Vec<(String, LogRecord)>>,
+    sstables: &[Arc<SstableReader>],
+    start: Bound<&str>,
+    end: Bound<&[u8]>,
+    direction: Direction,
+    comparator: KeyComparator,
+) -> Result<KeyValueIterator> {
+    let start_bytes = start.map(str::as_bytes);
+    let mut sources: Vec<RecordSource<'static>> =
+        Vec::with_capacity(sstables.len() + memtable_records.len());
+    for records in memtable_records {
+        sources.push(Box::new(records.into_iter().map(Ok)));
+    }
+    for sst in sstables {
+        // Blobs are fetched by the merge, and only for the versions it yields
+        let records = match direction {
+            Direction::Ascending => sst.range(start_bytes, end)?.raw(),
+            Direction::Descending => sst.range_rev(start_bytes, end)?.raw(),
+        };
+        sources.push(Box::new(records.map(|entry| {
+            let (key_bytes, record) = entry?;
+            let key =
+                String::from_utf8(key_bytes).map_err(|e| LsmError::CorruptedData(e.to_string()))?;
+            Ok((key, record))
+        })));
+    }
+
+    let iter = KeyValueIterator::new(sources, direction, comparator);
+    Ok(match sstables.first() {
+        Some(sst) => iter.with_blob_dir(blob_dir_of(sst.path())),
+        None => iter,
+    })
+}
+
+/// Whether no key can satisfy both bounds under `comparator` (also keeps
+/// `BTreeMap::range` from panicking on inverted bounds)
+pub(crate) fn is_empty_range(
+    comparator: KeyComparator,
+    start: Bound<&[u8]>,
+    end: Bound<&[u8]>,
+) -> bool {
+    match (start, end) {
+        (Bound::Included(s), Bound::Included(e)) => comparator.compare(s, e).is_gt(),
+        (Bound::Included(s), Bound::Excluded(e))
+        | (Bound::Excluded(s), Bound::Included(e))
+        | (Bound::Excluded(s), Bound::Excluded(e)) => comparator.compare(s, e).is_ge(),
+        _ => false,
+    }
+}
+
+/// Smallest byte string greater than every string starting with `prefix`,
+/// or `None` when there is none (empty or all-`0xFF` prefix)
+fn next_prefix(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut next = prefix.to_vec();
+    while let Some(last) = next.pop() {
+        if last < u8::MAX {
+            next.push(last + 1);
+            return Some(next);
+        }
+    }
+    None
+}
+
+/// Combined on-disk size of `tables` (files that can't be stat'ed count as 0)
+fn total_file_size(tables: &[Arc<SstableReader>]) -> u64 {
+    tables
+        .iter()
+        .filter_map(|sst| std::fs::metadata(sst.path()).ok())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+/// File name of a table, as the manifest lists it
+fn table_name(sst: &SstableReader) -> String {
+    sst.path()
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Names of the `.sst` files directly in `dir`
+fn sst_file_names(dir: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "sst") {
+            if let Some(name) = path.file_name() {
+                names.push(name.to_string_lossy().into_owned());
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Delete the `.sst` files in `dir` that are not in `listed`: outputs of a
+/// flush or compaction that crashed before recording them, or compaction
+/// inputs that were retired but not yet deleted. Tables a crash cut short
+/// while they were still being written (`.sst.tmp`) go too.
+fn remove_orphans(dir: &Path, listed: &[String]) -> Result<()> {
+    let tmp_suffix = format!(".sst{}", SST_TMP_SUFFIX);
+    let mut orphans: Vec<String> = sst_file_names(dir)?
+        .into_iter()
+        .filter(|name| !listed.contains(name))
+        .collect();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name.ends_with(&tmp_suffix) {
+            orphans.push(name);
+        }
+    }
+
+    for name in orphans {
+        let path = dir.join(&name);
+        match std::fs::remove_file(&path) {
+            Ok(()) => info!("Removed orphaned SSTable {}", path.display()),
+            Err(e) => warn!(
+                "Failed to remove orphaned SSTable {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Delete blobs no live table points at: left by a flush or compaction that
+/// crashed before its tables went live, or after replacing the tables that
+/// referenced them but before removing them.
+///
+/// Nothing is removed while any table is quarantined (`quarantined` is set
+/// when this open moved one aside), since its blobs would look orphaned too
+/// and it could never be recovered whole.
+fn remove_orphaned_blobs(
+    dir: &Path,
+    sstables: &[Arc<SstableReader>],
+    quarantined: bool,
+) -> Result<()> {
+    let blob_dir = dir.join(BLOB_DIR);
+    let ids = blob_ids(&blob_dir)?;
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let quarantine_dir = dir.join(QUARANTINE_DIR);
+    if quarantined
+        || (quarantine_dir.exists() && std::fs::read_dir(&quarantine_dir)?.next().is_some())
+    {
+        warn!("Skipping orphaned blob cleanup while SSTables are quarantined");
+        return Ok(());
+    }
+
+    let mut referenced = BTreeSet::new();
+    for sst in sstables {
+        // Tables from before `MetaBlock::blob_ids` have to be scanned
+        if let Some(ids) = &sst.metadata().blob_ids {
+            referenced.extend(ids.iter().cloned());
+            continue;
+        }
+        let records = match sst.scan_raw() {
+            Ok(records) => records,
+            Err(e) => {
+                // Can't tell which blobs it needs, so keep them all
+                warn!(
+                    "Skipping orphaned blob cleanup, {} is unreadable: {}",
+                    sst.path().display(),
+                    e
+                );
+                return Ok(());
+            }
+        };
+        referenced.extend(
+            records
+                .into_iter()
+                .filter_map(|(_, record)| record.blob.map(|blob| blob.id)),
+        );
+    }
+
+    for id in ids.iter().filter(|id| !referenced.contains(*id)) {
+        let path = blob_path(&blob_dir, id);
+        match std::fs::remove_file(&path) {
+            Ok(()) => info!("Removed orphaned blob {}", path.display()),
+            Err(e) => warn!("Failed to remove orphaned blob {}: {}", path.display(), e),
+        }
+    }
+    Ok(())
+}
+
+/// Whether a table failed to open because its bytes are damaged, rather than
+/// because it is in a format this build doesn't support or the disk failed
+fn is_corruption(err: &LsmError) -> bool {
+    match err {
+        LsmError::CorruptedData(_)
+        | LsmError::DecompressionFailed(_)
+        | LsmError::Serialization(_)
+        | LsmError::DeserializationFailed(_) => true,
+        // A file cut short before its footer
+        LsmError::Io(e) => matches!(
+            e.kind(),
+            std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::InvalidInput
+        ),
+        _ => false,
+    }
+}
+
+/// Move an SSTable that failed to open out of the live set, keeping it on disk
+/// for inspection. Falls back to leaving it in place if the move fails.
+fn quarantine(dir: &Path, path: &Path, cause: &LsmError) {
+    let Some(file_name) = path.file_name() else {
+        return;
+    };
+    let quarantine_dir = dir.join(QUARANTINE_DIR);
+
+    let mut target = quarantine_dir.join(file_name);
+    if target.exists() {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        target = quarantine_dir.join(format!("{}.{}", file_name.to_string_lossy(), nanos));
+    }
+
+    match std::fs::create_dir_all(&quarantine_dir).and_then(|_| std::fs::rename(path, &target)) {
+        Ok(()) => error!(
+            "Quarantined unreadable SSTable {} -> {}: {}",
+            path.display(),
+            target.display(),
+            cause
+        ),
+        Err(e) => warn!(
+            "Failed to load SSTable {} ({}) and could not quarantine it: {}",
+            path.display(),
+            cause,
+            e
+        ),
+    }
+}
+
+/// `idx:<field>:<value>:`, the prefix shared by every entry for that pair.
+fn index_entry_prefix(field: &str, value: &str) -> Result<String> {
+    if field.is_empty() || field.contains(':') || value.contains(':') {
+        return Err(LsmError::ValidationRejected(format!(
+            "index field '{}' and value '{}' must not contain ':' (and the field must not be empty)",
+            field, value
+        )));
+    }
+    Ok(format!("{}{}:{}:", INDEX_PREFIX, field, value))
+}
+
+/// Read up to `blocks` blocks into the cache, newest tables first, without
+/// going past what the cache can hold. Best effort: a table that fails to
+/// load is skipped, leaving its error to the first read that needs it.
+fn preload(sstables: &[Arc<SstableReader>], blocks: usize, cache: &GlobalBlockCache) {
+    let budget = blocks.min(cache.stats().cap);
+    let mut loaded = 0;
+    for sst in sstables {
+        if loaded >= budget {
+            break;
+        }
+        match sst.preload(budget - loaded) {
+            Ok(n) => loaded += n,
+            Err(e) => warn!("Failed to preload {}: {}", sst.path().display(), e),
+        }
+    }
+    if loaded > 0 {
+        info!("Preloaded {} blocks into the block cache", loaded);
+    }
+}
+
+/// Number of keys checked after a flush when the table is too big to check all of them
+const FLUSH_VERIFY_SAMPLE: usize = 1024;
+
+/// Check that `reader` returns exactly `records` (all of them for small tables,
+/// an evenly spaced sample otherwise).
+fn verify_flushed(reader: &SstableReader, records: &[(String, LogRecord)]) -> Result<()> {
+    let step = records.len().div_ceil(FLUSH_VERIFY_SAMPLE).max(1);
+
+    for (key, expected) in records.iter().step_by(step) {
+        match reader.get(key)? {
+            Some(actual) if &actual == expected => {}
+            Some(_) => {
+                return Err(LsmError::CorruptedData(format!(
+                    "Flush verification failed: key '{}' read back with different contents from {}",
+                    key,
+                    reader.path().display()
+                )))
+            }
+            None => {
+                return Err(LsmError::CorruptedData(format!(
+                    "Flush verification failed: key '{}' missing from {}",
+                    key,
+                    reader.path().display()
+                )))
+            }
+        }
+    }
+
+    if reader.metadata().record_count != records.len() as u64 {
+        return Err(LsmError::CorruptedData(format!(
+            "Flush verification failed: expected {} records, table reports {}",
+            records.len(),
+            reader.metadata().record_count
+        )));
+    }
+
+    Ok(())
+}
+
+/// [`verify_flushed`] for a flush split across `readers`, which hold
+/// consecutive runs of `records`
+fn verify_flushed_split(
+    readers: &[Arc<SstableReader>],
+    records: &[(String, LogRecord)],
+) -> Result<()> {
+    let mut rest = records;
+    for reader in readers {
+        let count = (reader.metadata().record_count as usize).min(rest.len());
+        let (written, remaining) = rest.split_at(count);
+        verify_flushed(reader, written)?;
+        rest = remaining;
+    }
+
+    if !rest.is_empty() {
+        return Err(LsmError::CorruptedData(format!(
+            "Flush verification failed: {} records missing from the flushed tables",
+            rest.len()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn build_table(dir: &std::path::Path, records: &[(String, LogRecord)]) -> SstableReader {
+        let config = crate::infra::config::StorageConfig::default();
+        let path = dir.join("verify.sst");
+        let mut builder = SstableBuilder::new(path, config.clone(), 1).unwrap();
+        for (key, record) in records {
+            builder.add(key.as_bytes(), record).unwrap();
+        }
+        let path = builder.finish().unwrap();
+        let cache = GlobalBlockCache::new(config.block_cache_size_mb, config.block_size);
+        SstableReader::open(path, config, cache).unwrap()
+    }
+
+    fn records(n: usize) -> Vec<(String, LogRecord)> {
+        (0..n)
+            .map(|i| {
+                let key = format!("key_{:04}", i);
+                (
+                    key.clone(),
+                    LogRecord::new(key, format!("v{i}").into_bytes()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_size_tiers_group_by_order_of_magnitude() {
+        let tier = |min_bytes, tables, total_bytes| SizeTier {
+            min_bytes,
+            tables,
+            total_bytes,
+        };
+        assert_eq!(
+            size_tiers([0, 950, 1000, 4500, 9999, 2_000_000]),
+            vec![
+                tier(1, 1, 0),
+                tier(100, 1, 950),
+                tier(1000, 3, 15_499),
+                tier(1_000_000, 1, 2_000_000),
+            ]
+        );
+        assert!(size_tiers([]).is_empty());
+    }
+
+    #[test]
+    fn test_verify_flushed_accepts_matching_table() {
+        let dir = tempdir().unwrap();
+        let records = records(50);
+        let reader = build_table(dir.path(), &records);
+        assert!(verify_flushed(&reader, &records).is_ok());
+    }
+
+    #[test]
+    fn test_verify_flushed_detects_mismatch() {
+        let dir = tempdir().unwrap();
+        let written = records(50);
+        let reader = build_table(dir.path(), &written);
+
+        let mut expected = written.clone();
+        expected[10].1.value = b"something else".to_vec();
+        assert!(matches!(
+            verify_flushed(&reader, &expected),
+            Err(LsmError::CorruptedData(_))
+        ));
+
+        let mut expected = written;
+        expected.push((
+            "key_9999".to_string(),
+            LogRecord::new("key_9999".into(), vec![]),
+        ));
+        assert!(matches!(
+            verify_flushed(&reader, &expected),
+            Err(LsmError::CorruptedData(_))
+        ));
+    }
+
+    #[test]
+    fn test_flush_with_verification_enabled() {
+        let dir = tempdir().unwrap();
+        let config = LsmConfig::builder()
+            .dir_path(dir.path())
+            .memtable_max_size(1024)
+            .verify_after_flush(true)
+            .build()
+            .unwrap();
+        let engine = LsmEngine::new(config).unwrap();
+
+        for i in 0..100 {
+            engine.set(format!("k{i:03}"), vec![b'x'; 20]).unwrap();
+        }
+
+        assert!(!engine.sstables_read().unwrap().is_empty());
+        for i in 0..100 {
+            assert_eq!(
+                engine.get(&format!("k{i:03}")).unwrap(),
+                Some(vec![b'x'; 20])
+            );
+        }
+    }
+
+    #[test]
+    fn test_flushed_bloom_filter_survives_reopen() {
+        let dir = tempdir().unwrap();
+        let engine = history_engine(dir.path());
+        for i in 0..200 {
+            engine
+                .set(format!("key_{i:03}"), format!("value_{i}").into_bytes())
+                .unwrap();
+        }
+        engine.flush().unwrap();
+        let path = engine.sstables_read().unwrap()[0].path().clone();
+        drop(engine);
+
+        // A fresh reader with its own cache only has the on-disk filter to go on
+        let config = LsmConfig::default().storage;
+        let cache = GlobalBlockCache::new(config.block_cache_size_mb, config.block_size);
+        let reader = SstableReader::open(path, config, cache).unwrap();
+        assert!(reader.has_bloom());
+
+        for i in 0..200 {
+            let key = format!("key_{i:03}");
+            assert!(reader.might_contain(&key), "bloom filter rejected {key}");
+            let record = reader.get(&key).unwrap().unwrap();
+            assert_eq!(record.value, format!("value_{i}").into_bytes());
+        }
+    }
+
+    fn history_engine(dir: &std::path::Path) -> LsmEngine {
+        let config = LsmConfig::builder()
+            .dir_path(dir)
+            .memtable_max_size(1024 * 1024)
+            .build()
+            .unwrap();
+        LsmEngine::new(config).unwrap()
+    }
+
+    #[test]
+    fn test_exists_at_seq_in_memtable() {
+        let dir = tempdir().unwrap();
+        let engine = history_engine(dir.path());
+
+        engine.set("k".to_string(), b"1".to_vec()).unwrap();
+        let set1 = engine.last_seq();
+        engine.delete("k".to_string()).unwrap();
+        let del = engine.last_seq();
+        engine.set("k".to_string(), b"2".to_vec()).unwrap();
+        let set2 = engine.last_seq();
+
+        assert!(!engine.exists_at_seq("k", set1 - 1).unwrap());
+        assert!(engine.exists_at_seq("k", set1).unwrap());
+        assert!(!engine.exists_at_seq("k", del).unwrap());
+        assert!(engine.exists_at_seq("k", set2).unwrap());
+        assert!(!engine.exists_at_seq("missing", set2).unwrap());
+    }
+
+    #[test]
+    fn test_exists_at_seq_across_sstables() {
+        let dir = tempdir().unwrap();
+        let engine = history_engine(dir.path());
+
+        engine.set("k".to_string(), b"1".to_vec()).unwrap();
+        let set1 = engine.last_seq();
+        engine.flush().unwrap();
+        engine.delete("k".to_string()).unwrap();
+        let del = engine.last_seq();
+        engine.flush().unwrap();
+        engine.set("k".to_string(), b"2".to_vec()).unwrap();
+        let set2 = engine.last_seq();
+        engine.flush().unwrap();
+        assert_eq!(engine.sstables_read().unwrap().len(), 3);
+
+        assert!(!engine.exists_at_seq("k", set1 - 1).unwrap());
+        assert!(engine.exists_at_seq("k", set1).unwrap());
+        assert!(!engine.exists_at_seq("k", del).unwrap());
+        assert!(!engine.exists_at_seq("k", set2 - 1).unwrap());
+        assert!(engine.exists_at_seq("k", set2).unwrap());
+    }
+
+    #[test]
+    fn test_seq_continues_after_restart() {
+        let dir = tempdir().unwrap();
+        let last = {
+            let engine = history_engine(dir.path());
+            engine.set("a".to_string(), b"1".to_vec()).unwrap();
+            engine.flush().unwrap();
+            engine.set("b".to_string(), b"2".to_vec()).unwrap();
+            engine.last_seq()
+        };
+
+        let engine = history_engine(dir.path());
+        assert_eq!(engine.last_seq(), last);
+        engine.set("c".to_string(), b"3".to_vec()).unwrap();
+        assert_eq!(engine.last_seq(), last + 1);
+    }
+
+    /// The pre-`MergingIterator` scan: materialize everything in a map,
+    /// first (newest) version wins
+    fn map_based_scan(engine: &LsmEngine) -> Vec<(String, Vec<u8>)> {
+        let mut map: std::collections::HashMap<String, LogRecord> =
+            std::collections::HashMap::new();
+        for memtable in engine.memtables_read().unwrap().tables() {
+            for (key, record) in memtable.iter_ordered() {
+                map.entry(key).or_insert(record);
+            }
+        }
+        for sst in engine.sstables_read().unwrap().iter() {
+            for (key, record) in sst.scan().unwrap() {
+                map.entry(String::from_utf8(key).unwrap()).or_insert(record);
+            }
+        }
+
+        let mut live: Vec<(String, Vec<u8>)> = map
+            .into_iter()
+            .filter(|(_, record)| !record.is_deleted)
+            .map(|(key, record)| (key, record.value))
+            .collect();
+        live.sort();
+        live
+    }
+
+    #[test]
+    fn test_streaming_scan_matches_map_based_scan() {
+        let dir = tempdir().unwrap();
+        let engine = history_engine(dir.path());
+
+        // Overlapping key ranges across three SSTables and the MemTable
+        for i in 0..300 {
+            engine.set(format!("key_{:03}", i), b"t1".to_vec()).unwrap();
+        }
+        engine.flush().unwrap();
+        for i in (0..300).step_by(3) {
+            engine.set(format!("key_{:03}", i), b"t2".to_vec()).unwrap();
+        }
+        for i in (0..300).step_by(7) {
+            engine.delete(format!("key_{:03}", i)).unwrap();
+        }
+        engine.flush().unwrap();
+        for i in (150..450).step_by(2) {
+            engine.set(format!("key_{:03}", i), b"t3".to_vec()).unwrap();
+        }
+        engine.flush().unwrap();
+        for i in (0..450).step_by(5) {
+            engine
+                .set(format!("key_{:03}", i), b"mem".to_vec())
+                .unwrap();
+        }
+        engine.delete("key_001".to_string()).unwrap();
+        assert_eq!(engine.sstables_read().unwrap().len(), 3);
+
+        let streamed = engine.scan().unwrap();
+        assert_eq!(streamed, map_based_scan(&engine));
+        assert!(streamed.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(!streamed.iter().any(|(key, _)| key == "key_001"));
+        assert!(!streamed.iter().any(|(key, _)| key == "key_007"));
+        assert!(streamed.contains(&("key_003".to_string(), b"t2".to_vec())));
+        assert!(streamed.contains(&("key_152".to_string(), b"t3".to_vec())));
+        assert!(streamed.contains(&("key_155".to_string(), b"mem".to_vec())));
+    }
+
+    fn small_block_engine(dir: &std::path::Path) -> LsmEngine {
+        let config = LsmConfig::builder()
+            .dir_path(dir)
+            .memtable_max_size(1024 * 1024)
+            .block_size(256)
+            .build()
+            .unwrap();
+        LsmEngine::new(config).unwrap()
+    }
+
+    fn total_block_reads(engine: &LsmEngine) -> u64 {
+        engine
+            .sstables_read()
+            .unwrap()
+            .iter()
+            .map(|sst| sst.block_reads())
+            .sum()
+    }
+
+    /// Blocks of every table whose key span intersects `[start, end)`
+    fn blocks_overlapping(engine: &LsmEngine, start: &[u8], end: &[u8]) -> u64 {
+        let sstables = engine.sstables_read().unwrap();
+        let mut count = 0;
+        for sst in sstables.iter() {
+            let n = sst.block_count();
+            let firsts: Vec<Vec<u8>> = (0..n)
+                .map(|i| sst.block_meta(i).unwrap().first_key)
+                .collect();
+            for i in 0..n {
+                let starts_before_end = firsts[i].as_slice() < end;
+                let ends_after_start = i + 1 == n || firsts[i + 1].as_slice() > start;
+                if starts_before_end && ends_after_start {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_scan_parallel_matches_scan() {
+        let dir = tempdir().unwrap();
+        let engine = small_block_engine(dir.path());
+        // Six overlapping tables, each overwriting or deleting part of the
+        // ones before it
+        for round in 0..6 {
+            for i in (round * 50..round * 50 + 200).step_by(round + 1) {
+                engine
+                    .set(
+                        format!("key_{:04}", i),
+                        format!("r{round}_{i}").into_bytes(),
+                    )
+                    .unwrap();
+            }
+            for i in (round * 30..round * 30 + 20).step_by(3) {
+                engine.delete(format!("key_{:04}", i)).unwrap();
+            }
+            engine.flush().unwrap();
+        }
+        engine
+            .set("key_0001".to_string(), b"memtable".to_vec())
+            .unwrap();
+        engine.delete("key_0300".to_string()).unwrap();
+        assert_eq!(engine.sstables_read().unwrap().len(), 6);
+
+        let sequential = engine.scan().unwrap();
+        assert!(sequential.len() > 250);
+        assert_eq!(engine.scan_parallel().unwrap(), sequential);
+    }
+
+    #[test]
+    fn test_exists_reports_live_keys_and_skips_blocks_it_can() {
+        let dir = tempdir().unwrap();
+        let engine = small_block_engine(dir.path());
+        for i in (0..200).step_by(2) {
+            engine
+                .set(format!("key_{:03}", i), vec![b'v'; 100])
+                .unwrap();
+        }
+        engine.delete("key_010".to_string()).unwrap();
+        engine.flush().unwrap();
+        engine.set("mem".to_string(), b"v".to_vec()).unwrap();
+        engine.delete("key_020".to_string()).unwrap();
+
+        assert!(engine.exists("mem").unwrap());
+        assert!(engine.exists("key_100").unwrap());
+        assert!(!engine.exists("key_010").unwrap());
+        assert!(!engine.exists("key_020").unwrap());
+
+        // Outside the table's range, or inside it but rejected by the Bloom
+        // filter: answered without a block read
+        let sst = Arc::clone(&engine.sstables_read().unwrap()[0]);
+        let bloom_negative = (1..200)
+            .step_by(2)
+            .map(|i| format!("key_{:03}", i))
+            .find(|key| !sst.might_contain(key))
+            .unwrap();
+        let before = total_block_reads(&engine);
+        assert!(!engine.exists("zzz").unwrap());
+        assert!(!engine.exists(&bloom_negative).unwrap());
+        assert_eq!(total_block_reads(&engine), before);
+    }
+
+    #[test]
+    fn test_get_skips_tables_whose_range_excludes_the_key() {
+        let dir = tempdir().unwrap();
+        let engine = small_block_engine(dir.path());
+        // One table per prefix, with disjoint key ranges
+        for prefix in ["a", "b", "c", "d"] {
+            for i in 0..50 {
+                engine
+                    .set(format!("{prefix}_{:03}", i), prefix.as_bytes().to_vec())
+                    .unwrap();
+            }
+            engine.flush().unwrap();
+        }
+
+        let bloom_checks = |engine: &LsmEngine| -> Vec<u64> {
+            engine
+                .sstables_read()
+                .unwrap()
+                .iter()
+                .map(|sst| sst.bloom_checks())
+                .collect()
+        };
+        let sstables = engine.sstables_read().unwrap().clone();
+        assert_eq!(sstables.len(), 4);
+        assert!(sstables.iter().all(|sst| sst.has_bloom()));
+        let covering = sstables
+            .iter()
+            .position(|sst| sst.covers(b"c_010"))
+            .unwrap();
+
+        let before = bloom_checks(&engine);
+        assert_eq!(engine.get("c_010").unwrap(), Some(b"c".to_vec()));
+        let after = bloom_checks(&engine);
+        for (i, (before, after)) in before.iter().zip(&after).enumerate() {
+            let expected = if i == covering { 1 } else { 0 };
+            assert_eq!(after - before, expected, "table {i}");
+        }
+
+        // Between two ranges, or past all of them: no table is probed
+        assert!(engine.get("b_999").unwrap().is_none());
+        assert!(engine.get("z").unwrap().is_none());
+        assert_eq!(bloom_checks(&engine), after);
+    }
+
+    #[test]
+    fn test_multi_get_matches_get_with_fewer_block_reads() {
+        let dir = tempdir().unwrap();
+        let engine = small_block_engine(dir.path());
+        for i in 0..=100 {
+            engine
+                .set(format!("key_{:03}", i), format!("old_{i}").into_bytes())
+                .unwrap();
+        }
+        engine.flush().unwrap();
+        // Newer table shadowing some keys, and a MemTable on top
+        for i in (0..=100).step_by(10) {
+            engine
+                .set(format!("key_{:03}", i), format!("new_{i}").into_bytes())
+                .unwrap();
+        }
+        engine.delete("key_020".to_string()).unwrap();
+        engine.flush().unwrap();
+        engine
+            .set("key_030".to_string(), b"memtable".to_vec())
+            .unwrap();
+
+        let mut keys: Vec<String> = (0..=100).rev().map(|i| format!("key_{:03}", i)).collect();
+        keys.push("missing".to_string());
+        keys.push("key_050".to_string());
+
+        let before = total_block_reads(&engine);
+        let expected: Vec<Option<Vec<u8>>> =
+            keys.iter().map(|key| engine.get(key).unwrap()).collect();
+        let get_reads = total_block_reads(&engine) - before;
+
+        let before = total_block_reads(&engine);
+        assert_eq!(engine.multi_get(&keys).unwrap(), expected);
+        let multi_get_reads = total_block_reads(&engine) - before;
+
+        assert_eq!(expected[0], Some(b"new_100".to_vec()));
+        assert_eq!(expected[70], Some(b"memtable".to_vec()));
+        assert_eq!(expected[80], None);
+        assert!(
+            multi_get_reads < get_reads,
+            "multi_get read {multi_get_reads} blocks, get {get_reads}"
+        );
+    }
+
+    #[test]
+    fn test_range_reads_only_overlapping_blocks() {
+        let dir = tempdir().unwrap();
+        let engine = small_block_engine(dir.path());
+        for i in 0..=100 {
+            engine
+                .set(format!("key_{:03}", i), format!("value_{i}").into_bytes())
+                .unwrap();
+        }
+        engine.flush().unwrap();
+        assert!(engine.sstables_read().unwrap()[0].block_count() > 10);
+
+        let before = total_block_reads(&engine);
+        let entries = engine
+            .range(
+                Bound::Included("key_010".to_string()),
+                Bound::Excluded("key_020".to_string()),
+            )
+            .unwrap();
+        let read = total_block_reads(&engine) - before;
+
+        let keys: Vec<String> = entries.into_iter().map(|(key, _)| key).collect();
+        let expected: Vec<String> = (10..20).map(|i| format!("key_{:03}", i)).collect();
+        assert_eq!(keys, expected);
+        assert_eq!(read, blocks_overlapping(&engine, b"key_010", b"key_020"));
+    }
+
+    #[test]
+    fn test_range_bounds() {
+        let dir = tempdir().unwrap();
+        let engine = small_block_engine(dir.path());
+        for i in 0..=100 {
+            engine
+                .set(format!("key_{:03}", i), b"sst".to_vec())
+                .unwrap();
+        }
+        engine.flush().unwrap();
+        // Overlap the table with newer MemTable versions and a tombstone
+        engine.set("key_015".to_string(), b"mem".to_vec()).unwrap();
+        engine.delete("key_016".to_string()).unwrap();
+
+        let keys = |start: Bound<&str>, end: Bound<&str>| -> Vec<String> {
+            engine
+                .range(start.map(String::from), end.map(String::from))
+                .unwrap()
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect()
+        };
+
+        assert_eq!(
+            keys(Bound::Excluded("key_014"), Bound::Included("key_017")),
+            vec!["key_015", "key_017"]
+        );
+        assert_eq!(
+            keys(Bound::Unbounded, Bound::Excluded("key_002")),
+            vec!["key_000", "key_001"]
+        );
+        assert_eq!(
+            keys(Bound::Included("key_099"), Bound::Unbounded),
+            vec!["key_099", "key_100"]
+        );
+        assert!(keys(Bound::Included("key_050"), Bound::Excluded("key_050")).is_empty());
+        assert!(keys(Bound::Included("key_060"), Bound::Included("key_050")).is_empty());
+        assert!(keys(Bound::Included("zzz"), Bound::Unbounded).is_empty());
+
+        let mixed = engine
+            .range(
+                Bound::Included("key_015".to_string()),
+                Bound::Included("key_015".to_string()),
+            )
+            .unwrap();
+        assert_eq!(mixed, vec![("key_015".to_string(), b"mem".to_vec())]);
+    }
+
+    #[test]
+    fn test_reverse_iteration_over_memtable_and_two_sstables() {
+        let dir = tempdir().unwrap();
+        let engine = small_block_engine(dir.path());
+
+        // Oldest table: even keys
+        for i in (0..60).step_by(2) {
+            engine.set(format!("key_{:03}", i), b"t1".to_vec()).unwrap();
+        }
+        engine.flush().unwrap();
+        // Newer table: odd keys, one overwrite and a tombstone for key_010
+        for i in (1..60).step_by(2) {
+            engine.set(format!("key_{:03}", i), b"t2".to_vec()).unwrap();
+        }
+        engine.set("key_020".to_string(), b"t2".to_vec()).unwrap();
+        engine.delete("key_010".to_string()).unwrap();
+        engine.flush().unwrap();
+        assert_eq!(engine.sstables_read().unwrap().len(), 2);
+        // MemTable: a new key, an overwrite and a tombstone shadowing key_031
+        engine.set("key_060".to_string(), b"mem".to_vec()).unwrap();
+        engine.set("key_040".to_string(), b"mem".to_vec()).unwrap();
+        engine.delete("key_031".to_string()).unwrap();
+
+        let mut expected = engine.scan().unwrap();
+        expected.reverse();
+        let descending: Vec<(String, Vec<u8>)> =
+            engine.iter_rev().unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(descending, expected);
+
+        assert_eq!(descending.len(), 59);
+        assert_eq!(descending[0], ("key_060".to_string(), b"mem".to_vec()));
+        assert!(!descending.iter().any(|(key, _)| key == "key_010"));
+        assert!(!descending.iter().any(|(key, _)| key == "key_031"));
+        assert!(descending.contains(&("key_020".to_string(), b"t2".to_vec())));
+        assert!(descending.contains(&("key_040".to_string(), b"mem".to_vec())));
+
+        let latest: Vec<String> = engine
+            .range_rev(
+                Bound::Included("key_008".to_string()),
+                Bound::Excluded("key_033".to_string()),
+            )
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .take(4)
+            .collect();
+        assert_eq!(latest, vec!["key_032", "key_030", "key_029", "key_028"]);
+
+        let mut forward = engine
+            .range(
+                Bound::Excluded("key_008".to_string()),
+                Bound::Included("key_041".to_string()),
+            )
+            .unwrap();
+        forward.reverse();
+        let backward = engine
+            .range_rev(
+                Bound::Excluded("key_008".to_string()),
+                Bound::Included("key_041".to_string()),
+            )
+            .unwrap();
+        assert_eq!(backward, forward);
+    }
+
+    #[test]
+    fn test_next_prefix() {
+        assert_eq!(next_prefix(b"user:"), Some(b"user;".to_vec()));
+        assert_eq!(next_prefix(b"a\xff"), Some(b"b".to_vec()));
+        assert_eq!(next_prefix(b"\xff\xff"), None);
+        assert_eq!(next_prefix(b""), None);
+    }
+
+    #[test]
+    fn test_prefix_scan_reads_only_matching_blocks() {
+        let dir = tempdir().unwrap();
+        let engine = small_block_engine(dir.path());
+        for prefix in ["admin:", "order:", "user:", "zone:"] {
+            for i in 0..2500 {
+                engine
+                    .set(format!("{prefix}{:05}", i), b"v".to_vec())
+                    .unwrap();
+            }
+        }
+        engine.flush().unwrap();
+
+        let before = total_block_reads(&engine);
+        let users = engine.prefix_scan("user:", None).unwrap();
+        let read = total_block_reads(&engine) - before;
+
+        assert_eq!(users.len(), 2500);
+        assert!(users.iter().all(|(key, _)| key.starts_with("user:")));
+        assert_eq!(read, blocks_overlapping(&engine, b"user:", b"user;"));
+        assert!(read < engine.sstables_read().unwrap()[0].block_count() as u64 / 3);
+
+        let before = total_block_reads(&engine);
+        let first = engine.prefix_scan("order:", Some(3)).unwrap();
+        let keys: Vec<&str> = first.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["order:00000", "order:00001", "order:00002"]);
+        assert!(total_block_reads(&engine) - before <= 2);
+
+        assert_eq!(engine.search_prefix("zone:").unwrap().len(), 2500);
+        assert!(engine.prefix_scan("missing:", None).unwrap().is_empty());
+    }
+}
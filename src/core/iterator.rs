@@ -0,0 +1,330 @@
+use crate::core::log_record::LogRecord;
+use crate::infra::config::KeyComparator;
+use crate::infra::error::{LsmError, Result};
+use crate::storage::blob::read_blob;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::path::PathBuf;
+
+/// Iterator over the live key/value pairs of an [`LsmEngine`](crate::LsmEngine),
+/// yielded in ascending key order (descending for
+/// [`iter_rev`](crate::LsmEngine::iter_rev)).
+///
+/// Records are streamed: each SSTable contributes one decoded block at a time,
+/// so memory stays bounded no matter how large the result is.
+///
+/// The iterator works on a snapshot taken when it was created: a copy of the
+/// MemTable plus `Arc` handles to the SSTables live at that moment. It holds no
+/// engine lock while alive, so writes made afterwards are not observed and
+/// never block on (or get blocked by) an in-progress iteration. SSTables a
+/// compaction replaces are only deleted once the iterator is dropped.
+///
+/// ```
+/// use lsm_kv_store::{LsmConfig, LsmEngine};
+///
+/// # fn main() -> lsm_kv_store::Result<()> {
+/// let dir = tempfile::tempdir()?;
+/// let config = LsmConfig::builder().dir_path(dir.path()).build()?;
+/// let engine = LsmEngine::new(config)?;
+///
+/// engine.set("user:1".to_string(), b"alice".to_vec())?;
+/// engine.set("user:2".to_string(), b"bob".to_vec())?;
+/// engine.set("order:1".to_string(), b"book".to_vec())?;
+///
+/// for kv in engine.iter()? {
+///     let (key, value) = kv?;
+///     println!("{key} = {}", String::from_utf8_lossy(&value));
+/// }
+///
+/// let users: Vec<String> = engine
+///     .iter()?
+///     .filter_map(|kv| kv.ok())
+///     .filter(|(key, _)| key.starts_with("user:"))
+///     .map(|(key, _)| key)
+///     .collect();
+/// assert_eq!(users, vec!["user:1", "user:2"]);
+///
+/// let first = engine.iter()?.take(1).collect::<lsm_kv_store::Result<Vec<_>>>()?;
+/// assert_eq!(first, vec![("order:1".to_string(), b"book".to_vec())]);
+/// # Ok(())
+/// # }
+/// ```
+pub struct KeyValueIterator {
+    inner: MergingIterator<'static>,
+}
+
+impl KeyValueIterator {
+    pub(crate) fn new(
+        sources: Vec<RecordSource<'static>>,
+        direction: Direction,
+        comparator: KeyComparator,
+    ) -> Self {
+        Self {
+            inner: MergingIterator::new(sources, direction, comparator),
+        }
+    }
+
+    /// See [`MergingIterator::with_blob_dir`]
+    pub(crate) fn with_blob_dir(mut self, dir: PathBuf) -> Self {
+        self.inner = self.inner.with_blob_dir(dir);
+        self
+    }
+}
+
+impl Iterator for KeyValueIterator {
+    type Item = Result<(String, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+/// One input of a [`MergingIterator`]: records in the iterator's key order,
+/// one version per key
+pub(crate) type RecordSource<'a> =
+    Box<dyn Iterator<Item = Result<(String, LogRecord)>> + Send + 'a>;
+
+/// Key order a [`MergingIterator`] yields records in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Ascending,
+    Descending,
+}
+
+/// Head of one source, ordered by `(key, source)` with the key comparison
+/// following `comparator` and `direction`
+pub(crate) struct HeapEntry {
+    pub(crate) key: String,
+    pub(crate) source: usize,
+    pub(crate) record: LogRecord,
+    pub(crate) direction: Direction,
+    pub(crate) comparator: KeyComparator,
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let by_key = match self.direction {
+            Direction::Ascending => self
+                .comparator
+                .compare(self.key.as_bytes(), other.key.as_bytes()),
+            Direction::Descending => self
+                .comparator
+                .compare(other.key.as_bytes(), self.key.as_bytes()),
+        };
+        by_key.then(self.source.cmp(&other.source))
+    }
+}
+
+/// K-way merge of sorted record sources into the live key/value pairs, in
+/// ascending or descending key order. Every source must already be sorted in
+/// that direction.
+///
+/// Sources must be given newest first (MemTable, then SSTables in read
+/// order), the same precedence `get` uses: when several sources hold a key,
+/// the version from the lowest-numbered source wins and the others are
+/// skipped. Keys whose winning version is a tombstone or has expired are not
+/// yielded. Only the current head of each source is kept in memory, and a
+/// value stored out of line is only fetched once its version has won.
+pub(crate) struct MergingIterator<'a> {
+    sources: Vec<RecordSource<'a>>,
+    heap: BinaryHeap<Reverse<HeapEntry>>,
+    direction: Direction,
+    comparator: KeyComparator,
+    /// Where the blobs of records arriving with a blob pointer live
+    blob_dir: Option<PathBuf>,
+    /// Error from a source, reported on the next call
+    pending_error: Option<LsmError>,
+    failed: bool,
+}
+
+impl<'a> MergingIterator<'a> {
+    pub(crate) fn new(
+        sources: Vec<RecordSource<'a>>,
+        direction: Direction,
+        comparator: KeyComparator,
+    ) -> Self {
+        let mut iter = Self {
+            sources,
+            heap: BinaryHeap::new(),
+            direction,
+            comparator,
+            blob_dir: None,
+            pending_error: None,
+            failed: false,
+        };
+        for source in 0..iter.sources.len() {
+            iter.advance(source);
+        }
+        iter
+    }
+
+    /// Fetch values of winning records from `dir` when they arrive as blob
+    /// pointers (see [`SstableIter::raw`](crate::storage::reader::SstableIter::raw))
+    pub(crate) fn with_blob_dir(mut self, dir: PathBuf) -> Self {
+        self.blob_dir = Some(dir);
+        self
+    }
+
+    /// Value of a winning record, read from its blob if stored out of line
+    fn value_of(&self, mut record: LogRecord) -> Result<Vec<u8>> {
+        match (record.blob.take(), &self.blob_dir) {
+            (None, _) => Ok(record.value),
+            (Some(blob), Some(dir)) => read_blob(dir, &blob),
+            (Some(blob), None) => Err(LsmError::CorruptedData(format!(
+                "No blob directory to read blob {} from",
+                blob.id
+            ))),
+        }
+    }
+
+    /// Push the next record of `source` onto the heap
+    fn advance(&mut self, source: usize) {
+        match self.sources[source].next() {
+            Some(Ok((key, record))) => self.heap.push(Reverse(HeapEntry {
+                key,
+                source,
+                record,
+                direction: self.direction,
+                comparator: self.comparator,
+            })),
+            Some(Err(e)) => {
+                self.pending_error.get_or_insert(e);
+            }
+            None => {}
+        }
+    }
+}
+
+impl Iterator for MergingIterator<'_> {
+    type Item = Result<(String, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.failed {
+                return None;
+            }
+            if let Some(e) = self.pending_error.take() {
+                self.failed = true;
+                return Some(Err(e));
+            }
+
+            let Reverse(newest) = self.heap.pop()?;
+            self.advance(newest.source);
+
+            // Older versions of the same key sit right behind it in the heap
+            while let Some(Reverse(next)) = self.heap.peek() {
+                if next.key != newest.key {
+                    break;
+                }
+                let source = next.source;
+                self.heap.pop();
+                self.advance(source);
+            }
+
+            if self.pending_error.is_some() {
+                continue;
+            }
+            if newest.record.is_live() {
+                let value = self.value_of(newest.record);
+                self.failed = value.is_err();
+                return Some(value.map(|value| (newest.key, value)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source<'a>(records: Vec<LogRecord>) -> RecordSource<'a> {
+        Box::new(
+            records
+                .into_iter()
+                .map(|record| Ok((record.key.clone(), record))),
+        )
+    }
+
+    fn set(key: &str, value: &str) -> LogRecord {
+        LogRecord::new(key.to_string(), value.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_merge_prefers_earlier_source_and_skips_tombstones() {
+        let newest = source(vec![set("b", "new"), LogRecord::tombstone("c".to_string())]);
+        let older = source(vec![set("a", "old"), set("b", "old"), set("c", "old")]);
+        let oldest = source(vec![set("a", "oldest"), set("d", "oldest")]);
+
+        let merged: Vec<(String, Vec<u8>)> = MergingIterator::new(
+            vec![newest, older, oldest],
+            Direction::Ascending,
+            KeyComparator::Lexicographic,
+        )
+        .collect::<Result<_>>()
+        .unwrap();
+
+        assert_eq!(
+            merged,
+            vec![
+                ("a".to_string(), b"old".to_vec()),
+                ("b".to_string(), b"new".to_vec()),
+                ("d".to_string(), b"oldest".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_descending_merge_prefers_earlier_source() {
+        let newest = source(vec![LogRecord::tombstone("c".to_string()), set("b", "new")]);
+        let older = source(vec![set("c", "old"), set("b", "old"), set("a", "old")]);
+
+        let merged: Vec<(String, Vec<u8>)> = MergingIterator::new(
+            vec![newest, older],
+            Direction::Descending,
+            KeyComparator::Lexicographic,
+        )
+        .collect::<Result<_>>()
+        .unwrap();
+
+        assert_eq!(
+            merged,
+            vec![
+                ("b".to_string(), b"new".to_vec()),
+                ("a".to_string(), b"old".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_reports_source_error_once() {
+        let broken: RecordSource = Box::new(
+            vec![
+                Ok(("a".to_string(), set("a", "1"))),
+                Err(LsmError::CorruptedData("bad block".to_string())),
+            ]
+            .into_iter(),
+        );
+        let mut iter = MergingIterator::new(
+            vec![broken, source(vec![set("b", "2")])],
+            Direction::Ascending,
+            KeyComparator::Lexicographic,
+        );
+
+        assert!(matches!(iter.next(), Some(Err(_))));
+        assert!(iter.next().is_none());
+    }
+}
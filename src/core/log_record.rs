@@ -1,12 +1,71 @@
+use crate::infra::codec::decode_with;
+use crate::infra::config::Codec;
+use crate::infra::error::Result;
+use crate::storage::blob::BlobRef;
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Bytes an encoded record takes beyond its key and value: length prefixes
+/// (8 each), timestamp (16), seq (8), is_deleted (1), a set expires_at (17)
+/// and an unset blob (1; only SSTable records point at a blob)
+pub(crate) const MAX_RECORD_OVERHEAD: usize = 59;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct LogRecord {
     pub key: String,
     pub value: Vec<u8>,
     pub timestamp: u128,
+    /// Engine-wide write sequence number, assigned when the record is applied
+    pub seq: u64,
     pub is_deleted: bool,
+    /// Nanos since the epoch after which the record reads as absent
+    #[serde(default)]
+    pub expires_at: Option<u128>,
+    /// Set in SSTables for a value stored out of line (see
+    /// `StorageConfig::inline_value_max`); `value` is then empty until the
+    /// reader fetches the blob
+    #[serde(default)]
+    pub blob: Option<BlobRef>,
+}
+
+/// Record layout written before `blob` existed
+#[derive(Deserialize)]
+struct LogRecordV2 {
+    key: String,
+    value: Vec<u8>,
+    timestamp: u128,
+    seq: u64,
+    is_deleted: bool,
+    expires_at: Option<u128>,
+}
+
+/// Record layout written before `expires_at` existed. Bincode's fixed layout
+/// has no notion of a missing field, so `#[serde(default)]` alone can't read
+/// these back.
+#[derive(Deserialize)]
+struct LogRecordV1 {
+    key: String,
+    value: Vec<u8>,
+    timestamp: u128,
+    seq: u64,
+    is_deleted: bool,
+}
+
+/// The original record layout, from before sequence numbers; such records
+/// read back with `seq` 0, older than anything written since
+#[derive(Deserialize)]
+struct LogRecordV0 {
+    key: String,
+    value: Vec<u8>,
+    timestamp: u128,
+    is_deleted: bool,
+}
+
+fn now_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
 }
 
 impl LogRecord {
@@ -14,23 +73,201 @@ impl LogRecord {
         Self {
             key,
             value,
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_nanos(),
+            timestamp: now_nanos(),
+            seq: 0,
             is_deleted: false,
+            expires_at: None,
+            blob: None,
         }
     }
 
+    /// A set that expires `ttl` after now
+    pub fn with_ttl(key: String, value: Vec<u8>, ttl: Duration) -> Self {
+        let mut record = Self::new(key, value);
+        record.expires_at = Some(record.timestamp.saturating_add(ttl.as_nanos()));
+        record
+    }
+
     pub fn tombstone(key: String) -> Self {
         Self {
             key,
             value: Vec::new(),
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_nanos(),
+            timestamp: now_nanos(),
+            seq: 0,
             is_deleted: true,
+            expires_at: None,
+            blob: None,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= now_nanos())
+    }
+
+    /// Neither a tombstone nor expired
+    pub fn is_live(&self) -> bool {
+        !self.is_deleted && !self.is_expired()
+    }
+
+    /// Decode a record from the WAL or an SSTable, including ones written
+    /// before `seq`, `expires_at` or `blob` were added
+    pub(crate) fn decode(data: &[u8]) -> Result<Self> {
+        Self::decode_with(Codec::Fixint, data)
+    }
+
+    /// [`decode`](Self::decode) a record written with `codec`
+    pub(crate) fn decode_with(codec: Codec, data: &[u8]) -> Result<Self> {
+        decode_with::<Self>(codec, data)
+            .or_else(|err| -> Result<Self> {
+                let v2: LogRecordV2 = decode_with(codec, data).map_err(|_| err)?;
+                Ok(Self {
+                    key: v2.key,
+                    value: v2.value,
+                    timestamp: v2.timestamp,
+                    seq: v2.seq,
+                    is_deleted: v2.is_deleted,
+                    expires_at: v2.expires_at,
+                    blob: None,
+                })
+            })
+            .or_else(|err| -> Result<Self> {
+                let v1: LogRecordV1 = decode_with(codec, data).map_err(|_| err)?;
+                Ok(Self {
+                    key: v1.key,
+                    value: v1.value,
+                    timestamp: v1.timestamp,
+                    seq: v1.seq,
+                    is_deleted: v1.is_deleted,
+                    expires_at: None,
+                    blob: None,
+                })
+            })
+            .or_else(|err| {
+                let v0: LogRecordV0 = decode_with(codec, data).map_err(|_| err)?;
+                Ok(Self {
+                    key: v0.key,
+                    value: v0.value,
+                    timestamp: v0.timestamp,
+                    seq: 0,
+                    is_deleted: v0.is_deleted,
+                    expires_at: None,
+                    blob: None,
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::codec::{encode, encode_with};
+
+    #[derive(Serialize)]
+    struct V1<'a> {
+        key: &'a str,
+        value: &'a [u8],
+        timestamp: u128,
+        seq: u64,
+        is_deleted: bool,
+    }
+
+    #[test]
+    fn test_decode_reads_records_without_expiry() {
+        let old = encode(&V1 {
+            key: "k",
+            value: b"v",
+            timestamp: 7,
+            seq: 3,
+            is_deleted: false,
+        })
+        .unwrap();
+
+        let record = LogRecord::decode(&old).unwrap();
+        assert_eq!((record.key.as_str(), record.seq), ("k", 3));
+        assert_eq!(record.expires_at, None);
+
+        let current = LogRecord::with_ttl("k".to_string(), b"v".to_vec(), Duration::from_secs(60));
+        assert_eq!(
+            LogRecord::decode(&encode(&current).unwrap()).unwrap(),
+            current
+        );
+        assert!(LogRecord::decode(&old[..old.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_reads_records_without_seq() {
+        // LogRecord::new("wal:0", b"log-0") as the first release encoded it
+        let old: [u8; 43] = [
+            0x05, 0, 0, 0, 0, 0, 0, 0, b'w', b'a', b'l', b':', b'0', 0x05, 0, 0, 0, 0, 0, 0, 0,
+            b'l', b'o', b'g', b'-', b'0', 0x64, 0x00, 0x2a, 0x36, 0xfe, 0x9c, 0x97, 0x17, 0, 0, 0,
+            0, 0, 0, 0, 0, 0,
+        ];
+
+        let record = LogRecord::decode(&old).unwrap();
+        assert_eq!(
+            (record.key.as_str(), record.value.as_slice()),
+            ("wal:0", &b"log-0"[..])
+        );
+        assert_eq!(record.timestamp, 1_700_000_000_000_000_100);
+        assert_eq!((record.seq, record.is_deleted), (0, false));
+        assert!(LogRecord::decode(&old[..old.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_reads_records_without_a_blob() {
+        #[derive(Serialize)]
+        struct V2<'a> {
+            key: &'a str,
+            value: &'a [u8],
+            timestamp: u128,
+            seq: u64,
+            is_deleted: bool,
+            expires_at: Option<u128>,
+        }
+
+        for codec in [Codec::Fixint, Codec::Varint] {
+            let old = encode_with(
+                codec,
+                &V2 {
+                    key: "k",
+                    value: b"v",
+                    timestamp: 7,
+                    seq: 3,
+                    is_deleted: false,
+                    expires_at: Some(9),
+                },
+            )
+            .unwrap();
+
+            let record = LogRecord::decode_with(codec, &old).unwrap();
+            assert_eq!(
+                (record.value.as_slice(), record.expires_at),
+                (&b"v"[..], Some(9))
+            );
+            assert_eq!(record.blob, None);
         }
     }
+
+    #[test]
+    fn test_varint_records_are_smaller() {
+        let record = LogRecord::with_ttl("k".to_string(), b"v".to_vec(), Duration::from_secs(60));
+        let fixint = encode_with(Codec::Fixint, &record).unwrap();
+        let varint = encode_with(Codec::Varint, &record).unwrap();
+
+        assert!(varint.len() < fixint.len());
+        assert_eq!(
+            LogRecord::decode_with(Codec::Varint, &varint).unwrap(),
+            record
+        );
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut record = LogRecord::with_ttl("k".to_string(), Vec::new(), Duration::from_secs(60));
+        assert!(record.is_live());
+        record.expires_at = Some(record.timestamp);
+        assert!(record.is_expired());
+        assert!(!record.is_live());
+    }
 }
@@ -1,51 +1,582 @@
+use crate::core::iterator::Direction;
 use crate::core::log_record::LogRecord;
-use std::collections::BTreeMap;
+use crate::infra::config::{KeyComparator, MemTableImpl};
+use crossbeam_skiplist::SkipMap;
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
 
+/// Approximate footprint of one superseded-version entry
+const HISTORY_ENTRY_SIZE: usize = 16;
+
+/// Fixed part of a record's encoding (`infra::codec`, fixint): key and value
+/// length prefixes (8 each), timestamp (16), seq (8), is_deleted (1), the
+/// expires_at tag (1) and the blob tag (1; MemTable records never hold one)
+const RECORD_FIXED_SIZE: usize = 43;
+
+/// Encoded size of a set `expires_at`, on top of its tag
+const EXPIRY_SIZE: usize = 16;
+
+/// In-memory write buffer. Every method takes `&self`, so the engine can
+/// insert under a shared lock; how much actually runs in parallel depends on
+/// the [`MemTableImpl`].
 pub struct MemTable {
-    pub(crate) data: BTreeMap<String, LogRecord>,
-    pub(crate) size_bytes: usize,
+    entries: Entries,
+    /// Accounted bytes: live versions plus `HISTORY_ENTRY_SIZE` per
+    /// superseded one
+    size_bytes: AtomicUsize,
     pub(crate) max_size_bytes: usize,
+    /// Order of `range`; the maps themselves are always in byte order
+    comparator: KeyComparator,
+}
+
+enum Entries {
+    /// Readers share the lock, every insert takes it exclusively
+    BTree(RwLock<BTreeMap<String, Versions>>),
+    /// Lock-free skip list; an insert only locks the slot of its own key, so
+    /// writers of different keys never wait on each other
+    SkipList(Box<SkipMap<String, Mutex<Versions>>>),
+}
+
+/// Latest version of one key plus `(seq, is_deleted)` of the versions it
+/// superseded, so point-in-time queries can see past the latest one
+#[derive(Default)]
+struct Versions {
+    /// `None` only while a skip list slot is being filled in
+    latest: Option<LogRecord>,
+    history: Vec<(u64, bool)>,
+}
+
+impl Versions {
+    /// Apply `record`, unless a newer version of the key is already here
+    /// (concurrent writers may arrive out of sequence order). Returns the
+    /// accounted bytes `(added, removed)`.
+    fn apply(&mut self, record: LogRecord) -> (usize, usize) {
+        if let Some(existing) = &self.latest {
+            if existing.seq > record.seq {
+                self.history.push((record.seq, record.is_deleted));
+                return (HISTORY_ENTRY_SIZE, 0);
+            }
+        }
+
+        let added = MemTable::estimate_size(&record);
+        match self.latest.replace(record) {
+            Some(old) => {
+                self.history.push((old.seq, old.is_deleted));
+                (added + HISTORY_ENTRY_SIZE, MemTable::estimate_size(&old))
+            }
+            None => (added, 0),
+        }
+    }
+
+    fn visible_at(&self, seq: u64) -> Option<bool> {
+        let latest = self.latest.as_ref()?;
+
+        std::iter::once((latest.seq, latest.is_deleted))
+            .chain(self.history.iter().copied())
+            .filter(|(version_seq, _)| *version_seq <= seq)
+            .max_by_key(|(version_seq, _)| *version_seq)
+            .map(|(_, is_deleted)| !is_deleted)
+    }
+}
+
+// A panic can't leave a map or slot half-updated (each mutation is a single
+// insert or push), so a poisoned lock still guards consistent data.
+fn lock(slot: &Mutex<Versions>) -> std::sync::MutexGuard<'_, Versions> {
+    slot.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
 impl MemTable {
     pub fn new(max_size_bytes: usize) -> Self {
+        Self::with_impl(max_size_bytes, MemTableImpl::default())
+    }
+
+    pub fn with_impl(max_size_bytes: usize, memtable_impl: MemTableImpl) -> Self {
+        let entries = match memtable_impl {
+            MemTableImpl::BTree => Entries::BTree(RwLock::new(BTreeMap::new())),
+            MemTableImpl::SkipList => Entries::SkipList(Box::new(SkipMap::new())),
+        };
         Self {
-            data: BTreeMap::new(),
-            size_bytes: 0,
+            entries,
+            size_bytes: AtomicUsize::new(0),
             max_size_bytes,
+            comparator: KeyComparator::default(),
         }
     }
 
-    pub fn insert(&mut self, record: LogRecord) {
-        let record_size = Self::estimate_size(&record);
-        if let Some(old_record) = self.data.insert(record.key.clone(), record) {
-            self.size_bytes = self
-                .size_bytes
-                .saturating_sub(Self::estimate_size(&old_record));
+    /// Yield `range` and `iter_ordered` in `comparator` order
+    pub fn with_comparator(mut self, comparator: KeyComparator) -> Self {
+        self.comparator = comparator;
+        self
+    }
+
+    /// An empty MemTable with the same implementation, size limit and
+    /// comparator
+    pub fn empty_like(&self) -> Self {
+        let memtable_impl = match self.entries {
+            Entries::BTree(_) => MemTableImpl::BTree,
+            Entries::SkipList(_) => MemTableImpl::SkipList,
+        };
+        Self::with_impl(self.max_size_bytes, memtable_impl).with_comparator(self.comparator)
+    }
+
+    /// Insert `record`, unless the MemTable already holds a newer version of
+    /// the key (concurrent writers may arrive out of sequence order).
+    pub fn insert(&self, record: LogRecord) {
+        let (added, removed) = match &self.entries {
+            Entries::BTree(map) => map
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entry(record.key.clone())
+                .or_default()
+                .apply(record),
+            Entries::SkipList(map) => {
+                let slot = map.get_or_insert_with(record.key.clone(), Default::default);
+                let mut versions = lock(slot.value());
+                versions.apply(record)
+            }
+        };
+        // Added first: what gets removed was added by an earlier insert, so
+        // the counter never dips below zero
+        self.size_bytes.fetch_add(added, Ordering::Relaxed);
+        self.size_bytes.fetch_sub(removed, Ordering::Relaxed);
+    }
+
+    /// Whether the newest version of `key` with sequence `<= seq` is live.
+    ///
+    /// `None` means no such version is in the MemTable and older storage has
+    /// to be consulted.
+    pub fn visible_at(&self, key: &str, seq: u64) -> Option<bool> {
+        match &self.entries {
+            Entries::BTree(map) => map
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(key)?
+                .visible_at(seq),
+            Entries::SkipList(map) => lock(map.get(key)?.value()).visible_at(seq),
         }
-        self.size_bytes += record_size;
     }
 
     pub fn should_flush(&self) -> bool {
-        self.size_bytes >= self.max_size_bytes
+        self.size_bytes() >= self.max_size_bytes
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.size_bytes.load(Ordering::Relaxed)
     }
 
     pub fn get(&self, key: &str) -> Option<LogRecord> {
-        self.data.get(key).cloned()
+        match &self.entries {
+            Entries::BTree(map) => map
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(key)?
+                .latest
+                .clone(),
+            Entries::SkipList(map) => lock(map.get(key)?.value()).latest.clone(),
+        }
     }
 
-    pub fn iter_ordered(&self) -> impl Iterator<Item = (&String, &LogRecord)> {
-        self.data.iter()
+    /// Whether the newest version of `key` is live, without copying its
+    /// value. `None` when the key isn't in this MemTable.
+    pub fn is_live(&self, key: &str) -> Option<bool> {
+        match &self.entries {
+            Entries::BTree(map) => map
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(key)?
+                .latest
+                .as_ref()
+                .map(LogRecord::is_live),
+            Entries::SkipList(map) => lock(map.get(key)?.value())
+                .latest
+                .as_ref()
+                .map(LogRecord::is_live),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.entries {
+            Entries::BTree(map) => map.read().unwrap_or_else(PoisonError::into_inner).len(),
+            Entries::SkipList(map) => map.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Snapshot of the latest version of every key, in key order
+    pub fn iter_ordered(&self) -> impl Iterator<Item = (String, LogRecord)> {
+        self.range(
+            Bound::Unbounded,
+            Bound::<&[u8]>::Unbounded,
+            Direction::Ascending,
+        )
+        .into_iter()
     }
 
-    pub fn clear(&mut self) -> usize {
-        let count = self.data.len();
-        self.data.clear();
-        self.size_bytes = 0;
+    /// Snapshot of the latest versions with keys in `[start, end]`, in
+    /// `direction` order. The upper bound is raw bytes like
+    /// `SstableReader::range`.
+    pub(crate) fn range(
+        &self,
+        start: Bound<&str>,
+        end: Bound<&[u8]>,
+        direction: Direction,
+    ) -> Vec<(String, LogRecord)> {
+        // Other orders can't seek in the byte-ordered map, so they filter
+        // all of it
+        let lexicographic = self.comparator == KeyComparator::Lexicographic;
+        let bounds = (
+            if lexicographic {
+                start
+            } else {
+                Bound::Unbounded
+            },
+            Bound::Unbounded,
+        );
+        match &self.entries {
+            Entries::BTree(map) => {
+                let map = map.read().unwrap_or_else(PoisonError::into_inner);
+                let entries = map
+                    .range::<str, _>(bounds)
+                    .filter_map(|(key, versions)| Some((key.clone(), versions.latest.clone()?)));
+                self.collect_in_range(entries, start, end, direction)
+            }
+            Entries::SkipList(map) => {
+                let entries = map.range::<str, _>(bounds).filter_map(|entry| {
+                    let latest = lock(entry.value()).latest.clone()?;
+                    Some((entry.key().clone(), latest))
+                });
+                self.collect_in_range(entries, start, end, direction)
+            }
+        }
+    }
+
+    /// Byte order: take entries starting at the lower bound until the first
+    /// one past `end` (ascending), or those up to `end` from the top down
+    /// (descending). Other orders: keep the entries inside the bounds and
+    /// sort them.
+    fn collect_in_range(
+        &self,
+        entries: impl DoubleEndedIterator<Item = (String, LogRecord)>,
+        start: Bound<&str>,
+        end: Bound<&[u8]>,
+        direction: Direction,
+    ) -> Vec<(String, LogRecord)> {
+        let cmp = self.comparator;
+        if cmp == KeyComparator::Lexicographic {
+            return match direction {
+                Direction::Ascending => entries
+                    .take_while(|(key, _)| !cmp.after_end(key.as_bytes(), end))
+                    .collect(),
+                Direction::Descending => entries
+                    .rev()
+                    .skip_while(|(key, _)| cmp.after_end(key.as_bytes(), end))
+                    .collect(),
+            };
+        }
+
+        let start = start.map(str::as_bytes);
+        let mut in_range: Vec<_> = entries
+            .filter(|(key, _)| {
+                !cmp.before_start(key.as_bytes(), start) && !cmp.after_end(key.as_bytes(), end)
+            })
+            .collect();
+        in_range.sort_by(|(a, _), (b, _)| cmp.compare(a.as_bytes(), b.as_bytes()));
+        if direction == Direction::Descending {
+            in_range.reverse();
+        }
+        in_range
+    }
+
+    pub fn clear(&self) -> usize {
+        let count = self.len();
+        match &self.entries {
+            Entries::BTree(map) => map.write().unwrap_or_else(PoisonError::into_inner).clear(),
+            Entries::SkipList(map) => map.clear(),
+        }
+        self.size_bytes.store(0, Ordering::Relaxed);
         count
     }
 
+    /// The record's encoded size (what it costs in the WAL and, before
+    /// compression, in an SSTable) plus the copy of the key the map holds
     fn estimate_size(record: &LogRecord) -> usize {
-        record.key.len() + record.value.len() + 32
+        let expiry = if record.expires_at.is_some() {
+            EXPIRY_SIZE
+        } else {
+            0
+        };
+        record.key.len() * 2 + record.value.len() + RECORD_FIXED_SIZE + expiry
+    }
+}
+
+/// A MemTable that no longer takes writes and is waiting to become an SSTable
+pub struct FrozenMemTable {
+    pub(crate) table: Arc<MemTable>,
+    /// First WAL segment written after the freeze; the segments before it
+    /// hold nothing newer than this table
+    pub(crate) next_wal_segment: u64,
+}
+
+/// The active MemTable plus the frozen ones waiting to be flushed.
+///
+/// Lookups go active first, then frozen newest to oldest: every record in a
+/// newer table has a higher sequence number than any in an older one.
+pub struct MemTables {
+    pub(crate) active: MemTable,
+    /// Newest first; the flusher takes from the back
+    pub(crate) frozen: VecDeque<FrozenMemTable>,
+}
+
+impl MemTables {
+    pub fn new(active: MemTable) -> Self {
+        Self {
+            active,
+            frozen: VecDeque::new(),
+        }
+    }
+
+    /// Every table, newest first
+    pub fn tables(&self) -> impl Iterator<Item = &MemTable> {
+        std::iter::once(&self.active).chain(self.frozen.iter().map(|frozen| &*frozen.table))
+    }
+
+    pub fn get(&self, key: &str) -> Option<LogRecord> {
+        self.tables().find_map(|table| table.get(key))
+    }
+
+    /// See [`MemTable::visible_at`]
+    pub fn visible_at(&self, key: &str, seq: u64) -> Option<bool> {
+        self.tables().find_map(|table| table.visible_at(key, seq))
+    }
+
+    /// See [`MemTable::is_live`]
+    pub fn is_live(&self, key: &str) -> Option<bool> {
+        self.tables().find_map(|table| table.is_live(key))
+    }
+
+    /// Records across all tables (a key in several tables counts once per table)
+    pub fn len(&self) -> usize {
+        self.tables().map(MemTable::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables().all(MemTable::is_empty)
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.tables().map(MemTable::size_bytes).sum()
+    }
+
+    /// Every table, newest first, as handles that later writes can't change:
+    /// the frozen tables themselves and a copy of the active one
+    pub fn snapshot(&self) -> Vec<Arc<MemTable>> {
+        let copy = self.active.empty_like();
+        for (_, record) in self.active.iter_ordered() {
+            copy.insert(record);
+        }
+        std::iter::once(Arc::new(copy))
+            .chain(self.frozen.iter().map(|frozen| Arc::clone(&frozen.table)))
+            .collect()
+    }
+
+    /// Swap in an empty active MemTable and queue the old one for flushing
+    pub fn freeze(&mut self, next_wal_segment: u64) {
+        let fresh = self.active.empty_like();
+        let table = std::mem::replace(&mut self.active, fresh);
+        self.frozen.push_front(FrozenMemTable {
+            table: Arc::new(table),
+            next_wal_segment,
+        });
+    }
+
+    /// The frozen table to flush next and the WAL segment it is covered up to
+    pub fn oldest_frozen(&self) -> Option<(Arc<MemTable>, u64)> {
+        self.frozen
+            .back()
+            .map(|frozen| (Arc::clone(&frozen.table), frozen.next_wal_segment))
+    }
+
+    /// Drop the oldest frozen table once its SSTable is installed
+    pub fn remove_oldest_frozen(&mut self) {
+        self.frozen.pop_back();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(key: &str, seq: u64, is_deleted: bool) -> LogRecord {
+        let mut record = if is_deleted {
+            LogRecord::tombstone(key.to_string())
+        } else {
+            LogRecord::new(key.to_string(), format!("v{seq}").into_bytes())
+        };
+        record.seq = seq;
+        record
+    }
+
+    #[test]
+    fn test_insert_keeps_newer_seq() {
+        for memtable_impl in [MemTableImpl::BTree, MemTableImpl::SkipList] {
+            let memtable = MemTable::with_impl(1024, memtable_impl);
+
+            memtable.insert(record("k", 20, false));
+            memtable.insert(record("k", 10, false));
+
+            assert_eq!(memtable.get("k").unwrap().value, b"v20");
+            assert_eq!(memtable.visible_at("k", 15), Some(true));
+            assert_eq!(memtable.visible_at("k", 5), None);
+            assert_eq!(
+                memtable.size_bytes(),
+                MemTable::estimate_size(&record("k", 20, false)) + HISTORY_ENTRY_SIZE
+            );
+        }
+    }
+
+    #[test]
+    fn test_size_tracks_overwrites_and_tombstones() {
+        use crate::infra::codec::encode;
+
+        // Ground truth: encoded size plus map key for every live version,
+        // one history entry per superseded one
+        let expected = |latest: &[&LogRecord], superseded: usize| -> usize {
+            latest
+                .iter()
+                .map(|record| encode(record).unwrap().len() + record.key.len())
+                .sum::<usize>()
+                + superseded * HISTORY_ENTRY_SIZE
+        };
+
+        for memtable_impl in [MemTableImpl::BTree, MemTableImpl::SkipList] {
+            let memtable = MemTable::with_impl(1024 * 1024, memtable_impl);
+            let other = record("other", 1, false);
+            memtable.insert(other.clone());
+
+            let mut small = LogRecord::new("key".to_string(), vec![b'a'; 10]);
+            small.seq = 2;
+            memtable.insert(small.clone());
+            assert_eq!(memtable.size_bytes(), expected(&[&other, &small], 0));
+
+            let mut large = LogRecord::new("key".to_string(), vec![b'b'; 1000]);
+            large.seq = 3;
+            memtable.insert(large.clone());
+            assert_eq!(memtable.size_bytes(), expected(&[&other, &large], 1));
+
+            let tombstone = record("key", 4, true);
+            memtable.insert(tombstone.clone());
+            assert_eq!(memtable.size_bytes(), expected(&[&other, &tombstone], 2));
+        }
+    }
+
+    #[test]
+    fn test_visible_at() {
+        for memtable_impl in [MemTableImpl::BTree, MemTableImpl::SkipList] {
+            let memtable = MemTable::with_impl(1024, memtable_impl);
+            memtable.insert(record("k", 3, false));
+            memtable.insert(record("k", 5, true));
+            memtable.insert(record("k", 8, false));
+
+            assert_eq!(memtable.visible_at("k", 2), None);
+            assert_eq!(memtable.visible_at("k", 3), Some(true));
+            assert_eq!(memtable.visible_at("k", 6), Some(false));
+            assert_eq!(memtable.visible_at("k", 100), Some(true));
+            assert_eq!(memtable.visible_at("other", 100), None);
+        }
+    }
+
+    #[test]
+    fn test_is_live() {
+        for memtable_impl in [MemTableImpl::BTree, MemTableImpl::SkipList] {
+            let memtable = MemTable::with_impl(1024, memtable_impl);
+            memtable.insert(record("set", 1, false));
+            memtable.insert(record("deleted", 2, false));
+            memtable.insert(record("deleted", 3, true));
+            let mut expired =
+                LogRecord::with_ttl("expired".to_string(), vec![], std::time::Duration::ZERO);
+            expired.seq = 4;
+            memtable.insert(expired);
+
+            assert_eq!(memtable.is_live("set"), Some(true));
+            assert_eq!(memtable.is_live("deleted"), Some(false));
+            assert_eq!(memtable.is_live("expired"), Some(false));
+            assert_eq!(memtable.is_live("other"), None);
+        }
+    }
+
+    #[test]
+    fn test_skiplist_matches_btree_under_concurrent_inserts() {
+        const THREADS: u64 = 4;
+        const PER_THREAD: u64 = 2_000;
+
+        let fill = |memtable_impl| {
+            let memtable = MemTable::with_impl(usize::MAX, memtable_impl);
+            std::thread::scope(|scope| {
+                for t in 0..THREADS {
+                    let memtable = &memtable;
+                    scope.spawn(move || {
+                        for i in 0..PER_THREAD {
+                            // Threads share keys and race on them out of seq order
+                            let seq = i * THREADS + t;
+                            let key = format!("key{:05}", (i * 7 + t * 13) % 1_500);
+                            memtable.insert(record(&key, seq, seq.is_multiple_of(5)));
+                        }
+                    });
+                }
+            });
+            memtable
+        };
+
+        let btree = fill(MemTableImpl::BTree);
+        let skiplist = fill(MemTableImpl::SkipList);
+
+        let ordered: Vec<(String, LogRecord)> = skiplist.iter_ordered().collect();
+        assert!(ordered.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(ordered.len(), 1_500);
+
+        let summary = |memtable: &MemTable| -> Vec<(String, u64, bool)> {
+            memtable
+                .iter_ordered()
+                .map(|(key, record)| (key, record.seq, record.is_deleted))
+                .collect()
+        };
+        assert_eq!(summary(&skiplist), summary(&btree));
+        assert_eq!(skiplist.size_bytes(), btree.size_bytes());
+        assert_eq!(skiplist.len(), btree.len());
+        for (key, _) in &ordered {
+            assert_eq!(
+                skiplist.visible_at(key, 4_000),
+                btree.visible_at(key, 4_000)
+            );
+        }
+    }
+
+    #[test]
+    fn test_frozen_tables_are_read_newest_first() {
+        let mut memtables = MemTables::new(MemTable::new(1024));
+        memtables.active.insert(record("a", 1, false));
+        memtables.active.insert(record("b", 2, false));
+        memtables.freeze(1);
+
+        memtables.active.insert(record("a", 3, true));
+        memtables.freeze(2);
+        memtables.active.insert(record("c", 4, false));
+
+        assert_eq!(memtables.frozen.len(), 2);
+        assert!(memtables.get("a").unwrap().is_deleted);
+        assert_eq!(memtables.get("b").unwrap().value, b"v2");
+        assert_eq!(memtables.visible_at("a", 2), Some(true));
+        assert_eq!(memtables.visible_at("a", 3), Some(false));
+        assert_eq!(memtables.len(), 4);
+
+        assert_eq!(memtables.oldest_frozen().unwrap().1, 1);
+        memtables.remove_oldest_frozen();
+        assert_eq!(memtables.oldest_frozen().unwrap().1, 2);
+        assert!(memtables.get("b").is_none());
     }
 }
@@ -1,3 +1,9 @@
+pub mod batch;
+pub mod compaction;
+pub mod dump;
 pub mod engine;
+pub mod iterator;
 pub mod memtable;
 pub mod log_record;
+pub mod snapshot;
+pub mod subscription;
@@ -0,0 +1,79 @@
+use crate::core::engine::{is_empty_range, merge_sources};
+use crate::core::iterator::Direction;
+use crate::core::memtable::MemTable;
+use crate::infra::config::KeyComparator;
+use crate::infra::error::Result;
+use crate::storage::reader::SstableReader;
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Point-in-time, read-only view of an [`LsmEngine`](crate::LsmEngine),
+/// taken by [`LsmEngine::snapshot`](crate::LsmEngine::snapshot).
+///
+/// Holds a copy of the active MemTable plus `Arc` handles to the frozen
+/// MemTables and SSTables live when it was taken, so later writes, flushes
+/// and compactions don't change what it returns. SSTables replaced by a
+/// compaction stay on disk until the last snapshot (or iterator) using them
+/// is dropped, so keep snapshots short-lived.
+///
+/// Expiry is still checked against the current time when reading.
+pub struct Snapshot {
+    /// Newest first
+    pub(crate) memtables: Vec<Arc<MemTable>>,
+    /// In read order
+    pub(crate) sstables: Vec<Arc<SstableReader>>,
+    pub(crate) seq: u64,
+    pub(crate) comparator: KeyComparator,
+}
+
+impl Snapshot {
+    /// Sequence number of the last write visible in the snapshot
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let mut record = self.memtables.iter().find_map(|table| table.get(key));
+        if record.is_none() {
+            for sst in &self.sstables {
+                record = sst.get(key)?;
+                if record.is_some() {
+                    break;
+                }
+            }
+        }
+
+        Ok(record
+            .filter(|record| record.is_live())
+            .map(|record| record.value))
+    }
+
+    /// Live key/value pairs with keys between `start` and `end`, in ascending
+    /// key order, as of the snapshot (see [`LsmEngine::range`](crate::LsmEngine::range))
+    pub fn range(
+        &self,
+        start: Bound<String>,
+        end: Bound<String>,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let start = start.as_ref().map(String::as_str);
+        let end = end.as_ref().map(|key| key.as_bytes());
+        if is_empty_range(self.comparator, start.map(str::as_bytes), end) {
+            return Ok(Vec::new());
+        }
+
+        let memtable_records = self
+            .memtables
+            .iter()
+            .map(|memtable| memtable.range(start, end, Direction::Ascending))
+            .collect();
+        merge_sources(
+            memtable_records,
+            &self.sstables,
+            start,
+            end,
+            Direction::Ascending,
+            self.comparator,
+        )?
+        .collect()
+    }
+}
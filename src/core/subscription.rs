@@ -0,0 +1,264 @@
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender, TryRecvError, TrySendError};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+/// Kind of mutation reported to subscribers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Set,
+    Delete,
+}
+
+/// A single write observed by a subscription
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    pub key: String,
+    pub kind: ChangeKind,
+    /// Sequence number of the write (see `LsmEngine::last_seq`)
+    pub seq: u64,
+}
+
+/// What a subscription does when its channel is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued event to make room. Writers never wait, but a
+    /// slow subscriber silently misses events (visible as gaps in `seq`).
+    #[default]
+    DropOldest,
+    /// Block the writer until the subscriber catches up. No event is lost, but
+    /// a stalled subscriber stalls every write that matches its prefix.
+    Block,
+}
+
+struct Subscriber {
+    id: u64,
+    prefix: String,
+    sender: Sender<ChangeEvent>,
+    /// Extra handle on the channel so `DropOldest` can evict queued events
+    evictor: Option<Receiver<ChangeEvent>>,
+}
+
+/// Registry of live subscriptions, shared between the engine and the
+/// [`Subscription`] handles it hands out.
+pub(crate) struct SubscriberRegistry {
+    next_id: AtomicU64,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl SubscriberRegistry {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            next_id: AtomicU64::new(0),
+            subscribers: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub(crate) fn subscribe(
+        self: &Arc<Self>,
+        prefix: &str,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> Subscription {
+        let (sender, receiver) = bounded(capacity.max(1));
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        let evictor = match policy {
+            OverflowPolicy::DropOldest => Some(receiver.clone()),
+            OverflowPolicy::Block => None,
+        };
+
+        self.lock().push(Subscriber {
+            id,
+            prefix: prefix.to_string(),
+            sender,
+            evictor,
+        });
+
+        Subscription {
+            id,
+            receiver,
+            registry: Arc::downgrade(self),
+        }
+    }
+
+    /// Deliver a change to every subscriber whose prefix matches `key`
+    pub(crate) fn notify(&self, key: &str, kind: ChangeKind, seq: u64) {
+        // Blocking sends happen outside the registry lock so a stalled
+        // subscriber can still be dropped (which unblocks the send).
+        let mut blocking = Vec::new();
+        {
+            let subscribers = self.lock();
+            if subscribers.is_empty() {
+                return;
+            }
+
+            for sub in subscribers.iter().filter(|s| key.starts_with(&s.prefix)) {
+                let event = ChangeEvent {
+                    key: key.to_string(),
+                    kind,
+                    seq,
+                };
+                match &sub.evictor {
+                    Some(evictor) => Self::send_dropping_oldest(&sub.sender, evictor, event),
+                    None => blocking.push((sub.sender.clone(), event)),
+                }
+            }
+        }
+
+        for (sender, event) in blocking {
+            // A disconnected receiver just means the subscription went away
+            let _ = sender.send(event);
+        }
+    }
+
+    fn send_dropping_oldest(
+        sender: &Sender<ChangeEvent>,
+        evictor: &Receiver<ChangeEvent>,
+        mut event: ChangeEvent,
+    ) {
+        loop {
+            match sender.try_send(event) {
+                Ok(()) | Err(TrySendError::Disconnected(_)) => return,
+                Err(TrySendError::Full(rejected)) => {
+                    let _ = evictor.try_recv();
+                    event = rejected;
+                }
+            }
+        }
+    }
+
+    fn unsubscribe(&self, id: u64) {
+        self.lock().retain(|s| s.id != id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Subscriber>> {
+        // A panic while holding this lock can't leave the Vec inconsistent
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.lock().len()
+    }
+}
+
+/// Receiving end of [`LsmEngine::subscribe`](crate::LsmEngine::subscribe).
+///
+/// Dropping the handle unregisters it from the engine.
+pub struct Subscription {
+    id: u64,
+    receiver: Receiver<ChangeEvent>,
+    registry: Weak<SubscriberRegistry>,
+}
+
+impl Subscription {
+    /// Block until the next event arrives
+    pub fn recv(&self) -> Option<ChangeEvent> {
+        self.receiver.recv().ok()
+    }
+
+    /// Wait up to `timeout` for the next event
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<ChangeEvent, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+
+    /// Return the next queued event without waiting
+    pub fn try_recv(&self) -> Option<ChangeEvent> {
+        match self.receiver.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+
+    /// Underlying channel, for use with `crossbeam_channel::select!`
+    pub fn receiver(&self) -> &Receiver<ChangeEvent> {
+        &self.receiver
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(registry) = self.registry.upgrade() {
+            registry.unsubscribe(self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_filtering() {
+        let registry = SubscriberRegistry::new();
+        let sub = registry.subscribe("user:", 16, OverflowPolicy::DropOldest);
+
+        registry.notify("user:1", ChangeKind::Set, 1);
+        registry.notify("order:1", ChangeKind::Set, 2);
+        registry.notify("user:2", ChangeKind::Delete, 3);
+
+        let first = sub.try_recv().unwrap();
+        assert_eq!(first.key, "user:1");
+        assert_eq!(first.kind, ChangeKind::Set);
+        let second = sub.try_recv().unwrap();
+        assert_eq!(second.key, "user:2");
+        assert_eq!(second.kind, ChangeKind::Delete);
+        assert!(second.seq > first.seq);
+        assert!(sub.try_recv().is_none());
+    }
+
+    #[test]
+    fn test_drop_oldest_keeps_latest_events() {
+        let registry = SubscriberRegistry::new();
+        let sub = registry.subscribe("", 2, OverflowPolicy::DropOldest);
+
+        for key in ["a", "b", "c", "d"] {
+            registry.notify(key, ChangeKind::Set, 1);
+        }
+
+        assert_eq!(sub.try_recv().unwrap().key, "c");
+        assert_eq!(sub.try_recv().unwrap().key, "d");
+        assert!(sub.try_recv().is_none());
+    }
+
+    #[test]
+    fn test_block_policy_waits_for_consumer() {
+        let registry = SubscriberRegistry::new();
+        let sub = registry.subscribe("", 1, OverflowPolicy::Block);
+
+        let writer = {
+            let registry = Arc::clone(&registry);
+            std::thread::spawn(move || {
+                for key in ["a", "b", "c"] {
+                    registry.notify(key, ChangeKind::Set, 1);
+                }
+            })
+        };
+
+        let keys: Vec<String> = (0..3).map(|_| sub.recv().unwrap().key).collect();
+        writer.join().unwrap();
+        assert_eq!(keys, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_drop_unregisters() {
+        let registry = SubscriberRegistry::new();
+        let sub = registry.subscribe("", 4, OverflowPolicy::Block);
+        assert_eq!(registry.len(), 1);
+
+        drop(sub);
+        assert_eq!(registry.len(), 0);
+
+        // Nobody listening: must not block
+        registry.notify("k", ChangeKind::Set, 1);
+    }
+}
@@ -1,6 +1,8 @@
+use crossbeam_channel::{unbounded, Receiver, Sender};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
 
 use crate::core::engine::LsmEngine;
@@ -11,6 +13,25 @@ pub struct FeatureFlag {
     pub enabled: bool,
     #[serde(default)]
     pub description: String,
+    /// Share of identities, 0–100, that [`FeatureClient::is_enabled_for`]
+    /// turns the flag on for; `None` means everyone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout_percent: Option<u8>,
+}
+
+impl FeatureFlag {
+    /// Bucket in 0..100 for `identity` under `flag_name`.
+    ///
+    /// CRC32 rather than the std hasher so buckets stay the same across
+    /// processes and Rust releases; the flag name is mixed in so the same
+    /// users are not always the first to get every rollout.
+    pub fn bucket(flag_name: &str, identity: &str) -> u8 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(flag_name.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(identity.as_bytes());
+        (hasher.finalize() % 100) as u8
+    }
 }
 
 /// Container de todas as feature flags
@@ -22,128 +43,515 @@ pub struct Features {
     pub flags: HashMap<String, FeatureFlag>,
 }
 
+/// Names of the stored flags, kept under [`FeatureClient::INDEX_KEY`].
+///
+/// `version` moves only when a flag is added or removed, so updating an
+/// existing flag never touches the index.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct FeatureIndex {
+    #[serde(default)]
+    version: u64,
+    #[serde(default)]
+    names: BTreeSet<String>,
+}
+
+/// Cached flags by name; `None` records that the flag is absent.
+type FlagCache = HashMap<String, (Option<FeatureFlag>, Instant)>;
+
 /// Cliente para gerenciar feature flags com cache em memória
+///
+/// Each flag lives under its own `feature:<name>` key, so updates to
+/// different flags write different keys and never contend with each other.
 pub struct FeatureClient {
     engine: Arc<LsmEngine>,
-    cache: Arc<RwLock<Option<(Features, Instant)>>>,
+    cache: RwLock<FlagCache>,
     cache_ttl: Duration,
+    migrated: AtomicBool,
+    /// Senders handed out by [`watch`](Self::watch), by flag name
+    watchers: Mutex<HashMap<String, Vec<Sender<bool>>>>,
 }
 
 impl FeatureClient {
-    const KEY: &'static str = "feature:all";
+    const PREFIX: &'static str = "feature:";
+    const INDEX_KEY: &'static str = "feature:__index";
+    /// Where every flag used to be stored as a single [`Features`] blob.
+    const LEGACY_KEY: &'static str = "feature:all";
+    const MAX_INDEX_ATTEMPTS: usize = 16;
 
     pub fn new(engine: Arc<LsmEngine>, cache_ttl: Duration) -> Self {
         Self {
             engine,
-            cache: Arc::new(RwLock::new(None)),
+            cache: RwLock::new(HashMap::new()),
             cache_ttl,
+            migrated: AtomicBool::new(false),
+            watchers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn flag_key(flag_name: &str) -> String {
+        format!("{}{}", Self::PREFIX, flag_name)
+    }
+
+    fn read_json<T: serde::de::DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.engine.get(key)? {
+            Some(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| LsmError::DeserializationFailed(e.to_string())),
+            None => Ok(None),
         }
     }
 
-    fn load_features(&self) -> Result<Features> {
+    fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(|e| LsmError::SerializationFailed(e.to_string()))
+    }
+
+    /// Split a store written before per-flag keys into one key per flag.
+    ///
+    /// Runs once per client; the index is created with compare-and-swap, so
+    /// when two clients migrate at once only one of them writes it.
+    fn ensure_migrated(&self) -> Result<()> {
+        if self.migrated.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        if let Some(legacy) = self.read_json::<Features>(Self::LEGACY_KEY)? {
+            if self.engine.get(Self::INDEX_KEY)?.is_none() {
+                for (name, flag) in &legacy.flags {
+                    self.engine
+                        .set(Self::flag_key(name), Self::to_json(flag)?)?;
+                }
+                let index = FeatureIndex {
+                    version: legacy.version,
+                    names: legacy.flags.keys().cloned().collect(),
+                };
+                self.engine
+                    .compare_and_swap(Self::INDEX_KEY, None, Self::to_json(&index)?)?;
+            }
+            self.engine.delete(Self::LEGACY_KEY.to_string())?;
+        }
+
+        self.migrated.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn load_index(&self) -> Result<(Option<Vec<u8>>, FeatureIndex)> {
+        let raw = self.engine.get(Self::INDEX_KEY)?;
+        let index = match &raw {
+            Some(bytes) => serde_json::from_slice(bytes)
+                .map_err(|e| LsmError::DeserializationFailed(e.to_string()))?,
+            None => FeatureIndex::default(),
+        };
+        Ok((raw, index))
+    }
+
+    /// Apply `change` to the index with compare-and-swap, retrying when
+    /// another writer got there first. `change` returns whether it modified
+    /// the index; nothing is written when it did not.
+    fn update_index(&self, change: impl Fn(&mut FeatureIndex) -> bool) -> Result<()> {
+        for _ in 0..Self::MAX_INDEX_ATTEMPTS {
+            let (raw, mut index) = self.load_index()?;
+            if !change(&mut index) {
+                return Ok(());
+            }
+            index.version += 1;
+
+            let json = Self::to_json(&index)?;
+            if self
+                .engine
+                .compare_and_swap(Self::INDEX_KEY, raw.as_deref(), json)?
+            {
+                return Ok(());
+            }
+        }
+
+        Err(LsmError::ConcurrentModification)
+    }
+
+    fn load_flag(&self, flag_name: &str) -> Result<Option<FeatureFlag>> {
         {
             let cache = self.cache.read().unwrap();
-            if let Some((features, timestamp)) = cache.as_ref() {
+            if let Some((flag, timestamp)) = cache.get(flag_name) {
                 if timestamp.elapsed() < self.cache_ttl {
-                    return Ok(features.clone());
+                    return Ok(flag.clone());
                 }
             }
         }
 
-        let bytes_vec = match self.engine.get(Self::KEY)? {
-            Some(v) => v,
-            None => {
-                let features = Features::default();
-                let json = serde_json::to_vec(&features)
-                    .map_err(|e| LsmError::SerializationFailed(e.to_string()))?;
-                self.engine.set(Self::KEY.to_string(), json)?;
-                return Ok(features);
-            }
-        };
-
-        let features: Features = serde_json::from_slice(&bytes_vec)
-            .map_err(|e| LsmError::DeserializationFailed(e.to_string()))?;
+        let flag: Option<FeatureFlag> = self.read_json(&Self::flag_key(flag_name))?;
 
         let mut cache = self.cache.write().unwrap();
-        *cache = Some((features.clone(), Instant::now()));
+        cache.insert(flag_name.to_string(), (flag.clone(), Instant::now()));
 
-        Ok(features)
+        Ok(flag)
     }
 
-    fn invalidate_cache(&self) {
+    fn invalidate_cache(&self, flag_name: &str) {
         let mut cache = self.cache.write().unwrap();
-        *cache = None;
+        cache.remove(flag_name);
+    }
+
+    /// Send `enabled` to every watcher of `flag_name`, forgetting those
+    /// whose receiver has been dropped.
+    fn notify(&self, flag_name: &str, enabled: bool) {
+        let mut watchers = self.watchers.lock().unwrap();
+        if let Some(senders) = watchers.get_mut(flag_name) {
+            senders.retain(|sender| sender.send(enabled).is_ok());
+            if senders.is_empty() {
+                watchers.remove(flag_name);
+            }
+        }
     }
 
+    /// Receive the flag's new value every time a write through this client
+    /// turns it on or off. Removing a flag sends `false`.
+    ///
+    /// Only changes made after the call are reported, and only those made
+    /// through this `FeatureClient`; dropping the receiver unregisters it.
+    pub fn watch(&self, flag_name: &str) -> Receiver<bool> {
+        let (sender, receiver) = unbounded();
+        self.watchers
+            .lock()
+            .unwrap()
+            .entry(flag_name.to_string())
+            .or_default()
+            .push(sender);
+        receiver
+    }
+
+    /// Reject names whose key would collide with the index or legacy blob.
+    fn check_name(flag_name: &str) -> Result<()> {
+        let key = Self::flag_key(flag_name);
+        if key == Self::INDEX_KEY || key == Self::LEGACY_KEY {
+            return Err(LsmError::ValidationRejected(format!(
+                "'{}' is a reserved feature flag name",
+                flag_name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether the flag is globally on, whatever its rollout percentage.
     pub fn is_enabled(&self, flag_name: &str) -> Result<bool> {
-        let features = self.load_features()?;
-        Ok(features
-            .flags
-            .get(flag_name)
-            .map(|f| f.enabled)
-            .unwrap_or(false))
+        Ok(self.get_flag(flag_name)?.is_some_and(|f| f.enabled))
+    }
+
+    /// Whether the flag is on for `identity`: it must be globally enabled
+    /// and, when it has a rollout percentage, `identity` must hash into the
+    /// rolled-out buckets. The answer for a given identity only changes when
+    /// the flag does.
+    pub fn is_enabled_for(&self, flag_name: &str, identity: &str) -> Result<bool> {
+        Ok(match self.get_flag(flag_name)? {
+            Some(flag) if flag.enabled => match flag.rollout_percent {
+                Some(percent) => FeatureFlag::bucket(flag_name, identity) < percent,
+                None => true,
+            },
+            _ => false,
+        })
     }
 
+    /// Read a single flag without touching the others.
+    pub fn get_flag(&self, flag_name: &str) -> Result<Option<FeatureFlag>> {
+        self.ensure_migrated()?;
+        if Self::check_name(flag_name).is_err() {
+            return Ok(None);
+        }
+        self.load_flag(flag_name)
+    }
+
+    /// Every flag named in the index. Names whose flag is missing (a writer
+    /// stopped between the two writes) are skipped.
     pub fn list_all(&self) -> Result<Features> {
-        self.load_features()
+        self.ensure_migrated()?;
+        let (_, index) = self.load_index()?;
+
+        let mut flags = HashMap::with_capacity(index.names.len());
+        for name in index.names {
+            if let Some(flag) = self.load_flag(&name)? {
+                flags.insert(name, flag);
+            }
+        }
+
+        Ok(Features {
+            version: index.version,
+            flags,
+        })
     }
 
+    /// Turn a flag on or off, keeping its rollout percentage and, when
+    /// `description` is `None`, its description.
     pub fn set_flag(
         &self,
         flag_name: String,
         enabled: bool,
         description: Option<String>,
     ) -> Result<()> {
-        for attempt in 0..5 {
-            let mut features = self.load_features()?;
-
-            features
-                .flags
-                .entry(flag_name.clone())
-                .and_modify(|f| {
-                    f.enabled = enabled;
-                    if let Some(desc) = &description {
-                        f.description = desc.clone();
-                    }
-                })
-                .or_insert(FeatureFlag {
-                    enabled,
-                    description: description.clone().unwrap_or_default(),
-                });
-
-            features.version += 1;
+        Self::check_name(&flag_name)?;
+        self.ensure_migrated()?;
 
-            let json = serde_json::to_vec(&features)
-                .map_err(|e| LsmError::SerializationFailed(e.to_string()))?;
+        let existing: Option<FeatureFlag> = self.read_json(&Self::flag_key(&flag_name))?;
+        let (old_description, rollout_percent) = match existing {
+            Some(flag) => (flag.description, flag.rollout_percent),
+            None => (String::new(), None),
+        };
+        let flag = FeatureFlag {
+            enabled,
+            description: description.unwrap_or(old_description),
+            rollout_percent,
+        };
+        self.put_flag(flag_name, flag)
+    }
 
-            match self.engine.set(Self::KEY.to_string(), json) {
-                Ok(_) => {
-                    self.invalidate_cache();
-                    return Ok(());
-                }
-                Err(_) if attempt < 4 => {
-                    std::thread::sleep(Duration::from_millis(10 * 2u64.pow(attempt)));
-                    continue;
-                }
-                Err(e) => return Err(e),
-            }
+    /// Write one flag as given. The index is only rewritten when the flag
+    /// is new.
+    pub fn put_flag(&self, flag_name: String, flag: FeatureFlag) -> Result<()> {
+        Self::check_name(&flag_name)?;
+        if flag.rollout_percent.is_some_and(|p| p > 100) {
+            return Err(LsmError::ValidationRejected(format!(
+                "rollout_percent for '{}' must be between 0 and 100",
+                flag_name
+            )));
         }
+        self.ensure_migrated()?;
 
-        Err(LsmError::ConcurrentModification)
+        let key = Self::flag_key(&flag_name);
+        let was_enabled = self
+            .read_json::<FeatureFlag>(&key)?
+            .is_some_and(|f| f.enabled);
+        // Flag first, then index: a crash in between leaves a flag that
+        // `is_enabled` sees and the next `set_flag` lists, never a listed
+        // name with nothing behind it
+        self.engine.set(key, Self::to_json(&flag)?)?;
+        self.invalidate_cache(&flag_name);
+        if flag.enabled != was_enabled {
+            self.notify(&flag_name, flag.enabled);
+        }
+        self.update_index(|index| index.names.insert(flag_name.clone()))
     }
 
     pub fn remove_flag(&self, flag_name: &str) -> Result<bool> {
-        let mut features = self.load_features()?;
-        let removed = features.flags.remove(flag_name).is_some();
+        self.ensure_migrated()?;
+        if Self::check_name(flag_name).is_err() {
+            return Ok(false);
+        }
 
+        let key = Self::flag_key(flag_name);
+        let removed = self.engine.get(&key)?.is_some();
         if removed {
-            features.version += 1;
-            let json = serde_json::to_vec(&features)
-                .map_err(|e| LsmError::SerializationFailed(e.to_string()))?;
-            self.engine.set(Self::KEY.to_string(), json)?;
-            self.invalidate_cache();
+            self.engine.delete(key)?;
+            self.invalidate_cache(flag_name);
+            self.notify(flag_name, false);
         }
+        self.update_index(|index| index.names.remove(flag_name))?;
 
         Ok(removed)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::config::LsmConfig;
+    use tempfile::tempdir;
+
+    fn client(dir: &std::path::Path) -> (Arc<LsmEngine>, FeatureClient) {
+        let config = LsmConfig::builder()
+            .dir_path(dir.to_path_buf())
+            .build()
+            .unwrap();
+        let engine = Arc::new(LsmEngine::new(config).unwrap());
+        let client = FeatureClient::new(Arc::clone(&engine), Duration::ZERO);
+        (engine, client)
+    }
+
+    #[test]
+    fn test_each_flag_is_stored_under_its_own_key() {
+        let dir = tempdir().unwrap();
+        let (engine, client) = client(dir.path());
+
+        client
+            .set_flag("beta".into(), true, Some("new UI".into()))
+            .unwrap();
+        client.set_flag("dark".into(), false, None).unwrap();
+        client.set_flag("beta".into(), false, None).unwrap();
+
+        assert!(engine.get("feature:beta").unwrap().is_some());
+        assert!(engine.get("feature:dark").unwrap().is_some());
+        assert!(engine.get("feature:all").unwrap().is_none());
+        assert!(!client.is_enabled("beta").unwrap());
+        assert_eq!(
+            client.get_flag("beta").unwrap().unwrap().description,
+            "new UI"
+        );
+
+        let all = client.list_all().unwrap();
+        assert_eq!(all.flags.len(), 2);
+        // Only the two additions moved the index
+        assert_eq!(all.version, 2);
+
+        assert!(client.remove_flag("dark").unwrap());
+        assert!(!client.remove_flag("dark").unwrap());
+        let all = client.list_all().unwrap();
+        assert_eq!(all.flags.keys().collect::<Vec<_>>(), ["beta"]);
+        assert_eq!(all.version, 3);
+        assert!(client.set_flag("__index".into(), true, None).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_updates_to_different_flags_do_not_touch_the_index() {
+        let dir = tempdir().unwrap();
+        let (engine, client) = client(dir.path());
+        let client = Arc::new(client);
+        client.set_flag("a".into(), false, None).unwrap();
+        client.set_flag("b".into(), false, None).unwrap();
+        let index_before = engine.get(FeatureClient::INDEX_KEY).unwrap();
+
+        let handles: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|name| {
+                let client = Arc::clone(&client);
+                std::thread::spawn(move || {
+                    for i in 0..200 {
+                        client.set_flag(name.into(), i % 2 == 0, None).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(engine.get(FeatureClient::INDEX_KEY).unwrap(), index_before);
+        let all = client.list_all().unwrap();
+        assert_eq!(all.flags.len(), 2);
+        assert!(all.flags.values().all(|f| !f.enabled));
+    }
+
+    #[test]
+    fn test_concurrent_new_flags_all_reach_the_index() {
+        let dir = tempdir().unwrap();
+        let (_engine, client) = client(dir.path());
+        let client = Arc::new(client);
+
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let client = Arc::clone(&client);
+                std::thread::spawn(move || {
+                    for i in 0..10 {
+                        client.set_flag(format!("f{t}_{i}"), true, None).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let all = client.list_all().unwrap();
+        assert_eq!(all.flags.len(), 80);
+        assert_eq!(all.version, 80);
+    }
+
+    #[test]
+    fn test_rollout_buckets_are_stable_and_proportional() {
+        let dir = tempdir().unwrap();
+        let (_engine, client) = client(dir.path());
+        let rollout = |percent| FeatureFlag {
+            enabled: true,
+            description: String::new(),
+            rollout_percent: Some(percent),
+        };
+        client.put_flag("checkout".into(), rollout(25)).unwrap();
+
+        let users: Vec<String> = (0..10_000).map(|i| format!("user-{i}")).collect();
+        let enabled: Vec<bool> = users
+            .iter()
+            .map(|u| client.is_enabled_for("checkout", u).unwrap())
+            .collect();
+        let share = enabled.iter().filter(|&&on| on).count();
+        assert!((2_250..=2_750).contains(&share), "{share} of 10000 enabled");
+
+        // Same answer on every call, and growing the rollout only adds users
+        for (user, &on) in users.iter().zip(&enabled) {
+            assert_eq!(client.is_enabled_for("checkout", user).unwrap(), on);
+        }
+        client.put_flag("checkout".into(), rollout(50)).unwrap();
+        for (user, &on) in users.iter().zip(&enabled) {
+            assert!(!on || client.is_enabled_for("checkout", user).unwrap());
+        }
+
+        // Toggling keeps the percentage; turning it off wins over it
+        client.set_flag("checkout".into(), false, None).unwrap();
+        assert_eq!(
+            client
+                .get_flag("checkout")
+                .unwrap()
+                .unwrap()
+                .rollout_percent,
+            Some(50)
+        );
+        assert!(!client.is_enabled("checkout").unwrap());
+        assert!(users
+            .iter()
+            .all(|u| !client.is_enabled_for("checkout", u).unwrap()));
+
+        client.put_flag("all".into(), rollout(0)).unwrap_err();
+        client.put_flag("wide".into(), rollout(101)).unwrap_err();
+        client.set_flag("plain".into(), true, None).unwrap();
+        assert!(client.is_enabled_for("plain", "anyone").unwrap());
+    }
+
+    #[test]
+    fn test_watch_receives_each_change() {
+        let dir = tempdir().unwrap();
+        let (_engine, client) = client(dir.path());
+        let beta = client.watch("beta");
+        let other = client.watch("other");
+
+        client.set_flag("beta".into(), true, None).unwrap();
+        client
+            .set_flag("beta".into(), true, Some("same value".into()))
+            .unwrap();
+        client.set_flag("beta".into(), false, None).unwrap();
+        client.set_flag("beta".into(), true, None).unwrap();
+        client.remove_flag("beta").unwrap();
+        client.remove_flag("beta").unwrap();
+
+        assert_eq!(
+            beta.try_iter().collect::<Vec<_>>(),
+            [true, false, true, false]
+        );
+        assert!(other.try_recv().is_err());
+
+        // A dropped receiver is forgotten on the next change
+        drop(beta);
+        client.set_flag("beta".into(), true, None).unwrap();
+        assert!(client.watchers.lock().unwrap().get("beta").is_none());
+    }
+
+    #[test]
+    fn test_legacy_blob_is_split_into_per_flag_keys() {
+        let dir = tempdir().unwrap();
+        let (engine, client) = client(dir.path());
+        let mut legacy = Features {
+            version: 7,
+            ..Features::default()
+        };
+        legacy.flags.insert(
+            "beta".into(),
+            FeatureFlag {
+                enabled: true,
+                description: "old".into(),
+                rollout_percent: None,
+            },
+        );
+        engine
+            .set("feature:all".into(), serde_json::to_vec(&legacy).unwrap())
+            .unwrap();
+
+        assert!(client.is_enabled("beta").unwrap());
+        assert!(engine.get("feature:all").unwrap().is_none());
+        let all = client.list_all().unwrap();
+        assert_eq!(all.version, 7);
+        assert_eq!(all.flags["beta"].description, "old");
+    }
+}
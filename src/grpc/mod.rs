@@ -0,0 +1,198 @@
+//! gRPC front end (`proto/lsm.proto`) for typed clients.
+//!
+//! Unary `Get`/`Set`/`Delete` map straight onto the engine. `Scan` and
+//! `Watch` are server streams fed from a thread each, so a large scan is sent
+//! as it is read instead of being collected first.
+
+pub mod proto;
+
+use crate::core::engine::LsmEngine;
+use crate::core::iterator::Direction;
+use crate::core::subscription::{ChangeKind, OverflowPolicy, Subscription};
+use crate::infra::error::LsmError;
+use crossbeam_channel::RecvTimeoutError;
+use proto::lsm_server::{Lsm, LsmServer};
+use proto::{
+    change_event, ChangeEvent, DeleteRequest, DeleteResponse, GetRequest, GetResponse, Record,
+    ScanRequest, SetRequest, SetResponse, WatchRequest,
+};
+use std::io;
+use std::net::SocketAddr;
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status};
+
+/// Records or events buffered per stream before the reading thread waits
+const STREAM_BUFFER: usize = 64;
+/// Events buffered per `Watch` before the oldest are dropped
+const WATCH_CAPACITY: usize = 1024;
+/// How often an idle `Watch` checks whether its client is still there
+const WATCH_POLL: Duration = Duration::from_secs(1);
+
+/// TCP listener serving the `lsm.v1.Lsm` service for one engine
+pub struct GrpcServer {
+    listener: TcpListener,
+    engine: Arc<LsmEngine>,
+}
+
+impl GrpcServer {
+    pub async fn bind(addr: impl ToSocketAddrs, engine: Arc<LsmEngine>) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            engine,
+        })
+    }
+
+    /// Address actually bound, e.g. to find the port picked for `:0`
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve until the listener fails
+    pub async fn run(self) -> Result<(), tonic::transport::Error> {
+        tonic::transport::Server::builder()
+            .add_service(LsmServer::new(LsmService {
+                engine: self.engine,
+            }))
+            .serve_with_incoming(TcpListenerStream::new(self.listener))
+            .await
+    }
+}
+
+struct LsmService {
+    engine: Arc<LsmEngine>,
+}
+
+fn to_status(e: LsmError) -> Status {
+    match e {
+        LsmError::ValidationRejected(_) | LsmError::ValueTooLarge(_) => {
+            Status::invalid_argument(e.to_string())
+        }
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+#[tonic::async_trait]
+impl Lsm for LsmService {
+    async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        let value = self
+            .engine
+            .get(&request.into_inner().key)
+            .map_err(to_status)?;
+        Ok(Response::new(GetResponse {
+            found: value.is_some(),
+            value: value.unwrap_or_default(),
+        }))
+    }
+
+    async fn set(&self, request: Request<SetRequest>) -> Result<Response<SetResponse>, Status> {
+        let SetRequest { key, value, ttl_ms } = request.into_inner();
+        let result = match ttl_ms {
+            0 => self.engine.set(key, value),
+            ms => self
+                .engine
+                .set_with_ttl(key, value, Duration::from_millis(ms)),
+        };
+        result.map_err(to_status)?;
+        Ok(Response::new(SetResponse {}))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        self.engine
+            .delete(request.into_inner().key)
+            .map_err(to_status)?;
+        Ok(Response::new(DeleteResponse {}))
+    }
+
+    type ScanStream = ReceiverStream<Result<Record, Status>>;
+
+    async fn scan(
+        &self,
+        request: Request<ScanRequest>,
+    ) -> Result<Response<Self::ScanStream>, Status> {
+        let ScanRequest {
+            start_key,
+            end_key,
+            limit,
+        } = request.into_inner();
+        let start = match start_key.as_str() {
+            "" => Bound::Unbounded,
+            key => Bound::Included(key),
+        };
+        let end = match end_key.as_bytes() {
+            [] => Bound::Unbounded,
+            key => Bound::Excluded(key),
+        };
+        let limit = match usize::try_from(limit) {
+            Ok(0) | Err(_) => usize::MAX,
+            Ok(n) => n,
+        };
+        let records = self
+            .engine
+            .merged_iter(start, end, Direction::Ascending)
+            .map_err(to_status)?;
+
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        std::thread::spawn(move || {
+            for record in records.take(limit) {
+                let item = record
+                    .map(|(key, value)| Record { key, value })
+                    .map_err(to_status);
+                let failed = item.is_err();
+                // Stops early once the client has gone away
+                if tx.blocking_send(item).is_err() || failed {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    type WatchStream = ReceiverStream<Result<ChangeEvent, Status>>;
+
+    async fn watch(
+        &self,
+        request: Request<WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let subscription = self.engine.subscribe(
+            &request.into_inner().prefix,
+            WATCH_CAPACITY,
+            OverflowPolicy::DropOldest,
+        );
+
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        std::thread::spawn(move || forward_events(subscription, tx));
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+/// Feed a blocking subscription into a `Watch` stream until the client goes
+/// away
+fn forward_events(subscription: Subscription, tx: mpsc::Sender<Result<ChangeEvent, Status>>) {
+    loop {
+        let event = match subscription.recv_timeout(WATCH_POLL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) if !tx.is_closed() => continue,
+            Err(_) => return,
+        };
+        let kind = match event.kind {
+            ChangeKind::Set => change_event::Kind::Set,
+            ChangeKind::Delete => change_event::Kind::Delete,
+        };
+        let event = ChangeEvent {
+            key: event.key,
+            kind: kind as i32,
+            seq: event.seq,
+        };
+        if tx.blocking_send(Ok(event)).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Messages of `proto/lsm.proto` (package `lsm.v1`) and the generated
+//! `lsm_client` / `lsm_server` stubs. Field tags must match the `.proto` file.
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetRequest {
+    #[prost(string, tag = "1")]
+    pub key: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetResponse {
+    #[prost(bool, tag = "1")]
+    pub found: bool,
+    #[prost(bytes = "vec", tag = "2")]
+    pub value: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SetRequest {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub value: Vec<u8>,
+    /// Expire the value after this many milliseconds; 0 keeps it forever
+    #[prost(uint64, tag = "3")]
+    pub ttl_ms: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SetResponse {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DeleteRequest {
+    #[prost(string, tag = "1")]
+    pub key: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DeleteResponse {}
+
+/// Keys in `[start_key, end_key)`; an empty bound is open
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScanRequest {
+    #[prost(string, tag = "1")]
+    pub start_key: String,
+    #[prost(string, tag = "2")]
+    pub end_key: String,
+    /// At most this many records; 0 means no limit
+    #[prost(uint64, tag = "3")]
+    pub limit: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Record {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub value: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WatchRequest {
+    #[prost(string, tag = "1")]
+    pub prefix: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChangeEvent {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(enumeration = "change_event::Kind", tag = "2")]
+    pub kind: i32,
+    #[prost(uint64, tag = "3")]
+    pub seq: u64,
+}
+
+pub mod change_event {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Kind {
+        Set = 0,
+        Delete = 1,
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/lsm.v1.Lsm.rs"));
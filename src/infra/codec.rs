@@ -1,3 +1,4 @@
+use crate::infra::config::Codec;
 use crate::infra::error::Result; // Import corrigido
 use bincode::Options;
 use serde::{de::DeserializeOwned, Serialize};
@@ -8,6 +9,12 @@ fn opts() -> impl Options {
         .with_little_endian()
 }
 
+fn varint_opts() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_varint_encoding()
+        .with_little_endian()
+}
+
 pub fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
     Ok(opts().serialize(value)?)
 }
@@ -16,3 +23,37 @@ pub fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
     // CORREÇÃO: Especificamos o tipo de fallback para bincode
     Ok(opts().deserialize::<T>(data)?)
 }
+
+/// Like [`encode`], with the given record codec
+pub fn encode_with<T: Serialize>(codec: Codec, value: &T) -> Result<Vec<u8>> {
+    match codec {
+        Codec::Fixint => encode(value),
+        Codec::Varint => Ok(varint_opts().serialize(value)?),
+    }
+}
+
+/// Like [`decode`], with the given record codec
+pub fn decode_with<T: DeserializeOwned>(codec: Codec, data: &[u8]) -> Result<T> {
+    match codec {
+        Codec::Fixint => decode(data),
+        Codec::Varint => Ok(varint_opts().deserialize::<T>(data)?),
+    }
+}
+
+impl Codec {
+    /// Byte identifying the codec in WAL segment and SSTable headers
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Codec::Fixint => 0,
+            Codec::Varint => 1,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Codec::Fixint),
+            1 => Some(Codec::Varint),
+            _ => None,
+        }
+    }
+}
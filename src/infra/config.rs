@@ -1,6 +1,13 @@
+use crate::core::log_record::MAX_RECORD_OVERHEAD;
 use crate::infra::error::{LsmError, Result};
+pub use crate::storage::config::{
+    Codec, CompactionStrategy, Compression, IoMode, KeyComparator, MemTableImpl, WalSyncPolicy,
+};
+use crate::storage::wal::MAX_WAL_RECORD_BYTES;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct LsmConfig {
@@ -14,6 +21,33 @@ pub struct LsmConfig {
 pub struct CoreConfig {
     pub dir_path: PathBuf,
     pub memtable_max_size: usize,
+    /// Re-read every freshly flushed SSTable and compare it against the MemTable
+    /// before the WAL is cleared
+    #[serde(default)]
+    pub verify_after_flush: bool,
+    /// Batch WAL writes arriving within this many microseconds into one
+    /// fsync (0 = fsync every write on its own)
+    #[serde(default)]
+    pub wal_group_commit_us: u64,
+    /// WAL segments roll over to a new file once they reach this many bytes
+    #[serde(default = "default_wal_segment_size")]
+    pub wal_segment_size: usize,
+    /// Flush the MemTable once the WAL behind it reaches this many bytes, even
+    /// if the MemTable itself is still small (overwrites of the same keys grow
+    /// the log but not the map)
+    #[serde(default = "default_wal_max_size")]
+    pub wal_max_size: usize,
+    /// Durability of acknowledged writes versus fsync cost
+    #[serde(default)]
+    pub wal_sync_policy: WalSyncPolicy,
+}
+
+fn default_wal_segment_size() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_wal_max_size() -> usize {
+    256 * 1024 * 1024
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +56,103 @@ pub struct StorageConfig {
     pub block_cache_size_mb: usize,
     pub sparse_index_interval: usize,
     pub bloom_false_positive_rate: f64,
+    /// Hash keys of new Bloom filters; a random one per filter when unset.
+    /// Fixing it makes identical input produce byte-identical SSTables. Each
+    /// filter carries its seed, so tables stay readable whatever this is.
+    #[serde(default)]
+    pub bloom_seed: Option<[u8; 32]>,
+    /// Pad every block on disk to a 4096-byte boundary (groundwork for O_DIRECT reads)
+    #[serde(default)]
+    pub align_blocks: bool,
+    /// SSTables with fewer keys than this are written without a Bloom filter
+    #[serde(default)]
+    pub min_keys_for_bloom: usize,
+    /// When set, SSTables record the distinct key prefixes up to this separator
+    /// so `list_namespaces` doesn't need a full scan
+    #[serde(default)]
+    pub namespace_separator: Option<char>,
+    /// Values larger than this many bytes get a block of their own that is
+    /// stored without compression (for pre-compressed blobs)
+    #[serde(default)]
+    pub no_compress_value_threshold: Option<usize>,
+    /// Values larger than this many bytes are written to their own file under
+    /// `blobs/` when flushed, leaving only a pointer in the SSTable, so
+    /// compaction copies the pointer instead of the value
+    #[serde(default)]
+    pub inline_value_max: Option<usize>,
+    /// Write SSTables in the V4 format, whose block index is binary-searched on
+    /// disk instead of being loaded when the table is opened
+    #[serde(default)]
+    pub lazy_block_index: bool,
+    /// Give every data block its own Bloom filter so `get` can skip a block
+    /// the table-level filter let through, at the cost of extra index space
+    #[serde(default)]
+    pub per_block_bloom: bool,
+    /// Blocks read into the block cache when the engine opens, newest tables
+    /// first, so the first reads after a restart don't all go to disk
+    #[serde(default)]
+    pub preload_blocks: usize,
+    /// Number of keys `get` remembers as absent after a miss, answering the
+    /// next lookup without consulting the SSTables; 0 turns this off
+    #[serde(default)]
+    pub negative_cache_size: usize,
+    #[serde(default)]
+    pub key_comparator: KeyComparator,
+    #[serde(default)]
+    pub io_mode: IoMode,
+    #[serde(default)]
+    pub memtable_impl: MemTableImpl,
+    #[serde(default)]
+    pub compaction_strategy: CompactionStrategy,
+    /// Number of L0 tables that triggers a leveled compaction
+    #[serde(default = "default_level0_compaction_threshold")]
+    pub level0_compaction_threshold: usize,
+    /// Leveled compaction starts a new output table once this many bytes
+    /// have been written to the current one
+    #[serde(default = "default_target_file_size")]
+    pub target_file_size: usize,
+    /// Flushes, compactions and ingests start a new table once this many
+    /// bytes have been written to the current one, so a large MemTable
+    /// becomes several non-overlapping SSTables. Also caps
+    /// `target_file_size`; unlimited when unset.
+    #[serde(default)]
+    pub max_sstable_size: Option<usize>,
+    /// Longest key `set`/`delete` accept, in bytes
+    #[serde(default = "default_max_key_size")]
+    pub max_key_size: usize,
+    /// Largest value `set` accepts, in bytes
+    #[serde(default = "default_max_value_size")]
+    pub max_value_size: usize,
+    /// Encoding of records in newly written WAL segments and SSTables
+    #[serde(default)]
+    pub codec: Codec,
+    /// Compression of data blocks in newly written SSTables
+    #[serde(default)]
+    pub compression: Compression,
+    /// fsync the data directory after an SSTable or WAL segment is created or
+    /// deleted. Turn off where directories can't be fsynced.
+    #[serde(default = "default_sync_directory")]
+    pub sync_directory: bool,
+}
+
+fn default_level0_compaction_threshold() -> usize {
+    4
+}
+
+fn default_target_file_size() -> usize {
+    2 * 1024 * 1024
+}
+
+fn default_max_key_size() -> usize {
+    1024
+}
+
+fn default_max_value_size() -> usize {
+    60 * 1024
+}
+
+fn default_sync_directory() -> bool {
+    true
 }
 
 impl Default for CoreConfig {
@@ -29,6 +160,11 @@ impl Default for CoreConfig {
         Self {
             dir_path: PathBuf::from("./.lsmdata"),
             memtable_max_size: 4 * 1024 * 1024,
+            verify_after_flush: false,
+            wal_group_commit_us: 0,
+            wal_segment_size: default_wal_segment_size(),
+            wal_max_size: default_wal_max_size(),
+            wal_sync_policy: WalSyncPolicy::default(),
         }
     }
 }
@@ -40,6 +176,28 @@ impl Default for StorageConfig {
             block_cache_size_mb: 64,
             sparse_index_interval: 16,
             bloom_false_positive_rate: 0.01,
+            bloom_seed: None,
+            align_blocks: false,
+            min_keys_for_bloom: 0,
+            namespace_separator: None,
+            no_compress_value_threshold: None,
+            inline_value_max: None,
+            lazy_block_index: false,
+            per_block_bloom: false,
+            preload_blocks: 0,
+            negative_cache_size: 0,
+            key_comparator: KeyComparator::default(),
+            io_mode: IoMode::default(),
+            memtable_impl: MemTableImpl::default(),
+            compaction_strategy: CompactionStrategy::default(),
+            level0_compaction_threshold: default_level0_compaction_threshold(),
+            target_file_size: default_target_file_size(),
+            max_sstable_size: None,
+            max_key_size: default_max_key_size(),
+            max_value_size: default_max_value_size(),
+            codec: Codec::default(),
+            compression: Compression::default(),
+            sync_directory: default_sync_directory(),
         }
     }
 }
@@ -53,6 +211,28 @@ impl LsmConfig {
         LsmConfigBuilder::default()
     }
 
+    /// Read a TOML file with optional `[core]` and `[storage]` sections and
+    /// validate it. A missing section takes the defaults; within a section,
+    /// fields without a default are required.
+    pub fn from_toml_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let config: Self = toml::from_str(&text).map_err(|e| {
+            let line = e
+                .span()
+                .map_or(0, |span| text[..span.start].matches('\n').count() + 1);
+            LsmError::ConfigValidation(format!(
+                "{}:{}: {}",
+                path.display(),
+                line,
+                e.message()
+            ))
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
     /// Validate all configuration parameters
     pub fn validate(&self) -> Result<()> {
         self.core.validate()?;
@@ -83,6 +263,36 @@ impl CoreConfig {
             ));
         }
 
+        if self.wal_group_commit_us > 1_000_000 {
+            return Err(LsmError::ConfigValidation(
+                "wal_group_commit_us too large (maximum 1s)".to_string(),
+            ));
+        }
+
+        if self.wal_segment_size < 4096 {
+            return Err(LsmError::ConfigValidation(
+                "wal_segment_size too small (minimum 4KB)".to_string(),
+            ));
+        }
+
+        if self.wal_max_size < 4096 {
+            return Err(LsmError::ConfigValidation(
+                "wal_max_size too small (minimum 4KB)".to_string(),
+            ));
+        }
+
+        if self.wal_sync_policy == WalSyncPolicy::Interval(Duration::ZERO) {
+            return Err(LsmError::ConfigValidation(
+                "wal_sync_policy interval cannot be 0".to_string(),
+            ));
+        }
+
+        if self.wal_group_commit_us > 0 && self.wal_sync_policy != WalSyncPolicy::Always {
+            return Err(LsmError::ConfigValidation(
+                "wal_group_commit_us only applies to wal_sync_policy Always".to_string(),
+            ));
+        }
+
         Ok(())
     }
 }
@@ -151,18 +361,118 @@ impl StorageConfig {
             );
         }
 
+        if self.io_mode == IoMode::Mmap && !cfg!(feature = "mmap") {
+            return Err(LsmError::ConfigValidation(
+                "io_mode Mmap requires building with the `mmap` feature".to_string(),
+            ));
+        }
+
+        if self.level0_compaction_threshold < 2 {
+            return Err(LsmError::ConfigValidation(
+                "level0_compaction_threshold must be at least 2".to_string(),
+            ));
+        }
+
+        if self.target_file_size < self.block_size {
+            return Err(LsmError::ConfigValidation(
+                "target_file_size cannot be smaller than block_size".to_string(),
+            ));
+        }
+
+        if self
+            .max_sstable_size
+            .is_some_and(|max| max < self.block_size)
+        {
+            return Err(LsmError::ConfigValidation(
+                "max_sstable_size cannot be smaller than block_size".to_string(),
+            ));
+        }
+
+        if self.max_key_size == 0 || self.max_value_size == 0 {
+            return Err(LsmError::ConfigValidation(
+                "max_key_size and max_value_size must be greater than 0".to_string(),
+            ));
+        }
+        // Block entries store keys behind a u16 length
+        if self.max_key_size > u16::MAX as usize {
+            return Err(LsmError::ConfigValidation(format!(
+                "max_key_size cannot exceed {} bytes",
+                u16::MAX
+            )));
+        }
+        if self.max_key_size + self.max_value_size + MAX_RECORD_OVERHEAD > MAX_WAL_RECORD_BYTES {
+            return Err(LsmError::ConfigValidation(format!(
+                "max_key_size + max_value_size cannot exceed {} bytes",
+                MAX_WAL_RECORD_BYTES - MAX_RECORD_OVERHEAD
+            )));
+        }
+
         Ok(())
     }
+
+    /// Reject a key or value over `max_key_size` / `max_value_size` with
+    /// [`LsmError::ValueTooLarge`]
+    pub fn check_entry_size(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        if key.len() > self.max_key_size {
+            return Err(LsmError::ValueTooLarge(format!(
+                "key of {} bytes exceeds max_key_size ({})",
+                key.len(),
+                self.max_key_size
+            )));
+        }
+        if value.len() > self.max_value_size {
+            return Err(LsmError::ValueTooLarge(format!(
+                "value of {} bytes exceeds max_value_size ({})",
+                value.len(),
+                self.max_value_size
+            )));
+        }
+        Ok(())
+    }
+
+    /// Size at which a table being written is cut off: `target` capped by
+    /// `max_sstable_size`
+    pub(crate) fn table_size_limit(&self, target: u64) -> u64 {
+        self.max_sstable_size
+            .map_or(target, |max| target.min(max as u64))
+    }
 }
 
 #[derive(Default)]
 pub struct LsmConfigBuilder {
     dir_path: Option<PathBuf>,
     memtable_max_size: Option<usize>,
+    verify_after_flush: Option<bool>,
+    wal_group_commit_us: Option<u64>,
+    wal_segment_size: Option<usize>,
+    wal_max_size: Option<usize>,
+    wal_sync_policy: Option<WalSyncPolicy>,
     block_size: Option<usize>,
     block_cache_size_mb: Option<usize>,
     sparse_index_interval: Option<usize>,
     bloom_false_positive_rate: Option<f64>,
+    bloom_seed: Option<[u8; 32]>,
+    align_blocks: Option<bool>,
+    min_keys_for_bloom: Option<usize>,
+    namespace_separator: Option<char>,
+    no_compress_value_threshold: Option<usize>,
+    inline_value_max: Option<usize>,
+    lazy_block_index: Option<bool>,
+    per_block_bloom: Option<bool>,
+    preload_blocks: Option<usize>,
+    negative_cache_size: Option<usize>,
+    key_comparator: Option<KeyComparator>,
+    io_mode: Option<IoMode>,
+    memtable_impl: Option<MemTableImpl>,
+    compaction_strategy: Option<CompactionStrategy>,
+    level0_compaction_threshold: Option<usize>,
+    target_file_size: Option<usize>,
+    max_sstable_size: Option<usize>,
+    max_key_size: Option<usize>,
+    max_value_size: Option<usize>,
+    codec: Option<Codec>,
+    compression: Option<Compression>,
+    sync_directory: Option<bool>,
 }
 
 impl LsmConfigBuilder {
@@ -176,6 +486,31 @@ impl LsmConfigBuilder {
         self
     }
 
+    pub fn verify_after_flush(mut self, verify: bool) -> Self {
+        self.verify_after_flush = Some(verify);
+        self
+    }
+
+    pub fn wal_group_commit_us(mut self, window_us: u64) -> Self {
+        self.wal_group_commit_us = Some(window_us);
+        self
+    }
+
+    pub fn wal_segment_size(mut self, bytes: usize) -> Self {
+        self.wal_segment_size = Some(bytes);
+        self
+    }
+
+    pub fn wal_max_size(mut self, bytes: usize) -> Self {
+        self.wal_max_size = Some(bytes);
+        self
+    }
+
+    pub fn wal_sync_policy(mut self, policy: WalSyncPolicy) -> Self {
+        self.wal_sync_policy = Some(policy);
+        self
+    }
+
     pub fn block_size(mut self, size: usize) -> Self {
         self.block_size = Some(size);
         self
@@ -196,6 +531,116 @@ impl LsmConfigBuilder {
         self
     }
 
+    pub fn bloom_seed(mut self, seed: [u8; 32]) -> Self {
+        self.bloom_seed = Some(seed);
+        self
+    }
+
+    pub fn align_blocks(mut self, align: bool) -> Self {
+        self.align_blocks = Some(align);
+        self
+    }
+
+    pub fn min_keys_for_bloom(mut self, min_keys: usize) -> Self {
+        self.min_keys_for_bloom = Some(min_keys);
+        self
+    }
+
+    pub fn namespace_separator(mut self, separator: char) -> Self {
+        self.namespace_separator = Some(separator);
+        self
+    }
+
+    pub fn no_compress_value_threshold(mut self, threshold: usize) -> Self {
+        self.no_compress_value_threshold = Some(threshold);
+        self
+    }
+
+    pub fn inline_value_max(mut self, max: usize) -> Self {
+        self.inline_value_max = Some(max);
+        self
+    }
+
+    pub fn lazy_block_index(mut self, lazy: bool) -> Self {
+        self.lazy_block_index = Some(lazy);
+        self
+    }
+
+    pub fn per_block_bloom(mut self, enabled: bool) -> Self {
+        self.per_block_bloom = Some(enabled);
+        self
+    }
+
+    pub fn preload_blocks(mut self, blocks: usize) -> Self {
+        self.preload_blocks = Some(blocks);
+        self
+    }
+
+    pub fn negative_cache_size(mut self, keys: usize) -> Self {
+        self.negative_cache_size = Some(keys);
+        self
+    }
+
+    pub fn key_comparator(mut self, comparator: KeyComparator) -> Self {
+        self.key_comparator = Some(comparator);
+        self
+    }
+
+    pub fn io_mode(mut self, mode: IoMode) -> Self {
+        self.io_mode = Some(mode);
+        self
+    }
+
+    pub fn memtable_impl(mut self, memtable_impl: MemTableImpl) -> Self {
+        self.memtable_impl = Some(memtable_impl);
+        self
+    }
+
+    pub fn compaction_strategy(mut self, strategy: CompactionStrategy) -> Self {
+        self.compaction_strategy = Some(strategy);
+        self
+    }
+
+    pub fn level0_compaction_threshold(mut self, tables: usize) -> Self {
+        self.level0_compaction_threshold = Some(tables);
+        self
+    }
+
+    pub fn target_file_size(mut self, bytes: usize) -> Self {
+        self.target_file_size = Some(bytes);
+        self
+    }
+
+    pub fn max_sstable_size(mut self, bytes: usize) -> Self {
+        self.max_sstable_size = Some(bytes);
+        self
+    }
+
+    pub fn max_key_size(mut self, bytes: usize) -> Self {
+        self.max_key_size = Some(bytes);
+        self
+    }
+
+    pub fn max_value_size(mut self, bytes: usize) -> Self {
+        self.max_value_size = Some(bytes);
+        self
+    }
+
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.codec = Some(codec);
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    pub fn sync_directory(mut self, enabled: bool) -> Self {
+        self.sync_directory = Some(enabled);
+        self
+    }
+
     pub fn build(self) -> Result<LsmConfig> {
         let defaults = LsmConfig::default();
 
@@ -205,6 +650,19 @@ impl LsmConfigBuilder {
                 memtable_max_size: self
                     .memtable_max_size
                     .unwrap_or(defaults.core.memtable_max_size),
+                verify_after_flush: self
+                    .verify_after_flush
+                    .unwrap_or(defaults.core.verify_after_flush),
+                wal_group_commit_us: self
+                    .wal_group_commit_us
+                    .unwrap_or(defaults.core.wal_group_commit_us),
+                wal_segment_size: self
+                    .wal_segment_size
+                    .unwrap_or(defaults.core.wal_segment_size),
+                wal_max_size: self.wal_max_size.unwrap_or(defaults.core.wal_max_size),
+                wal_sync_policy: self
+                    .wal_sync_policy
+                    .unwrap_or(defaults.core.wal_sync_policy),
             },
             storage: StorageConfig {
                 block_size: self.block_size.unwrap_or(defaults.storage.block_size),
@@ -217,6 +675,54 @@ impl LsmConfigBuilder {
                 bloom_false_positive_rate: self
                     .bloom_false_positive_rate
                     .unwrap_or(defaults.storage.bloom_false_positive_rate),
+                bloom_seed: self.bloom_seed.or(defaults.storage.bloom_seed),
+                align_blocks: self.align_blocks.unwrap_or(defaults.storage.align_blocks),
+                min_keys_for_bloom: self
+                    .min_keys_for_bloom
+                    .unwrap_or(defaults.storage.min_keys_for_bloom),
+                namespace_separator: self
+                    .namespace_separator
+                    .or(defaults.storage.namespace_separator),
+                no_compress_value_threshold: self
+                    .no_compress_value_threshold
+                    .or(defaults.storage.no_compress_value_threshold),
+                inline_value_max: self.inline_value_max.or(defaults.storage.inline_value_max),
+                lazy_block_index: self
+                    .lazy_block_index
+                    .unwrap_or(defaults.storage.lazy_block_index),
+                per_block_bloom: self
+                    .per_block_bloom
+                    .unwrap_or(defaults.storage.per_block_bloom),
+                preload_blocks: self
+                    .preload_blocks
+                    .unwrap_or(defaults.storage.preload_blocks),
+                negative_cache_size: self
+                    .negative_cache_size
+                    .unwrap_or(defaults.storage.negative_cache_size),
+                key_comparator: self
+                    .key_comparator
+                    .unwrap_or(defaults.storage.key_comparator),
+                io_mode: self.io_mode.unwrap_or(defaults.storage.io_mode),
+                memtable_impl: self.memtable_impl.unwrap_or(defaults.storage.memtable_impl),
+                compaction_strategy: self
+                    .compaction_strategy
+                    .unwrap_or(defaults.storage.compaction_strategy),
+                level0_compaction_threshold: self
+                    .level0_compaction_threshold
+                    .unwrap_or(defaults.storage.level0_compaction_threshold),
+                target_file_size: self
+                    .target_file_size
+                    .unwrap_or(defaults.storage.target_file_size),
+                max_sstable_size: self.max_sstable_size.or(defaults.storage.max_sstable_size),
+                max_key_size: self.max_key_size.unwrap_or(defaults.storage.max_key_size),
+                max_value_size: self
+                    .max_value_size
+                    .unwrap_or(defaults.storage.max_value_size),
+                codec: self.codec.unwrap_or(defaults.storage.codec),
+                compression: self.compression.unwrap_or(defaults.storage.compression),
+                sync_directory: self
+                    .sync_directory
+                    .unwrap_or(defaults.storage.sync_directory),
             },
         };
 
@@ -299,6 +805,71 @@ mod tests {
         assert!(matches!(result.unwrap_err(), LsmError::InvalidBloomRate(_)));
     }
 
+    #[test]
+    fn test_mmap_io_mode_needs_feature() {
+        let config = StorageConfig {
+            io_mode: IoMode::Mmap,
+            ..Default::default()
+        };
+        let result = config.validate();
+        if cfg!(feature = "mmap") {
+            assert!(result.is_ok());
+        } else {
+            assert!(matches!(result.unwrap_err(), LsmError::ConfigValidation(_)));
+        }
+    }
+
+    #[test]
+    fn test_wal_sync_policy_validation() {
+        let zero_interval = CoreConfig {
+            wal_sync_policy: WalSyncPolicy::Interval(Duration::ZERO),
+            ..Default::default()
+        };
+        assert!(matches!(
+            zero_interval.validate().unwrap_err(),
+            LsmError::ConfigValidation(_)
+        ));
+
+        let group_commit_without_fsync = CoreConfig {
+            wal_sync_policy: WalSyncPolicy::Never,
+            wal_group_commit_us: 100,
+            ..Default::default()
+        };
+        assert!(matches!(
+            group_commit_without_fsync.validate().unwrap_err(),
+            LsmError::ConfigValidation(_)
+        ));
+    }
+
+    #[test]
+    fn test_entry_size_limits() {
+        let at_limit = StorageConfig {
+            max_key_size: u16::MAX as usize,
+            max_value_size: MAX_WAL_RECORD_BYTES - u16::MAX as usize - MAX_RECORD_OVERHEAD,
+            ..Default::default()
+        };
+        assert!(at_limit.validate().is_ok());
+
+        let key_too_long = StorageConfig {
+            max_key_size: u16::MAX as usize + 1,
+            max_value_size: 1024,
+            ..Default::default()
+        };
+        assert!(matches!(
+            key_too_long.validate().unwrap_err(),
+            LsmError::ConfigValidation(_)
+        ));
+
+        let too_large = StorageConfig {
+            max_value_size: at_limit.max_value_size + 1,
+            ..at_limit
+        };
+        assert!(matches!(
+            too_large.validate().unwrap_err(),
+            LsmError::ConfigValidation(_)
+        ));
+    }
+
     #[test]
     fn test_invalid_memtable_size_zero() {
         let mut config = CoreConfig::default();
@@ -346,4 +917,83 @@ mod tests {
 
         assert!(config.is_ok());
     }
+
+    #[test]
+    fn test_from_toml_path_reads_every_field() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/lsm.toml");
+        let config = LsmConfig::from_toml_path(path).unwrap();
+
+        let core = &config.core;
+        assert_eq!(core.dir_path, PathBuf::from("/var/lib/lsm"));
+        assert_eq!(core.memtable_max_size, 8 * 1024 * 1024);
+        assert!(core.verify_after_flush);
+        assert_eq!(core.wal_group_commit_us, 0);
+        assert_eq!(core.wal_segment_size, 16 * 1024 * 1024);
+        assert_eq!(core.wal_max_size, 32 * 1024 * 1024);
+        assert_eq!(
+            core.wal_sync_policy,
+            WalSyncPolicy::Interval(Duration::from_secs(2))
+        );
+
+        let storage = &config.storage;
+        assert_eq!(storage.block_size, 8192);
+        assert_eq!(storage.block_cache_size_mb, 128);
+        assert_eq!(storage.sparse_index_interval, 32);
+        assert_eq!(storage.bloom_false_positive_rate, 0.001);
+        assert_eq!(storage.bloom_seed, Some([7; 32]));
+        assert!(storage.align_blocks);
+        assert_eq!(storage.min_keys_for_bloom, 64);
+        assert_eq!(storage.namespace_separator, Some(':'));
+        assert_eq!(storage.no_compress_value_threshold, Some(65536));
+        assert_eq!(storage.inline_value_max, Some(1048576));
+        assert!(storage.lazy_block_index);
+        assert!(storage.per_block_bloom);
+        assert_eq!(storage.preload_blocks, 256);
+        assert_eq!(storage.negative_cache_size, 4096);
+        assert_eq!(storage.key_comparator, KeyComparator::NumericSuffix);
+        assert_eq!(storage.io_mode, IoMode::Syscall);
+        assert_eq!(storage.memtable_impl, MemTableImpl::SkipList);
+        assert_eq!(storage.compaction_strategy, CompactionStrategy::Leveled);
+        assert_eq!(storage.level0_compaction_threshold, 8);
+        assert_eq!(storage.target_file_size, 4 * 1024 * 1024);
+        assert_eq!(storage.max_sstable_size, Some(16 * 1024 * 1024));
+        assert_eq!(storage.max_key_size, 512);
+        assert_eq!(storage.max_value_size, 32 * 1024);
+        assert_eq!(storage.codec, Codec::Varint);
+        assert_eq!(storage.compression, Compression::Zstd);
+        assert!(!storage.sync_directory);
+    }
+
+    #[test]
+    fn test_from_toml_path_rejects_bad_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let load = |text: &str| {
+            let path = dir.path().join("lsm.toml");
+            std::fs::write(&path, text).unwrap();
+            LsmConfig::from_toml_path(path)
+        };
+
+        // Sections left out keep their defaults
+        let config = load("[core]\ndir_path = \"data\"\nmemtable_max_size = 65536\n").unwrap();
+        assert_eq!(config.storage.block_size, 4096);
+
+        let out_of_range = "[storage]\nblock_size = 4096\nblock_cache_size_mb = 64\n\
+                            sparse_index_interval = 16\nbloom_false_positive_rate = 1.5\n";
+        assert!(matches!(
+            load(out_of_range).unwrap_err(),
+            LsmError::InvalidBloomRate(_)
+        ));
+
+        let missing = load("[core]\ndir_path = \"data\"\n").unwrap_err().to_string();
+        assert!(missing.contains("lsm.toml:1:"), "{missing}");
+        assert!(missing.contains("missing field `memtable_max_size`"), "{missing}");
+
+        let wrong_type = load("[core]\ndir_path = \"data\"\nmemtable_max_size = \"big\"\n")
+            .unwrap_err()
+            .to_string();
+        assert!(wrong_type.contains("lsm.toml:3:"), "{wrong_type}");
+
+        let missing_file = LsmConfig::from_toml_path(dir.path().join("absent.toml")).unwrap_err();
+        assert!(missing_file.to_string().contains("absent.toml"));
+    }
 }
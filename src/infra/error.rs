@@ -50,6 +50,15 @@ pub enum LsmError {
     #[error("Key not found")]
     NotFound,
 
+    #[error("Validation rejected: {0}")]
+    ValidationRejected(String),
+
+    #[error("Counter overflow: {0}")]
+    Overflow(String),
+
+    #[error("Entry too large: {0}")]
+    ValueTooLarge(String),
+
     // Configuration validation errors
     #[error("Invalid block size: {0}")]
     InvalidBlockSize(String),
@@ -6,8 +6,23 @@ pub mod storage;
 #[cfg(feature = "api")]
 pub mod api;
 
-pub use crate::core::engine::LsmEngine;
+#[cfg(feature = "resp")]
+pub mod resp;
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
+pub use crate::core::batch::WriteBatch;
+pub use crate::core::engine::{
+    CompactionSummary, KeyStatus, LsmEngine, RecordView, SstableInfo, Validator,
+};
+pub use crate::core::iterator::KeyValueIterator;
 pub use crate::core::log_record::LogRecord;
+pub use crate::core::snapshot::Snapshot;
+pub use crate::core::subscription::{ChangeEvent, ChangeKind, OverflowPolicy, Subscription};
 pub use crate::features::{FeatureClient, FeatureFlag, Features};
-pub use crate::infra::config::{CoreConfig, LsmConfig, LsmConfigBuilder, StorageConfig};
+pub use crate::infra::config::{
+    Codec, CompactionStrategy, Compression, CoreConfig, IoMode, KeyComparator, LsmConfig,
+    LsmConfigBuilder, MemTableImpl, StorageConfig, WalSyncPolicy,
+};
 pub use crate::infra::error::{LsmError, Result};
@@ -0,0 +1,422 @@
+//! Redis protocol (RESP2) front end, so Redis clients can `GET`/`SET`/`DEL`
+//! against the engine.
+//!
+//! Supported commands: `PING`, `GET`, `SET` (with `EX`/`PX`), `DEL`,
+//! `EXISTS`, `SCAN` (with `MATCH`/`COUNT`) and `QUIT`. Values are binary
+//! safe; keys must be valid UTF-8 since the engine keys on `String`.
+//!
+//! Each client gets its own thread; pipelined commands are answered in order
+//! and flushed together.
+
+use crate::core::engine::LsmEngine;
+use crate::core::iterator::Direction;
+use crate::infra::error::LsmError;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::ops::Bound;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Longest `*<count>` / `$<len>` header line accepted, CRLF included
+const MAX_HEADER_LINE: u64 = 32;
+/// Most arguments accepted in one command
+const MAX_ARGS: usize = 1024 * 1024;
+/// Largest bulk string accepted (Redis' own `proto-max-bulk-len`)
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+/// Entries `SCAN` walks per call unless `COUNT` says otherwise
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// TCP listener speaking RESP2, serving one engine
+pub struct RespServer {
+    listener: TcpListener,
+    engine: Arc<LsmEngine>,
+}
+
+impl RespServer {
+    pub fn bind(addr: impl ToSocketAddrs, engine: Arc<LsmEngine>) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            engine,
+        })
+    }
+
+    /// Address actually bound, e.g. to find the port picked for `:0`
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept clients until the listener fails, one thread each
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("RESP accept failed: {}", e);
+                    continue;
+                }
+            };
+            let engine = Arc::clone(&self.engine);
+            thread::Builder::new()
+                .name("resp-client".to_string())
+                .spawn(move || {
+                    if let Err(e) = serve_client(stream, &engine) {
+                        debug!("RESP client disconnected: {}", e);
+                    }
+                })?;
+        }
+        Ok(())
+    }
+}
+
+fn serve_client(stream: TcpStream, engine: &LsmEngine) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    loop {
+        let args = match read_command(&mut reader) {
+            Ok(Some(args)) => args,
+            Ok(None) => return writer.flush(),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                // The stream can't be resynchronized after a framing error
+                Reply::Error(format!("ERR Protocol error: {e}")).write_to(&mut writer)?;
+                return writer.flush();
+            }
+            Err(e) => return Err(e),
+        };
+
+        let quit = args
+            .first()
+            .is_some_and(|name| name.eq_ignore_ascii_case(b"QUIT"));
+        let reply = if quit {
+            Reply::Simple("OK")
+        } else {
+            execute(engine, &args)
+        };
+        reply.write_to(&mut writer)?;
+
+        if quit {
+            return writer.flush();
+        }
+        // Answer a pipelined batch in one write
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+}
+
+/// Read one command, an array of bulk strings. `None` once the client has
+/// closed the connection between commands.
+fn read_command(reader: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let Some(line) = read_header_line(reader)? else {
+        return Ok(None);
+    };
+    let count = parse_header(&line, b'*', MAX_ARGS)?;
+
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        let line = read_header_line(reader)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+        let len = parse_header(&line, b'$', MAX_BULK_LEN)?;
+
+        let mut arg = vec![0u8; len + 2];
+        reader.read_exact(&mut arg)?;
+        if !arg.ends_with(b"\r\n") {
+            return Err(protocol_error("bulk string not terminated by CRLF"));
+        }
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+/// A CRLF-terminated header line without its terminator
+fn read_header_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    reader.take(MAX_HEADER_LINE).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    match line.strip_suffix(b"\r\n") {
+        Some(content) => Ok(Some(content.to_vec())),
+        None => Err(protocol_error("expected a CRLF-terminated header")),
+    }
+}
+
+/// Parse `<marker><n>`, with `n` at most `max`
+fn parse_header(line: &[u8], marker: u8, max: usize) -> io::Result<usize> {
+    let digits = line
+        .strip_prefix(&[marker])
+        .ok_or_else(|| protocol_error(format!("expected '{}'", marker as char)))?;
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse::<usize>().ok())
+        .filter(|&n| n <= max)
+        .ok_or_else(|| protocol_error("invalid length"))
+}
+
+fn protocol_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[derive(Debug)]
+enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        match self {
+            Reply::Simple(status) => write!(out, "+{status}\r\n"),
+            // A newline would end the error early
+            Reply::Error(message) => write!(out, "-{}\r\n", message.replace(['\r', '\n'], " ")),
+            Reply::Integer(n) => write!(out, ":{n}\r\n"),
+            Reply::Bulk(bytes) => {
+                write!(out, "${}\r\n", bytes.len())?;
+                out.write_all(bytes)?;
+                out.write_all(b"\r\n")
+            }
+            Reply::Null => out.write_all(b"$-1\r\n"),
+            Reply::Array(items) => {
+                write!(out, "*{}\r\n", items.len())?;
+                items.iter().try_for_each(|item| item.write_to(out))
+            }
+        }
+    }
+}
+
+/// Outcome of a command; `Err` holds the error reply
+type CommandResult = std::result::Result<Reply, Reply>;
+
+fn execute(engine: &LsmEngine, args: &[Vec<u8>]) -> Reply {
+    let Some((name, args)) = args.split_first() else {
+        return Reply::Error("ERR empty command".to_string());
+    };
+    let name = String::from_utf8_lossy(name).to_ascii_lowercase();
+    let result = match name.as_str() {
+        "ping" => ping(args),
+        "get" => get(engine, args),
+        "set" => set(engine, args),
+        "del" => del(engine, args),
+        "exists" => exists(engine, args),
+        "scan" => scan(engine, args),
+        _ => Err(Reply::Error(format!("ERR unknown command '{name}'"))),
+    };
+    result.unwrap_or_else(|error| error)
+}
+
+fn wrong_arity(command: &str) -> Reply {
+    Reply::Error(format!(
+        "ERR wrong number of arguments for '{command}' command"
+    ))
+}
+
+fn syntax_error() -> Reply {
+    Reply::Error("ERR syntax error".to_string())
+}
+
+fn engine_error(e: LsmError) -> Reply {
+    Reply::Error(format!("ERR {e}"))
+}
+
+fn key(arg: &[u8]) -> std::result::Result<String, Reply> {
+    String::from_utf8(arg.to_vec())
+        .map_err(|_| Reply::Error("ERR keys must be valid UTF-8".to_string()))
+}
+
+fn number(arg: &[u8]) -> std::result::Result<u64, Reply> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| Reply::Error("ERR value is not an integer or out of range".to_string()))
+}
+
+fn ping(args: &[Vec<u8>]) -> CommandResult {
+    match args {
+        [] => Ok(Reply::Simple("PONG")),
+        [message] => Ok(Reply::Bulk(message.clone())),
+        _ => Err(wrong_arity("ping")),
+    }
+}
+
+fn get(engine: &LsmEngine, args: &[Vec<u8>]) -> CommandResult {
+    let [k] = args else {
+        return Err(wrong_arity("get"));
+    };
+    match engine.get(&key(k)?).map_err(engine_error)? {
+        Some(value) => Ok(Reply::Bulk(value)),
+        None => Ok(Reply::Null),
+    }
+}
+
+/// `SET key value [EX seconds | PX milliseconds]`
+fn set(engine: &LsmEngine, args: &[Vec<u8>]) -> CommandResult {
+    let [k, value, options @ ..] = args else {
+        return Err(wrong_arity("set"));
+    };
+    let ttl = match options {
+        [] => None,
+        [unit, amount] if unit.eq_ignore_ascii_case(b"EX") => {
+            Some(Duration::from_secs(number(amount)?))
+        }
+        [unit, amount] if unit.eq_ignore_ascii_case(b"PX") => {
+            Some(Duration::from_millis(number(amount)?))
+        }
+        _ => return Err(syntax_error()),
+    };
+    if ttl.is_some_and(|ttl| ttl.is_zero()) {
+        return Err(Reply::Error(
+            "ERR invalid expire time in 'set' command".to_string(),
+        ));
+    }
+
+    let k = key(k)?;
+    match ttl {
+        Some(ttl) => engine.set_with_ttl(k, value.clone(), ttl),
+        None => engine.set(k, value.clone()),
+    }
+    .map_err(engine_error)?;
+    Ok(Reply::Simple("OK"))
+}
+
+/// Deletes the keys that exist and returns how many there were
+fn del(engine: &LsmEngine, args: &[Vec<u8>]) -> CommandResult {
+    if args.is_empty() {
+        return Err(wrong_arity("del"));
+    }
+    let mut deleted = 0;
+    for k in args {
+        let k = key(k)?;
+        if engine.get(&k).map_err(engine_error)?.is_some() {
+            engine.delete(k).map_err(engine_error)?;
+            deleted += 1;
+        }
+    }
+    Ok(Reply::Integer(deleted))
+}
+
+/// Number of the given keys that exist, counting repeats
+fn exists(engine: &LsmEngine, args: &[Vec<u8>]) -> CommandResult {
+    if args.is_empty() {
+        return Err(wrong_arity("exists"));
+    }
+    let mut found = 0;
+    for k in args {
+        if engine.get(&key(k)?).map_err(engine_error)?.is_some() {
+            found += 1;
+        }
+    }
+    Ok(Reply::Integer(found))
+}
+
+/// `SCAN cursor [MATCH pattern] [COUNT count]`.
+///
+/// Walks up to `count` keys in ascending order starting after the cursor.
+/// The cursor is `0` to start and the hex-encoded last key walked otherwise;
+/// a returned cursor of `0` means the scan is complete. As in Redis, `MATCH`
+/// filters the walked keys, so a call can return fewer than `count`.
+fn scan(engine: &LsmEngine, args: &[Vec<u8>]) -> CommandResult {
+    let [cursor, options @ ..] = args else {
+        return Err(wrong_arity("scan"));
+    };
+    let start = if cursor.as_slice() == b"0" {
+        None
+    } else {
+        let after =
+            hex_decode(cursor).ok_or_else(|| Reply::Error("ERR invalid cursor".to_string()))?;
+        Some(key(&after)?)
+    };
+
+    let mut pattern = None;
+    let mut count = DEFAULT_SCAN_COUNT;
+    for option in options.chunks(2) {
+        match option {
+            [name, value] if name.eq_ignore_ascii_case(b"MATCH") => pattern = Some(value),
+            [name, value] if name.eq_ignore_ascii_case(b"COUNT") => {
+                count = number(value)? as usize;
+                if count == 0 {
+                    return Err(syntax_error());
+                }
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+
+    let start_bound = match &start {
+        Some(after) => Bound::Excluded(after.as_str()),
+        None => Bound::Unbounded,
+    };
+    let entries = engine
+        .merged_iter(start_bound, Bound::Unbounded, Direction::Ascending)
+        .map_err(engine_error)?;
+
+    let mut walked = 0;
+    let mut last_key = None;
+    let mut keys = Vec::new();
+    for entry in entries.take(count) {
+        let (k, _) = entry.map_err(engine_error)?;
+        walked += 1;
+        if pattern.is_none_or(|pattern| glob_match(pattern, k.as_bytes())) {
+            keys.push(Reply::Bulk(k.clone().into_bytes()));
+        }
+        last_key = Some(k);
+    }
+
+    let next_cursor = match last_key {
+        Some(last_key) if walked == count => hex_encode(last_key.as_bytes()),
+        _ => b"0".to_vec(),
+    };
+    Ok(Reply::Array(vec![
+        Reply::Bulk(next_cursor),
+        Reply::Array(keys),
+    ]))
+}
+
+/// Redis-style glob: `*` matches any run of bytes, `?` any single byte
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text offset it is matching up to
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+fn hex_encode(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|b| format!("{b:02x}").into_bytes())
+        .collect()
+}
+
+fn hex_decode(hex: &[u8]) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
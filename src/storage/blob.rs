@@ -0,0 +1,161 @@
+use crate::infra::error::{LsmError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Subdirectory of the data dir holding values stored out of line
+pub const BLOB_DIR: &str = "blobs";
+
+const BLOB_SUFFIX: &str = ".blob";
+
+/// Where an out-of-line value lives, stored in its SSTable record in place of
+/// the value
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BlobRef {
+    /// File name under [`BLOB_DIR`], without the `.blob` suffix
+    pub id: String,
+    pub len: u64,
+    /// CRC32 of the value
+    pub crc: u32,
+}
+
+/// Blob directory of the data dir holding `table`
+pub(crate) fn blob_dir_of(table: &Path) -> PathBuf {
+    table
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(BLOB_DIR)
+}
+
+pub(crate) fn blob_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}{}", id, BLOB_SUFFIX))
+}
+
+/// Ids of the blob files in `dir`, empty if it doesn't exist
+pub(crate) fn blob_ids(dir: &Path) -> Result<Vec<String>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut ids = Vec::new();
+    for entry in entries {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if let Some(id) = name.strip_suffix(BLOB_SUFFIX) {
+            ids.push(id.to_string());
+        }
+    }
+    Ok(ids)
+}
+
+/// Write `value` to a new, fsynced file in `dir` named `{stem}-{n}`, starting
+/// at `*next` and skipping ids already taken. Returns the pointer and the
+/// file's path.
+pub(crate) fn write_blob(
+    dir: &Path,
+    stem: &str,
+    next: &mut u64,
+    value: &[u8],
+) -> Result<(BlobRef, PathBuf)> {
+    std::fs::create_dir_all(dir)?;
+
+    let (id, path, mut file) = loop {
+        let id = format!("{}-{}", stem, next);
+        *next += 1;
+        let path = blob_path(dir, &id);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => break (id, path, file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    let written = file.write_all(value).and_then(|_| file.sync_all());
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&path);
+        return Err(e.into());
+    }
+
+    let blob = BlobRef {
+        id,
+        len: value.len() as u64,
+        crc: crc32fast::hash(value),
+    };
+    Ok((blob, path))
+}
+
+/// Read the value `blob` points at, checking its length and checksum
+pub(crate) fn read_blob(dir: &Path, blob: &BlobRef) -> Result<Vec<u8>> {
+    let path = blob_path(dir, &blob.id);
+    let value = std::fs::read(&path).map_err(|e| {
+        LsmError::CorruptedData(format!("Blob {} is unreadable: {}", path.display(), e))
+    })?;
+
+    if value.len() as u64 != blob.len || crc32fast::hash(&value) != blob.crc {
+        return Err(LsmError::CorruptedData(format!(
+            "Blob {} does not match its pointer ({} bytes, crc {:#010x})",
+            path.display(),
+            blob.len,
+            blob.crc
+        )));
+    }
+    Ok(value)
+}
+
+/// Blobs whose last reference a compaction dropped. Shared by the tables the
+/// compaction replaced and deleted when the last of them is dropped, so an
+/// iterator or snapshot still reading an old table can fetch its blobs.
+#[derive(Debug)]
+pub(crate) struct ObsoleteBlobs {
+    paths: Vec<PathBuf>,
+}
+
+impl ObsoleteBlobs {
+    pub(crate) fn new(dir: &Path, blobs: &[BlobRef]) -> Self {
+        Self {
+            paths: blobs.iter().map(|blob| blob_path(dir, &blob.id)).collect(),
+        }
+    }
+}
+
+impl Drop for ObsoleteBlobs {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("Failed to remove obsolete blob {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_round_trip_and_corruption() {
+        let dir = tempfile::tempdir().unwrap();
+        let blobs = dir.path().join(BLOB_DIR);
+        let mut next = 0;
+
+        let (first, path) = write_blob(&blobs, "7", &mut next, b"payload").unwrap();
+        assert_eq!(read_blob(&blobs, &first).unwrap(), b"payload");
+
+        // Ids already on disk are skipped
+        next = 0;
+        let (second, _) = write_blob(&blobs, "7", &mut next, b"other").unwrap();
+        assert_ne!(second.id, first.id);
+
+        std::fs::write(&path, b"payloaX").unwrap();
+        assert!(matches!(
+            read_blob(&blobs, &first),
+            Err(LsmError::CorruptedData(_))
+        ));
+
+        drop(ObsoleteBlobs::new(&blobs, &[first, second]));
+        assert_eq!(std::fs::read_dir(&blobs).unwrap().count(), 0);
+    }
+}
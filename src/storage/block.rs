@@ -1,9 +1,14 @@
-use crate::infra::config::StorageConfig;
+use crate::infra::config::{KeyComparator, StorageConfig};
 use std::mem::size_of;
 
 pub const BLOCK_SIZE: usize = 4096;
 const U32_SIZE: usize = size_of::<u32>();
 
+/// Value length meaning "up to the end of the entry", for values too long
+/// for the `u16` length field. Also what a value of exactly `u16::MAX` bytes
+/// records, which reads back the same.
+pub const LONG_VALUE: u16 = u16::MAX;
+
 #[derive(Debug, Clone)]
 pub struct Block {
     pub(crate) data: Vec<u8>,
@@ -41,6 +46,9 @@ impl Block {
     }
 
     pub fn add(&mut self, key: &[u8], value: &[u8]) -> bool {
+        if key.len() > u16::MAX as usize || value.len() > u16::MAX as usize {
+            return false;
+        }
         let entry_size = Self::entry_size(key, value);
         let new_offset_size = U32_SIZE;
         let total_needed = self.current_size() + entry_size + new_offset_size;
@@ -49,20 +57,109 @@ impl Block {
             return false;
         }
 
+        self.push_entry(key, value);
+        true
+    }
+
+    /// A block holding just `key` and `value`, however large they are.
+    ///
+    /// The key must fit a `u16` length. A value that doesn't is stored with
+    /// the length [`LONG_VALUE`] and read up to the end of the entry.
+    pub fn single(key: &[u8], value: &[u8]) -> Self {
+        let mut block = Self::new(0);
+        block.push_entry(key, value);
+        block
+    }
+
+    /// Whether the block is larger than its configured size, i.e. was built
+    /// by [`single`](Self::single) for an entry `add` would not take
+    pub fn is_oversized(&self) -> bool {
+        self.current_size() > self.block_size
+    }
+
+    fn push_entry(&mut self, key: &[u8], value: &[u8]) {
         let offset = self.data.len() as u32;
         self.offsets.push(offset);
 
-        // Cast to u16 is safe for key/value lengths as we assume
-        // individual entries don't exceed 64KB, even if the block does.
         let key_len = key.len() as u16;
-        let val_len = value.len() as u16;
+        let val_len = value.len().min(LONG_VALUE as usize) as u16;
 
         self.data.extend_from_slice(&key_len.to_le_bytes());
         self.data.extend_from_slice(key);
         self.data.extend_from_slice(&val_len.to_le_bytes());
         self.data.extend_from_slice(value);
+    }
 
-        true
+    /// Key and value of entry `i`, or `None` if it runs past the block
+    pub(crate) fn entry(&self, i: usize) -> Option<(&[u8], &[u8])> {
+        let start = *self.offsets.get(i)? as usize;
+        let end = match self.offsets.get(i + 1) {
+            Some(&next) => next as usize,
+            None => self.data.len(),
+        };
+        let entry = self.data.get(start..end)?;
+
+        let key_len = u16::from_le_bytes([*entry.first()?, *entry.get(1)?]) as usize;
+        let key = entry.get(2..2 + key_len)?;
+        let rest = entry.get(2 + key_len..)?;
+        let val_len = u16::from_le_bytes([*rest.first()?, *rest.get(1)?]);
+        let value = if val_len == LONG_VALUE {
+            &rest[2..]
+        } else {
+            rest.get(2..2 + val_len as usize)?
+        };
+        Some((key, value))
+    }
+
+    /// Entry whose key is `key`, adding the number of keys compared to
+    /// `comparisons`.
+    ///
+    /// Every `interval`-th entry is a restart point: those are binary-searched
+    /// for the last one not past `key`, and at most `interval` entries are then
+    /// scanned from there. Keys within a block are sorted by `comparator`, so
+    /// this finds what a full scan would.
+    pub(crate) fn find(
+        &self,
+        key: &[u8],
+        interval: usize,
+        comparator: KeyComparator,
+        comparisons: &mut u64,
+    ) -> Option<(&[u8], &[u8])> {
+        let interval = interval.max(1);
+
+        let (mut lo, mut hi) = (0, self.len().div_ceil(interval));
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            *comparisons += 1;
+            match self.entry(mid * interval) {
+                Some((entry_key, _)) if comparator.compare(entry_key, key).is_le() => {
+                    lo = mid + 1
+                }
+                _ => hi = mid,
+            }
+        }
+        if lo == 0 {
+            return None;
+        }
+
+        let start = (lo - 1) * interval;
+        for i in start..(start + interval).min(self.len()) {
+            let Some((entry_key, value)) = self.entry(i) else {
+                break;
+            };
+            *comparisons += 1;
+            match comparator.compare(entry_key, key) {
+                std::cmp::Ordering::Less => {}
+                std::cmp::Ordering::Equal => return Some((entry_key, value)),
+                std::cmp::Ordering::Greater => break,
+            }
+        }
+        None
+    }
+
+    /// Every entry in order, stopping at the first malformed one
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        (0..self.len()).map_while(|i| self.entry(i))
     }
 
     pub fn encode(&self) -> Vec<u8> {
@@ -159,6 +256,27 @@ mod tests {
         assert_eq!(block.len(), 10);
     }
 
+    #[test]
+    fn test_find_matches_a_full_scan_for_every_interval() {
+        let mut block = Block::new(64 * 1024);
+        for i in (0..300).step_by(2) {
+            let key = format!("key_{:03}", i);
+            assert!(block.add(key.as_bytes(), format!("v{i}").as_bytes()));
+        }
+
+        let comparator = KeyComparator::Lexicographic;
+        for interval in [1, 2, 7, 16, 150, 1000] {
+            for i in 0..=300 {
+                let key = format!("key_{:03}", i);
+                let found = block.find(key.as_bytes(), interval, comparator, &mut 0);
+                let expected = block.entries().find(|(k, _)| *k == key.as_bytes());
+                assert_eq!(found, expected, "{key} with interval {interval}");
+            }
+            assert_eq!(block.find(b"a", interval, comparator, &mut 0), None);
+            assert_eq!(block.find(b"z", interval, comparator, &mut 0), None);
+        }
+    }
+
     #[test]
     fn test_add_until_full() {
         let mut block = Block::new(256);
@@ -229,6 +347,24 @@ mod tests {
         assert_eq!(block.len(), 0, "Block should remain empty");
     }
 
+    #[test]
+    fn test_single_entry_block_holds_long_values() {
+        let value = vec![b'v'; 100_000];
+        let block = Block::single(b"key", &value);
+        assert!(block.is_oversized());
+
+        let decoded = Block::decode(&block.encode());
+        assert_eq!(decoded.entry(0), Some((b"key" as &[u8], value.as_slice())));
+
+        let mut regular = Block::new(BLOCK_SIZE);
+        assert!(!regular.add(b"key", &value));
+        assert!(regular.add(b"a", b"1"));
+        assert!(regular.add(b"b", b"2"));
+        assert!(!regular.is_oversized());
+        let entries: Vec<_> = regular.entries().collect();
+        assert_eq!(entries, vec![(b"a" as &[u8], b"1" as &[u8]), (b"b", b"2")]);
+    }
+
     #[test]
     fn test_encode_decode_empty_block() {
         let block = Block::new(BLOCK_SIZE);
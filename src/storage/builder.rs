@@ -1,33 +1,325 @@
 use crate::core::log_record::LogRecord;
-use crate::infra::codec::encode;
-use crate::infra::config::StorageConfig;
+use crate::infra::codec::{decode, encode, encode_with};
+use crate::infra::config::{Codec, Compression, KeyComparator, StorageConfig};
 use crate::infra::error::{LsmError, Result};
+use crate::storage::blob::{blob_dir_of, write_blob};
 use crate::storage::block::Block;
+use crate::storage::index::{encode_entry, INDEX_ENTRY_SIZE};
+use crate::storage::sync_parent_dir;
 use bloomfilter::Bloom;
 use lz4_flex::compress_prepend_size;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::collections::BTreeSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
 
 const SST_MAGIC_V2: &[u8; 8] = b"LSMSST03";
+/// Same data blocks as V3, with the block index stored separately (see `storage::index`)
+pub(crate) const SST_MAGIC_V4: &[u8; 8] = b"LSMSST04";
+/// V3 or V4 layout whose records use a codec other than `Codec::Fixint`. The
+/// magic is followed by `[lazy_index u8][codec id u8]`.
+pub(crate) const SST_MAGIC_V5: &[u8; 8] = b"LSMSST05";
+
+/// Appended to a table's file name while it is being written
+pub(crate) const SST_TMP_SUFFIX: &str = ".tmp";
+
+/// On-disk alignment used when `StorageConfig::align_blocks` is enabled
+pub const BLOCK_ALIGNMENT: u64 = 4096;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockMeta {
     pub first_key: Vec<u8>,
     pub offset: u64,
+    /// Bytes occupied on disk, including any alignment padding
     pub size: u32,
     pub uncompressed_size: u32,
+    /// Zero bytes appended after the compressed data to reach `BLOCK_ALIGNMENT`
+    pub padding: u32,
+    /// False for blocks holding a single value above `no_compress_value_threshold`,
+    /// which are written as-is
+    pub compressed: bool,
+    /// CRC32 of the stored (possibly compressed) bytes, excluding padding
+    pub checksum: u32,
+    /// Serialized Bloom filter over the block's keys; empty unless
+    /// `StorageConfig::per_block_bloom` is set
+    pub bloom: Vec<u8>,
+    /// Dedicated block for one entry larger than `block_size`; the reader
+    /// keeps these out of the block cache
+    #[serde(default)]
+    pub single_entry: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetaBlock {
     pub blocks: Vec<BlockMeta>,
+    /// False when the table was too small to be worth a Bloom filter
+    pub has_bloom: bool,
     pub bloom_filter_data: Vec<u8>,
     pub min_key: Vec<u8>,
     pub max_key: Vec<u8>,
     pub record_count: u64,
     pub timestamp: u128,
+    /// Distinct key prefixes of live records (empty unless `namespace_separator` is set)
+    pub namespaces: Vec<String>,
+    /// Highest record sequence number in the table
+    pub max_seq: u64,
+    /// Compaction level: 0 for flushed tables, 1+ for leveled compaction output
+    pub level: u32,
+    /// Algorithm of the data blocks marked `compressed`, whatever the
+    /// current config says (the MetaBlock itself is always LZ4)
+    #[serde(default)]
+    pub compression: Compression,
+    /// Order of the table's keys, whatever the current config says
+    #[serde(default)]
+    pub comparator: KeyComparator,
+    /// Ids of the blob files the table's records point at; `None` for tables
+    /// written before this was recorded, whose blobs are only found by
+    /// scanning them
+    #[serde(default)]
+    pub blob_ids: Option<Vec<String>>,
+}
+
+/// `MetaBlock` as written before `blob_ids` existed
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct UntrackedBlobsMetaBlock {
+    blocks: Vec<BlockMeta>,
+    has_bloom: bool,
+    bloom_filter_data: Vec<u8>,
+    min_key: Vec<u8>,
+    max_key: Vec<u8>,
+    record_count: u64,
+    timestamp: u128,
+    namespaces: Vec<String>,
+    max_seq: u64,
+    level: u32,
+    compression: Compression,
+    comparator: KeyComparator,
+}
+
+impl From<UntrackedBlobsMetaBlock> for MetaBlock {
+    fn from(meta: UntrackedBlobsMetaBlock) -> Self {
+        Self {
+            blocks: meta.blocks,
+            has_bloom: meta.has_bloom,
+            bloom_filter_data: meta.bloom_filter_data,
+            min_key: meta.min_key,
+            max_key: meta.max_key,
+            record_count: meta.record_count,
+            timestamp: meta.timestamp,
+            namespaces: meta.namespaces,
+            max_seq: meta.max_seq,
+            level: meta.level,
+            compression: meta.compression,
+            comparator: meta.comparator,
+            blob_ids: None,
+        }
+    }
+}
+
+/// `MetaBlock` as written before `comparator` existed, when every table was
+/// in byte order
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct ByteOrderMetaBlock {
+    blocks: Vec<BlockMeta>,
+    has_bloom: bool,
+    bloom_filter_data: Vec<u8>,
+    min_key: Vec<u8>,
+    max_key: Vec<u8>,
+    record_count: u64,
+    timestamp: u128,
+    namespaces: Vec<String>,
+    max_seq: u64,
+    level: u32,
+    compression: Compression,
+}
+
+impl From<ByteOrderMetaBlock> for MetaBlock {
+    fn from(meta: ByteOrderMetaBlock) -> Self {
+        Self {
+            blocks: meta.blocks,
+            has_bloom: meta.has_bloom,
+            bloom_filter_data: meta.bloom_filter_data,
+            min_key: meta.min_key,
+            max_key: meta.max_key,
+            record_count: meta.record_count,
+            timestamp: meta.timestamp,
+            namespaces: meta.namespaces,
+            max_seq: meta.max_seq,
+            level: meta.level,
+            compression: meta.compression,
+            comparator: KeyComparator::Lexicographic,
+            blob_ids: None,
+        }
+    }
+}
+
+/// `BlockMeta` as written before `single_entry` existed
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct BlockMetaV1 {
+    first_key: Vec<u8>,
+    offset: u64,
+    size: u32,
+    uncompressed_size: u32,
+    padding: u32,
+    compressed: bool,
+    checksum: u32,
+    bloom: Vec<u8>,
+}
+
+impl From<BlockMetaV1> for BlockMeta {
+    fn from(block: BlockMetaV1) -> Self {
+        Self {
+            first_key: block.first_key,
+            offset: block.offset,
+            size: block.size,
+            uncompressed_size: block.uncompressed_size,
+            padding: block.padding,
+            compressed: block.compressed,
+            checksum: block.checksum,
+            bloom: block.bloom,
+            single_entry: false,
+        }
+    }
+}
+
+/// `BlockMeta` as written by the first V3 tables, before blocks were
+/// checksummed, padded or given their own Bloom filter
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct BlockMetaV0 {
+    first_key: Vec<u8>,
+    offset: u64,
+    size: u32,
+    uncompressed_size: u32,
+}
+
+/// `MetaBlock` as written by the first V3 tables: always an LZ4 table with a
+/// Bloom filter, with no sequence numbers, levels or namespaces
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct MetaBlockV0 {
+    blocks: Vec<BlockMetaV0>,
+    bloom_filter_data: Vec<u8>,
+    min_key: Vec<u8>,
+    max_key: Vec<u8>,
+    record_count: u64,
+    timestamp: u128,
+}
+
+impl From<MetaBlockV0> for MetaBlock {
+    fn from(meta: MetaBlockV0) -> Self {
+        Self {
+            blocks: meta
+                .blocks
+                .into_iter()
+                .map(|block| BlockMeta {
+                    first_key: block.first_key,
+                    offset: block.offset,
+                    size: block.size,
+                    uncompressed_size: block.uncompressed_size,
+                    padding: 0,
+                    compressed: true,
+                    checksum: 0,
+                    bloom: Vec::new(),
+                    single_entry: false,
+                })
+                .collect(),
+            has_bloom: true,
+            bloom_filter_data: meta.bloom_filter_data,
+            min_key: meta.min_key,
+            max_key: meta.max_key,
+            record_count: meta.record_count,
+            timestamp: meta.timestamp,
+            namespaces: Vec::new(),
+            max_seq: 0,
+            level: 0,
+            compression: Compression::Lz4,
+            comparator: KeyComparator::Lexicographic,
+            blob_ids: None,
+        }
+    }
+}
+
+/// `MetaBlock` as written before `compression` existed, when every table was
+/// LZ4. `B` is [`BlockMetaV1`] for tables from before `single_entry`.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct LegacyMetaBlock<B> {
+    blocks: Vec<B>,
+    has_bloom: bool,
+    bloom_filter_data: Vec<u8>,
+    min_key: Vec<u8>,
+    max_key: Vec<u8>,
+    record_count: u64,
+    timestamp: u128,
+    namespaces: Vec<String>,
+    max_seq: u64,
+    level: u32,
+}
+
+impl<B: Into<BlockMeta>> LegacyMetaBlock<B> {
+    fn upgrade(self) -> MetaBlock {
+        MetaBlock {
+            blocks: self.blocks.into_iter().map(Into::into).collect(),
+            has_bloom: self.has_bloom,
+            bloom_filter_data: self.bloom_filter_data,
+            min_key: self.min_key,
+            max_key: self.max_key,
+            record_count: self.record_count,
+            timestamp: self.timestamp,
+            namespaces: self.namespaces,
+            max_seq: self.max_seq,
+            level: self.level,
+            compression: Compression::Lz4,
+            comparator: KeyComparator::Lexicographic,
+            blob_ids: None,
+        }
+    }
+}
+
+impl MetaBlock {
+    /// Decode a MetaBlock, including ones written before
+    /// `BlockMeta::single_entry`, `compression`, `comparator` or `blob_ids`
+    /// were added (bincode can't skip a missing field on its own)
+    pub(crate) fn decode(data: &[u8]) -> Result<Self> {
+        decode::<Self>(data)
+            .or_else(|err| {
+                decode::<UntrackedBlobsMetaBlock>(data)
+                    .map(Into::into)
+                    .map_err(|_| err)
+            })
+            .or_else(|err| {
+                decode::<ByteOrderMetaBlock>(data)
+                    .map(Into::into)
+                    .map_err(|_| err)
+            })
+            .or_else(|err| {
+                decode::<LegacyMetaBlock<BlockMeta>>(data)
+                    .map(LegacyMetaBlock::upgrade)
+                    .map_err(|_| err)
+            })
+            .or_else(|err| {
+                decode::<LegacyMetaBlock<BlockMetaV1>>(data)
+                    .map(LegacyMetaBlock::upgrade)
+                    .map_err(|_| err)
+            })
+    }
+
+    /// Decode the MetaBlock of a table from before blocks were checksummed.
+    /// Its `BlockMeta::checksum`s are zero and must not be verified.
+    pub(crate) fn decode_unchecksummed(data: &[u8]) -> Result<Self> {
+        decode::<MetaBlockV0>(data).map(Into::into)
+    }
+}
+
+/// Part of `key` before the first `separator`, if the key has one
+pub(crate) fn namespace_of(key: &str, separator: char) -> Option<&str> {
+    key.split_once(separator).map(|(namespace, _)| namespace)
 }
 
 pub struct SstableBuilder {
@@ -35,22 +327,110 @@ pub struct SstableBuilder {
     current_block: Block,
     block_metas: Vec<BlockMeta>,
     keys_for_bloom: Vec<Vec<u8>>,
+    /// Index into `keys_for_bloom` of the current block's first key
+    block_keys_start: usize,
+    namespaces: BTreeSet<String>,
     config: StorageConfig,
     current_offset: u64,
     first_key: Option<Vec<u8>>,
     last_key: Option<Vec<u8>>,
     record_count: u64,
+    max_seq: u64,
+    level: u32,
     path: PathBuf,
+    tmp_path: PathBuf,
+    /// Set once the table is renamed into place, so drop leaves it alone
+    published: bool,
+    /// Blob files written for values over `inline_value_max`; removed on
+    /// drop if the table is never published
+    blob_paths: Vec<PathBuf>,
+    /// Every blob the added records point at, written here or not
+    blob_ids: Vec<String>,
+    /// Suffix tried for the next blob id
+    next_blob: u64,
     timestamp: u128,
 }
 
 impl SstableBuilder {
+    /// Start a table at `path`, which must not exist yet: an existing file is
+    /// an error, never overwritten. The table is written to `{path}.tmp` and
+    /// only appears under `path` once [`finish`](Self::finish) completes it.
     pub fn new(path: PathBuf, config: StorageConfig, timestamp: u128) -> Result<Self> {
-        let file = File::create(&path)?;
+        match Self::reserve(&path)? {
+            Some(file) => Self::with_file(file, path, config, timestamp),
+            None => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            )
+            .into()),
+        }
+    }
+
+    /// Start a table named `{timestamp}.sst` in `dir`, or `{timestamp}-{n}.sst`
+    /// with the first free `n` when another table already took that timestamp
+    pub fn create_in(dir: &Path, config: StorageConfig, timestamp: u128) -> Result<Self> {
+        let mut n = 0u64;
+        loop {
+            let path = match n {
+                0 => dir.join(format!("{}.sst", timestamp)),
+                n => dir.join(format!("{}-{}.sst", timestamp, n)),
+            };
+            match Self::reserve(&path)? {
+                Some(file) => return Self::with_file(file, path, config, timestamp),
+                None => n += 1,
+            }
+        }
+    }
+
+    /// Create the temporary file for a table to be published at `path`, or
+    /// `None` when that name is taken by a finished table or one in progress.
+    /// The temporary file is created first so that two builders can't both
+    /// claim a name that is still free.
+    fn reserve(path: &Path) -> Result<Option<File>> {
+        let tmp_path = tmp_path_for(path);
+        let file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if path.exists() {
+            drop(file);
+            std::fs::remove_file(&tmp_path)?;
+            return Ok(None);
+        }
+        Ok(Some(file))
+    }
+
+    fn with_file(
+        file: File,
+        path: PathBuf,
+        config: StorageConfig,
+        timestamp: u128,
+    ) -> Result<Self> {
         let mut writer = BufWriter::new(file);
 
-        writer.write_all(SST_MAGIC_V2)?;
-        let current_offset = SST_MAGIC_V2.len() as u64;
+        let header = match config.codec {
+            Codec::Fixint if config.lazy_block_index => SST_MAGIC_V4.to_vec(),
+            Codec::Fixint => SST_MAGIC_V2.to_vec(),
+            codec => [
+                &SST_MAGIC_V5[..],
+                &[config.lazy_block_index as u8, codec.id()],
+            ]
+            .concat(),
+        };
+        writer.write_all(&header)?;
+        let mut current_offset = header.len() as u64;
+
+        // Pad the header so the first block also starts on an aligned offset
+        if config.align_blocks {
+            let padding = Self::padding_for(current_offset);
+            writer.write_all(&vec![0u8; padding as usize])?;
+            current_offset += padding;
+        }
 
         let current_block = Block::from_config(&config);
 
@@ -59,69 +439,200 @@ impl SstableBuilder {
             current_block,
             block_metas: Vec::new(),
             keys_for_bloom: Vec::new(),
+            block_keys_start: 0,
+            namespaces: BTreeSet::new(),
             config,
             current_offset,
             first_key: None,
             last_key: None,
             record_count: 0,
+            max_seq: 0,
+            level: 0,
+            tmp_path: tmp_path_for(&path),
             path,
+            published: false,
+            blob_paths: Vec::new(),
+            blob_ids: Vec::new(),
+            next_blob: 0,
             timestamp,
         })
     }
 
+    /// File the table is published as once finished
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record the compaction level the table is written for (default 0)
+    pub fn set_level(&mut self, level: u32) {
+        self.level = level;
+    }
+
+    /// Bytes written so far plus the pending block, before compression
+    pub fn estimated_size(&self) -> u64 {
+        self.current_offset + self.current_block.data_size() as u64
+    }
+
     pub fn add(&mut self, key: &[u8], record: &LogRecord) -> Result<()> {
+        self.config.check_entry_size(key, &record.value)?;
+        // Every block entry stores its key behind a u16 length
+        if key.len() > u16::MAX as usize {
+            return Err(LsmError::ValueTooLarge(format!(
+                "key of {} bytes does not fit a block entry",
+                key.len()
+            )));
+        }
+        // One entry per key keeps the record count and the Bloom filter,
+        // which is sized from the keys added, exact
+        let comparator = self.config.key_comparator;
+        if let Some(last_key) = self
+            .last_key
+            .as_deref()
+            .filter(|last| comparator.compare(last, key).is_ge())
+        {
+            return Err(LsmError::CompactionFailed(format!(
+                "SSTable keys must be strictly ascending: {:?} after {:?}",
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(last_key)
+            )));
+        }
+        // Values over `inline_value_max` move to a blob file; a record that
+        // already points at one (compaction output) keeps its pointer
+        let outlined;
+        let record = match self.config.inline_value_max {
+            Some(max) if record.blob.is_none() && record.value.len() > max => {
+                let stem = self
+                    .path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let (blob, blob_path) = write_blob(
+                    &blob_dir_of(&self.path),
+                    &stem,
+                    &mut self.next_blob,
+                    &record.value,
+                )?;
+                self.blob_paths.push(blob_path);
+                outlined = LogRecord {
+                    key: record.key.clone(),
+                    value: Vec::new(),
+                    blob: Some(blob),
+                    ..*record
+                };
+                &outlined
+            }
+            _ => record,
+        };
+        if let Some(blob) = &record.blob {
+            self.blob_ids.push(blob.id.clone());
+        }
+        let value_bytes = encode_with(self.config.codec, record)?;
+
         if self.first_key.is_none() {
             self.first_key = Some(key.to_vec());
         }
         self.last_key = Some(key.to_vec());
 
-        let value_bytes = encode(record)?;
-
-        if !self.current_block.add(key, &value_bytes) {
-            self.flush_current_block()?;
+        // Large values go into their own uncompressed block so the small
+        // records around them are still compressed together
+        let store_raw = self
+            .config
+            .no_compress_value_threshold
+            .is_some_and(|threshold| record.value.len() > threshold);
+        if store_raw {
+            self.flush_current_block(true)?;
+            if !self.current_block.add(key, &value_bytes) {
+                self.current_block = Block::single(key, &value_bytes);
+            }
+            self.flush_current_block(false)?;
+        } else if !self.current_block.add(key, &value_bytes) {
+            self.flush_current_block(true)?;
 
+            // Too big for any block: give it a dedicated one
             if !self.current_block.add(key, &value_bytes) {
-                return Err(LsmError::CompactionFailed(
-                    "Entry too large for a single block".to_string(),
-                ));
+                self.current_block = Block::single(key, &value_bytes);
+                self.flush_current_block(true)?;
             }
         }
 
         self.keys_for_bloom.push(key.to_vec());
         self.record_count += 1;
+        self.max_seq = self.max_seq.max(record.seq);
+
+        if let Some(separator) = self.config.namespace_separator {
+            if !record.is_deleted {
+                if let Some(namespace) = namespace_of(&record.key, separator) {
+                    if !self.namespaces.contains(namespace) {
+                        self.namespaces.insert(namespace.to_string());
+                    }
+                }
+            }
+        }
 
         Ok(())
     }
 
-    fn flush_current_block(&mut self) -> Result<()> {
+    fn flush_current_block(&mut self, compress: bool) -> Result<()> {
         if self.current_block.is_empty() {
             return Ok(());
         }
 
         let first_key = self.extract_first_key_from_block()?;
+        let bloom = if self.config.per_block_bloom {
+            self.build_bloom_filter(&self.keys_for_bloom[self.block_keys_start..])?
+                .into_bytes()
+        } else {
+            Vec::new()
+        };
+        self.block_keys_start = self.keys_for_bloom.len();
+
         let encoded = self.current_block.encode();
         let uncompressed_size = encoded.len() as u32;
 
-        let compressed = compress_prepend_size(&encoded);
-        let compressed_size = compressed.len() as u32;
+        let compress = compress && self.config.compression != Compression::None;
+        let stored = if compress {
+            self.config.compression.compress(&encoded)?
+        } else {
+            encoded
+        };
+        let compressed_size = stored.len() as u32;
+        let checksum = crc32fast::hash(&stored);
 
-        self.writer.write_all(&compressed)?;
+        self.writer.write_all(&stored)?;
+
+        let padding = if self.config.align_blocks {
+            Self::padding_for(self.current_offset + compressed_size as u64) as u32
+        } else {
+            0
+        };
+        if padding > 0 {
+            self.writer.write_all(&vec![0u8; padding as usize])?;
+        }
 
         let block_meta = BlockMeta {
             first_key,
             offset: self.current_offset,
-            size: compressed_size,
+            size: compressed_size + padding,
             uncompressed_size,
+            padding,
+            compressed: compress,
+            checksum,
+            bloom,
+            single_entry: self.current_block.is_oversized(),
         };
 
         self.block_metas.push(block_meta);
-        self.current_offset += compressed_size as u64;
+        self.current_offset += (compressed_size + padding) as u64;
 
         self.current_block = Block::from_config(&self.config);
 
         Ok(())
     }
 
+    fn padding_for(offset: u64) -> u64 {
+        (BLOCK_ALIGNMENT - offset % BLOCK_ALIGNMENT) % BLOCK_ALIGNMENT
+    }
+
     fn extract_first_key_from_block(&self) -> Result<Vec<u8>> {
         let encoded = self.current_block.encode();
         if encoded.len() < 2 {
@@ -139,7 +650,7 @@ impl SstableBuilder {
     }
 
     pub fn finish(mut self) -> Result<PathBuf> {
-        self.flush_current_block()?;
+        self.flush_current_block(true)?;
 
         if self.block_metas.is_empty() {
             return Err(LsmError::CompactionFailed(
@@ -147,16 +658,40 @@ impl SstableBuilder {
             ));
         }
 
-        let bloom = self.build_bloom_filter()?;
-        let bloom_bytes = bloom.into_bytes();
+        // Tiny tables are cheaper to probe through the sparse index directly
+        let has_bloom = self.keys_for_bloom.len() >= self.config.min_keys_for_bloom;
+        let bloom_bytes = if has_bloom {
+            self.build_bloom_filter(&self.keys_for_bloom)?.into_bytes()
+        } else {
+            Vec::new()
+        };
+
+        // V4 keeps the index out of the MetaBlock
+        let lazy_index = if self.config.lazy_block_index {
+            Some(self.write_lazy_index()?)
+        } else {
+            None
+        };
+        let blocks = if lazy_index.is_some() {
+            Vec::new()
+        } else {
+            std::mem::take(&mut self.block_metas)
+        };
 
         let meta_block = MetaBlock {
-            blocks: self.block_metas,
+            blocks,
+            has_bloom,
             bloom_filter_data: bloom_bytes,
-            min_key: self.first_key.unwrap(),
-            max_key: self.last_key.unwrap(),
+            min_key: self.first_key.take().unwrap(),
+            max_key: self.last_key.take().unwrap(),
             record_count: self.record_count,
             timestamp: self.timestamp,
+            namespaces: std::mem::take(&mut self.namespaces).into_iter().collect(),
+            max_seq: self.max_seq,
+            level: self.level,
+            compression: self.config.compression,
+            comparator: self.config.key_comparator,
+            blob_ids: Some(std::mem::take(&mut self.blob_ids)),
         };
 
         let meta_encoded = encode(&meta_block)?;
@@ -165,23 +700,71 @@ impl SstableBuilder {
 
         self.writer.write_all(&meta_compressed)?;
 
-        let footer_bytes = meta_offset.to_le_bytes();
-        self.writer.write_all(&footer_bytes)?;
+        match lazy_index {
+            Some((heap_offset, index_offset, count)) => {
+                for value in [heap_offset, index_offset, count, meta_offset] {
+                    self.writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+            None => {
+                let footer_bytes = meta_offset.to_le_bytes();
+                self.writer.write_all(&footer_bytes)?;
+            }
+        }
 
         self.writer.flush()?;
         self.writer.get_ref().sync_all()?;
+        // Every blob file was fsynced when written; their directory entries
+        // must be durable before the table pointing at them is
+        if self.config.sync_directory {
+            if let Some(blob) = self.blob_paths.first() {
+                sync_parent_dir(blob)?;
+            }
+        }
+
+        // Readers never see a partial table: it only takes its real name
+        // once every byte is on disk
+        std::fs::rename(&self.tmp_path, &self.path)?;
+        self.published = true;
+        if self.config.sync_directory {
+            sync_parent_dir(&self.path)?;
+        }
 
-        Ok(self.path)
+        Ok(std::mem::take(&mut self.path))
     }
 
-    fn build_bloom_filter(&self) -> Result<Bloom<[u8]>> {
-        let mut bloom = Bloom::<[u8]>::new_for_fp_rate(
-            self.keys_for_bloom.len(),
-            self.config.bloom_false_positive_rate,
-        )
+    /// Write the key heap and fixed-size index entries, returning
+    /// `(heap_offset, index_offset, entry_count)`
+    fn write_lazy_index(&mut self) -> Result<(u64, u64, u64)> {
+        let heap_offset = self.current_offset;
+        let mut key_offsets = Vec::with_capacity(self.block_metas.len());
+        let mut heap_len = 0u64;
+        for meta in &self.block_metas {
+            self.writer.write_all(&meta.first_key)?;
+            self.writer.write_all(&meta.bloom)?;
+            key_offsets.push(heap_len);
+            heap_len += (meta.first_key.len() + meta.bloom.len()) as u64;
+        }
+
+        let index_offset = heap_offset + heap_len;
+        for (meta, key_offset) in self.block_metas.iter().zip(key_offsets) {
+            self.writer.write_all(&encode_entry(meta, key_offset))?;
+        }
+
+        let count = self.block_metas.len() as u64;
+        self.current_offset = index_offset + count * INDEX_ENTRY_SIZE as u64;
+        Ok((heap_offset, index_offset, count))
+    }
+
+    fn build_bloom_filter(&self, keys: &[Vec<u8>]) -> Result<Bloom<[u8]>> {
+        let fp_rate = self.config.bloom_false_positive_rate;
+        let mut bloom = match &self.config.bloom_seed {
+            Some(seed) => Bloom::<[u8]>::new_for_fp_rate_with_seed(keys.len(), fp_rate, seed),
+            None => Bloom::<[u8]>::new_for_fp_rate(keys.len(), fp_rate),
+        }
         .map_err(|e| LsmError::CompactionFailed(format!("Bloom filter creation failed: {}", e)))?;
 
-        for key in &self.keys_for_bloom {
+        for key in keys {
             bloom.set(key);
         }
 
@@ -189,9 +772,45 @@ impl SstableBuilder {
     }
 }
 
+impl Drop for SstableBuilder {
+    /// Remove the temporary file and blobs of a table that was never finished
+    fn drop(&mut self) {
+        if self.published {
+            return;
+        }
+        for blob in &self.blob_paths {
+            if let Err(e) = std::fs::remove_file(blob) {
+                warn!(
+                    "Failed to remove unpublished blob {}: {}",
+                    blob.display(),
+                    e
+                );
+            }
+        }
+        if let Err(e) = std::fs::remove_file(&self.tmp_path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!(
+                    "Failed to remove unfinished SSTable {}: {}",
+                    self.tmp_path.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// `{path}.tmp`, where a table destined for `path` is written
+fn tmp_path_for(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(SST_TMP_SUFFIX);
+    PathBuf::from(name)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::cache::GlobalBlockCache;
+    use crate::storage::reader::SstableReader;
 
     fn create_test_record(key: &str, value: &[u8]) -> LogRecord {
         LogRecord::new(key.to_string(), value.to_vec())
@@ -214,6 +833,53 @@ mod tests {
         assert!(path.exists());
     }
 
+    #[test]
+    fn test_builder_publishes_the_table_only_when_finished() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("atomic.sst");
+        let tmp_path = dir.path().join("atomic.sst.tmp");
+
+        let mut builder = SstableBuilder::new(path.clone(), StorageConfig::default(), 1).unwrap();
+        builder
+            .add(b"key1", &create_test_record("key1", b"value1"))
+            .unwrap();
+        assert!(tmp_path.exists());
+        assert!(!path.exists());
+
+        // The name is taken while the table is in progress
+        assert!(SstableBuilder::new(path.clone(), StorageConfig::default(), 2).is_err());
+
+        builder.finish().unwrap();
+        assert!(path.exists());
+        assert!(!tmp_path.exists());
+
+        // An abandoned table leaves nothing behind
+        let other = dir.path().join("abandoned.sst");
+        let mut builder = SstableBuilder::new(other.clone(), StorageConfig::default(), 3).unwrap();
+        builder
+            .add(b"key1", &create_test_record("key1", b"value1"))
+            .unwrap();
+        drop(builder);
+        assert!(!other.exists());
+        assert!(!dir.path().join("abandoned.sst.tmp").exists());
+    }
+
+    #[test]
+    fn test_builder_rejects_keys_out_of_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("order.sst");
+        let mut builder = SstableBuilder::new(path, StorageConfig::default(), 1).unwrap();
+
+        builder.add(b"b", &create_test_record("b", b"1")).unwrap();
+        for key in ["b", "a"] {
+            assert!(matches!(
+                builder.add(key.as_bytes(), &create_test_record(key, b"2")),
+                Err(LsmError::CompactionFailed(_))
+            ));
+        }
+        builder.add(b"c", &create_test_record("c", b"3")).unwrap();
+    }
+
     #[test]
     fn test_builder_multiple_blocks() {
         let dir = tempfile::tempdir().unwrap();
@@ -259,4 +925,202 @@ mod tests {
         let result = builder.finish();
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_builder_rejects_entries_over_the_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("oversized.sst");
+        let config = StorageConfig {
+            max_value_size: 64 * 1024,
+            ..Default::default()
+        };
+
+        let mut builder = SstableBuilder::new(path, config, 999).unwrap();
+        let record = create_test_record("k", &vec![b'x'; 70 * 1024]);
+        assert!(matches!(
+            builder.add(b"k", &record),
+            Err(LsmError::ValueTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_builder_gives_oversized_entries_their_own_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("single.sst");
+        let config = StorageConfig {
+            max_value_size: 1024 * 1024,
+            ..Default::default()
+        };
+
+        let big = vec![b'x'; 100 * 1024];
+        let mut builder = SstableBuilder::new(path.clone(), config.clone(), 999).unwrap();
+        builder
+            .add(b"a", &create_test_record("a", b"small"))
+            .unwrap();
+        builder.add(b"b", &create_test_record("b", &big)).unwrap();
+        builder
+            .add(b"c", &create_test_record("c", b"small"))
+            .unwrap();
+        builder.finish().unwrap();
+
+        let reader = SstableReader::open(path, config, GlobalBlockCache::new(1, 4096)).unwrap();
+        let single: Vec<bool> = (0..reader.block_count())
+            .map(|i| reader.block_meta(i).unwrap().single_entry)
+            .collect();
+        assert_eq!(single, vec![false, true, false]);
+        assert_eq!(reader.get("b").unwrap().unwrap().value, big);
+        assert_eq!(reader.get("c").unwrap().unwrap().value, b"small");
+        assert_eq!(reader.scan().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_meta_block_decodes_legacy_layouts() {
+        fn legacy<B>(blocks: Vec<B>) -> LegacyMetaBlock<B> {
+            LegacyMetaBlock {
+                blocks,
+                has_bloom: false,
+                bloom_filter_data: Vec::new(),
+                min_key: b"a".to_vec(),
+                max_key: b"z".to_vec(),
+                record_count: 3,
+                timestamp: 1,
+                namespaces: Vec::new(),
+                max_seq: 9,
+                level: 0,
+            }
+        }
+
+        let v1 = legacy(vec![BlockMetaV1 {
+            first_key: b"a".to_vec(),
+            offset: 0,
+            size: 10,
+            uncompressed_size: 20,
+            padding: 0,
+            compressed: true,
+            checksum: 7,
+            bloom: Vec::new(),
+        }]);
+        let meta = MetaBlock::decode(&encode(&v1).unwrap()).unwrap();
+        assert_eq!(meta.blocks.len(), 1);
+        assert_eq!(meta.blocks[0].checksum, 7);
+        assert!(!meta.blocks[0].single_entry);
+        assert_eq!((meta.record_count, meta.max_seq), (3, 9));
+        assert_eq!(meta.compression, Compression::Lz4);
+
+        // Single-entry flags, but no compression id yet
+        let mut v2 = legacy(meta.blocks.clone());
+        v2.blocks[0].single_entry = true;
+        let meta = MetaBlock::decode(&encode(&v2).unwrap()).unwrap();
+        assert!(meta.blocks[0].single_entry);
+        assert_eq!(meta.compression, Compression::Lz4);
+
+        // Compression id, but no comparator yet
+        let v3 = ByteOrderMetaBlock {
+            blocks: meta.blocks.clone(),
+            has_bloom: false,
+            bloom_filter_data: Vec::new(),
+            min_key: b"a".to_vec(),
+            max_key: b"z".to_vec(),
+            record_count: 3,
+            timestamp: 1,
+            namespaces: Vec::new(),
+            max_seq: 9,
+            level: 0,
+            compression: Compression::Zstd,
+        };
+        let meta = MetaBlock::decode(&encode(&v3).unwrap()).unwrap();
+        assert_eq!(meta.compression, Compression::Zstd);
+        assert_eq!(meta.comparator, KeyComparator::Lexicographic);
+
+        let current = MetaBlock {
+            comparator: KeyComparator::NumericSuffix,
+            ..meta
+        };
+        let meta = MetaBlock::decode(&encode(&current).unwrap()).unwrap();
+        assert_eq!(meta.compression, Compression::Zstd);
+        assert_eq!(meta.comparator, KeyComparator::NumericSuffix);
+    }
+
+    #[test]
+    fn test_builder_records_namespaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ns.sst");
+        let config = StorageConfig {
+            namespace_separator: Some(':'),
+            ..Default::default()
+        };
+
+        let mut builder = SstableBuilder::new(path, config, 1).unwrap();
+        builder
+            .add(b"order:1", &create_test_record("order:1", b"a"))
+            .unwrap();
+        builder
+            .add(b"plain", &create_test_record("plain", b"b"))
+            .unwrap();
+        builder
+            .add(b"session:9", &LogRecord::tombstone("session:9".to_string()))
+            .unwrap();
+        builder
+            .add(b"user:1", &create_test_record("user:1", b"c"))
+            .unwrap();
+        builder
+            .add(b"user:2", &create_test_record("user:2", b"d"))
+            .unwrap();
+        assert_eq!(
+            builder.namespaces.iter().collect::<Vec<_>>(),
+            vec!["order", "user"]
+        );
+    }
+
+    #[test]
+    fn test_builder_records_blob_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig {
+            inline_value_max: Some(16),
+            ..Default::default()
+        };
+
+        let first = dir.path().join("1.sst");
+        let mut builder = SstableBuilder::new(first.clone(), config.clone(), 1).unwrap();
+        builder
+            .add(b"a", &create_test_record("a", &[1; 64]))
+            .unwrap();
+        builder
+            .add(b"b", &create_test_record("b", b"small"))
+            .unwrap();
+        builder.finish().unwrap();
+        let cache = GlobalBlockCache::new(1, 4096);
+        let reader = SstableReader::open(first, config.clone(), cache.clone()).unwrap();
+        let ids = reader.metadata().blob_ids.clone().unwrap();
+        assert_eq!(ids.len(), 1);
+
+        // Compaction output keeps pointing at the input's blob
+        let outlined = reader.get_raw("a").unwrap().unwrap();
+        let second = dir.path().join("2.sst");
+        let mut builder = SstableBuilder::new(second.clone(), config.clone(), 2).unwrap();
+        builder.add(b"a", &outlined).unwrap();
+        builder.finish().unwrap();
+        let reader = SstableReader::open(second, config, cache).unwrap();
+        assert_eq!(reader.metadata().blob_ids, Some(ids));
+
+        // Tables from before `blob_ids` existed
+        let meta = reader.metadata();
+        let untracked = UntrackedBlobsMetaBlock {
+            blocks: meta.blocks.clone(),
+            has_bloom: meta.has_bloom,
+            bloom_filter_data: meta.bloom_filter_data.clone(),
+            min_key: meta.min_key.clone(),
+            max_key: meta.max_key.clone(),
+            record_count: meta.record_count,
+            timestamp: meta.timestamp,
+            namespaces: meta.namespaces.clone(),
+            max_seq: meta.max_seq,
+            level: meta.level,
+            compression: meta.compression,
+            comparator: KeyComparator::NumericSuffix,
+        };
+        let meta = MetaBlock::decode(&encode(&untracked).unwrap()).unwrap();
+        assert_eq!(meta.blob_ids, None);
+        assert_eq!(meta.comparator, KeyComparator::NumericSuffix);
+    }
 }
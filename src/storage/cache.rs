@@ -3,6 +3,7 @@ use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
 /// Cache key that uniquely identifies a block across multiple SSTable files.
@@ -36,6 +37,11 @@ impl CacheKey {
 #[derive(Debug)]
 pub struct GlobalBlockCache {
     cache: Mutex<LruCache<CacheKey, Arc<Vec<u8>>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    /// Entries pushed out by `put` to make room (replacing a key's block
+    /// doesn't count)
+    evictions: AtomicU64,
 }
 
 impl GlobalBlockCache {
@@ -54,6 +60,9 @@ impl GlobalBlockCache {
 
         Arc::new(Self {
             cache: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
         })
     }
 
@@ -66,7 +75,14 @@ impl GlobalBlockCache {
     /// Some(Arc<Vec<u8>>) if found, None if cache miss
     pub fn get(&self, key: &CacheKey) -> Option<Arc<Vec<u8>>> {
         let mut cache = self.cache.lock().unwrap();
-        cache.get(key).cloned()
+        let block = cache.get(key).cloned();
+        let counter = if block.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        block
     }
 
     /// Inserts a block into the cache.
@@ -76,7 +92,13 @@ impl GlobalBlockCache {
     /// * `value` - Block data to cache
     pub fn put(&self, key: CacheKey, value: Vec<u8>) {
         let mut cache = self.cache.lock().unwrap();
-        cache.put(key, Arc::new(value));
+        // `push` hands back either the key's previous block or the LRU entry
+        // it displaced
+        if let Some((displaced, _)) = cache.push(key.clone(), Arc::new(value)) {
+            if displaced != key {
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
     }
 
     /// Clears all entries from the cache.
@@ -91,6 +113,9 @@ impl GlobalBlockCache {
         CacheStats {
             len: cache.len(),
             cap: cache.cap().get(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
         }
     }
 }
@@ -102,6 +127,98 @@ pub struct CacheStats {
     pub len: usize,
     /// Maximum capacity of the cache
     pub cap: usize,
+    /// Lookups served from the cache since it was created
+    pub hits: u64,
+    /// Lookups that had to go to disk
+    pub misses: u64,
+    /// Blocks dropped to make room for new ones
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// Share of lookups served from the cache, 0.0 before the first one
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+/// Keys recently found absent, so repeated lookups of a missing key skip the
+/// SSTables.
+///
+/// Every write of a key must [`invalidate`](Self::invalidate) it after the
+/// write is visible to readers. A lookup takes the [`generation`](Self::generation)
+/// before reading and hands it back to [`insert`](Self::insert), which drops
+/// the entry if any invalidation happened in between: the miss may predate
+/// that write.
+#[derive(Debug)]
+pub struct NegativeCache {
+    inner: Mutex<NegativeEntries>,
+    hits: AtomicU64,
+}
+
+#[derive(Debug)]
+struct NegativeEntries {
+    keys: LruCache<String, ()>,
+    generation: u64,
+}
+
+impl NegativeCache {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            inner: Mutex::new(NegativeEntries {
+                keys: LruCache::new(capacity),
+                generation: 0,
+            }),
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether `key` is known to be absent
+    pub fn contains(&self, key: &str) -> bool {
+        let found = self.inner.lock().unwrap().keys.get(key).is_some();
+        if found {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        found
+    }
+
+    /// Take before a lookup whose miss will be passed to `insert`
+    pub fn generation(&self) -> u64 {
+        self.inner.lock().unwrap().generation
+    }
+
+    /// Remember `key` as absent, unless something was invalidated since
+    /// `generation` was taken
+    pub fn insert(&self, key: &str, generation: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation == generation {
+            inner.keys.put(key.to_string(), ());
+        }
+    }
+
+    /// Forget `key`, which may have just been written
+    pub fn invalidate(&self, key: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.keys.pop(key);
+        inner.generation += 1;
+    }
+
+    /// Forget every key, for when the set of SSTables changes
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.keys.clear();
+        inner.generation += 1;
+    }
+
+    /// Lookups answered from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
 }
 
 #[cfg(test)]
@@ -274,4 +391,56 @@ mod tests {
 
         assert_eq!(cache.stats().len, 2);
     }
+
+    #[test]
+    fn test_hit_miss_and_eviction_counters() {
+        let cache = GlobalBlockCache::new(1, 512 * 1024); // 2 blocks
+        let key = |n: u64| CacheKey::new(&PathBuf::from("test.sst"), n);
+        assert_eq!(cache.stats().hit_ratio(), 0.0);
+
+        assert!(cache.get(&key(0)).is_none());
+        cache.put(key(0), vec![0]);
+        cache.put(key(1), vec![1]);
+        assert!(cache.get(&key(0)).is_some());
+        assert!(cache.get(&key(1)).is_some());
+        assert!(cache.get(&key(0)).is_some());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (3, 1, 0));
+        assert_eq!(stats.hit_ratio(), 0.75);
+
+        // Replacing a block isn't an eviction; pushing out key(1) is
+        cache.put(key(0), vec![9]);
+        assert_eq!(cache.stats().evictions, 0);
+        cache.put(key(2), vec![2]);
+        assert!(cache.get(&key(1)).is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (3, 2, 1));
+        assert_eq!(stats.hit_ratio(), 0.6);
+    }
+
+    #[test]
+    fn test_negative_cache_skips_misses_older_than_a_write() {
+        let cache = NegativeCache::new(NonZeroUsize::new(2).unwrap());
+
+        let generation = cache.generation();
+        cache.insert("a", generation);
+        assert!(cache.contains("a"));
+        assert_eq!(cache.hits(), 1);
+
+        // A write lands between the lookup and the insert of its miss
+        let generation = cache.generation();
+        cache.invalidate("b");
+        cache.insert("b", generation);
+        assert!(!cache.contains("b"));
+
+        cache.invalidate("a");
+        assert!(!cache.contains("a"));
+
+        cache.insert("c", cache.generation());
+        cache.clear();
+        assert!(!cache.contains("c"));
+        assert_eq!(cache.hits(), 1);
+    }
 }
@@ -0,0 +1,74 @@
+//! Key ordering behind [`KeyComparator`].
+
+use crate::infra::config::KeyComparator;
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+impl KeyComparator {
+    pub fn compare(self, a: &[u8], b: &[u8]) -> Ordering {
+        match self {
+            KeyComparator::Lexicographic => a.cmp(b),
+            KeyComparator::NumericSuffix => {
+                let (a_prefix, a_number) = split_numeric_suffix(a);
+                let (b_prefix, b_number) = split_numeric_suffix(b);
+                a_prefix
+                    .cmp(b_prefix)
+                    .then_with(|| {
+                        // Without leading zeros, the longer number is larger
+                        a_number
+                            .len()
+                            .cmp(&b_number.len())
+                            .then_with(|| a_number.cmp(b_number))
+                    })
+                    .then_with(|| a.cmp(b))
+            }
+        }
+    }
+
+    /// Whether `key` sorts before the lower bound `start`
+    pub(crate) fn before_start<K: AsRef<[u8]>>(self, key: &[u8], start: Bound<K>) -> bool {
+        match start {
+            Bound::Included(start) => self.compare(key, start.as_ref()).is_lt(),
+            Bound::Excluded(start) => self.compare(key, start.as_ref()).is_le(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    /// Whether `key` sorts after the upper bound `end`
+    pub(crate) fn after_end<K: AsRef<[u8]>>(self, key: &[u8], end: Bound<K>) -> bool {
+        match end {
+            Bound::Included(end) => self.compare(key, end.as_ref()).is_gt(),
+            Bound::Excluded(end) => self.compare(key, end.as_ref()).is_ge(),
+            Bound::Unbounded => false,
+        }
+    }
+}
+
+/// `key` split before its trailing ASCII digits, with the digits' leading
+/// zeros dropped
+fn split_numeric_suffix(key: &[u8]) -> (&[u8], &[u8]) {
+    let digits = key.iter().rev().take_while(|b| b.is_ascii_digit()).count();
+    let (prefix, number) = key.split_at(key.len() - digits);
+    let zeros = number.iter().take_while(|&&b| b == b'0').count();
+    (prefix, &number[zeros..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numeric_suffix_order() {
+        let mut keys = vec![
+            "key_10", "key_2", "key", "key_002", "key_1", "key_a", "key_", "ke9", "key_100",
+        ];
+        keys.sort_by(|a, b| KeyComparator::NumericSuffix.compare(a.as_bytes(), b.as_bytes()));
+        assert_eq!(
+            keys,
+            vec!["ke9", "key", "key_", "key_1", "key_002", "key_2", "key_10", "key_100", "key_a"]
+        );
+
+        keys.sort_by(|a, b| KeyComparator::Lexicographic.compare(a.as_bytes(), b.as_bytes()));
+        assert_eq!(keys[3..6], ["key_002", "key_1", "key_10"]);
+    }
+}
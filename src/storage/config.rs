@@ -1,28 +1,87 @@
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub enum CompactionStrategy {
+    /// Tables are only merged on demand (`LsmEngine::compact_to_single_file`)
+    #[default]
     SizeTiered,
+    /// Flushed tables land in L0; once there are `level0_compaction_threshold`
+    /// of them they are merged into non-overlapping L1 tables
     Leveled,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct StorageConfig {
-    pub block_size: usize,
-    pub block_cache_size_mb: usize,
-    pub sparse_index_interval: usize,
-    pub compaction_strategy: CompactionStrategy,
-    pub bloom_false_positive_rate: f64,
+/// How `SstableReader` fetches blocks that miss the block cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum IoMode {
+    /// `seek` + `read_exact` on the table's file for every block
+    #[default]
+    Syscall,
+    /// Map the table once when it is opened and copy blocks out of the
+    /// mapping (requires the `mmap` feature)
+    Mmap,
 }
 
-impl Default for StorageConfig {
-    fn default() -> Self {
-        Self {
-            block_size: 4096,
-            block_cache_size_mb: 64,
-            sparse_index_interval: 16,
-            compaction_strategy: CompactionStrategy::SizeTiered,
-            bloom_false_positive_rate: 0.01,
-        }
-    }
+/// Data structure behind each MemTable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum MemTableImpl {
+    /// `BTreeMap` behind a read/write lock: inserts are serialized
+    #[default]
+    BTree,
+    /// Lock-free skip list: inserts of different keys run in parallel
+    SkipList,
+}
+
+/// How SSTable data blocks are compressed. Each table records the
+/// algorithm it was written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Compression {
+    /// Blocks are stored as-is: no CPU spent on reads or writes
+    None,
+    /// Fast compression with a moderate ratio
+    #[default]
+    Lz4,
+    /// Slower, with a better ratio: suits cold data
+    Zstd,
+}
+
+/// Order of keys in MemTables, SSTables and every scan. Each SSTable records
+/// the comparator it was built with; a store can't switch comparators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum KeyComparator {
+    /// Byte order: `key_10` sorts before `key_2`
+    #[default]
+    Lexicographic,
+    /// Keys are compared by everything before their trailing ASCII digits,
+    /// then by the digits' numeric value, so `key_2` sorts before `key_10`
+    /// (keys whose digits only differ in leading zeros fall back to byte order)
+    NumericSuffix,
+}
+
+/// Serialization of records in the WAL and in SSTable data blocks. Each file
+/// records the codec it was written with, so changing it leaves existing
+/// files readable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Codec {
+    /// Fixed-width integers: 16 bytes for every timestamp
+    #[default]
+    Fixint,
+    /// Variable-length integers: smaller records, slightly more CPU to decode
+    Varint,
+}
+
+/// When the WAL forces appended records down to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum WalSyncPolicy {
+    /// fsync before every write returns: an acknowledged write survives a
+    /// power loss
+    #[default]
+    Always,
+    /// Hand each write to the OS and fsync from a background thread at this
+    /// interval: survives a process crash, but a power loss can drop up to
+    /// one interval of acknowledged writes
+    Interval(Duration),
+    /// Hand each write to the OS and never fsync: survives a process crash,
+    /// but a power loss can drop anything the OS hasn't written back yet
+    Never,
 }
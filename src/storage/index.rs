@@ -0,0 +1,206 @@
+//! Fixed-size block index used by `LSMSST04` tables.
+//!
+//! V3 tables keep every `BlockMeta` (including its first key) inside the
+//! `MetaBlock`, so opening a table deserializes the whole index. V4 tables
+//! store the index after the data blocks as:
+//!
+//! ```text
+//! [key heap: first key (+ block Bloom filter) of each block, back to back]
+//! [entry 0][entry 1]...[entry N-1]      (INDEX_ENTRY_SIZE bytes each)
+//! [MetaBlock without `blocks`]
+//! [footer: heap_offset | index_offset | entry_count | meta_offset]  (u64 LE each)
+//! ```
+//!
+//! Entry `i` can be located with a single seek, so a lookup binary-searches the
+//! index reading only O(log n) entries and opening the file costs O(1) memory
+//! for the index.
+
+use crate::infra::config::KeyComparator;
+use crate::infra::error::{LsmError, Result};
+use crate::storage::builder::BlockMeta;
+use crate::storage::read_exact_at;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// key_offset u64 | offset u64 | key_len u32 | size u32 | uncompressed_size u32
+/// | padding u32 | flags u32 | checksum u32 | bloom_len u32
+///
+/// A block's Bloom filter, if any, follows its first key in the heap.
+pub const INDEX_ENTRY_SIZE: usize = 44;
+
+/// Size of the V4 footer
+pub const FOOTER_V4_SIZE: u64 = 32;
+
+const FLAG_COMPRESSED: u32 = 1;
+const FLAG_SINGLE_ENTRY: u32 = 2;
+
+/// Serialize one index entry; `key_offset` is relative to the start of the key heap
+pub(crate) fn encode_entry(meta: &BlockMeta, key_offset: u64) -> [u8; INDEX_ENTRY_SIZE] {
+    let mut buf = [0u8; INDEX_ENTRY_SIZE];
+    let mut flags = 0;
+    if meta.compressed {
+        flags |= FLAG_COMPRESSED;
+    }
+    if meta.single_entry {
+        flags |= FLAG_SINGLE_ENTRY;
+    }
+
+    buf[0..8].copy_from_slice(&key_offset.to_le_bytes());
+    buf[8..16].copy_from_slice(&meta.offset.to_le_bytes());
+    buf[16..20].copy_from_slice(&(meta.first_key.len() as u32).to_le_bytes());
+    buf[20..24].copy_from_slice(&meta.size.to_le_bytes());
+    buf[24..28].copy_from_slice(&meta.uncompressed_size.to_le_bytes());
+    buf[28..32].copy_from_slice(&meta.padding.to_le_bytes());
+    buf[32..36].copy_from_slice(&flags.to_le_bytes());
+    buf[36..40].copy_from_slice(&meta.checksum.to_le_bytes());
+    buf[40..44].copy_from_slice(&(meta.bloom.len() as u32).to_le_bytes());
+    buf
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[at..at + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Location of a V4 index inside an open table
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LazyIndex {
+    heap_offset: u64,
+    index_offset: u64,
+    count: usize,
+}
+
+impl LazyIndex {
+    /// Read the V4 footer, returning the index and the MetaBlock offset
+    pub(crate) fn read_footer(file: &mut File) -> Result<(Self, u64)> {
+        file.seek(SeekFrom::End(-(FOOTER_V4_SIZE as i64)))?;
+        let mut footer = [0u8; FOOTER_V4_SIZE as usize];
+        file.read_exact(&mut footer)?;
+
+        let index = Self {
+            heap_offset: u64_at(&footer, 0),
+            index_offset: u64_at(&footer, 8),
+            count: u64_at(&footer, 16) as usize,
+        };
+        let meta_offset = u64_at(&footer, 24);
+
+        if index.heap_offset > index.index_offset || index.index_offset > meta_offset {
+            return Err(LsmError::CorruptedData(
+                "V4 footer offsets out of order".to_string(),
+            ));
+        }
+        if index.index_offset + (index.count * INDEX_ENTRY_SIZE) as u64 != meta_offset {
+            return Err(LsmError::CorruptedData(
+                "V4 index size does not match footer".to_string(),
+            ));
+        }
+
+        Ok((index, meta_offset))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Read entry `i`, including its first key and Bloom filter
+    pub(crate) fn entry(&self, file: &File, i: usize) -> Result<BlockMeta> {
+        if i >= self.count {
+            return Err(LsmError::CorruptedData(format!(
+                "Index entry {} out of range ({} entries)",
+                i, self.count
+            )));
+        }
+
+        let mut buf = [0u8; INDEX_ENTRY_SIZE];
+        read_exact_at(
+            file,
+            &mut buf,
+            self.index_offset + (i * INDEX_ENTRY_SIZE) as u64,
+        )?;
+
+        let key_offset = u64_at(&buf, 0);
+        let key_len = u32_at(&buf, 16) as usize;
+        let bloom_len = u32_at(&buf, 40) as usize;
+        if self.heap_offset + key_offset + (key_len + bloom_len) as u64 > self.index_offset {
+            return Err(LsmError::CorruptedData(format!(
+                "Index entry {} points outside the key heap",
+                i
+            )));
+        }
+
+        let mut first_key = vec![0u8; key_len + bloom_len];
+        read_exact_at(file, &mut first_key, self.heap_offset + key_offset)?;
+        let bloom = first_key.split_off(key_len);
+
+        Ok(BlockMeta {
+            first_key,
+            offset: u64_at(&buf, 8),
+            size: u32_at(&buf, 20),
+            uncompressed_size: u32_at(&buf, 24),
+            padding: u32_at(&buf, 28),
+            compressed: u32_at(&buf, 32) & FLAG_COMPRESSED != 0,
+            checksum: u32_at(&buf, 36),
+            bloom,
+            single_entry: u32_at(&buf, 32) & FLAG_SINGLE_ENTRY != 0,
+        })
+    }
+
+    /// Number of entries whose first key is `<= key` (binary search, reading
+    /// O(log n) entries)
+    pub(crate) fn partition_point(
+        &self,
+        file: &File,
+        key: &[u8],
+        comparator: KeyComparator,
+    ) -> Result<usize> {
+        let (mut lo, mut hi) = (0, self.count);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if comparator
+                .compare(&self.entry(file, mid)?.first_key, key)
+                .is_le()
+            {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(lo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_roundtrip() {
+        let meta = BlockMeta {
+            first_key: b"apple".to_vec(),
+            offset: 4096,
+            size: 300,
+            uncompressed_size: 512,
+            padding: 12,
+            compressed: false,
+            checksum: 0xdead_beef,
+            bloom: vec![1, 2, 3],
+            single_entry: true,
+        };
+        let buf = encode_entry(&meta, 77);
+
+        assert_eq!(u64_at(&buf, 0), 77);
+        assert_eq!(u64_at(&buf, 8), 4096);
+        assert_eq!(u32_at(&buf, 16), 5);
+        assert_eq!(u32_at(&buf, 20), 300);
+        assert_eq!(u32_at(&buf, 24), 512);
+        assert_eq!(u32_at(&buf, 28), 12);
+        assert_eq!(u32_at(&buf, 32), FLAG_SINGLE_ENTRY);
+        assert_eq!(u32_at(&buf, 36), 0xdead_beef);
+        assert_eq!(u32_at(&buf, 40), 3);
+    }
+}
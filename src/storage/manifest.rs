@@ -0,0 +1,201 @@
+//! `MANIFEST`: the set of live SSTables, kept as an append-only log of edits.
+//!
+//! A table is live exactly when the edit adding it reached the disk, so a
+//! half-written flush output or a compaction input awaiting deletion is never
+//! mistaken for live data, whatever else sits in the directory. Each edit is
+//! framed like a WAL record, `[len u32][crc32 u32][payload]`, around a bincode
+//! [`ManifestEdit`]. A compaction adds its outputs and removes its inputs in a
+//! single edit, so a crash leaves either the old tables live or the new ones.
+
+use crate::infra::codec::{decode, encode};
+use crate::infra::error::{LsmError, Result};
+use crate::storage::sync_parent_dir;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const MANIFEST_FILE: &str = "MANIFEST";
+const MANIFEST_TMP_FILE: &str = "MANIFEST.tmp";
+
+/// Bytes before each edit's payload: `[len u32][crc32 u32]`
+const FRAME_HEADER_SIZE: usize = 8;
+
+/// One atomic change to the live set, by SSTable file name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEdit {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+pub struct Manifest {
+    file: Mutex<File>,
+}
+
+impl Manifest {
+    /// Replay the manifest in `dir` into the names of the live tables, in the
+    /// order they were added. `None` when there is no manifest yet, i.e. a
+    /// store written before manifests existed.
+    ///
+    /// A final edit that is incomplete or fails its checksum was cut short by
+    /// a crash and never took effect, so it is skipped. A bad edit with more
+    /// data behind it is corruption.
+    pub fn load(dir: &Path) -> Result<Option<Vec<String>>> {
+        let data = match std::fs::read(dir.join(MANIFEST_FILE)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut live: Vec<String> = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let Some(edit) = read_edit(&data[pos..]) else {
+                break;
+            };
+            let (edit, size) = edit.map_err(|e| {
+                LsmError::CorruptedData(format!("{} at offset {}: {}", MANIFEST_FILE, pos, e))
+            })?;
+            live.retain(|name| !edit.removed.contains(name));
+            live.extend(edit.added);
+            pos += size;
+        }
+        Ok(Some(live))
+    }
+
+    /// Start a fresh manifest in `dir` listing `live`, replacing any existing
+    /// one in a single rename. Done on every open, which also keeps the log
+    /// from growing without bound.
+    pub fn create(dir: &Path, live: &[String], sync_directory: bool) -> Result<Self> {
+        let tmp = dir.join(MANIFEST_TMP_FILE);
+        let mut file = File::create(&tmp)?;
+        file.write_all(&frame(&ManifestEdit {
+            added: live.to_vec(),
+            removed: Vec::new(),
+        })?)?;
+        file.sync_all()?;
+        drop(file);
+
+        let path: PathBuf = dir.join(MANIFEST_FILE);
+        std::fs::rename(&tmp, &path)?;
+        if sync_directory {
+            sync_parent_dir(&path)?;
+        }
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Append `edit` and fsync it; the change is durable once this returns
+    pub fn record(&self, edit: &ManifestEdit) -> Result<()> {
+        let frame = frame(edit)?;
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| LsmError::LockPoisoned("manifest"))?;
+        file.write_all(&frame)?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+fn frame(edit: &ManifestEdit) -> Result<Vec<u8>> {
+    let payload = encode(edit)?;
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// The edit at the start of `data` and its framed size. `None` for a torn
+/// final edit; an error for a bad one with more data behind it.
+fn read_edit(data: &[u8]) -> Option<std::result::Result<(ManifestEdit, usize), String>> {
+    if data.len() < FRAME_HEADER_SIZE {
+        return None;
+    }
+    let len = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(data[4..8].try_into().unwrap());
+    let size = FRAME_HEADER_SIZE + len;
+    if data.len() < size {
+        return None;
+    }
+
+    let payload = &data[FRAME_HEADER_SIZE..size];
+    let last = data.len() == size;
+    if crc32fast::hash(payload) != crc {
+        return (!last).then(|| Err("checksum mismatch".to_string()));
+    }
+    match decode::<ManifestEdit>(payload) {
+        Ok(edit) => Some(Ok((edit, size))),
+        Err(_) if last => None,
+        Err(e) => Some(Err(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_edits_replay_in_order() {
+        let dir = tempdir().unwrap();
+        assert_eq!(Manifest::load(dir.path()).unwrap(), None);
+
+        let manifest = Manifest::create(dir.path(), &names(&["1.sst", "2.sst"]), true).unwrap();
+        manifest
+            .record(&ManifestEdit {
+                added: names(&["3.sst"]),
+                removed: Vec::new(),
+            })
+            .unwrap();
+        manifest
+            .record(&ManifestEdit {
+                added: names(&["4.sst"]),
+                removed: names(&["1.sst", "3.sst"]),
+            })
+            .unwrap();
+
+        assert_eq!(
+            Manifest::load(dir.path()).unwrap(),
+            Some(names(&["2.sst", "4.sst"]))
+        );
+    }
+
+    #[test]
+    fn test_torn_tail_is_ignored_but_corruption_is_not() {
+        let dir = tempdir().unwrap();
+        let manifest = Manifest::create(dir.path(), &names(&["1.sst"]), true).unwrap();
+        manifest
+            .record(&ManifestEdit {
+                added: names(&["2.sst"]),
+                removed: names(&["1.sst"]),
+            })
+            .unwrap();
+        drop(manifest);
+
+        let path = dir.path().join(MANIFEST_FILE);
+        let full = std::fs::read(&path).unwrap();
+
+        // Crash mid-append: the compaction never happened
+        std::fs::write(&path, &full[..full.len() - 3]).unwrap();
+        assert_eq!(Manifest::load(dir.path()).unwrap(), Some(names(&["1.sst"])));
+
+        // A flipped bit in the first edit, with another one behind it
+        let mut corrupted = full.clone();
+        corrupted[FRAME_HEADER_SIZE] ^= 0xFF;
+        std::fs::write(&path, corrupted).unwrap();
+        assert!(matches!(
+            Manifest::load(dir.path()),
+            Err(LsmError::CorruptedData(_))
+        ));
+    }
+}
@@ -1,6 +1,50 @@
+pub mod blob;
 pub mod block;
 pub mod builder;
 pub mod cache;
+pub mod comparator;
+pub mod compression;
 pub mod config;
+pub mod index;
+pub mod manifest;
 pub mod reader;
 pub mod wal;
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// fsync the directory holding `path`, so a file just created in it or
+/// removed from it stays that way after a crash. Fsyncing a file only makes
+/// its contents durable, not its directory entry.
+pub(crate) fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+/// Fill `buf` from `file` starting at `offset`, without touching the file
+/// cursor, so several threads can read through one handle at once
+pub(crate) fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileExt;
+        let mut done = 0;
+        while done < buf.len() {
+            match file.seek_read(&mut buf[done..], offset + done as u64) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => done += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
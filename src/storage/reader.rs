@@ -1,16 +1,21 @@
 use crate::core::log_record::LogRecord;
-use crate::infra::codec::decode;
-use crate::infra::config::StorageConfig;
+use crate::infra::config::{Codec, IoMode, KeyComparator, StorageConfig};
 use crate::infra::error::{LsmError, Result};
+use crate::storage::blob::{blob_dir_of, read_blob, ObsoleteBlobs};
 use crate::storage::block::Block;
-use crate::storage::builder::{BlockMeta, MetaBlock};
+use crate::storage::builder::{BlockMeta, MetaBlock, SST_MAGIC_V4, SST_MAGIC_V5};
 use crate::storage::cache::{CacheKey, GlobalBlockCache};
+use crate::storage::index::{LazyIndex, FOOTER_V4_SIZE};
+use crate::storage::{read_exact_at, sync_parent_dir};
 use bloomfilter::Bloom;
 use lz4_flex::decompress_size_prepended;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
+use std::ops::Bound;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
 
 const SST_MAGIC_V2: &[u8; 8] = b"LSMSST03";
 const FOOTER_SIZE: u64 = 8;
@@ -19,12 +24,36 @@ const FOOTER_SIZE: u64 = 8;
 #[derive(Debug)]
 pub struct SstableReader {
     metadata: MetaBlock,
-    bloom_filter: Bloom<[u8]>,
+    /// On-disk index of a V4 table; V3 tables use `metadata.blocks`
+    lazy_index: Option<LazyIndex>,
+    /// Encoding of the records in the data blocks, from the file header
+    codec: Codec,
+    /// False for tables written before data blocks carried a checksum
+    checksummed: bool,
+    bloom_filter: Option<Bloom<[u8]>>,
+    /// Shared by every lookup on this table. Reads are positional, so
+    /// concurrent lookups don't contend for a cursor.
     file: File,
+    /// Whole-file mapping used for block reads when `io_mode` is `Mmap`
+    #[cfg(feature = "mmap")]
+    mmap: Option<memmap2::Mmap>,
     block_cache: Arc<GlobalBlockCache>,
     path: PathBuf,
-    #[allow(dead_code)]
     config: StorageConfig,
+    /// Data blocks requested so far, whether served by the cache or the disk
+    block_reads: AtomicU64,
+    /// Data blocks read from disk and decompressed so far
+    block_decompressions: AtomicU64,
+    /// Keys compared while searching inside blocks so far
+    key_comparisons: AtomicU64,
+    /// Lookups checked against the table-level Bloom filter so far
+    bloom_checks: AtomicU64,
+    /// Replaced by compaction: the file is removed once the last handle
+    /// (engine, iterator or snapshot) is dropped
+    obsolete: AtomicBool,
+    /// Blobs a compaction dropped while replacing this table, kept until it
+    /// is dropped
+    obsolete_blobs: Mutex<Vec<Arc<ObsoleteBlobs>>>,
 }
 
 impl SstableReader {
@@ -44,53 +73,173 @@ impl SstableReader {
         // Verify magic number
         let mut magic = [0u8; 8];
         file.read_exact(&mut magic)?;
-        if &magic != SST_MAGIC_V2 {
+        let (lazy, codec) = if &magic == SST_MAGIC_V2 {
+            (false, Codec::Fixint)
+        } else if &magic == SST_MAGIC_V4 {
+            (true, Codec::Fixint)
+        } else if &magic == SST_MAGIC_V5 {
+            Self::read_codec_header(&mut file)?
+        } else {
             return Err(LsmError::InvalidSstableFormat(format!(
-                "Invalid magic number: expected {:?}, found {:?}",
-                SST_MAGIC_V2, magic
+                "Invalid magic number: expected {:?}, {:?} or {:?}, found {:?}",
+                SST_MAGIC_V2, SST_MAGIC_V4, SST_MAGIC_V5, magic
             )));
-        }
-
-        // Read footer to get metadata offset
-        let meta_offset = Self::read_footer(&mut file)?;
+        };
+        let (lazy_index, meta_offset, footer_size) = if lazy {
+            let (index, meta_offset) = LazyIndex::read_footer(&mut file)?;
+            (Some(index), meta_offset, FOOTER_V4_SIZE)
+        } else {
+            // Read footer to get metadata offset
+            (None, Self::read_footer(&mut file)?, FOOTER_SIZE)
+        };
 
         // Read and decompress metadata block
-        let metadata = Self::read_meta_block(&mut file, meta_offset)?;
+        let (metadata, checksummed) = Self::read_meta_block(&mut file, meta_offset, footer_size)?;
+
+        #[cfg(feature = "mmap")]
+        let mmap = match config.io_mode {
+            // SAFETY: SSTables are never modified after `finish`; a table
+            // deleted by compaction stays mapped until the reader is dropped.
+            IoMode::Mmap => Some(unsafe { memmap2::Mmap::map(&file)? }),
+            IoMode::Syscall => None,
+        };
+        #[cfg(not(feature = "mmap"))]
+        if config.io_mode == IoMode::Mmap {
+            return Err(LsmError::ConfigValidation(
+                "io_mode Mmap requires building with the `mmap` feature".to_string(),
+            ));
+        }
 
         // Deserialize Bloom filter from stored bytes (clone to avoid moving)
-        let bloom_filter =
-            Bloom::<[u8]>::from_bytes(metadata.bloom_filter_data.clone()).map_err(|e| {
-                LsmError::CompactionFailed(format!("Bloom filter deserialization failed: {}", e))
-            })?;
+        let bloom_filter = if metadata.has_bloom {
+            Some(
+                Bloom::<[u8]>::from_bytes(metadata.bloom_filter_data.clone()).map_err(|e| {
+                    LsmError::CorruptedData(format!("Bloom filter deserialization failed: {}", e))
+                })?,
+            )
+        } else {
+            None
+        };
 
         Ok(Self {
             metadata,
+            lazy_index,
+            codec,
+            checksummed,
             bloom_filter,
             file,
+            #[cfg(feature = "mmap")]
+            mmap,
             block_cache,
             path,
             config,
+            block_reads: AtomicU64::new(0),
+            block_decompressions: AtomicU64::new(0),
+            key_comparisons: AtomicU64::new(0),
+            bloom_checks: AtomicU64::new(0),
+            obsolete: AtomicBool::new(false),
+            obsolete_blobs: Mutex::new(Vec::new()),
         })
     }
 
+    /// Like [`open`](Self::open), but also checks every block against its
+    /// stored checksum before returning (see
+    /// [`verify_integrity`](Self::verify_integrity)).
+    pub fn open_verified(
+        path: PathBuf,
+        config: StorageConfig,
+        block_cache: Arc<GlobalBlockCache>,
+    ) -> Result<Self> {
+        let reader = Self::open(path, config, block_cache)?;
+        reader.verify_integrity()?;
+        Ok(reader)
+    }
+
+    /// Read every data block from disk, bypassing the cache, and compare its
+    /// CRC32 with the one recorded at write time.
+    ///
+    /// Returns `LsmError::CorruptedData` for the first block that doesn't
+    /// match. Blocks are checksummed on every disk read anyway; this is for
+    /// scrubbing a whole table up front. Tables from before block checksums
+    /// can only be checked for blocks that fail to decompress.
+    pub fn verify_integrity(&self) -> Result<()> {
+        for i in 0..self.block_count() {
+            self.read_and_decompress_block(&self.block_meta(i)?)?;
+        }
+        Ok(())
+    }
+
     /// Check if key might exist using Bloom filter (fast pre-check)
+    ///
+    /// Tables written without a Bloom filter (see `min_keys_for_bloom`) can't
+    /// rule anything out, so this always returns `true` for them.
     pub fn might_contain(&self, key: &str) -> bool {
-        self.bloom_filter.check(key.as_bytes())
+        self.might_contain_bytes(key.as_bytes())
+    }
+
+    fn might_contain_bytes(&self, key: &[u8]) -> bool {
+        match &self.bloom_filter {
+            Some(bloom) => {
+                self.bloom_checks.fetch_add(1, Ordering::Relaxed);
+                bloom.check(key)
+            }
+            None => true,
+        }
+    }
+
+    /// Whether `key` lies within `[min_key, max_key]`; a table can't hold a
+    /// key outside it, so this is checked before the Bloom filter
+    pub fn covers(&self, key: &[u8]) -> bool {
+        let cmp = self.metadata.comparator;
+        cmp.compare(key, &self.metadata.min_key).is_ge()
+            && cmp.compare(key, &self.metadata.max_key).is_le()
+    }
+
+    /// Order the table's keys were written in
+    pub fn comparator(&self) -> KeyComparator {
+        self.metadata.comparator
+    }
+
+    /// Whether this table carries a Bloom filter
+    pub fn has_bloom(&self) -> bool {
+        self.bloom_filter.is_some()
     }
 
     /// Retrieve a value by key using sparse index and Bloom filter
-    pub fn get(&mut self, key: &str) -> Result<Option<LogRecord>> {
-        // Fast rejection using Bloom filter
-        if !self.might_contain(key) {
+    pub fn get(&self, key: &str) -> Result<Option<LogRecord>> {
+        self.get_raw(key)?
+            .map(|record| self.resolve(record))
+            .transpose()
+    }
+
+    /// Like [`get`](Self::get), but leaves a value stored out of line as its
+    /// blob pointer, for callers that only need liveness or the sequence number
+    pub(crate) fn get_raw(&self, key: &str) -> Result<Option<LogRecord>> {
+        // Fast rejection using the key range, then the Bloom filter
+        if !self.covers(key.as_bytes()) || !self.might_contain(key) {
             return Ok(None);
         }
 
-        // Binary search on sparse index to find the block (clone to avoid borrow issues)
-        let block_meta = match self.binary_search_block(key.as_bytes()) {
-            Some(meta) => meta.clone(),
+        // Binary search on sparse index to find the block
+        let mut block_meta = match self.binary_search_block(key.as_bytes())? {
+            Some(meta) => meta,
             None => return Ok(None),
         };
 
+        // The block's own filter can reject what the table-level one let through
+        if !block_meta.bloom.is_empty() {
+            let bloom =
+                Bloom::<[u8]>::from_bytes(std::mem::take(&mut block_meta.bloom)).map_err(|e| {
+                    LsmError::CorruptedData(format!(
+                        "Block Bloom filter at offset {} is unreadable: {}",
+                        block_meta.offset, e
+                    ))
+                })?;
+            if !bloom.check(key.as_bytes()) {
+                return Ok(None);
+            }
+        }
+
         // Read and decompress the block (with caching)
         let block_data = self.read_block(&block_meta)?;
 
@@ -98,114 +247,346 @@ impl SstableReader {
         let block = Block::decode(&block_data);
 
         // Linear scan within the block to find the key
-        Self::search_in_block(&block, key.as_bytes())
+        self.search_in_block(&block, key.as_bytes())
     }
 
-    /// Search for a key within a decoded block
-    fn search_in_block(block: &Block, key: &[u8]) -> Result<Option<LogRecord>> {
-        // Access block data through pub(crate) fields
-        for &offset in &block.offsets {
-            let offset = offset as usize;
-            if offset + 2 > block.data.len() {
-                break;
+    /// Look up several keys, which must be in ascending order.
+    ///
+    /// Works like calling [`get`](Self::get) per key, except that consecutive
+    /// keys landing in the same block share one read and decode of it.
+    pub fn multi_get(&self, keys: &[&str]) -> Result<Vec<Option<LogRecord>>> {
+        let mut results = Vec::with_capacity(keys.len());
+        // Index and contents of the last block read
+        let mut loaded: Option<(usize, Block)> = None;
+
+        for key in keys {
+            let key = key.as_bytes();
+            if !self.covers(key) || !self.might_contain_bytes(key) {
+                results.push(None);
+                continue;
             }
 
-            // Read key length
-            let key_len = u16::from_le_bytes([block.data[offset], block.data[offset + 1]]) as usize;
-            if offset + 2 + key_len + 2 > block.data.len() {
-                break;
+            let idx = match self.block_partition_point(key)? {
+                0 => {
+                    results.push(None);
+                    continue;
+                }
+                n => n - 1,
+            };
+
+            if loaded.as_ref().map(|(i, _)| *i) != Some(idx) {
+                let block_meta = self.block_meta(idx)?;
+                if !block_meta.bloom.is_empty() {
+                    let bloom =
+                        Bloom::<[u8]>::from_bytes(block_meta.bloom.clone()).map_err(|e| {
+                            LsmError::CorruptedData(format!(
+                                "Block Bloom filter at offset {} is unreadable: {}",
+                                block_meta.offset, e
+                            ))
+                        })?;
+                    if !bloom.check(key) {
+                        results.push(None);
+                        continue;
+                    }
+                }
+                loaded = Some((idx, Block::decode(&self.read_block(&block_meta)?)));
             }
 
-            // Read key
-            let entry_key = &block.data[offset + 2..offset + 2 + key_len];
+            let (_, block) = loaded.as_ref().expect("block loaded above");
+            let record = self.search_in_block(block, key)?;
+            results.push(record.map(|record| self.resolve(record)).transpose()?);
+        }
 
-            if entry_key == key {
-                // Read value length
-                let val_len_offset = offset + 2 + key_len;
-                let val_len = u16::from_le_bytes([
-                    block.data[val_len_offset],
-                    block.data[val_len_offset + 1],
-                ]) as usize;
+        Ok(results)
+    }
 
-                if val_len_offset + 2 + val_len > block.data.len() {
-                    break;
-                }
+    /// Load the block that may hold `key` into the shared cache without
+    /// decoding it. Returns `false` if the table can't contain the key.
+    pub fn warm_key(&self, key: &str) -> Result<bool> {
+        if !self.covers(key.as_bytes()) || !self.might_contain(key) {
+            return Ok(false);
+        }
 
-                // Read value
-                let entry_value = &block.data[val_len_offset + 2..val_len_offset + 2 + val_len];
+        let block_meta = match self.binary_search_block(key.as_bytes())? {
+            Some(meta) => meta,
+            None => return Ok(false),
+        };
 
-                // Decode the LogRecord from value
-                let record: LogRecord = decode(entry_value)?;
-                return Ok(Some(record));
+        self.read_block(&block_meta)?;
+        Ok(true)
+    }
+
+    /// Load the blocks covering keys in `[start, end)` into the shared cache,
+    /// stopping after `max_blocks`. Returns the number of blocks read.
+    pub fn warm_range(&self, start: &[u8], end: &[u8], max_blocks: usize) -> Result<usize> {
+        let cmp = self.metadata.comparator;
+        if cmp.compare(start, end).is_ge()
+            || cmp.compare(end, &self.metadata.min_key).is_le()
+            || cmp.compare(start, &self.metadata.max_key).is_gt()
+        {
+            return Ok(0);
+        }
+
+        // First block whose range can include `start`
+        let first = self.block_partition_point(start)?.saturating_sub(1);
+
+        let mut loaded = 0;
+        for i in first..self.block_count() {
+            if loaded >= max_blocks {
+                break;
             }
+            let block_meta = self.block_meta(i)?;
+            if cmp.compare(&block_meta.first_key, end).is_ge() {
+                break;
+            }
+            self.read_block(&block_meta)?;
+            loaded += 1;
         }
 
-        Ok(None)
+        Ok(loaded)
     }
 
-    /// Scan all records in the SSTable (for compaction)
-    pub fn scan(&mut self) -> Result<Vec<(Vec<u8>, LogRecord)>> {
-        let mut records = Vec::new();
-
-        // Clone blocks to avoid borrow issues
-        let blocks = self.metadata.blocks.clone();
+    /// Load the first `max_blocks` blocks into the shared cache. Returns the
+    /// number of blocks read.
+    pub fn preload(&self, max_blocks: usize) -> Result<usize> {
+        let count = self.block_count().min(max_blocks);
+        for i in 0..count {
+            self.read_block(&self.block_meta(i)?)?;
+        }
+        Ok(count)
+    }
 
-        for block_meta in &blocks {
-            let block_data = self.read_block(block_meta)?;
-            let block = Block::decode(&block_data);
+    /// Search for a key within a decoded block
+    fn search_in_block(&self, block: &Block, key: &[u8]) -> Result<Option<LogRecord>> {
+        let mut comparisons = 0;
+        let found = block.find(
+            key,
+            self.config.sparse_index_interval,
+            self.metadata.comparator,
+            &mut comparisons,
+        );
+        self.key_comparisons
+            .fetch_add(comparisons, Ordering::Relaxed);
+        found
+            .map(|(_, value)| LogRecord::decode_with(self.codec, value))
+            .transpose()
+    }
 
-            // Access block data through pub(crate) fields
-            for &offset in &block.offsets {
-                let offset = offset as usize;
-                if offset + 2 > block.data.len() {
-                    break;
-                }
+    /// Fill in the value of a record stored out of line
+    pub(crate) fn resolve(&self, mut record: LogRecord) -> Result<LogRecord> {
+        if let Some(blob) = record.blob.take() {
+            record.value = read_blob(&blob_dir_of(&self.path), &blob)?;
+        }
+        Ok(record)
+    }
 
-                // Read key length
-                let key_len =
-                    u16::from_le_bytes([block.data[offset], block.data[offset + 1]]) as usize;
-                if offset + 2 + key_len + 2 > block.data.len() {
-                    break;
-                }
+    /// Scan all records in the SSTable, with out-of-line values fetched
+    pub fn scan(&self) -> Result<Vec<(Vec<u8>, LogRecord)>> {
+        self.scan_raw()?
+            .into_iter()
+            .map(|(key, record)| Ok((key, self.resolve(record)?)))
+            .collect()
+    }
 
-                // Read key
-                let key = block.data[offset + 2..offset + 2 + key_len].to_vec();
+    /// Scan all records as stored, leaving blob pointers in place (for
+    /// compaction, which carries them forward)
+    pub(crate) fn scan_raw(&self) -> Result<Vec<(Vec<u8>, LogRecord)>> {
+        let mut records = Vec::new();
+        for i in 0..self.block_count() {
+            records.extend(self.read_block_records(i)?);
+        }
+        Ok(records)
+    }
 
-                // Read value length
-                let val_len_offset = offset + 2 + key_len;
-                let val_len = u16::from_le_bytes([
-                    block.data[val_len_offset],
-                    block.data[val_len_offset + 1],
-                ]) as usize;
+    /// Stream the table's records in key order, decoding one block at a time
+    /// as the previous one runs out, with out-of-line values fetched. Unlike
+    /// [`scan`](Self::scan) memory stays bounded by one block, so tools can
+    /// walk a single `.sst` file of any size.
+    pub fn iter(self: &Arc<Self>) -> SstableIter {
+        SstableIter {
+            reader: Arc::clone(self),
+            next_block: 0,
+            buffered: std::collections::VecDeque::new(),
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+            reverse: false,
+            resolve_blobs: true,
+        }
+    }
 
-                if val_len_offset + 2 + val_len > block.data.len() {
-                    break;
+    /// Stream the records with keys inside `(start, end)`.
+    ///
+    /// The sparse index locates the first block that can hold `start`, and
+    /// iteration stops before loading a block whose first key is past `end`,
+    /// so only blocks overlapping the range are read.
+    pub fn range(self: &Arc<Self>, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<SstableIter> {
+        let cmp = self.metadata.comparator;
+        let disjoint = cmp.after_end(&self.metadata.min_key, end)
+            || cmp.before_start(&self.metadata.max_key, start);
+
+        let next_block = if disjoint {
+            self.block_count()
+        } else {
+            match start {
+                Bound::Included(key) | Bound::Excluded(key) => {
+                    self.block_partition_point(key)?.saturating_sub(1)
                 }
+                Bound::Unbounded => 0,
+            }
+        };
 
-                // Read value
-                let value = &block.data[val_len_offset + 2..val_len_offset + 2 + val_len];
+        Ok(SstableIter {
+            reader: Arc::clone(self),
+            next_block,
+            buffered: std::collections::VecDeque::new(),
+            start: start.map(|key| key.to_vec()),
+            end: end.map(|key| key.to_vec()),
+            reverse: false,
+            resolve_blobs: true,
+        })
+    }
 
-                // Decode the LogRecord from value
-                let record: LogRecord = decode(value)?;
-                records.push((key, record));
+    /// Stream the records with keys inside `(start, end)` in descending key
+    /// order.
+    ///
+    /// Blocks are visited from the last one that can hold `end` down to the
+    /// one holding `start`, and the records of each block are yielded in
+    /// reverse.
+    pub fn range_rev(
+        self: &Arc<Self>,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<SstableIter> {
+        let cmp = self.metadata.comparator;
+        let disjoint = cmp.after_end(&self.metadata.min_key, end)
+            || cmp.before_start(&self.metadata.max_key, start);
+
+        let next_block = if disjoint {
+            0
+        } else {
+            match end {
+                Bound::Included(key) | Bound::Excluded(key) => self.block_partition_point(key)?,
+                Bound::Unbounded => self.block_count(),
             }
-        }
+        };
 
-        Ok(records)
+        Ok(SstableIter {
+            reader: Arc::clone(self),
+            next_block,
+            buffered: std::collections::VecDeque::new(),
+            start: start.map(|key| key.to_vec()),
+            end: end.map(|key| key.to_vec()),
+            reverse: true,
+            resolve_blobs: true,
+        })
+    }
+
+    /// Decode every record of block `i`, leaving blob pointers unresolved
+    fn read_block_records(&self, i: usize) -> Result<Vec<(Vec<u8>, LogRecord)>> {
+        let block_meta = self.block_meta(i)?;
+        let block_data = self.read_block(&block_meta)?;
+        let block = Block::decode(&block_data);
+
+        block
+            .entries()
+            .map(|(key, value)| Ok((key.to_vec(), LogRecord::decode_with(self.codec, value)?)))
+            .collect()
     }
 
     /// Get metadata information
+    ///
+    /// For V4 tables `blocks` is empty; use [`block_count`](Self::block_count)
+    /// and [`block_meta`](Self::block_meta) instead.
     pub fn metadata(&self) -> &MetaBlock {
         &self.metadata
     }
 
+    /// Number of data blocks in the table
+    pub fn block_count(&self) -> usize {
+        match &self.lazy_index {
+            Some(index) => index.len(),
+            None => self.metadata.blocks.len(),
+        }
+    }
+
+    /// Index entry of block `i` (read from disk for V4 tables)
+    pub fn block_meta(&self, i: usize) -> Result<BlockMeta> {
+        match &self.lazy_index {
+            Some(index) => index.entry(&self.file, i),
+            None => self
+                .metadata
+                .blocks
+                .get(i)
+                .cloned()
+                .ok_or_else(|| LsmError::CorruptedData(format!("Block {} out of range", i))),
+        }
+    }
+
+    /// Compaction level the table was written for
+    pub fn level(&self) -> u32 {
+        self.metadata.level
+    }
+
+    /// Number of data blocks requested from this table since it was opened,
+    /// including cache hits
+    pub fn block_reads(&self) -> u64 {
+        self.block_reads.load(Ordering::Relaxed)
+    }
+
+    /// Number of data blocks read from disk and decompressed since the table
+    /// was opened (cache misses only)
+    pub fn block_decompressions(&self) -> u64 {
+        self.block_decompressions.load(Ordering::Relaxed)
+    }
+
+    /// Number of keys compared while searching inside blocks since the table
+    /// was opened
+    pub fn key_comparisons(&self) -> u64 {
+        self.key_comparisons.load(Ordering::Relaxed)
+    }
+
+    /// Number of lookups checked against the table's Bloom filter since it
+    /// was opened; lookups outside the key range never reach it
+    pub fn bloom_checks(&self) -> u64 {
+        self.bloom_checks.load(Ordering::Relaxed)
+    }
+
+    /// Whether the block index is read lazily from disk (V4 format)
+    pub fn has_lazy_index(&self) -> bool {
+        self.lazy_index.is_some()
+    }
+
     /// Get file path
     pub fn path(&self) -> &PathBuf {
         &self.path
     }
 
+    /// Delete the file when this reader is dropped, i.e. once nothing holds
+    /// an `Arc` to it any more
+    pub(crate) fn mark_obsolete(&self) {
+        self.obsolete.store(true, Ordering::SeqCst);
+    }
+
+    /// Keep `blobs` alive until this reader is dropped; they are deleted
+    /// once every table holding them is
+    pub(crate) fn hold_obsolete_blobs(&self, blobs: Arc<ObsoleteBlobs>) {
+        if let Ok(mut held) = self.obsolete_blobs.lock() {
+            held.push(blobs);
+        }
+    }
+
     // Private helper methods
 
+    /// `[lazy_index u8][codec id u8]` following an `LSMSST05` magic
+    fn read_codec_header(file: &mut File) -> Result<(bool, Codec)> {
+        let mut header = [0u8; 2];
+        file.read_exact(&mut header)?;
+        let codec = Codec::from_id(header[1]).ok_or_else(|| {
+            LsmError::InvalidSstableFormat(format!("Unknown record codec {}", header[1]))
+        })?;
+        Ok((header[0] != 0, codec))
+    }
+
     fn read_footer(file: &mut File) -> Result<u64> {
         // Seek to the last 8 bytes (footer)
         file.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
@@ -217,13 +598,24 @@ impl SstableReader {
         Ok(meta_offset)
     }
 
-    fn read_meta_block(file: &mut File, offset: u64) -> Result<MetaBlock> {
+    /// The table's metadata, and whether its data blocks have checksums
+    fn read_meta_block(
+        file: &mut File,
+        offset: u64,
+        footer_size: u64,
+    ) -> Result<(MetaBlock, bool)> {
         // Seek to metadata block
         file.seek(SeekFrom::Start(offset))?;
 
         // Read compressed metadata until footer
         let file_len = file.metadata()?.len();
-        let meta_size = (file_len - offset - FOOTER_SIZE) as usize;
+        if offset.saturating_add(footer_size) > file_len {
+            return Err(LsmError::CorruptedData(format!(
+                "MetaBlock offset {} is past the end of the file",
+                offset
+            )));
+        }
+        let meta_size = (file_len - offset - footer_size) as usize;
 
         let mut compressed_meta = vec![0u8; meta_size];
         file.read_exact(&mut compressed_meta)?;
@@ -233,12 +625,24 @@ impl SstableReader {
             LsmError::DecompressionFailed(format!("Metadata decompression failed: {}", e))
         })?;
 
-        // Deserialize metadata
-        let metadata: MetaBlock = decode(&decompressed)?;
-        Ok(metadata)
+        // Deserialize metadata, falling back to the layout of the first V3
+        // tables (same magic, no block checksums)
+        match MetaBlock::decode(&decompressed) {
+            Ok(metadata) => Ok((metadata, true)),
+            Err(err) => MetaBlock::decode_unchecksummed(&decompressed)
+                .map(|metadata| (metadata, false))
+                .map_err(|_| err),
+        }
     }
 
-    fn read_block(&mut self, block_meta: &BlockMeta) -> Result<Vec<u8>> {
+    fn read_block(&self, block_meta: &BlockMeta) -> Result<Vec<u8>> {
+        self.block_reads.fetch_add(1, Ordering::Relaxed);
+
+        // One oversized entry would evict many regular blocks for a single use
+        if block_meta.single_entry {
+            return self.read_and_decompress_block(block_meta);
+        }
+
         // Create cache key with file path and block offset
         let cache_key = CacheKey::new(&self.path, block_meta.offset);
 
@@ -256,21 +660,65 @@ impl SstableReader {
         Ok(block_data)
     }
 
-    fn read_and_decompress_block(&mut self, block_meta: &BlockMeta) -> Result<Vec<u8>> {
-        // Seek to block offset
-        self.file.seek(SeekFrom::Start(block_meta.offset))?;
+    /// Copy `len` bytes at `offset` out of the mapping, or read them from
+    /// the file when the table isn't mapped
+    fn read_extent(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        #[cfg(feature = "mmap")]
+        if let Some(mmap) = &self.mmap {
+            let start = offset as usize;
+            return mmap
+                .get(start..start + len)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| {
+                    LsmError::CorruptedData(format!(
+                        "Block at offset {} runs past the end of {}",
+                        offset,
+                        self.path.display()
+                    ))
+                });
+        }
 
-        // Read compressed block
-        let mut compressed_block = vec![0u8; block_meta.size as usize];
-        self.file.read_exact(&mut compressed_block)?;
+        let mut buf = vec![0u8; len];
+        read_exact_at(&self.file, &mut buf, offset)?;
+        Ok(buf)
+    }
 
-        // Decompress block
-        let decompressed = decompress_size_prepended(&compressed_block).map_err(|e| {
-            LsmError::DecompressionFailed(format!(
-                "Block decompression failed at offset {}: {}",
-                block_meta.offset, e
-            ))
-        })?;
+    fn read_and_decompress_block(&self, block_meta: &BlockMeta) -> Result<Vec<u8>> {
+        self.block_decompressions.fetch_add(1, Ordering::Relaxed);
+
+        // Read compressed block (the extent may include alignment padding)
+        let mut compressed_block = self.read_extent(block_meta.offset, block_meta.size as usize)?;
+        compressed_block.truncate((block_meta.size - block_meta.padding) as usize);
+
+        let checksum = crc32fast::hash(&compressed_block);
+        if self.checksummed && checksum != block_meta.checksum {
+            return Err(LsmError::CorruptedData(format!(
+                "Block checksum mismatch at offset {} in {}: expected {:08x}, got {:08x}",
+                block_meta.offset,
+                self.path.display(),
+                block_meta.checksum,
+                checksum
+            )));
+        }
+
+        // Decompress block with the table's algorithm (blocks holding one
+        // oversized value are stored raw)
+        let decompressed = if block_meta.compressed {
+            self.metadata
+                .compression
+                .decompress(&compressed_block, block_meta.uncompressed_size as usize)
+                .map_err(|e| match e {
+                    LsmError::DecompressionFailed(reason) => {
+                        LsmError::DecompressionFailed(format!(
+                            "Block decompression failed at offset {}: {}",
+                            block_meta.offset, reason
+                        ))
+                    }
+                    e => e,
+                })?
+        } else {
+            compressed_block
+        };
 
         // Verify decompressed size matches metadata
         if decompressed.len() != block_meta.uncompressed_size as usize {
@@ -284,36 +732,278 @@ impl SstableReader {
         Ok(decompressed)
     }
 
-    fn binary_search_block(&self, key: &[u8]) -> Option<&BlockMeta> {
+    /// Number of blocks whose first key is `<= key`
+    fn block_partition_point(&self, key: &[u8]) -> Result<usize> {
+        match &self.lazy_index {
+            Some(index) => index.partition_point(&self.file, key, self.metadata.comparator),
+            None => Ok(self.metadata.blocks.partition_point(|block_meta| {
+                self.metadata
+                    .comparator
+                    .compare(&block_meta.first_key, key)
+                    .is_le()
+            })),
+        }
+    }
+
+    fn binary_search_block(&self, key: &[u8]) -> Result<Option<BlockMeta>> {
         // If key is smaller than the first key in the SSTable, it doesn't exist
-        if key < self.metadata.min_key.as_slice() {
-            return None;
+        if self
+            .metadata
+            .comparator
+            .compare(key, &self.metadata.min_key)
+            .is_lt()
+        {
+            return Ok(None);
         }
 
         // If key is larger than the last key in the SSTable, it doesn't exist
-        if key > self.metadata.max_key.as_slice() {
-            return None;
+        if self
+            .metadata
+            .comparator
+            .compare(key, &self.metadata.max_key)
+            .is_gt()
+        {
+            return Ok(None);
         }
 
-        // Binary search using partition_point to find the block where first_key <= search_key
-        let idx = self
-            .metadata
-            .blocks
-            .partition_point(|block_meta| block_meta.first_key.as_slice() <= key);
+        // Binary search to find the block where first_key <= search_key
+        let idx = self.block_partition_point(key)?;
 
         // If idx is 0, key is smaller than all first_keys
         if idx == 0 {
-            return None;
+            return Ok(None);
         }
 
         // Return the block at idx - 1 (the last block where first_key <= search_key)
-        Some(&self.metadata.blocks[idx - 1])
+        self.block_meta(idx - 1).map(Some)
+    }
+}
+
+impl Drop for SstableReader {
+    fn drop(&mut self) {
+        if !self.obsolete.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!(
+                "Failed to remove compacted SSTable {}: {}",
+                self.path.display(),
+                e
+            );
+        } else if self.config.sync_directory {
+            if let Err(e) = sync_parent_dir(&self.path) {
+                warn!(
+                    "Failed to sync directory of removed SSTable {}: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Streaming iterator returned by [`SstableReader::iter`],
+/// [`SstableReader::range`] and [`SstableReader::range_rev`]; holds at most one
+/// decoded block in memory
+pub struct SstableIter {
+    reader: Arc<SstableReader>,
+    /// Next block to load going forward; one past it going in reverse
+    next_block: usize,
+    buffered: std::collections::VecDeque<(Vec<u8>, LogRecord)>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    reverse: bool,
+    /// False to yield out-of-line values as their blob pointers
+    resolve_blobs: bool,
+}
+
+impl SstableIter {
+    /// Yield values stored out of line as their blob pointers, for a caller
+    /// that fetches only the versions it keeps
+    pub(crate) fn raw(mut self) -> Self {
+        self.resolve_blobs = false;
+        self
+    }
+
+    fn finish(&mut self) {
+        self.next_block = if self.reverse {
+            0
+        } else {
+            self.reader.block_count()
+        };
+        self.buffered.clear();
+    }
+
+    /// Reposition at the first record with a key `>= key` (the last one
+    /// `<= key` for a reverse iterator), still within the iterator's bounds.
+    ///
+    /// The sparse index picks the one block that can hold `key`, so only that
+    /// block is read; earlier blocks are skipped however far along the
+    /// iterator was. A `key` before the first record positions at the start,
+    /// one past the last leaves nothing to yield (the reverse holds going
+    /// backwards). Seeking again, in either direction, is allowed.
+    pub fn seek(&mut self, key: &[u8]) -> Result<()> {
+        let result = if self.reverse {
+            self.seek_back(key)
+        } else {
+            self.seek_forward(key)
+        };
+        if result.is_err() {
+            self.finish();
+        }
+        result
+    }
+
+    fn seek_forward(&mut self, key: &[u8]) -> Result<()> {
+        let cmp = self.reader.metadata.comparator;
+        if cmp.compare(key, &self.reader.metadata.max_key).is_gt() {
+            self.finish();
+            return Ok(());
+        }
+
+        let index = self.reader.block_partition_point(key)?.saturating_sub(1);
+        let first_key = self.reader.block_meta(index)?.first_key;
+        self.buffered.clear();
+        self.next_block = index + 1;
+        if cmp.after_end(&first_key, self.end.as_ref()) {
+            self.finish();
+            return Ok(());
+        }
+
+        let records = self.reader.read_block_records(index)?;
+        self.buffered
+            .extend(records.into_iter().filter(|(record_key, _)| {
+                cmp.compare(record_key, key).is_ge()
+                    && !cmp.before_start(record_key, self.start.as_ref())
+            }));
+        Ok(())
+    }
+
+    fn seek_back(&mut self, key: &[u8]) -> Result<()> {
+        let cmp = self.reader.metadata.comparator;
+        let index = match self.reader.block_partition_point(key)? {
+            // Every record sorts after `key`
+            0 => {
+                self.finish();
+                return Ok(());
+            }
+            n => n - 1,
+        };
+
+        let first_key = self.reader.block_meta(index)?.first_key;
+        let records = self.reader.read_block_records(index)?;
+        self.buffered.clear();
+        self.buffered
+            .extend(records.into_iter().rev().filter(|(record_key, _)| {
+                cmp.compare(record_key, key).is_le()
+                    && !cmp.after_end(record_key, self.end.as_ref())
+            }));
+        self.next_block = if cmp.before_start(&first_key, self.start.as_ref()) {
+            0
+        } else {
+            index
+        };
+        Ok(())
+    }
+
+    /// Load the next block within the range into `buffered`; `false` once
+    /// there are no more
+    fn load_next_block(&mut self) -> Result<bool> {
+        if self.reverse {
+            return self.load_previous_block();
+        }
+        if self.next_block >= self.reader.block_count() {
+            return Ok(false);
+        }
+
+        let first_key = self.reader.block_meta(self.next_block)?.first_key;
+        let cmp = self.reader.metadata.comparator;
+        if cmp.after_end(&first_key, self.end.as_ref()) {
+            self.finish();
+            return Ok(false);
+        }
+
+        let records = self.reader.read_block_records(self.next_block)?;
+        self.next_block += 1;
+        self.buffered.extend(
+            records
+                .into_iter()
+                .filter(|(key, _)| !cmp.before_start(key, self.start.as_ref())),
+        );
+        Ok(true)
+    }
+
+    /// Reverse counterpart of `load_next_block`: buffers block
+    /// `next_block - 1` with its records in descending order
+    fn load_previous_block(&mut self) -> Result<bool> {
+        if self.next_block == 0 {
+            return Ok(false);
+        }
+
+        let index = self.next_block - 1;
+        let cmp = self.reader.metadata.comparator;
+        let first_key = self.reader.block_meta(index)?.first_key;
+        let records = self.reader.read_block_records(index)?;
+        self.buffered.extend(
+            records
+                .into_iter()
+                .rev()
+                .filter(|(key, _)| !cmp.after_end(key, self.end.as_ref())),
+        );
+
+        // Blocks further down only hold keys below this one's first key
+        self.next_block = if cmp.before_start(&first_key, self.start.as_ref()) {
+            0
+        } else {
+            index
+        };
+        Ok(true)
+    }
+}
+
+impl Iterator for SstableIter {
+    type Item = Result<(Vec<u8>, LogRecord)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffered.is_empty() {
+            match self.load_next_block() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => {
+                    // Don't retry the broken block forever
+                    self.finish();
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        let (key, record) = self.buffered.pop_front()?;
+        let cmp = self.reader.metadata.comparator;
+        let past_range = if self.reverse {
+            cmp.before_start(&key, self.start.as_ref())
+        } else {
+            cmp.after_end(&key, self.end.as_ref())
+        };
+        if past_range {
+            self.finish();
+            return None;
+        }
+        if !self.resolve_blobs {
+            return Some(Ok((key, record)));
+        }
+        Some(self.reader.resolve(record).map(|record| (key, record)))
     }
 }
 
+// Once exhausted (or after an error) every block is marked consumed
+impl std::iter::FusedIterator for SstableIter {}
+
 #[cfg(test)]
+// Readers took `&mut self` before they were shared across threads
+#[allow(unused_mut)]
 mod tests {
     use super::*;
+    use crate::infra::config::Compression;
     use crate::storage::builder::SstableBuilder;
     use tempfile::tempdir;
 
@@ -400,6 +1090,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_per_block_bloom_skips_block_reads() {
+        let decompressions = |per_block_bloom: bool, lazy_block_index: bool| -> u64 {
+            let dir = tempdir().unwrap();
+            let path = dir.path().join("block_bloom.sst");
+            // A loose table-level filter so plenty of negative lookups get past it
+            let config = StorageConfig {
+                block_size: 256,
+                bloom_false_positive_rate: 0.2,
+                per_block_bloom,
+                lazy_block_index,
+                ..Default::default()
+            };
+
+            let mut builder = SstableBuilder::new(path.clone(), config.clone(), 457).unwrap();
+            for i in 0..1000 {
+                let key = format!("key_{:05}", i * 2);
+                builder
+                    .add(key.as_bytes(), &create_test_record(&key, b"value"))
+                    .unwrap();
+            }
+            builder.finish().unwrap();
+
+            // Room for a single block, so every block read is a decompression
+            let cache = GlobalBlockCache::new(1, 1024 * 1024);
+            let reader = SstableReader::open(path, config, cache).unwrap();
+
+            for i in 0..1000 {
+                let key = format!("key_{:05}", i * 2 + 1);
+                assert!(reader.get(&key).unwrap().is_none());
+            }
+            for i in (0..1000).step_by(97) {
+                let key = format!("key_{:05}", i * 2);
+                assert!(reader.get(&key).unwrap().is_some(), "{key} went missing");
+            }
+            reader.block_decompressions()
+        };
+
+        for lazy_block_index in [false, true] {
+            let table_only = decompressions(false, lazy_block_index);
+            let per_block = decompressions(true, lazy_block_index);
+            assert!(
+                per_block * 2 < table_only,
+                "per-block blooms: {per_block} decompressions, table-level only: {table_only}"
+            );
+        }
+    }
+
     #[test]
     fn test_reader_multiple_blocks() {
         let dir = tempdir().unwrap();
@@ -428,6 +1166,317 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_reader_serves_concurrent_gets_through_one_handle() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("concurrent.sst");
+        // Lazy index entries are always read from the file, cache or not
+        let config = StorageConfig {
+            block_size: 256,
+            lazy_block_index: true,
+            ..Default::default()
+        };
+
+        let mut builder = SstableBuilder::new(path.clone(), config.clone(), 1).unwrap();
+        for i in 0..2000 {
+            let key = format!("key_{:04}", i);
+            builder
+                .add(
+                    key.as_bytes(),
+                    &create_test_record(&key, format!("value_{i}").as_bytes()),
+                )
+                .unwrap();
+        }
+        builder.finish().unwrap();
+
+        let cache = create_test_cache(&config);
+        let reader = Arc::new(SstableReader::open(path, config, cache).unwrap());
+        assert!(reader.block_count() > 50);
+
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let reader = Arc::clone(&reader);
+                std::thread::spawn(move || {
+                    // Each thread walks the keys from a different starting point
+                    for n in 0..2000 {
+                        let i = (n * 7 + t * 250) % 2000;
+                        let record = reader.get(&format!("key_{:04}", i)).unwrap().unwrap();
+                        assert_eq!(record.value, format!("value_{i}").into_bytes());
+                    }
+                    assert!(reader.get("key_9999").unwrap().is_none());
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sparse_index_interval_bounds_in_block_comparisons() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("interval.sst");
+        let config = StorageConfig {
+            block_size: 64 * 1024,
+            ..Default::default()
+        };
+
+        let mut builder = SstableBuilder::new(path.clone(), config.clone(), 1).unwrap();
+        for i in 0..1000 {
+            let key = format!("key_{:04}", i);
+            builder
+                .add(key.as_bytes(), &create_test_record(&key, b"v"))
+                .unwrap();
+        }
+        builder.finish().unwrap();
+
+        // The interval only affects lookups, so the same file serves both
+        let comparisons_with = |interval: usize| {
+            let config = StorageConfig {
+                sparse_index_interval: interval,
+                ..config.clone()
+            };
+            let reader =
+                SstableReader::open(path.clone(), config.clone(), create_test_cache(&config))
+                    .unwrap();
+            assert!(reader.block_count() <= 2);
+            for i in 0..1000 {
+                let key = format!("key_{:04}", i);
+                assert_eq!(reader.get(&key).unwrap().unwrap().key, key);
+            }
+            reader.key_comparisons() / 1000
+        };
+
+        let dense = comparisons_with(4);
+        let scan = comparisons_with(1000);
+        assert!(dense <= 4 + 10, "{dense} comparisons per lookup");
+        assert!(scan > 100, "{scan} comparisons per lookup");
+    }
+
+    #[test]
+    fn test_reader_range_rev_matches_reversed_range() {
+        for lazy_block_index in [false, true] {
+            let dir = tempdir().unwrap();
+            let path = dir.path().join("range_rev.sst");
+            let config = StorageConfig {
+                block_size: 256,
+                lazy_block_index,
+                ..Default::default()
+            };
+            let cache = create_test_cache(&config);
+
+            let mut builder = SstableBuilder::new(path.clone(), config.clone(), 790).unwrap();
+            for i in 0..50 {
+                let key = format!("key_{:03}", i);
+                builder
+                    .add(key.as_bytes(), &create_test_record(&key, &[b'x'; 20]))
+                    .unwrap();
+            }
+            builder.finish().unwrap();
+
+            let reader = Arc::new(SstableReader::open(path, config, cache).unwrap());
+            assert!(reader.block_count() > 2);
+
+            let keys = |iter: SstableIter| -> Vec<Vec<u8>> {
+                iter.map(|entry| entry.unwrap().0).collect()
+            };
+            let (k010, k040, k100) = (
+                b"key_010".as_slice(),
+                b"key_040".as_slice(),
+                b"key_100".as_slice(),
+            );
+            let bounds = [
+                (Bound::Unbounded, Bound::Unbounded),
+                (Bound::Included(k010), Bound::Excluded(k040)),
+                (Bound::Excluded(k010), Bound::Included(k040)),
+                (Bound::Included(k100), Bound::Unbounded),
+            ];
+            for (start, end) in bounds {
+                let mut expected = keys(reader.range(start, end).unwrap());
+                expected.reverse();
+                assert_eq!(keys(reader.range_rev(start, end).unwrap()), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_iter_seek_positions_within_a_multi_block_table() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("seek.sst");
+        let config = StorageConfig {
+            block_size: 256,
+            ..Default::default()
+        };
+        let cache = create_test_cache(&config);
+
+        // Even numbers only, so odd ones fall between keys
+        let mut builder = SstableBuilder::new(path.clone(), config.clone(), 791).unwrap();
+        for i in (0..200).step_by(2) {
+            let key = format!("key_{:03}", i);
+            builder
+                .add(key.as_bytes(), &create_test_record(&key, &[b'x'; 20]))
+                .unwrap();
+        }
+        builder.finish().unwrap();
+
+        let reader = Arc::new(SstableReader::open(path, config, cache).unwrap());
+        assert!(reader.block_count() > 5);
+
+        let next_key = |iter: &mut SstableIter| -> Option<String> {
+            iter.next()
+                .map(|entry| String::from_utf8(entry.unwrap().0).unwrap())
+        };
+
+        let mut iter = reader.iter();
+        for (target, expected) in [
+            ("key_100", Some("key_100")),
+            ("key_101", Some("key_102")),
+            // Backwards after moving forward
+            ("key_010", Some("key_010")),
+            ("a", Some("key_000")),
+            ("key_198", Some("key_198")),
+            ("key_199", None),
+            ("z", None),
+        ] {
+            iter.seek(target.as_bytes()).unwrap();
+            assert_eq!(next_key(&mut iter).as_deref(), expected, "seek {target}");
+        }
+        iter.seek(b"key_195").unwrap();
+        let rest: Vec<String> = std::iter::from_fn(|| next_key(&mut iter)).collect();
+        assert_eq!(rest, ["key_196", "key_198"]);
+
+        let mut iter = reader
+            .range_rev(Bound::Unbounded, Bound::Unbounded)
+            .unwrap();
+        iter.seek(b"key_101").unwrap();
+        assert_eq!(next_key(&mut iter).as_deref(), Some("key_100"));
+        assert_eq!(next_key(&mut iter).as_deref(), Some("key_098"));
+        iter.seek(b"a").unwrap();
+        assert_eq!(next_key(&mut iter), None);
+        iter.seek(b"z").unwrap();
+        assert_eq!(next_key(&mut iter).as_deref(), Some("key_198"));
+
+        // Bounds still apply
+        let mut iter = reader
+            .range(
+                Bound::Included(b"key_050".as_slice()),
+                Bound::Excluded(b"key_060".as_slice()),
+            )
+            .unwrap();
+        iter.seek(b"key_000").unwrap();
+        assert_eq!(next_key(&mut iter).as_deref(), Some("key_050"));
+        iter.seek(b"key_057").unwrap();
+        assert_eq!(next_key(&mut iter).as_deref(), Some("key_058"));
+        assert_eq!(next_key(&mut iter), None);
+        iter.seek(b"key_070").unwrap();
+        assert_eq!(next_key(&mut iter), None);
+    }
+
+    #[test]
+    fn test_reader_detects_flipped_block_byte() {
+        for lazy_block_index in [false, true] {
+            let dir = tempdir().unwrap();
+            let path = dir.path().join("bitrot.sst");
+            let config = StorageConfig {
+                block_size: 256,
+                lazy_block_index,
+                ..Default::default()
+            };
+
+            let mut builder = SstableBuilder::new(path.clone(), config.clone(), 791).unwrap();
+            for i in 0..50 {
+                let key = format!("key_{:03}", i);
+                builder
+                    .add(key.as_bytes(), &create_test_record(&key, &[b'x'; 20]))
+                    .unwrap();
+            }
+            builder.finish().unwrap();
+
+            let reader = SstableReader::open_verified(
+                path.clone(),
+                config.clone(),
+                create_test_cache(&config),
+            )
+            .unwrap();
+            let target = reader.block_meta(1).unwrap();
+            drop(reader);
+
+            let mut bytes = std::fs::read(&path).unwrap();
+            bytes[target.offset as usize + 4] ^= 0x01;
+            std::fs::write(&path, bytes).unwrap();
+
+            let reader =
+                SstableReader::open(path.clone(), config.clone(), create_test_cache(&config))
+                    .unwrap();
+            let key = String::from_utf8(target.first_key).unwrap();
+            assert!(matches!(reader.get(&key), Err(LsmError::CorruptedData(_))));
+            assert!(matches!(
+                reader.verify_integrity(),
+                Err(LsmError::CorruptedData(_))
+            ));
+            // Blocks that weren't touched still read fine
+            assert!(reader.get("key_000").unwrap().is_some());
+
+            assert!(matches!(
+                SstableReader::open_verified(path, config.clone(), create_test_cache(&config)),
+                Err(LsmError::CorruptedData(_))
+            ));
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap_and_syscall_reads_match() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("io_mode.sst");
+        let config = StorageConfig {
+            block_size: 256,
+            ..Default::default()
+        };
+
+        let mut builder = SstableBuilder::new(path.clone(), config.clone(), 792).unwrap();
+        for i in 0..200 {
+            let key = format!("key_{:03}", i);
+            let value = format!("value_{i}");
+            builder
+                .add(key.as_bytes(), &create_test_record(&key, value.as_bytes()))
+                .unwrap();
+        }
+        builder.finish().unwrap();
+
+        let open = |io_mode: IoMode| {
+            let config = StorageConfig {
+                io_mode,
+                ..config.clone()
+            };
+            let cache = create_test_cache(&config);
+            Arc::new(SstableReader::open(path.clone(), config, cache).unwrap())
+        };
+        let syscall = open(IoMode::Syscall);
+        let mmap = open(IoMode::Mmap);
+        assert!(mmap.block_count() > 10);
+
+        let scan = |reader: &Arc<SstableReader>| -> Vec<(Vec<u8>, Vec<u8>)> {
+            reader
+                .iter()
+                .map(|entry| {
+                    let (key, record) = entry.unwrap();
+                    (key, record.value)
+                })
+                .collect()
+        };
+        assert_eq!(scan(&mmap), scan(&syscall));
+        assert_eq!(scan(&mmap).len(), 200);
+
+        for i in (0..220).step_by(7) {
+            let key = format!("key_{:03}", i);
+            let from_mmap = mmap.get(&key).unwrap().map(|r| r.value);
+            let from_file = syscall.get(&key).unwrap().map(|r| r.value);
+            assert_eq!(from_mmap, from_file, "{key}");
+        }
+        mmap.verify_integrity().unwrap();
+    }
+
     #[test]
     fn test_reader_boundary_keys() {
         let dir = tempdir().unwrap();
@@ -515,6 +1564,127 @@ mod tests {
         assert_eq!(records.len(), test_keys.len(), "Should scan all records");
     }
 
+    #[test]
+    fn test_reader_uncompressed_large_value() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("blob.sst");
+        let config = StorageConfig {
+            no_compress_value_threshold: Some(512),
+            ..Default::default()
+        };
+        let cache = create_test_cache(&config);
+
+        // Pseudo-random bytes stand in for an already-compressed JPEG payload
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let blob: Vec<u8> = (0..2048)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let text = b"small text value that repeats, small text value that repeats";
+
+        let mut builder = SstableBuilder::new(path.clone(), config.clone(), 7).unwrap();
+        builder
+            .add(b"a_note", &create_test_record("a_note", text))
+            .unwrap();
+        builder
+            .add(b"b_photo", &create_test_record("b_photo", &blob))
+            .unwrap();
+        builder
+            .add(b"c_note", &create_test_record("c_note", text))
+            .unwrap();
+        builder
+            .add(b"d_note", &create_test_record("d_note", text))
+            .unwrap();
+        builder.finish().unwrap();
+
+        let mut reader = SstableReader::open(path, config, cache).unwrap();
+        let blocks = &reader.metadata().blocks;
+        assert_eq!(blocks.len(), 3);
+        assert!(blocks[0].compressed);
+        assert!(!blocks[1].compressed);
+        assert!(blocks[2].compressed);
+
+        assert_eq!(reader.get("b_photo").unwrap().unwrap().value, blob);
+        assert_eq!(reader.get("a_note").unwrap().unwrap().value, text);
+        assert_eq!(reader.get("d_note").unwrap().unwrap().value, text);
+        assert_eq!(reader.scan().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_reader_warm_range() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("warm.sst");
+        let config = StorageConfig {
+            block_size: 256,
+            ..Default::default()
+        };
+        let cache = create_test_cache(&config);
+
+        let mut builder = SstableBuilder::new(path.clone(), config.clone(), 1).unwrap();
+        for i in 0..100 {
+            let key = format!("key_{:03}", i);
+            builder
+                .add(key.as_bytes(), &create_test_record(&key, &[b'v'; 20]))
+                .unwrap();
+        }
+        builder.finish().unwrap();
+
+        let mut reader = SstableReader::open(path, config, Arc::clone(&cache)).unwrap();
+        let total_blocks = reader.metadata().blocks.len();
+        assert!(total_blocks > 4);
+
+        assert_eq!(reader.warm_range(b"a", b"b", usize::MAX).unwrap(), 0);
+        assert_eq!(
+            reader
+                .warm_range(b"key_050", b"key_050", usize::MAX)
+                .unwrap(),
+            0
+        );
+        assert_eq!(reader.warm_range(b"key_000", b"key_999", 2).unwrap(), 2);
+
+        let warmed = reader.warm_range(b"key_000", b"z", usize::MAX).unwrap();
+        assert_eq!(warmed, total_blocks);
+        assert_eq!(cache.stats().len, total_blocks);
+
+        assert!(reader.warm_key("key_042").unwrap());
+        assert!(!reader.warm_key("zzz").unwrap());
+    }
+
+    #[test]
+    fn test_reader_tiny_table_without_bloom() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("tiny.sst");
+        let config = StorageConfig {
+            min_keys_for_bloom: 8,
+            ..Default::default()
+        };
+        let cache = create_test_cache(&config);
+
+        let mut builder = SstableBuilder::new(path.clone(), config.clone(), 321).unwrap();
+        for key in ["a", "b", "c"] {
+            builder
+                .add(key.as_bytes(), &create_test_record(key, key.as_bytes()))
+                .unwrap();
+        }
+        builder.finish().unwrap();
+
+        let mut reader = SstableReader::open(path, config, cache).unwrap();
+        assert!(!reader.has_bloom());
+        assert!(!reader.metadata().has_bloom);
+        assert!(reader.metadata().bloom_filter_data.is_empty());
+
+        for key in ["a", "b", "c"] {
+            let record = reader.get(key).unwrap().expect("Key should exist");
+            assert_eq!(record.value, key.as_bytes());
+        }
+        assert!(reader.get("bb").unwrap().is_none());
+        assert!(reader.get("zzz").unwrap().is_none());
+    }
+
     #[test]
     fn test_reader_invalid_magic() {
         let dir = tempdir().unwrap();
@@ -577,4 +1747,145 @@ mod tests {
         // Both readers share the same cache
         assert!(stats_after2.len <= stats_after2.cap);
     }
+
+    #[test]
+    fn test_varint_tables_are_smaller_and_read_back() {
+        let dir = tempdir().unwrap();
+        let fixint = StorageConfig::default();
+        let cache = create_test_cache(&fixint);
+
+        let mut sizes = Vec::new();
+        for (name, config) in [
+            ("fixint.sst", fixint.clone()),
+            (
+                "varint.sst",
+                StorageConfig {
+                    codec: Codec::Varint,
+                    ..fixint.clone()
+                },
+            ),
+            (
+                "varint_lazy.sst",
+                StorageConfig {
+                    codec: Codec::Varint,
+                    lazy_block_index: true,
+                    ..fixint.clone()
+                },
+            ),
+        ] {
+            let path = dir.path().join(name);
+            let mut builder = SstableBuilder::new(path.clone(), config, 1).unwrap();
+            for i in 0..500 {
+                let key = format!("key{i:04}");
+                let mut record = create_test_record(&key, b"v");
+                record.seq = i;
+                builder.add(key.as_bytes(), &record).unwrap();
+            }
+            builder.finish().unwrap();
+            sizes.push(std::fs::metadata(&path).unwrap().len());
+
+            // The header names the codec; the reader's own config doesn't matter
+            let reader = SstableReader::open(path, fixint.clone(), Arc::clone(&cache)).unwrap();
+            assert_eq!(reader.get("key0123").unwrap().unwrap().seq, 123);
+            assert_eq!(reader.get("key0499").unwrap().unwrap().value, b"v");
+            assert!(reader.get("key0500").unwrap().is_none());
+        }
+
+        assert!(sizes[1] < sizes[0], "{sizes:?}");
+    }
+
+    #[test]
+    fn test_each_compression_round_trips_under_the_default_config() {
+        let dir = tempdir().unwrap();
+        let default_config = StorageConfig::default();
+        let cache = create_test_cache(&default_config);
+
+        let mut sizes = Vec::new();
+        for compression in [Compression::None, Compression::Lz4, Compression::Zstd] {
+            let path = dir.path().join(format!("{compression:?}.sst"));
+            let config = StorageConfig {
+                compression,
+                ..default_config.clone()
+            };
+            let mut builder = SstableBuilder::new(path.clone(), config, 1).unwrap();
+            for i in 0..500 {
+                let key = format!("key{i:04}");
+                let value = format!("value{}", i % 10).repeat(8);
+                builder
+                    .add(key.as_bytes(), &create_test_record(&key, value.as_bytes()))
+                    .unwrap();
+            }
+            builder.finish().unwrap();
+            sizes.push(std::fs::metadata(&path).unwrap().len());
+
+            // The table's own algorithm wins over the reader's config
+            let reader =
+                SstableReader::open_verified(path, default_config.clone(), Arc::clone(&cache))
+                    .unwrap();
+            assert_eq!(reader.metadata().compression, compression);
+            assert_eq!(
+                reader.get("key0123").unwrap().unwrap().value,
+                "value3".repeat(8).as_bytes()
+            );
+            assert_eq!(reader.scan().unwrap().len(), 500);
+        }
+
+        assert!(sizes[1] < sizes[0] && sizes[2] < sizes[0], "{sizes:?}");
+    }
+
+    #[test]
+    fn test_numeric_suffix_table_reads_in_its_own_order() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("numeric.sst");
+        let config = StorageConfig {
+            block_size: 256,
+            key_comparator: KeyComparator::NumericSuffix,
+            ..StorageConfig::default()
+        };
+        let mut builder = SstableBuilder::new(path.clone(), config, 1).unwrap();
+        for i in 1..=300 {
+            let key = format!("key_{i}");
+            builder
+                .add(key.as_bytes(), &create_test_record(&key, b"v"))
+                .unwrap();
+        }
+        // Byte order would accept this, numeric order doesn't
+        assert!(builder
+            .add(b"key_1000", &create_test_record("key_1000", b"v"))
+            .is_ok());
+        assert!(builder
+            .add(b"key_999", &create_test_record("key_999", b"v"))
+            .is_err());
+        builder.finish().unwrap();
+
+        // The reader's own config says Lexicographic; the table's order wins
+        let default_config = StorageConfig::default();
+        let cache = create_test_cache(&default_config);
+        let reader = Arc::new(SstableReader::open(path, default_config, cache).unwrap());
+        assert_eq!(reader.comparator(), KeyComparator::NumericSuffix);
+        assert!(reader.block_count() > 1);
+
+        for i in [1, 2, 9, 10, 99, 100, 300, 1000] {
+            assert!(
+                reader.get(&format!("key_{i}")).unwrap().is_some(),
+                "key_{i}"
+            );
+        }
+        assert!(reader.get("key_301").unwrap().is_none());
+
+        let keys = |iter: SstableIter| -> Vec<String> {
+            iter.map(|entry| String::from_utf8(entry.unwrap().0).unwrap())
+                .collect()
+        };
+        let start = Bound::Included(&b"key_8"[..]);
+        let end = Bound::Excluded(&b"key_12"[..]);
+        assert_eq!(
+            keys(reader.range(start, end).unwrap()),
+            ["key_8", "key_9", "key_10", "key_11"]
+        );
+        assert_eq!(
+            keys(reader.range_rev(start, end).unwrap()),
+            ["key_11", "key_10", "key_9", "key_8"]
+        );
+    }
 }
@@ -1,111 +1,1074 @@
 use crate::core::log_record::LogRecord;
-use crate::infra::codec::{decode, encode};
+use crate::infra::codec::encode_with;
+use crate::infra::config::{Codec, WalSyncPolicy};
 use crate::infra::error::{LsmError, Result};
+use crate::storage::sync_parent_dir;
+use crossbeam_channel::{bounded, unbounded, Receiver, RecvTimeoutError, Sender};
+use serde::Serialize;
 use std::fs::{File, OpenOptions};
-use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
-use std::path::PathBuf;
-use std::sync::Mutex;
-use tracing::debug;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
 
+/// Segment size used by [`WriteAheadLog::new`]
+pub const DEFAULT_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Bytes before each record's payload: `[len u32][crc32 u32]`
+const FRAME_HEADER_SIZE: u64 = 8;
+
+/// Length-field flag: the record belongs to a batch whose commit marker
+/// follows it. Record lengths stay far below these bits.
+const BATCH_MEMBER_FLAG: u32 = 1 << 31;
+/// Length-field flag: the frame is a batch commit marker whose payload is
+/// the batch's record count (u64)
+const BATCH_COMMIT_FLAG: u32 = 1 << 30;
+const FRAME_FLAGS: u32 = BATCH_MEMBER_FLAG | BATCH_COMMIT_FLAG;
+
+/// Starts a segment whose records use a codec other than `Codec::Fixint`,
+/// followed by the codec id. Read as a frame length it is far above
+/// `MAX_WAL_RECORD_BYTES`, so it can't be mistaken for a record; segments
+/// without it hold fixint records.
+const SEGMENT_MAGIC: &[u8; 7] = b"LSMWAL2";
+const SEGMENT_HEADER_SIZE: u64 = 8;
+
+/// Single-file WAL written before segments existed, framed `[len u32][payload]`
+/// without a checksum. Replayed before the segments and removed by the next flush.
+const LEGACY_WAL_FILE: &str = "wal.log";
+
+/// Write-ahead log split into numbered segments (`wal-000001.log`, ...).
+///
+/// Each record is framed as `[len u32][crc32 u32][payload]`, so a record cut
+/// short by a crash mid-append can be told apart from corruption (see
+/// [`recover`](Self::recover)). The records of a
+/// [`write_batch`](Self::write_batch) are flagged in `len` and closed by a
+/// commit marker frame. Segments written with a non-default [`Codec`] start
+/// with a header naming it.
+///
+/// Records are appended to the highest-numbered segment, which is rolled over
+/// once it grows past the segment size. A flush seals the active segment with
+/// [`rotate`](Self::rotate) and, once the SSTable is safely written, deletes
+/// the sealed segments with [`remove_segments_before`](Self::remove_segments_before);
+/// writes arriving meanwhile go to the new segment and survive.
 pub struct WriteAheadLog {
-    pub(crate) file: Mutex<BufWriter<File>>,
-    pub(crate) path: PathBuf,
+    active: Arc<Mutex<ActiveSegment>>,
+    pub(crate) dir: PathBuf,
+    sync_metrics: Arc<SyncMetrics>,
+    sync_policy: WalSyncPolicy,
+    codec: Codec,
+    /// fsync the directory after adding or removing a segment
+    sync_directory: bool,
+    /// Background committer, when group commit is enabled
+    group_commit: Option<GroupCommit>,
+    /// Background fsync timer, under `WalSyncPolicy::Interval`
+    interval_sync: Option<IntervalSync>,
+    /// Bytes not yet sealed by [`rotate`](WriteAheadLog::rotate)
+    unsealed_bytes: AtomicU64,
 }
 
-const MAX_WAL_RECORD_BYTES: usize = 32 * 1024 * 1024;
+/// The segment currently being appended to
+struct ActiveSegment {
+    writer: BufWriter<File>,
+    id: u64,
+    bytes: u64,
+    max_bytes: u64,
+    dir: PathBuf,
+    codec: Codec,
+    sync_directory: bool,
+    /// The segment file was created and its directory entry not yet fsynced
+    new_entry: bool,
+    /// Records have been handed to the OS since the last fsync
+    unsynced: bool,
+}
 
-impl WriteAheadLog {
-    pub fn new(dir_path: &std::path::Path) -> Result<Self> {
-        let wal_path = dir_path.join("wal.log");
+impl ActiveSegment {
+    fn open(
+        dir: &Path,
+        id: u64,
+        max_bytes: u64,
+        codec: Codec,
+        sync_directory: bool,
+    ) -> io::Result<Self> {
         let file = OpenOptions::new()
             .create(true)
             .append(true)
-            .open(&wal_path)?;
+            .open(segment_path(dir, id))?;
+        let bytes = file.metadata()?.len();
+        let mut segment = Self {
+            bytes,
+            writer: BufWriter::new(file),
+            id,
+            max_bytes,
+            dir: dir.to_path_buf(),
+            codec,
+            sync_directory,
+            new_entry: bytes == 0,
+            unsynced: false,
+        };
+        segment.write_header()?;
+        Ok(segment)
+    }
+
+    /// Start an empty segment with the codec header, unless it uses the default codec
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.bytes == 0 && self.codec != Codec::Fixint {
+            self.append(SEGMENT_MAGIC)?;
+            self.append(&[self.codec.id()])?;
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.writer.write_all(frame)?;
+        self.bytes += frame.len() as u64;
+        Ok(())
+    }
+
+    /// Hand buffered records to the OS without waiting for the disk
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.unsynced = true;
+        Ok(())
+    }
+
+    fn sync(&mut self, metrics: &SyncMetrics) -> io::Result<()> {
+        self.writer.flush()?;
+        let sync_start = Instant::now();
+        self.writer.get_ref().sync_all()?;
+        // A fresh segment's records are only durable once its name is too
+        if self.new_entry && self.sync_directory {
+            sync_parent_dir(&segment_path(&self.dir, self.id))?;
+        }
+        self.new_entry = false;
+        metrics.record(sync_start.elapsed().as_nanos() as u64);
+        self.unsynced = false;
+        Ok(())
+    }
+
+    /// Start the next segment; the current one must already be synced
+    fn roll(&mut self) -> io::Result<()> {
+        *self = Self::open(
+            &self.dir,
+            self.id + 1,
+            self.max_bytes,
+            self.codec,
+            self.sync_directory,
+        )?;
+        debug!("WAL rolled to segment {}", self.id);
+        Ok(())
+    }
+
+    fn roll_if_full(&mut self) -> io::Result<()> {
+        if self.bytes >= self.max_bytes {
+            self.roll()?;
+        }
+        Ok(())
+    }
+}
+
+/// Path of segment `id` inside `dir`
+pub(crate) fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("wal-{:06}.log", id))
+}
+
+/// Ids of the WAL segments in `dir`, ascending
+fn segment_ids(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut ids = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let id = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("wal-"))
+            .and_then(|rest| rest.strip_suffix(".log"))
+            .and_then(|digits| digits.parse::<u64>().ok());
+        if let Some(id) = id {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// A framed record waiting for the group-commit thread, plus the channel its
+/// writer blocks on until the batch holding it has been fsynced
+struct PendingWrite {
+    frame: Vec<u8>,
+    done: Sender<io::Result<()>>,
+}
+
+struct GroupCommit {
+    sender: Option<Sender<PendingWrite>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+struct IntervalSync {
+    /// Dropped to stop the thread after one last fsync
+    stop: Option<Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+/// Lock-free fsync latency accumulator (one `Instant` pair per write)
+struct SyncMetrics {
+    count: AtomicU64,
+    total_ns: AtomicU64,
+    min_ns: AtomicU64,
+    max_ns: AtomicU64,
+}
+
+impl SyncMetrics {
+    fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            total_ns: AtomicU64::new(0),
+            min_ns: AtomicU64::new(u64::MAX),
+            max_ns: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, elapsed_ns: u64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_ns.fetch_add(elapsed_ns, Ordering::Relaxed);
+        self.min_ns.fetch_min(elapsed_ns, Ordering::Relaxed);
+        self.max_ns.fetch_max(elapsed_ns, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> WalStats {
+        let syncs = self.count.load(Ordering::Relaxed);
+        let total_ns = self.total_ns.load(Ordering::Relaxed);
+        let min_ns = self.min_ns.load(Ordering::Relaxed);
+
+        WalStats {
+            syncs,
+            min_sync_us: if syncs == 0 { 0 } else { min_ns / 1000 },
+            max_sync_us: self.max_ns.load(Ordering::Relaxed) / 1000,
+            avg_sync_us: total_ns.checked_div(syncs).unwrap_or(0) / 1000,
+            total_sync_ms: total_ns / 1_000_000,
+        }
+    }
+}
+
+/// WAL fsync latency statistics, accumulated since the engine was opened
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub struct WalStats {
+    /// Number of fsyncs issued (one per batch under group commit)
+    pub syncs: u64,
+    pub min_sync_us: u64,
+    pub max_sync_us: u64,
+    pub avg_sync_us: u64,
+    pub total_sync_ms: u64,
+}
+
+/// Running totals of a replay, reported by
+/// [`WriteAheadLog::recover_with_progress`] after each segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryProgress {
+    pub segments_done: usize,
+    pub segments_total: usize,
+    /// Records replayed so far
+    pub records: usize,
+    /// Size of the segments replayed so far
+    pub bytes: u64,
+}
+
+pub(crate) const MAX_WAL_RECORD_BYTES: usize = 32 * 1024 * 1024;
+
+impl WriteAheadLog {
+    pub fn new(dir_path: &Path) -> Result<Self> {
+        Self::open(dir_path, DEFAULT_SEGMENT_SIZE)
+    }
+
+    /// Open the WAL in `dir_path`, rolling segments over at `segment_size`
+    /// bytes. Existing segments are kept for [`recover`](Self::recover) and
+    /// new records go to a fresh segment after them.
+    pub fn open(dir_path: &Path, segment_size: u64) -> Result<Self> {
+        let existing = segment_ids(dir_path)?;
+        let next_id = existing.last().map_or(1, |id| id + 1);
+        // Recovered records count until the first rotate seals them
+        let existing_bytes: u64 = existing
+            .iter()
+            .filter_map(|&id| std::fs::metadata(segment_path(dir_path, id)).ok())
+            .map(|meta| meta.len())
+            .sum();
+        let active = ActiveSegment::open(dir_path, next_id, segment_size, Codec::Fixint, true)?;
 
         Ok(Self {
-            file: Mutex::new(BufWriter::new(file)),
-            path: wal_path,
+            active: Arc::new(Mutex::new(active)),
+            dir: dir_path.to_path_buf(),
+            sync_metrics: Arc::new(SyncMetrics::new()),
+            sync_policy: WalSyncPolicy::Always,
+            codec: Codec::Fixint,
+            sync_directory: true,
+            group_commit: None,
+            interval_sync: None,
+            unsealed_bytes: AtomicU64::new(existing_bytes),
         })
     }
 
-    pub fn write_record(&self, record: &LogRecord) -> Result<()> {
-        let serialized = encode(record)?;
-        let length = serialized.len() as u32;
+    /// Choose when appended records are fsynced (default `Always`).
+    ///
+    /// - `Always`: `write_record` returns once the record is on disk, so an
+    ///   acknowledged write survives a power loss.
+    /// - `Interval(d)`: `write_record` returns once the record is handed to
+    ///   the OS and a background thread fsyncs every `d`. A process crash loses
+    ///   nothing, a power loss up to `d` of acknowledged writes.
+    /// - `Never`: records are handed to the OS and only fsynced when a segment
+    ///   is sealed or [`sync`](Self::sync) is called. A process crash loses
+    ///   nothing, a power loss whatever the OS hadn't written back.
+    ///
+    /// Group commit always fsyncs its batches and ignores this setting.
+    pub fn with_sync_policy(mut self, policy: WalSyncPolicy) -> Result<Self> {
+        if let WalSyncPolicy::Interval(interval) = policy {
+            let (stop, stopped) = bounded(0);
+            let active = Arc::clone(&self.active);
+            let metrics = Arc::clone(&self.sync_metrics);
+            let worker = std::thread::Builder::new()
+                .name("wal-interval-sync".to_string())
+                .spawn(move || run_interval_sync(&active, &metrics, &stopped, interval))?;
 
-        let mut writer = self
-            .file
-            .lock()
-            .map_err(|_| LsmError::LockPoisoned("wal_writer"))?;
+            self.interval_sync = Some(IntervalSync {
+                stop: Some(stop),
+                worker: Some(worker),
+            });
+        }
+        self.sync_policy = policy;
+        Ok(self)
+    }
+
+    /// Encode new records with `codec` (default `Codec::Fixint`). Segments
+    /// already on disk keep the codec they were written with.
+    pub fn with_codec(mut self, codec: Codec) -> Result<Self> {
+        {
+            let mut active = self.active()?;
+            active.codec = codec;
+            active.write_header()?;
+        }
+        self.codec = codec;
+        Ok(self)
+    }
+
+    /// Whether to fsync the directory when a new segment is first synced and
+    /// after segments are removed (default `true`), so the change survives a
+    /// crash. Turn off where directories can't be fsynced.
+    pub fn with_directory_sync(mut self, enabled: bool) -> Result<Self> {
+        self.active()?.sync_directory = enabled;
+        self.sync_directory = enabled;
+        Ok(self)
+    }
 
-        writer.write_all(&length.to_le_bytes())?;
-        writer.write_all(&serialized)?;
-        writer.flush()?;
-        writer.get_ref().sync_all()?;
+    /// Switch to group commit: records arriving within `window` of the first
+    /// one in a batch are written together and share one fsync.
+    ///
+    /// `write_record` still returns only once its own record is on disk, so
+    /// durability is unchanged; a lone writer just waits up to `window` longer.
+    pub fn with_group_commit(mut self, window: Duration) -> Result<Self> {
+        let (sender, receiver) = unbounded();
+        let active = Arc::clone(&self.active);
+        let metrics = Arc::clone(&self.sync_metrics);
+        let worker = std::thread::Builder::new()
+            .name("wal-group-commit".to_string())
+            .spawn(move || run_group_commit(&active, &metrics, &receiver, window))?;
+
+        self.group_commit = Some(GroupCommit {
+            sender: Some(sender),
+            worker: Some(worker),
+        });
+        Ok(self)
+    }
+
+    pub fn write_record(&self, record: &LogRecord) -> Result<()> {
+        let mut frames = Vec::new();
+        push_frame(&mut frames, &encode_with(self.codec, record)?, 0);
+        self.append(frames)?;
 
         debug!("WAL persisted: key={}, ts={}", record.key, record.timestamp);
         Ok(())
     }
 
+    /// Append `records` as one atomic group: a single write and (under
+    /// `WalSyncPolicy::Always`) a single fsync, followed by a commit marker.
+    /// Recovery drops a batch whose marker never made it to disk.
+    pub fn write_batch(&self, records: &[LogRecord]) -> Result<()> {
+        let mut frames = Vec::new();
+        for record in records {
+            push_frame(
+                &mut frames,
+                &encode_with(self.codec, record)?,
+                BATCH_MEMBER_FLAG,
+            );
+        }
+        push_frame(
+            &mut frames,
+            &(records.len() as u64).to_le_bytes(),
+            BATCH_COMMIT_FLAG,
+        );
+        self.append(frames)?;
+
+        debug!("WAL persisted batch of {} records", records.len());
+        Ok(())
+    }
+
+    /// Write already framed bytes to the active segment and make them as
+    /// durable as the sync policy asks
+    fn append(&self, frames: Vec<u8>) -> Result<()> {
+        self.unsealed_bytes
+            .fetch_add(frames.len() as u64, Ordering::Relaxed);
+        if let Some(sender) = self.group_commit.as_ref().and_then(|g| g.sender.as_ref()) {
+            let (done, durable) = bounded(1);
+            let stopped = || io::Error::other("WAL group commit thread stopped");
+            sender
+                .send(PendingWrite {
+                    frame: frames,
+                    done,
+                })
+                .map_err(|_| stopped())?;
+            durable.recv().map_err(|_| stopped())??;
+            return Ok(());
+        }
+
+        let mut active = self.active()?;
+        active.append(&frames)?;
+        match self.sync_policy {
+            WalSyncPolicy::Always => active.sync(&self.sync_metrics)?,
+            WalSyncPolicy::Interval(_) | WalSyncPolicy::Never => active.flush()?,
+        }
+        if active.bytes >= active.max_bytes
+            && matches!(self.sync_policy, WalSyncPolicy::Interval(_))
+        {
+            // The timer only covers the active segment
+            active.sync(&self.sync_metrics)?;
+        }
+        active.roll_if_full()?;
+        Ok(())
+    }
+
+    /// Force every record written so far to disk, whatever the sync policy.
+    /// Call before a clean shutdown under `Interval` or `Never`.
+    pub fn sync(&self) -> Result<()> {
+        let mut active = self.active()?;
+        if active.unsynced {
+            active.sync(&self.sync_metrics)?;
+        }
+        Ok(())
+    }
+
+    /// Snapshot of the fsync latency counters
+    pub fn stats(&self) -> WalStats {
+        self.sync_metrics.snapshot()
+    }
+
+    /// Every record in the log, oldest segment first.
+    ///
+    /// A final record of the newest segment that is incomplete or fails its
+    /// checksum is a torn write from a crash during the append; it was never
+    /// acknowledged, so it is logged, cut off the segment and skipped. Older
+    /// segments were synced before the next one started, so any bad record
+    /// in them is real corruption, as is a bad record with an intact one
+    /// behind it or a frame header no append could have written; these fail
+    /// with `LsmError::WalCorruption` and leave the segment untouched.
     pub fn recover(&self) -> Result<Vec<LogRecord>> {
+        self.recover_with_progress(|_| {})
+    }
+
+    /// [`recover`](Self::recover), calling `progress` after each segment is
+    /// replayed
+    pub fn recover_with_progress(
+        &self,
+        mut progress: impl FnMut(RecoveryProgress),
+    ) -> Result<Vec<LogRecord>> {
         let mut records = Vec::new();
-        let file = File::open(&self.path)?;
-        let mut reader = BufReader::new(file);
+        let legacy = self.dir.join(LEGACY_WAL_FILE);
+        let paths = self.segment_paths()?;
+        let mut done = RecoveryProgress {
+            segments_done: 0,
+            segments_total: paths.len(),
+            records: 0,
+            bytes: 0,
+        };
+        // Only the newest segment holding frames can end in a torn append
+        let mut tail = None;
+        for path in paths.iter().rev().filter(|path| **path != legacy) {
+            if has_frames(path)? {
+                tail = Some(path);
+                break;
+            }
+        }
+        for path in &paths {
+            // Taken before replay, which may cut off a torn tail
+            let bytes = std::fs::metadata(path)?.len();
+            if *path == legacy {
+                read_legacy_wal(path, &mut records)?;
+            } else {
+                read_segment(path, tail == Some(path), &mut records)?;
+            }
+            done.segments_done += 1;
+            done.records = records.len();
+            done.bytes += bytes;
+            progress(done);
+        }
+        Ok(records)
+    }
+
+    /// Seal the active segment and start a new one, returning the new
+    /// segment's id. Everything written before the call lives in segments
+    /// with a smaller id.
+    pub fn rotate(&self) -> Result<u64> {
+        let mut active = self.active()?;
+        active.sync(&self.sync_metrics)?;
+        active.roll()?;
+        self.unsealed_bytes.store(0, Ordering::Relaxed);
+        Ok(active.id)
+    }
+
+    /// Bytes appended since the last [`rotate`](Self::rotate), plus those
+    /// already on disk when the log was opened: what replay would have to
+    /// read for records not yet sealed away
+    pub fn unsealed_bytes(&self) -> u64 {
+        self.unsealed_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Delete the sealed segments older than `id` (see [`rotate`](Self::rotate)),
+    /// returning how many were removed
+    pub fn remove_segments_before(&self, id: u64) -> Result<usize> {
+        let mut removed = 0;
+        let legacy = self.dir.join(LEGACY_WAL_FILE);
+        if legacy.exists() {
+            std::fs::remove_file(legacy)?;
+            removed += 1;
+        }
+        for old in segment_ids(&self.dir)?.into_iter().filter(|&old| old < id) {
+            std::fs::remove_file(segment_path(&self.dir, old))?;
+            removed += 1;
+        }
+        if removed > 0 && self.sync_directory {
+            sync_parent_dir(&segment_path(&self.dir, id))?;
+        }
+        debug!("Removed {} WAL segments before {}", removed, id);
+        Ok(removed)
+    }
+
+    /// Paths of all segments on disk, oldest first (a legacy `wal.log`
+    /// counts as the oldest)
+    pub fn segment_paths(&self) -> Result<Vec<PathBuf>> {
+        let legacy = self.dir.join(LEGACY_WAL_FILE);
+        let legacy = legacy.exists().then_some(legacy);
+        Ok(legacy
+            .into_iter()
+            .chain(
+                segment_ids(&self.dir)?
+                    .into_iter()
+                    .map(|id| segment_path(&self.dir, id)),
+            )
+            .collect())
+    }
 
-        loop {
-            let buf = reader.fill_buf()?;
-            if buf.is_empty() {
+    /// Total size of all segments on disk
+    pub fn size_bytes(&self) -> u64 {
+        self.segment_paths()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|meta| meta.len())
+            .sum()
+    }
+
+    fn active(&self) -> Result<std::sync::MutexGuard<'_, ActiveSegment>> {
+        self.active
+            .lock()
+            .map_err(|_| LsmError::LockPoisoned("wal_writer"))
+    }
+}
+
+/// Append `[len | flags][crc32][payload]` to `frames`
+fn push_frame(frames: &mut Vec<u8>, payload: &[u8], flags: u32) {
+    frames.extend_from_slice(&(payload.len() as u32 | flags).to_le_bytes());
+    frames.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    frames.extend_from_slice(payload);
+}
+
+/// Outcome of reading one frame of a segment
+enum Frame {
+    Record {
+        record: LogRecord,
+        in_batch: bool,
+        size: u64,
+    },
+    /// Closes the batch of `count` records before it
+    BatchCommit { count: u64, size: u64 },
+    /// The record was cut short or failed its checksum with no room for
+    /// another frame behind it
+    TornTail,
+}
+
+/// Whether the segment at `path` holds anything past its header
+fn has_frames(path: &Path) -> Result<bool> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(&file);
+    let (_, pos) = read_segment_header(&mut reader, file_len)?;
+    Ok(pos < file_len)
+}
+
+/// Append the records of one segment to `records`. In the newest segment
+/// (`is_tail`) a torn tail and a trailing batch that was never committed are
+/// cut off; anywhere else they fail with `LsmError::WalCorruption`
+fn read_segment(path: &Path, is_tail: bool, records: &mut Vec<LogRecord>) -> Result<()> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(&file);
+    let (codec, mut pos) = read_segment_header(&mut reader, file_len)?;
+
+    // Records of the batch being read and the offset where it starts
+    let mut batch: Vec<LogRecord> = Vec::new();
+    let mut batch_start = 0;
+
+    while pos < file_len {
+        match read_frame(&mut reader, file_len - pos, codec)? {
+            Frame::Record {
+                record,
+                in_batch: true,
+                size,
+            } => {
+                if batch.is_empty() {
+                    batch_start = pos;
+                }
+                batch.push(record);
+                pos += size;
+            }
+            Frame::Record { record, size, .. } => {
+                // Batches are appended in one piece, so nothing can follow an
+                // unfinished one
+                if !batch.is_empty() {
+                    return Err(LsmError::WalCorruption);
+                }
+                records.push(record);
+                pos += size;
+            }
+            Frame::BatchCommit { count, size } => {
+                if count != batch.len() as u64 {
+                    return Err(LsmError::WalCorruption);
+                }
+                records.append(&mut batch);
+                pos += size;
+            }
+            Frame::TornTail => {
+                if !is_tail || frame_follows(&mut reader, pos)? {
+                    return Err(LsmError::WalCorruption);
+                }
+                warn!(
+                    "Discarding torn record at the end of {} ({} bytes at offset {})",
+                    path.display(),
+                    file_len - pos,
+                    pos
+                );
+                file.set_len(pos)?;
+                file.sync_all()?;
                 break;
             }
+        }
+    }
+
+    if !batch.is_empty() {
+        if !is_tail {
+            return Err(LsmError::WalCorruption);
+        }
+        warn!(
+            "Discarding uncommitted batch of {} records at the end of {}",
+            batch.len(),
+            path.display()
+        );
+        file.set_len(batch_start)?;
+        file.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// The codec of a segment and the offset of its first frame, leaving the
+/// reader there
+fn read_segment_header(reader: &mut BufReader<&File>, file_len: u64) -> Result<(Codec, u64)> {
+    if file_len < SEGMENT_HEADER_SIZE || !reader.fill_buf()?.starts_with(SEGMENT_MAGIC) {
+        return Ok((Codec::Fixint, 0));
+    }
+
+    let mut header = [0u8; SEGMENT_HEADER_SIZE as usize];
+    reader.read_exact(&mut header)?;
+    let codec = Codec::from_id(header[SEGMENT_MAGIC.len()]).ok_or(LsmError::WalCorruption)?;
+    Ok((codec, SEGMENT_HEADER_SIZE))
+}
+
+/// Whether an intact frame starts anywhere after the header of the frame at
+/// `pos`. A torn append leaves nothing whole behind its last frame, so a
+/// frame whose length runs past the end but is followed by one had its
+/// length damaged after it was written
+fn frame_follows(reader: &mut BufReader<&File>, pos: u64) -> Result<bool> {
+    reader.seek(SeekFrom::Start(pos + FRAME_HEADER_SIZE))?;
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest)?;
+
+    let header_size = FRAME_HEADER_SIZE as usize;
+    Ok((0..rest.len().saturating_sub(header_size)).any(|start| {
+        let header = &rest[start..start + header_size];
+        let length_field = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let checksum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let length = (length_field & !FRAME_FLAGS) as usize;
+        let payload = start + header_size;
+        length != 0
+            && length <= rest.len() - payload
+            && (length_field & FRAME_FLAGS) != FRAME_FLAGS
+            && crc32fast::hash(&rest[payload..payload + length]) == checksum
+    }))
+}
+
+/// Read the frame at the reader's position; `remaining` is the number of
+/// bytes left in the segment
+fn read_frame(reader: &mut impl Read, remaining: u64, codec: Codec) -> Result<Frame> {
+    if remaining < FRAME_HEADER_SIZE {
+        return Ok(Frame::TornTail);
+    }
+
+    let mut header = [0u8; FRAME_HEADER_SIZE as usize];
+    reader.read_exact(&mut header)?;
+    let length_field = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let checksum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    let flags = length_field & FRAME_FLAGS;
+    let length = (length_field & !FRAME_FLAGS) as usize;
+
+    // No append writes such a header, torn or not
+    if length == 0 || length > MAX_WAL_RECORD_BYTES || flags == FRAME_FLAGS {
+        return Err(LsmError::WalCorruption);
+    }
+
+    // The append stopped partway through the payload
+    let size = FRAME_HEADER_SIZE + length as u64;
+    if size > remaining {
+        return Ok(Frame::TornTail);
+    }
+
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload)?;
+    if crc32fast::hash(&payload) != checksum {
+        // Only the last frame can be torn; one with room for another frame
+        // behind it was damaged after it was written
+        return if remaining - size < FRAME_HEADER_SIZE {
+            Ok(Frame::TornTail)
+        } else {
+            Err(LsmError::WalCorruption)
+        };
+    }
+
+    if flags == BATCH_COMMIT_FLAG {
+        let count: [u8; 8] = payload
+            .as_slice()
+            .try_into()
+            .map_err(|_| LsmError::WalCorruption)?;
+        return Ok(Frame::BatchCommit {
+            count: u64::from_le_bytes(count),
+            size,
+        });
+    }
+
+    let record = LogRecord::decode_with(codec, &payload).map_err(|_| LsmError::WalCorruption)?;
+    Ok(Frame::Record {
+        record,
+        in_batch: flags == BATCH_MEMBER_FLAG,
+        size,
+    })
+}
+
+/// Append the records of a pre-segment `wal.log` (`[len u32][payload]`
+/// frames) to `records`
+fn read_legacy_wal(path: &Path, records: &mut Vec<LogRecord>) -> Result<()> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+
+        if buf.len() < 4 {
+            return Err(LsmError::WalCorruption);
+        }
+
+        let mut lengthbuf = [0u8; 4];
+        reader.read_exact(&mut lengthbuf)?;
+        let length = u32::from_le_bytes(lengthbuf) as usize;
 
-            if buf.len() < 4 {
+        if length == 0 || length > MAX_WAL_RECORD_BYTES {
+            return Err(LsmError::WalCorruption);
+        }
+
+        let mut buffer = vec![0u8; length];
+        if let Err(e) = reader.read_exact(&mut buffer) {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
                 return Err(LsmError::WalCorruption);
             }
+            return Err(e.into());
+        }
+
+        let record = LogRecord::decode(&buffer).map_err(|_| LsmError::WalCorruption)?;
+        records.push(record);
+    }
 
-            let mut lengthbuf = [0u8; 4];
-            reader.read_exact(&mut lengthbuf)?;
-            let length = u32::from_le_bytes(lengthbuf) as usize;
+    Ok(())
+}
 
-            if length == 0 || length > MAX_WAL_RECORD_BYTES {
-                return Err(LsmError::WalCorruption);
+impl Drop for WriteAheadLog {
+    fn drop(&mut self) {
+        if let Some(group_commit) = &mut self.group_commit {
+            // Closing the channel lets the worker drain what's queued and exit
+            group_commit.sender.take();
+            if let Some(worker) = group_commit.worker.take() {
+                let _ = worker.join();
             }
+        }
+        if let Some(interval_sync) = &mut self.interval_sync {
+            interval_sync.stop.take();
+            if let Some(worker) = interval_sync.worker.take() {
+                let _ = worker.join();
+            }
+        }
+    }
+}
 
-            let mut buffer = vec![0u8; length];
-            if let Err(e) = reader.read_exact(&mut buffer) {
-                if e.kind() == io::ErrorKind::UnexpectedEof {
-                    return Err(LsmError::WalCorruption);
+/// Body of the group-commit thread: gather a batch, write it, fsync once,
+/// then release every writer in the batch with the shared outcome
+fn run_group_commit(
+    active: &Mutex<ActiveSegment>,
+    metrics: &SyncMetrics,
+    receiver: &Receiver<PendingWrite>,
+    window: Duration,
+) {
+    while let Ok(first) = receiver.recv() {
+        let mut batch = vec![first];
+        let deadline = Instant::now() + window;
+        while let Ok(pending) = receiver.recv_deadline(deadline) {
+            batch.push(pending);
+        }
+
+        let result = commit_group(active, metrics, &batch);
+        debug!(
+            "WAL group commit: {} records, ok={}",
+            batch.len(),
+            result.is_ok()
+        );
+        for pending in batch {
+            let outcome = match &result {
+                Ok(()) => Ok(()),
+                Err(e) => Err(io::Error::new(e.kind(), e.to_string())),
+            };
+            // The writer may have given up waiting; nothing to do then
+            let _ = pending.done.send(outcome);
+        }
+    }
+}
+
+/// Body of the `Interval` sync thread: fsync the active segment every
+/// `interval` if anything was written, and once more when stopped
+fn run_interval_sync(
+    active: &Mutex<ActiveSegment>,
+    metrics: &SyncMetrics,
+    stopped: &Receiver<()>,
+    interval: Duration,
+) {
+    loop {
+        let stopping = stopped.recv_timeout(interval) != Err(RecvTimeoutError::Timeout);
+        match active.lock() {
+            Ok(mut active) if active.unsynced => {
+                if let Err(e) = active.sync(metrics) {
+                    warn!("WAL interval sync failed: {}", e);
                 }
-                return Err(e.into());
             }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+        if stopping {
+            break;
+        }
+    }
+}
+
+fn commit_group(
+    active: &Mutex<ActiveSegment>,
+    metrics: &SyncMetrics,
+    batch: &[PendingWrite],
+) -> io::Result<()> {
+    let mut active = active
+        .lock()
+        .map_err(|_| io::Error::other("WAL writer lock poisoned"))?;
+
+    for pending in batch {
+        active.append(&pending.frame)?;
+    }
+    active.sync(metrics)?;
+    active.roll_if_full()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_stats_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WriteAheadLog::new(dir.path()).unwrap();
 
-            let record: LogRecord = decode(&buffer).map_err(|_| LsmError::WalCorruption)?;
-            records.push(record);
+        let stats = wal.stats();
+        assert_eq!(stats.syncs, 0);
+        assert_eq!(stats.min_sync_us, 0);
+        assert_eq!(stats.avg_sync_us, 0);
+    }
+
+    #[test]
+    fn test_sync_stats_recorded_per_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WriteAheadLog::new(dir.path()).unwrap();
+
+        for i in 0..5 {
+            wal.write_record(&LogRecord::new(format!("k{i}"), b"v".to_vec()))
+                .unwrap();
         }
 
-        Ok(records)
+        let stats = wal.stats();
+        assert_eq!(stats.syncs, 5);
+        assert!(stats.min_sync_us <= stats.avg_sync_us);
+        assert!(stats.avg_sync_us <= stats.max_sync_us);
     }
 
-    pub fn clear(&self) -> Result<()> {
-        let mut guard = self
-            .file
-            .lock()
-            .map_err(|_| LsmError::LockPoisoned("wal_writer"))?;
+    #[test]
+    fn test_interval_policy_recovers_after_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WriteAheadLog::new(dir.path())
+            .unwrap()
+            .with_sync_policy(WalSyncPolicy::Interval(Duration::from_secs(60)))
+            .unwrap();
 
-        guard.flush()?;
-        guard.get_ref().sync_all()?;
+        for i in 0..50 {
+            wal.write_record(&LogRecord::new(format!("k{i:02}"), b"v".to_vec()))
+                .unwrap();
+        }
+        // Nothing waits on the disk per write; the timer hasn't fired yet
+        assert_eq!(wal.stats().syncs, 0);
 
-        let truncfile = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&self.path)?;
-        truncfile.sync_all()?;
+        wal.sync().unwrap();
+        assert_eq!(wal.stats().syncs, 1);
+        drop(wal);
 
-        let appendfile = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.path)?;
+        let wal = WriteAheadLog::new(dir.path()).unwrap();
+        let keys: Vec<String> = wal.recover().unwrap().into_iter().map(|r| r.key).collect();
+        assert_eq!(keys.len(), 50);
+        assert_eq!(keys[0], "k00");
+        assert_eq!(keys[49], "k49");
+    }
 
-        *guard = BufWriter::new(appendfile);
-        Ok(())
+    #[test]
+    fn test_interval_policy_syncs_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WriteAheadLog::new(dir.path())
+            .unwrap()
+            .with_sync_policy(WalSyncPolicy::Interval(Duration::from_millis(10)))
+            .unwrap();
+
+        wal.write_record(&LogRecord::new("k".to_string(), b"v".to_vec()))
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while wal.stats().syncs == 0 {
+            assert!(Instant::now() < deadline, "interval sync never ran");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_never_policy_skips_fsync() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WriteAheadLog::new(dir.path())
+            .unwrap()
+            .with_sync_policy(WalSyncPolicy::Never)
+            .unwrap();
+
+        wal.write_record(&LogRecord::new("k".to_string(), b"v".to_vec()))
+            .unwrap();
+        assert_eq!(wal.stats().syncs, 0);
+
+        // Already handed to the OS, so a second handle sees it
+        let reader = WriteAheadLog::new(dir.path()).unwrap();
+        assert_eq!(reader.recover().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_segments_roll_and_recover_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WriteAheadLog::open(dir.path(), 4096).unwrap();
+
+        for i in 0..200 {
+            wal.write_record(&LogRecord::new(format!("k{i:03}"), vec![b'v'; 32]))
+                .unwrap();
+        }
+        assert!(wal.segment_paths().unwrap().len() >= 3);
+
+        let sealed_before = wal.rotate().unwrap();
+        wal.write_record(&LogRecord::new("after".to_string(), b"v".to_vec()))
+            .unwrap();
+        drop(wal);
+
+        let wal = WriteAheadLog::open(dir.path(), 4096).unwrap();
+        let mut reports = Vec::new();
+        let keys: Vec<String> = wal
+            .recover_with_progress(|progress| reports.push(progress))
+            .unwrap()
+            .into_iter()
+            .map(|r| r.key)
+            .collect();
+        assert_eq!(keys.len(), 201);
+        let last = reports.last().unwrap();
+        assert_eq!(reports.len(), last.segments_total);
+        assert_eq!(last.segments_done, last.segments_total);
+        assert_eq!(last.records, 201);
+        assert_eq!(last.bytes, wal.size_bytes());
+        assert_eq!(keys[0], "k000");
+        assert_eq!(keys[199], "k199");
+        assert_eq!(keys[200], "after");
+
+        assert!(wal.remove_segments_before(sealed_before).unwrap() >= 3);
+        let keys: Vec<String> = wal.recover().unwrap().into_iter().map(|r| r.key).collect();
+        assert_eq!(keys, vec!["after"]);
+    }
+
+    #[test]
+    fn test_varint_segments_recover_next_to_fixint_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WriteAheadLog::new(dir.path()).unwrap();
+        wal.write_record(&LogRecord::new("fixint".to_string(), b"v".to_vec()))
+            .unwrap();
+        drop(wal);
+
+        let wal = WriteAheadLog::new(dir.path())
+            .unwrap()
+            .with_codec(Codec::Varint)
+            .unwrap();
+        wal.write_record(&LogRecord::new("varint".to_string(), b"v".to_vec()))
+            .unwrap();
+        wal.write_batch(&[
+            LogRecord::new("batch1".to_string(), b"v".to_vec()),
+            LogRecord::tombstone("batch2".to_string()),
+        ])
+        .unwrap();
+        let segments = wal.segment_paths().unwrap();
+        assert!(std::fs::read(&segments[1])
+            .unwrap()
+            .starts_with(SEGMENT_MAGIC));
+        drop(wal);
+
+        // Readable whatever codec the reader writes with
+        let wal = WriteAheadLog::new(dir.path()).unwrap();
+        let records = wal.recover().unwrap();
+        let keys: Vec<&str> = records.iter().map(|r| r.key.as_str()).collect();
+        assert_eq!(keys, vec!["fixint", "varint", "batch1", "batch2"]);
+        assert!(records[3].is_deleted);
     }
 }
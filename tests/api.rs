@@ -0,0 +1,596 @@
+#![cfg(feature = "api")]
+
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::{test, web, App};
+use lsm_kv_store::api::{routes, AppState};
+use lsm_kv_store::{FeatureClient, LsmConfig, LsmEngine, OverflowPolicy, WriteBatch};
+use std::collections::BTreeSet;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::tempdir;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::{reload, Layer, Registry};
+
+fn state(engine: LsmEngine) -> AppState {
+    let engine = Arc::new(engine);
+    AppState {
+        features: Arc::new(FeatureClient::new(
+            Arc::clone(&engine),
+            Duration::from_secs(10),
+        )),
+        engine,
+        watch_capacity: 16,
+        watch_policy: OverflowPolicy::DropOldest,
+        hidden_prefixes: vec!["feature:".to_string()],
+        api_token: None,
+        auth_reads: false,
+        log_level: None,
+    }
+}
+
+fn app_state(engine: LsmEngine) -> web::Data<AppState> {
+    web::Data::new(state(engine))
+}
+
+#[actix_web::test]
+async fn scan_paginates_with_a_cursor() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .memtable_max_size(16 * 1024)
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config).unwrap();
+    // Inserted out of order and spread over several SSTables
+    for i in (0..1000).rev() {
+        engine
+            .set(format!("key{i:04}"), format!("v{i}").into_bytes())
+            .unwrap();
+    }
+    let app = test::init_service(App::new().app_data(app_state(engine)).configure(routes)).await;
+
+    let mut seen = Vec::new();
+    let mut cursor = String::new();
+    let mut pages = 0;
+    loop {
+        let uri = format!("/scan?limit=100&cursor={cursor}");
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, test::TestRequest::get().uri(&uri).to_request())
+                .await;
+        let records = body["data"]["records"].as_array().unwrap();
+        assert!(records.len() <= 100);
+        seen.extend(
+            records
+                .iter()
+                .map(|r| r["key"].as_str().unwrap().to_string()),
+        );
+        pages += 1;
+
+        cursor = body["data"]["next_cursor"].as_str().unwrap().to_string();
+        if cursor.is_empty() {
+            break;
+        }
+    }
+
+    assert_eq!(pages, 10);
+    assert_eq!(seen.len(), 1000);
+    assert_eq!(seen.iter().collect::<BTreeSet<_>>().len(), 1000);
+    assert!(seen.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(seen[0], "key0000");
+    assert_eq!(seen[999], "key0999");
+
+    let request = test::TestRequest::get()
+        .uri("/scan?cursor=not*base64")
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 400);
+}
+
+fn record_keys(body: serde_json::Value) -> Vec<String> {
+    body["data"]["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|record| record["key"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[actix_web::test]
+async fn scan_returns_a_key_window() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config).unwrap();
+    for key in ["a", "b", "c", "d", "e"] {
+        engine.set(key.to_string(), b"v".to_vec()).unwrap();
+    }
+    engine.flush().unwrap();
+    engine.set("bb".to_string(), b"v".to_vec()).unwrap();
+    let app = test::init_service(App::new().app_data(app_state(engine)).configure(routes)).await;
+
+    let cases: [(&str, &[&str]); 7] = [
+        ("start_key=c", &["c", "d", "e"]),
+        ("end_key=c", &["a", "b", "bb"]),
+        ("start_key=b&end_key=d", &["b", "bb", "c"]),
+        ("start_key=b&end_key=e&limit=2", &["b", "bb"]),
+        ("start_key=f", &[]),
+        ("start_key=c&end_key=c", &[]),
+        ("start_key=d&end_key=b", &[]),
+    ];
+    for (query, expected) in cases {
+        let request = test::TestRequest::get()
+            .uri(&format!("/scan?{query}"))
+            .to_request();
+        let body = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(record_keys(body), expected, "{query}");
+    }
+}
+
+#[actix_web::test]
+async fn scan_stream_sends_every_record_in_chunks() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config).unwrap();
+    for chunk in (0..50_000u32).collect::<Vec<_>>().chunks(5_000) {
+        let mut batch = WriteBatch::new();
+        for i in chunk {
+            batch.set(format!("key{i:05}"), format!("v{i}").into_bytes());
+        }
+        engine.commit(batch).unwrap();
+    }
+    engine
+        .set("feature:beta".to_string(), b"on".to_vec())
+        .unwrap();
+    let app = test::init_service(App::new().app_data(app_state(engine)).configure(routes)).await;
+
+    let request = test::TestRequest::get().uri("/scan/stream").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 200);
+    let mut body = response.into_body();
+    // No Content-Length: the server never held the whole body
+    assert_eq!(body.size(), BodySize::Stream);
+
+    let mut received = Vec::new();
+    let mut chunks = 0;
+    let mut largest = 0;
+    while let Some(chunk) = poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await {
+        let chunk = chunk.unwrap();
+        chunks += 1;
+        largest = largest.max(chunk.len());
+        received.extend_from_slice(&chunk);
+    }
+    assert!(chunks > 1);
+    assert!(largest < received.len() / 4);
+
+    let keys: Vec<String> = received
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let record: serde_json::Value = serde_json::from_slice(line).unwrap();
+            record["key"].as_str().unwrap().to_string()
+        })
+        .collect();
+    let expected: Vec<String> = (0..50_000).map(|i| format!("key{i:05}")).collect();
+    assert!(keys == expected, "streamed {} keys", keys.len());
+
+    let request = test::TestRequest::get()
+        .uri("/scan/stream?start_key=key00010&end_key=key00012")
+        .to_request();
+    let body = test::call_and_read_body(&app, request).await;
+    assert_eq!(
+        body,
+        "{\"key\":\"key00010\",\"value\":\"v10\",\"value_b64\":\"djEw\"}\n\
+         {\"key\":\"key00011\",\"value\":\"v11\",\"value_b64\":\"djEx\"}\n"
+    );
+}
+
+#[actix_web::test]
+async fn export_then_import_restores_every_record() {
+    let source_dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(source_dir.path().to_path_buf())
+        .memtable_max_size(16 * 1024)
+        .build()
+        .unwrap();
+    let source = LsmEngine::new(config).unwrap();
+    for i in 0..500 {
+        source
+            .set(format!("key{i:03}"), format!("v{i}").into_bytes())
+            .unwrap();
+    }
+    // Values that aren't valid UTF-8, a deleted key and a feature flag
+    source
+        .set("binary".to_string(), vec![0, 159, 255, b'\n'])
+        .unwrap();
+    source.delete("key000".to_string()).unwrap();
+    source
+        .set("feature:beta".to_string(), b"on".to_vec())
+        .unwrap();
+    let expected: Vec<(String, Vec<u8>)> = source
+        .scan()
+        .unwrap()
+        .into_iter()
+        .filter(|(k, _)| !k.starts_with("feature:"))
+        .collect();
+    let source_app =
+        test::init_service(App::new().app_data(app_state(source)).configure(routes)).await;
+
+    let request = test::TestRequest::get().uri("/export").to_request();
+    let dump = test::call_and_read_body(&source_app, request).await;
+    assert_eq!(
+        dump.split(|&b| b == b'\n')
+            .filter(|l| !l.is_empty())
+            .count(),
+        500
+    );
+
+    let target_dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(target_dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let target = app_state(LsmEngine::new(config).unwrap());
+    let target_app =
+        test::init_service(App::new().app_data(target.clone()).configure(routes)).await;
+    let request = test::TestRequest::post()
+        .uri("/import")
+        .set_payload(dump)
+        .to_request();
+    let response = test::call_service(&target_app, request).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(target.engine.scan().unwrap(), expected);
+
+    let request = test::TestRequest::post()
+        .uri("/import")
+        .set_payload("{\"key\": \"k\", \"value_b64\": \"!!\"}\n")
+        .to_request();
+    let response = test::call_service(&target_app, request).await;
+    assert_eq!(response.status(), 400);
+}
+
+#[actix_web::test]
+async fn delete_batch_removes_every_key() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config).unwrap();
+    for key in ["a", "b", "c", "keep"] {
+        engine.set(key.to_string(), b"v".to_vec()).unwrap();
+    }
+    let app = test::init_service(App::new().app_data(app_state(engine)).configure(routes)).await;
+
+    let request = test::TestRequest::delete()
+        .uri("/keys/batch")
+        .set_json(serde_json::json!({ "keys": ["a", "b", "c"] }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["data"]["count"], 3);
+
+    for (key, status) in [("a", 404), ("b", 404), ("c", 404), ("keep", 200)] {
+        let request = test::TestRequest::get()
+            .uri(&format!("/keys/{key}"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), status, "{key}");
+    }
+
+    // A single key still goes through DELETE /keys/{key}
+    let request = test::TestRequest::delete().uri("/keys/keep").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+    let request = test::TestRequest::get().uri("/keys/keep").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+}
+
+#[actix_web::test]
+async fn binary_values_round_trip_as_base64() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let state = app_state(LsmEngine::new(config).unwrap());
+    let app = test::init_service(App::new().app_data(state.clone()).configure(routes)).await;
+
+    // Null bytes and invalid UTF-8; `value_b64` wins over `value`
+    let request = test::TestRequest::post()
+        .uri("/keys")
+        .set_json(serde_json::json!({ "key": "bin", "value": "ignored", "value_b64": "AJ//eAA=" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+    let request = test::TestRequest::post()
+        .uri("/keys/batch")
+        .set_json(serde_json::json!({ "records": [{ "key": "bin2", "value_b64": "AQD+" }] }))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+
+    assert_eq!(
+        state.engine.get("bin").unwrap(),
+        Some(vec![0, 159, 255, b'x', 0])
+    );
+    for (key, b64) in [("bin", "AJ//eAA="), ("bin2", "AQD+")] {
+        let request = test::TestRequest::get()
+            .uri(&format!("/keys/{key}"))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(body["data"]["value_b64"], b64);
+    }
+
+    // Listings carry the same encoding per record
+    for uri in ["/scan", "/keys/search?q=bin&prefix=true"] {
+        let request = test::TestRequest::get().uri(uri).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        let encoded: Vec<&str> = body["data"]["records"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| record["value_b64"].as_str().unwrap())
+            .collect();
+        assert_eq!(encoded, vec!["AJ//eAA=", "AQD+"], "{uri}");
+    }
+    let request = test::TestRequest::get().uri("/scan/stream").to_request();
+    let body = test::call_and_read_body(&app, request).await;
+    let encoded: Vec<String> = String::from_utf8(body.to_vec())
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            record["value_b64"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(encoded, vec!["AJ//eAA=", "AQD+"]);
+
+    let request = test::TestRequest::post()
+        .uri("/keys")
+        .set_json(serde_json::json!({ "key": "bad", "value_b64": "!!" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 400);
+    assert_eq!(state.engine.get("bad").unwrap(), None);
+}
+
+#[actix_web::test]
+async fn features_can_be_read_and_deleted_one_at_a_time() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state(LsmEngine::new(config).unwrap()))
+            .configure(routes),
+    )
+    .await;
+
+    let request = test::TestRequest::post()
+        .uri("/features/beta")
+        .set_json(serde_json::json!({ "enabled": true, "description": "new UI" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+
+    let request = test::TestRequest::get().uri("/features/beta").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(body["data"]["enabled"], true);
+    assert_eq!(body["data"]["description"], "new UI");
+
+    let request = test::TestRequest::delete()
+        .uri("/features/beta")
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+    for request in [
+        test::TestRequest::get().uri("/features/beta").to_request(),
+        test::TestRequest::delete()
+            .uri("/features/beta")
+            .to_request(),
+    ] {
+        assert_eq!(test::call_service(&app, request).await.status(), 404);
+    }
+}
+
+#[actix_web::test]
+async fn feature_rollout_percent_is_stored_and_validated() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state(LsmEngine::new(config).unwrap()))
+            .configure(routes),
+    )
+    .await;
+
+    let request = test::TestRequest::post()
+        .uri("/features/checkout")
+        .set_json(serde_json::json!({ "enabled": true, "rollout_percent": 150 }))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 400);
+
+    let request = test::TestRequest::post()
+        .uri("/features/checkout")
+        .set_json(serde_json::json!({ "enabled": true, "rollout_percent": 30 }))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+
+    let request = test::TestRequest::get()
+        .uri("/features/checkout")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(body["data"]["rollout_percent"], 30);
+}
+
+#[actix_web::test]
+async fn writes_need_the_api_token_once_one_is_configured() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let state = web::Data::new(AppState {
+        api_token: Some("s3cret".to_string()),
+        ..state(LsmEngine::new(config).unwrap())
+    });
+    let app = test::init_service(App::new().app_data(state).configure(routes)).await;
+    let set = |auth: Option<&str>| {
+        let request = test::TestRequest::post()
+            .uri("/keys")
+            .set_json(serde_json::json!({ "key": "k", "value": "v" }));
+        match auth {
+            Some(auth) => request.insert_header(("Authorization", auth)),
+            None => request,
+        }
+        .to_request()
+    };
+
+    for auth in [
+        None,
+        Some("Bearer wrong"),
+        Some("s3cret"),
+        Some("Bearer s3cre"),
+    ] {
+        let response = test::call_service(&app, set(auth)).await;
+        assert_eq!(response.status(), 401, "{auth:?}");
+    }
+    let request = test::TestRequest::delete().uri("/keys/k").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 401);
+
+    let response = test::call_service(&app, set(Some("Bearer s3cret"))).await;
+    assert_eq!(response.status(), 200);
+    // Reads stay open unless auth_reads is set
+    let request = test::TestRequest::get().uri("/keys/k").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+}
+
+#[actix_web::test]
+async fn auth_reads_gates_get_requests_too() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let state = web::Data::new(AppState {
+        api_token: Some("s3cret".to_string()),
+        auth_reads: true,
+        ..state(LsmEngine::new(config).unwrap())
+    });
+    let app = test::init_service(App::new().app_data(state).configure(routes)).await;
+
+    let request = test::TestRequest::get().uri("/health").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 401);
+    let request = test::TestRequest::get()
+        .uri("/health")
+        .insert_header(("Authorization", "Bearer s3cret"))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+}
+
+#[actix_web::test]
+async fn without_a_token_nothing_needs_auth() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state(LsmEngine::new(config).unwrap()))
+            .configure(routes),
+    )
+    .await;
+
+    let request = test::TestRequest::post()
+        .uri("/keys")
+        .set_json(serde_json::json!({ "key": "k", "value": "v" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+    let request = test::TestRequest::delete().uri("/keys/k").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+}
+
+/// Counts the debug events that get past the level filter
+struct DebugEvents(Arc<AtomicUsize>);
+
+impl<S: Subscriber> Layer<S> for DebugEvents {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() == Level::DEBUG {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[actix_web::test]
+async fn loglevel_changes_the_filter_at_runtime() {
+    let (filter, handle) = reload::Layer::new(LevelFilter::INFO);
+    let debug_events = Arc::new(AtomicUsize::new(0));
+    let subscriber = Registry::default()
+        .with(filter)
+        .with(DebugEvents(Arc::clone(&debug_events)));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let state = web::Data::new(AppState {
+        log_level: Some(handle),
+        ..state(LsmEngine::new(config).unwrap())
+    });
+    let app = test::init_service(App::new().app_data(state).configure(routes)).await;
+    // Every write logs its WAL append at debug level
+    let write = || {
+        test::TestRequest::post()
+            .uri("/keys")
+            .set_json(serde_json::json!({ "key": "k", "value": "v" }))
+            .to_request()
+    };
+    let set_level = |level: &str| {
+        test::TestRequest::post()
+            .uri("/loglevel")
+            .set_json(serde_json::json!({ "level": level }))
+            .to_request()
+    };
+
+    test::call_service(&app, write()).await;
+    assert_eq!(debug_events.load(Ordering::SeqCst), 0);
+
+    assert_eq!(
+        test::call_service(&app, set_level("debug")).await.status(),
+        200
+    );
+    let request = test::TestRequest::get().uri("/loglevel").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(body["data"]["level"], "debug");
+    test::call_service(&app, write()).await;
+    let seen = debug_events.load(Ordering::SeqCst);
+    assert!(seen > 0);
+
+    assert_eq!(
+        test::call_service(&app, set_level("info")).await.status(),
+        200
+    );
+    test::call_service(&app, write()).await;
+    assert_eq!(debug_events.load(Ordering::SeqCst), seen);
+
+    assert_eq!(
+        test::call_service(&app, set_level("loud")).await.status(),
+        400
+    );
+    let request = test::TestRequest::get().uri("/loglevel").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(body["data"]["level"], "info");
+}
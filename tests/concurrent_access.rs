@@ -0,0 +1,173 @@
+use lsm_kv_store::{LsmConfig, LsmEngine};
+use rand::Rng;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use tempfile::tempdir;
+
+const WRITERS: usize = 2;
+const READERS: usize = 8;
+const WRITES_PER_WRITER: usize = 500;
+
+fn value_for(writer: usize, i: usize) -> Vec<u8> {
+    format!("w{writer}_v{i}_{}", "x".repeat(32)).into_bytes()
+}
+
+#[test]
+fn readers_and_writers_race_through_flushes_without_losing_updates() {
+    let dir = tempdir().unwrap();
+    // Small MemTable so freezes and flushes happen throughout the run
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(8 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    let engine = Arc::new(LsmEngine::new(cfg.clone()).unwrap());
+    // Number of keys each writer has acknowledged so far
+    let progress: Arc<Vec<AtomicUsize>> =
+        Arc::new((0..WRITERS).map(|_| AtomicUsize::new(0)).collect());
+    let done = Arc::new(AtomicBool::new(false));
+
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|w| {
+            let engine = Arc::clone(&engine);
+            let progress = Arc::clone(&progress);
+            thread::spawn(move || {
+                for i in 0..WRITES_PER_WRITER {
+                    let key = format!("w{w}_k{i:05}");
+                    engine.set(key.clone(), value_for(w, i)).unwrap();
+                    // Read-your-writes, even if the set just triggered a flush
+                    assert_eq!(engine.get(&key).unwrap(), Some(value_for(w, i)));
+
+                    engine
+                        .set(format!("w{w}_latest"), i.to_string().into_bytes())
+                        .unwrap();
+                    progress[w].store(i + 1, Ordering::SeqCst);
+                }
+            })
+        })
+        .collect();
+
+    let readers: Vec<_> = (0..READERS)
+        .map(|_| {
+            let engine = Arc::clone(&engine);
+            let progress = Arc::clone(&progress);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                let mut rng = rand::thread_rng();
+                let mut last_seen = [0usize; WRITERS];
+                let mut reads = 0;
+                while !done.load(Ordering::SeqCst) {
+                    let w = rng.gen_range(0..WRITERS);
+                    let acknowledged = progress[w].load(Ordering::SeqCst);
+                    if acknowledged == 0 {
+                        continue;
+                    }
+
+                    // An acknowledged write never disappears, e.g. between a
+                    // MemTable being frozen and its SSTable being installed
+                    let i = rng.gen_range(0..acknowledged);
+                    assert_eq!(
+                        engine.get(&format!("w{w}_k{i:05}")).unwrap(),
+                        Some(value_for(w, i)),
+                        "w{w}_k{i:05} went missing"
+                    );
+
+                    // ... and the newest value only moves forward
+                    let latest: usize =
+                        String::from_utf8(engine.get(&format!("w{w}_latest")).unwrap().unwrap())
+                            .unwrap()
+                            .parse()
+                            .unwrap();
+                    assert!(latest + 1 >= acknowledged);
+                    assert!(latest >= last_seen[w], "w{w}_latest went backwards");
+                    last_seen[w] = latest;
+                    reads += 1;
+                }
+                reads
+            })
+        })
+        .collect();
+
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    done.store(true, Ordering::SeqCst);
+    for reader in readers {
+        assert!(reader.join().unwrap() > 0);
+    }
+
+    let expected = WRITERS * (WRITES_PER_WRITER + 1);
+    assert_eq!(engine.count().unwrap(), expected);
+    assert!(engine.stats_all().unwrap().sst_files > 1);
+    drop(engine);
+
+    // Nothing lost across a restart either
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(engine.count().unwrap(), expected);
+    for w in 0..WRITERS {
+        assert_eq!(
+            engine.get(&format!("w{w}_latest")).unwrap(),
+            Some((WRITES_PER_WRITER - 1).to_string().into_bytes())
+        );
+        for i in 0..WRITES_PER_WRITER {
+            assert_eq!(
+                engine.get(&format!("w{w}_k{i:05}")).unwrap(),
+                Some(value_for(w, i))
+            );
+        }
+    }
+}
+
+#[test]
+fn writes_and_flushes_racing_manual_compactions_keep_the_newest_values() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(4 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = Arc::new(LsmEngine::new(cfg.clone()).unwrap());
+    let done = Arc::new(AtomicBool::new(false));
+
+    let compactor = {
+        let engine = Arc::clone(&engine);
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                engine.compact_now().unwrap();
+            }
+        })
+    };
+
+    // Overwrite a small key set over and over, so stale versions sit in
+    // older tables while newer ones are flushed mid-compaction
+    for round in 0..20 {
+        for k in 0..50 {
+            let key = format!("k{k:02}");
+            engine
+                .set(key.clone(), format!("r{round}").into_bytes())
+                .unwrap();
+            assert_eq!(
+                engine.get(&key).unwrap(),
+                Some(format!("r{round}").into_bytes())
+            );
+        }
+        engine.flush().unwrap();
+    }
+    done.store(true, Ordering::SeqCst);
+    compactor.join().unwrap();
+
+    let check = |engine: &LsmEngine| {
+        for k in 0..50 {
+            assert_eq!(
+                engine.get(&format!("k{k:02}")).unwrap(),
+                Some(b"r19".to_vec())
+            );
+        }
+    };
+    check(&engine);
+    drop(engine);
+    check(&LsmEngine::new(cfg).unwrap());
+}
@@ -0,0 +1,1746 @@
+use lsm_kv_store::{
+    ChangeKind, Codec, CompactionStrategy, KeyComparator, KeyStatus, LsmConfig, LsmEngine,
+    LsmError, OverflowPolicy, WriteBatch,
+};
+use std::ops::Bound;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn test_config(dir: &std::path::Path) -> LsmConfig {
+    LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir.to_path_buf())
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn validator_rejects_before_write() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::with_validator(
+        test_config(dir.path()),
+        Box::new(|key, _value| {
+            if key.starts_with("tenant_a:") {
+                Ok(())
+            } else {
+                Err(format!("key '{key}' is outside tenant_a"))
+            }
+        }),
+    )
+    .unwrap();
+
+    engine
+        .set("tenant_a:user".to_string(), b"ok".to_vec())
+        .unwrap();
+
+    match engine.set("tenant_b:user".to_string(), b"nope".to_vec()) {
+        Err(LsmError::ValidationRejected(msg)) => assert!(msg.contains("tenant_b:user")),
+        other => panic!("expected ValidationRejected, got {other:?}"),
+    }
+
+    assert_eq!(engine.get("tenant_a:user").unwrap(), Some(b"ok".to_vec()));
+    assert!(engine.get("tenant_b:user").unwrap().is_none());
+}
+
+#[test]
+fn validator_sees_value_bytes() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::with_validator(
+        test_config(dir.path()),
+        Box::new(|_key, value| {
+            if value.len() <= 4 {
+                Ok(())
+            } else {
+                Err("value too long".to_string())
+            }
+        }),
+    )
+    .unwrap();
+
+    assert!(engine.set("k".to_string(), b"1234".to_vec()).is_ok());
+    assert!(matches!(
+        engine.set_batch(vec![("k2".to_string(), b"12345".to_vec())]),
+        Err(LsmError::ValidationRejected(_))
+    ));
+}
+
+#[test]
+fn iter_yields_live_pairs_in_key_order() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    engine.set("b".to_string(), b"2".to_vec()).unwrap();
+    engine.set("a".to_string(), b"1".to_vec()).unwrap();
+    engine.set("c".to_string(), b"3".to_vec()).unwrap();
+    engine.delete("b".to_string()).unwrap();
+
+    let pairs: Vec<(String, Vec<u8>)> = engine
+        .iter()
+        .unwrap()
+        .collect::<lsm_kv_store::Result<_>>()
+        .unwrap();
+    assert_eq!(
+        pairs,
+        vec![
+            ("a".to_string(), b"1".to_vec()),
+            ("c".to_string(), b"3".to_vec())
+        ]
+    );
+}
+
+#[test]
+fn iter_is_unaffected_by_later_writes() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    engine.set("k1".to_string(), b"v1".to_vec()).unwrap();
+
+    let iter = engine.iter().unwrap();
+    engine.set("k2".to_string(), b"v2".to_vec()).unwrap();
+
+    let keys: Vec<String> = iter.map(|kv| kv.unwrap().0).collect();
+    assert_eq!(keys, vec!["k1".to_string()]);
+}
+
+#[test]
+fn subscribe_sees_matching_writes() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    let sub = engine.subscribe("user:", 16, OverflowPolicy::DropOldest);
+
+    engine.set("user:1".to_string(), b"a".to_vec()).unwrap();
+    engine.set("order:1".to_string(), b"b".to_vec()).unwrap();
+    engine.delete("user:1".to_string()).unwrap();
+
+    let set = sub.try_recv().unwrap();
+    assert_eq!(set.key, "user:1");
+    assert_eq!(set.kind, ChangeKind::Set);
+
+    let delete = sub.try_recv().unwrap();
+    assert_eq!(delete.key, "user:1");
+    assert_eq!(delete.kind, ChangeKind::Delete);
+    assert!(delete.seq > set.seq);
+
+    assert!(sub.try_recv().is_none());
+}
+
+#[test]
+fn rejected_write_is_not_published() {
+    let dir = tempdir().unwrap();
+    let engine =
+        LsmEngine::with_validator(test_config(dir.path()), Box::new(|_, _| Err("no".into())))
+            .unwrap();
+    let sub = engine.subscribe("", 16, OverflowPolicy::Block);
+
+    assert!(engine.set("k".to_string(), b"v".to_vec()).is_err());
+    assert!(sub.try_recv().is_none());
+}
+
+#[test]
+fn concurrent_writers_and_readers() {
+    let dir = tempdir().unwrap();
+    let engine = std::sync::Arc::new(LsmEngine::new(test_config(dir.path())).unwrap());
+    engine
+        .set("shared".to_string(), b"initial".to_vec())
+        .unwrap();
+
+    let mut handles = Vec::new();
+    for w in 0..4 {
+        let engine = std::sync::Arc::clone(&engine);
+        handles.push(std::thread::spawn(move || {
+            for i in 0..25 {
+                engine
+                    .set(format!("w{w}_{i}"), i.to_string().into_bytes())
+                    .unwrap();
+            }
+        }));
+    }
+    for _ in 0..4 {
+        let engine = std::sync::Arc::clone(&engine);
+        handles.push(std::thread::spawn(move || {
+            for _ in 0..100 {
+                assert_eq!(engine.get("shared").unwrap().unwrap(), b"initial");
+            }
+        }));
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(engine.count().unwrap(), 101);
+    assert_eq!(engine.get("w3_24").unwrap().unwrap(), b"24");
+}
+
+fn sst_files(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "sst"))
+        .collect()
+}
+
+#[test]
+fn compact_to_single_file_leaves_one_table() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        for i in 0..200 {
+            engine.set(format!("k{i:03}"), vec![b'x'; 20]).unwrap();
+        }
+        for i in 0..50 {
+            engine.delete(format!("k{i:03}")).unwrap();
+        }
+        engine.set("k199".to_string(), b"latest".to_vec()).unwrap();
+        assert!(sst_files(dir.path()).len() > 1);
+
+        let path = engine.compact_to_single_file().unwrap();
+        assert_eq!(sst_files(dir.path()), vec![path]);
+        assert_eq!(engine.count().unwrap(), 150);
+        assert_eq!(engine.get("k000").unwrap(), None);
+        assert_eq!(engine.get("k199").unwrap().unwrap(), b"latest");
+    }
+
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(engine.count().unwrap(), 150);
+    assert_eq!(engine.get("k199").unwrap().unwrap(), b"latest");
+}
+
+#[test]
+fn compact_to_single_file_on_empty_store_fails() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    assert!(matches!(
+        engine.compact_to_single_file(),
+        Err(LsmError::CompactionFailed(_))
+    ));
+}
+
+#[test]
+fn full_compaction_drops_tombstones() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(cfg).unwrap();
+
+    for i in 0..100 {
+        engine.set(format!("gone_{i:03}"), vec![b'x'; 20]).unwrap();
+    }
+    for i in 0..10 {
+        engine.set(format!("kept_{i:03}"), vec![b'y'; 20]).unwrap();
+    }
+    for i in 0..100 {
+        engine.delete(format!("gone_{i:03}")).unwrap();
+    }
+
+    engine.compact_to_single_file().unwrap();
+
+    // Neither the values nor their tombstones survive the bottom-most merge
+    let tables = engine.sstable_info().unwrap();
+    assert_eq!(tables.len(), 1);
+    assert_eq!(tables[0].record_count, 10);
+    assert_eq!(tables[0].min_key, "kept_000");
+    assert_eq!(engine.get_status("gone_042").unwrap(), KeyStatus::Absent);
+}
+
+#[test]
+fn compact_now_summary_matches_files_on_disk() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(cfg).unwrap();
+
+    for round in 0..3 {
+        for i in 0..60 {
+            engine
+                .set(format!("k{i:03}"), format!("v{round}").into_bytes())
+                .unwrap();
+        }
+    }
+    for i in 0..30 {
+        engine.delete(format!("k{i:03}")).unwrap();
+    }
+
+    engine.close().unwrap();
+    let files_before = sst_files(dir.path());
+    let bytes_before: u64 = files_before
+        .iter()
+        .map(|p| std::fs::metadata(p).unwrap().len())
+        .sum();
+
+    let summary = engine.compact_now().unwrap();
+    let files_after = sst_files(dir.path());
+    let bytes_after: u64 = files_after
+        .iter()
+        .map(|p| std::fs::metadata(p).unwrap().len())
+        .sum();
+
+    assert_eq!(summary.tables_before, files_before.len());
+    assert_eq!(summary.tables_after, files_after.len());
+    assert_eq!(summary.tables_after, 1);
+    assert_eq!(summary.bytes_reclaimed, bytes_before - bytes_after);
+    assert_eq!(engine.count().unwrap(), 30);
+    assert_eq!(engine.get("k045").unwrap().unwrap(), b"v2");
+
+    // Nothing left to do on a second run, and an empty store is fine too
+    let again = engine.compact_now().unwrap();
+    assert_eq!((again.tables_before, again.tables_after), (1, 1));
+
+    let empty_dir = tempdir().unwrap();
+    let empty = LsmEngine::new(test_config(empty_dir.path())).unwrap();
+    assert_eq!(empty.compact_now().unwrap().tables_after, 0);
+}
+
+#[test]
+fn list_namespaces_unions_memtable_and_sstables() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024)
+        .namespace_separator(':')
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(cfg).unwrap();
+
+    for i in 0..60 {
+        engine.set(format!("user:{i}"), vec![b'x'; 20]).unwrap();
+    }
+    engine.set("order:1".to_string(), b"o".to_vec()).unwrap();
+    engine
+        .set("no_namespace".to_string(), b"n".to_vec())
+        .unwrap();
+
+    assert_eq!(engine.list_namespaces().unwrap(), vec!["order", "user"]);
+}
+
+#[test]
+fn list_namespaces_without_separator_is_empty() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    engine.set("user:1".to_string(), b"x".to_vec()).unwrap();
+
+    assert!(engine.list_namespaces().unwrap().is_empty());
+}
+
+#[test]
+fn effective_config_matches_open_config() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .block_size(8192)
+        .block_cache_size_mb(2)
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(cfg).unwrap();
+
+    let effective = engine.effective_config();
+    assert_eq!(effective.core.dir_path, dir.path());
+    assert_eq!(effective.storage.block_size, 8192);
+    assert_eq!(engine.block_cache_capacity_blocks(), 2 * 1024 * 1024 / 8192);
+}
+
+#[test]
+fn set_memtable_max_size_at_runtime() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    for i in 0..40 {
+        engine.set(format!("k{i:02}"), vec![b'x'; 20]).unwrap();
+    }
+    assert_eq!(engine.stats_all().unwrap().sst_files, 0);
+
+    assert!(matches!(
+        engine.set_memtable_max_size(10),
+        Err(LsmError::InvalidMemtableSize(_))
+    ));
+    assert_eq!(
+        engine.effective_config().core.memtable_max_size,
+        1024 * 1024
+    );
+
+    // Already over the new limit: flushes immediately
+    engine.set_memtable_max_size(1024).unwrap();
+    assert_eq!(engine.effective_config().core.memtable_max_size, 1024);
+    let stats = engine.stats_all().unwrap();
+    assert_eq!(stats.sst_files, 1);
+    assert_eq!(stats.mem_records, 0);
+    assert_eq!(engine.get("k07").unwrap().unwrap(), vec![b'x'; 20]);
+}
+
+#[test]
+fn overwrites_flush_once_the_wal_reaches_its_limit() {
+    let overwrite = |engine: &LsmEngine| {
+        for i in 0..10_000 {
+            engine
+                .set("hot".to_string(), format!("value_{i:05}").into_bytes())
+                .unwrap();
+        }
+    };
+
+    // Without the WAL limit in reach, the one-key MemTable never fills up
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    overwrite(&engine);
+    assert_eq!(engine.stats_all().unwrap().sst_files, 0);
+    assert!(wal_bytes(dir.path()) > 256 * 1024);
+    drop(engine);
+
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .wal_max_size(64 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config).unwrap();
+    overwrite(&engine);
+
+    let stats = engine.stats_all().unwrap();
+    assert!(stats.sst_files >= 1);
+    assert!(stats.mem_records <= 1);
+    assert!(wal_bytes(dir.path()) < 2 * 64 * 1024);
+    assert_eq!(engine.get("hot").unwrap(), Some(b"value_09999".to_vec()));
+}
+
+#[test]
+fn get_status_distinguishes_deleted_from_absent() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(cfg).unwrap();
+
+    engine.set("present".to_string(), b"v".to_vec()).unwrap();
+    engine.set("gone".to_string(), b"v".to_vec()).unwrap();
+    engine.delete("gone".to_string()).unwrap();
+
+    assert_eq!(
+        engine.get_status("present").unwrap(),
+        KeyStatus::Present(b"v".to_vec())
+    );
+    assert_eq!(engine.get_status("gone").unwrap(), KeyStatus::Deleted);
+    assert_eq!(engine.get_status("never").unwrap(), KeyStatus::Absent);
+
+    // Push the tombstone out of the MemTable into an SSTable
+    for i in 0..60 {
+        engine.set(format!("filler{i}"), vec![b'x'; 20]).unwrap();
+    }
+    assert!(engine.stats_all().unwrap().sst_files > 0);
+
+    assert_eq!(engine.get_status("gone").unwrap(), KeyStatus::Deleted);
+    assert_eq!(engine.get("gone").unwrap(), None);
+    assert_eq!(engine.get_status("never").unwrap(), KeyStatus::Absent);
+}
+
+#[test]
+fn get_record_reports_timestamps_and_tombstones() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    assert_eq!(engine.get_record("k").unwrap(), None);
+
+    let mut last = 0;
+    for i in 0..5 {
+        engine
+            .set("k".to_string(), format!("v{i}").into_bytes())
+            .unwrap();
+        let record = engine.get_record("k").unwrap().unwrap();
+        assert_eq!(record.value, format!("v{i}").into_bytes());
+        assert!(!record.is_deleted);
+        assert!(
+            record.timestamp > last,
+            "{} after {}",
+            record.timestamp,
+            last
+        );
+        last = record.timestamp;
+    }
+
+    engine.delete("k".to_string()).unwrap();
+    let tombstone = engine.get_record("k").unwrap().unwrap();
+    assert!(tombstone.is_deleted);
+    assert!(tombstone.value.is_empty());
+    assert!(tombstone.timestamp > last);
+    assert_eq!(engine.get("k").unwrap(), None);
+
+    // Same answers once the records come from an SSTable
+    engine.set("other".to_string(), b"v".to_vec()).unwrap();
+    let other = engine.get_record("other").unwrap();
+    engine.flush().unwrap();
+    assert_eq!(engine.get_record("k").unwrap(), Some(tombstone));
+    assert_eq!(engine.get_record("other").unwrap(), other);
+    let other = other.unwrap();
+    assert_eq!((other.value, other.is_deleted), (b"v".to_vec(), false));
+}
+
+#[test]
+fn warmup_loads_blocks_after_restart() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024)
+        .block_size(256)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        for i in 0..100 {
+            engine.set(format!("k{i:03}"), vec![b'x'; 20]).unwrap();
+        }
+    }
+
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert!(
+        engine
+            .warmup(&["k010".to_string(), "k020".to_string()])
+            .unwrap()
+            >= 2
+    );
+    assert_eq!(engine.warmup(&["missing".to_string()]).unwrap(), 0);
+    assert!(engine.warmup_range("k000", "k100").unwrap() > 0);
+    assert_eq!(engine.warmup_range("x", "z").unwrap(), 0);
+}
+
+fn leveled_config(dir: &std::path::Path) -> LsmConfig {
+    LsmConfig::builder()
+        .dir_path(dir.to_path_buf())
+        .memtable_max_size(4 * 1024)
+        .compaction_strategy(CompactionStrategy::Leveled)
+        .level0_compaction_threshold(3)
+        .target_file_size(8 * 1024)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn leveled_compaction_keeps_l1_non_overlapping() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(leveled_config(dir.path())).unwrap();
+
+    // Hammer a small key range so every flush overlaps the previous ones
+    for round in 0..40 {
+        for i in 0..50 {
+            engine
+                .set(
+                    format!("key_{:03}", i),
+                    format!("v{round}").repeat(8).into_bytes(),
+                )
+                .unwrap();
+        }
+    }
+    for i in 0..10 {
+        engine.delete(format!("key_{:03}", i)).unwrap();
+    }
+
+    let tables = engine.sstable_info().unwrap();
+    let level0 = tables.iter().filter(|t| t.level == 0).count();
+    assert!(
+        level0 < 3,
+        "L0 should have been compacted, found {level0} tables"
+    );
+
+    let mut level1: Vec<_> = tables.iter().filter(|t| t.level == 1).collect();
+    assert!(!level1.is_empty());
+    level1.sort_by(|a, b| a.min_key.cmp(&b.min_key));
+    for pair in level1.windows(2) {
+        assert!(pair[0].max_key < pair[1].min_key, "L1 tables overlap");
+    }
+
+    // Each key appears at most once in L1, so superseded versions are gone
+    let l1_records: u64 = level1.iter().map(|t| t.record_count).sum();
+    assert!(l1_records <= 50);
+
+    for i in 0..50 {
+        let expected = (i >= 10).then(|| "v39".repeat(8).into_bytes());
+        assert_eq!(engine.get(&format!("key_{:03}", i)).unwrap(), expected);
+    }
+}
+
+#[test]
+fn leveled_compaction_levels_survive_restart() {
+    let dir = tempdir().unwrap();
+    {
+        let engine = LsmEngine::new(leveled_config(dir.path())).unwrap();
+        for round in 0..20 {
+            for i in 0..50 {
+                engine
+                    .set(format!("key_{:03}", i), vec![round as u8; 64])
+                    .unwrap();
+            }
+        }
+        engine.close().unwrap();
+    }
+
+    let engine = LsmEngine::new(leveled_config(dir.path())).unwrap();
+    let tables = engine.sstable_info().unwrap();
+    assert!(tables.iter().any(|t| t.level == 1));
+    // Reads consult L0 before L1
+    assert!(tables.windows(2).all(|pair| pair[0].level <= pair[1].level));
+    assert_eq!(engine.get("key_007").unwrap(), Some(vec![19u8; 64]));
+}
+
+#[test]
+fn scan_returns_exactly_the_flushed_live_keys() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    for i in 0..40 {
+        engine
+            .set(format!("key_{i:02}"), format!("v{i}").into_bytes())
+            .unwrap();
+    }
+    for i in (0..40).step_by(4) {
+        engine.delete(format!("key_{i:02}")).unwrap();
+    }
+    engine.set("key_01".to_string(), b"new".to_vec()).unwrap();
+    engine.close().unwrap();
+
+    // Everything now lives in block-compressed SSTables
+    assert!(!sst_files(dir.path()).is_empty());
+
+    let expected: Vec<(String, Vec<u8>)> = (0..40)
+        .filter(|i| i % 4 != 0)
+        .map(|i| {
+            let value = if i == 1 {
+                b"new".to_vec()
+            } else {
+                format!("v{i}").into_bytes()
+            };
+            (format!("key_{i:02}"), value)
+        })
+        .collect();
+
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    assert_eq!(engine.scan().unwrap(), expected);
+    assert_eq!(
+        engine.keys().unwrap(),
+        expected
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(engine.count().unwrap(), expected.len());
+}
+
+#[test]
+fn compare_and_swap_claims_an_empty_slot_once() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    assert!(engine
+        .compare_and_swap("lock", None, b"a".to_vec())
+        .unwrap());
+    assert!(!engine
+        .compare_and_swap("lock", None, b"b".to_vec())
+        .unwrap());
+    assert_eq!(engine.get("lock").unwrap(), Some(b"a".to_vec()));
+
+    // A deleted key counts as empty again
+    engine.delete("lock".to_string()).unwrap();
+    assert!(engine
+        .compare_and_swap("lock", None, b"c".to_vec())
+        .unwrap());
+    assert_eq!(engine.get("lock").unwrap(), Some(b"c".to_vec()));
+}
+
+#[test]
+fn compare_and_swap_rejects_a_stale_expected_value() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    engine.set("counter".to_string(), b"1".to_vec()).unwrap();
+    // Compared against the SSTable copy, not just the MemTable
+    engine.close().unwrap();
+
+    assert!(!engine
+        .compare_and_swap("counter", Some(b"0"), b"2".to_vec())
+        .unwrap());
+    assert_eq!(engine.get("counter").unwrap(), Some(b"1".to_vec()));
+
+    assert!(engine
+        .compare_and_swap("counter", Some(b"1"), b"2".to_vec())
+        .unwrap());
+    assert_eq!(engine.get("counter").unwrap(), Some(b"2".to_vec()));
+}
+
+#[test]
+fn concurrent_compare_and_swap_has_one_winner() {
+    let dir = tempdir().unwrap();
+    let engine = std::sync::Arc::new(LsmEngine::new(test_config(dir.path())).unwrap());
+
+    for round in 0..50 {
+        let key = format!("slot{round}");
+        engine.set(key.clone(), b"start".to_vec()).unwrap();
+
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+        let contenders: Vec<_> = ["left", "right"]
+            .into_iter()
+            .map(|name| {
+                let (engine, barrier, key) = (
+                    std::sync::Arc::clone(&engine),
+                    std::sync::Arc::clone(&barrier),
+                    key.clone(),
+                );
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let won = engine
+                        .compare_and_swap(&key, Some(b"start"), name.as_bytes().to_vec())
+                        .unwrap();
+                    (name, won)
+                })
+            })
+            .collect();
+
+        let winners: Vec<&str> = contenders
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|(_, won)| *won)
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(winners.len(), 1, "round {round}: {winners:?}");
+        assert_eq!(
+            engine.get(&key).unwrap(),
+            Some(winners[0].as_bytes().to_vec())
+        );
+    }
+}
+
+#[test]
+fn write_batch_commits_with_one_fsync() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    engine.set("stale".to_string(), b"x".to_vec()).unwrap();
+    let syncs_before = engine.wal_stats().syncs;
+
+    let mut batch = WriteBatch::new();
+    for i in 0..100 {
+        batch.set(format!("k{i:03}"), vec![b'v'; 16]);
+    }
+    batch.delete("stale").set("k000", b"last".to_vec());
+    engine.commit(batch).unwrap();
+
+    assert_eq!(engine.wal_stats().syncs, syncs_before + 1);
+    assert_eq!(engine.count().unwrap(), 100);
+    assert!(engine.get("stale").unwrap().is_none());
+    // Later writes in the batch win
+    assert_eq!(engine.get("k000").unwrap(), Some(b"last".to_vec()));
+}
+
+#[test]
+fn rejected_batch_writes_nothing() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::with_validator(
+        test_config(dir.path()),
+        Box::new(|_key, value| {
+            if value.is_empty() {
+                Err("empty value".to_string())
+            } else {
+                Ok(())
+            }
+        }),
+    )
+    .unwrap();
+
+    let result = engine.set_batch(vec![
+        ("a".to_string(), b"1".to_vec()),
+        ("b".to_string(), Vec::new()),
+    ]);
+    assert!(matches!(result, Err(LsmError::ValidationRejected(_))));
+    assert!(engine.get("a").unwrap().is_none());
+}
+
+#[test]
+fn expired_keys_read_as_absent() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    // An older, non-expiring version on disk must not show through
+    engine.set("session".to_string(), b"old".to_vec()).unwrap();
+    engine.flush().unwrap();
+    engine
+        .set_with_ttl(
+            "session".to_string(),
+            b"new".to_vec(),
+            Duration::from_millis(100),
+        )
+        .unwrap();
+    engine
+        .set_with_ttl(
+            "cache".to_string(),
+            b"v".to_vec(),
+            Duration::from_secs(3600),
+        )
+        .unwrap();
+    assert_eq!(engine.get("session").unwrap(), Some(b"new".to_vec()));
+
+    std::thread::sleep(Duration::from_millis(150));
+    assert!(engine.get("session").unwrap().is_none());
+    assert_eq!(engine.get_status("session").unwrap(), KeyStatus::Absent);
+    assert_eq!(engine.get("cache").unwrap(), Some(b"v".to_vec()));
+    assert_eq!(
+        engine.scan().unwrap(),
+        vec![("cache".to_string(), b"v".to_vec())]
+    );
+
+    // ... including after recovery from the WAL
+    drop(engine);
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    assert!(engine.get("session").unwrap().is_none());
+    assert_eq!(engine.get("cache").unwrap(), Some(b"v".to_vec()));
+}
+
+#[test]
+fn compaction_drops_expired_records() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    for i in 0..10 {
+        engine
+            .set_with_ttl(format!("tmp{i}"), b"v".to_vec(), Duration::from_millis(50))
+            .unwrap();
+    }
+    engine.set("keep".to_string(), b"v".to_vec()).unwrap();
+    engine.flush().unwrap();
+    assert_eq!(engine.sstable_info().unwrap()[0].record_count, 11);
+
+    std::thread::sleep(Duration::from_millis(100));
+    engine.compact_now().unwrap();
+
+    let tables = engine.sstable_info().unwrap();
+    assert_eq!(tables.iter().map(|t| t.record_count).sum::<u64>(), 1);
+    assert_eq!(engine.count().unwrap(), 1);
+}
+
+#[test]
+fn concurrent_increments_are_not_lost() {
+    let dir = tempdir().unwrap();
+    let engine = std::sync::Arc::new(LsmEngine::new(test_config(dir.path())).unwrap());
+
+    let threads: Vec<_> = (0..10)
+        .map(|_| {
+            let engine = std::sync::Arc::clone(&engine);
+            std::thread::spawn(move || {
+                for _ in 0..1000 {
+                    engine.increment("hits", 1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(engine.increment("hits", 0).unwrap(), 10_000);
+    assert_eq!(
+        engine.get("hits").unwrap(),
+        Some(10_000i64.to_le_bytes().to_vec())
+    );
+}
+
+#[test]
+fn increment_rejects_non_counters_and_overflow() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    assert_eq!(engine.increment("n", -5).unwrap(), -5);
+
+    engine.set("name".to_string(), b"alice".to_vec()).unwrap();
+    assert!(matches!(
+        engine.increment("name", 1),
+        Err(LsmError::CorruptedData(_))
+    ));
+
+    engine
+        .set("max".to_string(), i64::MAX.to_le_bytes().to_vec())
+        .unwrap();
+    assert!(matches!(
+        engine.increment("max", 1),
+        Err(LsmError::Overflow(_))
+    ));
+    assert_eq!(
+        engine.get("max").unwrap(),
+        Some(i64::MAX.to_le_bytes().to_vec())
+    );
+}
+
+#[test]
+fn snapshot_survives_writes_flush_and_compaction() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    for i in 0..10 {
+        engine.set(format!("k{i}"), b"old".to_vec()).unwrap();
+    }
+    engine.flush().unwrap();
+    engine.set("k0".to_string(), b"memtable".to_vec()).unwrap();
+
+    let snapshot = engine.snapshot().unwrap();
+    assert_eq!(snapshot.seq(), engine.last_seq());
+
+    engine.set("k0".to_string(), b"new".to_vec()).unwrap();
+    engine.delete("k1".to_string()).unwrap();
+    engine.set("k99".to_string(), b"new".to_vec()).unwrap();
+    engine.flush().unwrap();
+    engine.compact_now().unwrap();
+    assert_eq!(engine.get("k0").unwrap(), Some(b"new".to_vec()));
+    assert!(engine.get("k1").unwrap().is_none());
+
+    assert_eq!(snapshot.get("k0").unwrap(), Some(b"memtable".to_vec()));
+    assert_eq!(snapshot.get("k1").unwrap(), Some(b"old".to_vec()));
+    assert!(snapshot.get("k99").unwrap().is_none());
+    let range = snapshot
+        .range(
+            Bound::Included("k0".to_string()),
+            Bound::Excluded("k3".to_string()),
+        )
+        .unwrap();
+    assert_eq!(
+        range,
+        vec![
+            ("k0".to_string(), b"memtable".to_vec()),
+            ("k1".to_string(), b"old".to_vec()),
+            ("k2".to_string(), b"old".to_vec()),
+        ]
+    );
+
+    // The compacted inputs are only deleted once the snapshot lets go
+    assert!(sst_files(dir.path()).len() > engine.sstable_info().unwrap().len());
+    drop(snapshot);
+    assert_eq!(sst_files(dir.path()).len(), engine.sstable_info().unwrap().len());
+}
+
+#[test]
+fn flush_writes_an_sstable_and_clears_the_wal() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    // Nothing to flush yet
+    engine.flush().unwrap();
+    assert!(sst_files(dir.path()).is_empty());
+
+    for i in 0..5 {
+        engine.set(format!("k{i}"), b"v".to_vec()).unwrap();
+    }
+    assert!(wal_bytes(dir.path()) > 0);
+
+    engine.flush().unwrap();
+    assert_eq!(sst_files(dir.path()).len(), 1);
+    assert_eq!(wal_bytes(dir.path()), 0);
+    assert_eq!(engine.get("k3").unwrap(), Some(b"v".to_vec()));
+
+    // Idempotent
+    engine.flush().unwrap();
+    assert_eq!(sst_files(dir.path()).len(), 1);
+}
+
+fn wal_bytes(dir: &std::path::Path) -> u64 {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "log"))
+        .map(|p| std::fs::metadata(p).unwrap().len())
+        .sum()
+}
+
+#[test]
+fn oversized_values_are_rejected_before_the_wal() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let max_value_size = config.storage.max_value_size;
+    let engine = LsmEngine::new(config.clone()).unwrap();
+
+    let result = engine.set("big".to_string(), vec![b'x'; 70 * 1024]);
+    assert!(matches!(result, Err(LsmError::ValueTooLarge(_))));
+    let result = engine.set("k".repeat(2000), b"v".to_vec());
+    assert!(matches!(result, Err(LsmError::ValueTooLarge(_))));
+
+    // Right at the limit still round-trips through an SSTable and a restart
+    let value = vec![b'y'; max_value_size];
+    engine.set("edge".to_string(), value.clone()).unwrap();
+    engine.flush().unwrap();
+    drop(engine);
+
+    let engine = LsmEngine::new(config).unwrap();
+    assert_eq!(engine.get("edge").unwrap(), Some(value));
+    assert!(engine.get("big").unwrap().is_none());
+}
+
+#[test]
+fn values_larger_than_a_block_round_trip() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .block_size(4096)
+        .max_value_size(1024 * 1024)
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config.clone()).unwrap();
+
+    let big: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    engine.set("a".to_string(), b"before".to_vec()).unwrap();
+    engine.set("big".to_string(), big.clone()).unwrap();
+    engine.set("c".to_string(), b"after".to_vec()).unwrap();
+    engine.flush().unwrap();
+    engine.compact_now().unwrap();
+    drop(engine);
+
+    let engine = LsmEngine::new(config).unwrap();
+    assert_eq!(engine.get("big").unwrap(), Some(big.clone()));
+    assert_eq!(
+        engine.scan().unwrap(),
+        vec![
+            ("a".to_string(), b"before".to_vec()),
+            ("big".to_string(), big),
+            ("c".to_string(), b"after".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn switching_codecs_keeps_existing_files_readable() {
+    let dir = tempdir().unwrap();
+    let config = |codec| {
+        LsmConfig::builder()
+            .dir_path(dir.path().to_path_buf())
+            .codec(codec)
+            .build()
+            .unwrap()
+    };
+
+    // A fixint SSTable and a fixint WAL segment
+    let engine = LsmEngine::new(config(Codec::Fixint)).unwrap();
+    engine
+        .set("flushed_fixint".to_string(), b"1".to_vec())
+        .unwrap();
+    engine.flush().unwrap();
+    engine
+        .set("logged_fixint".to_string(), b"2".to_vec())
+        .unwrap();
+    drop(engine);
+
+    // ... next to varint ones
+    let engine = LsmEngine::new(config(Codec::Varint)).unwrap();
+    assert_eq!(engine.get("logged_fixint").unwrap(), Some(b"2".to_vec()));
+    engine
+        .set("flushed_varint".to_string(), b"3".to_vec())
+        .unwrap();
+    engine.flush().unwrap();
+    engine
+        .set("logged_varint".to_string(), b"4".to_vec())
+        .unwrap();
+    drop(engine);
+
+    let engine = LsmEngine::new(config(Codec::Fixint)).unwrap();
+    assert_eq!(engine.count().unwrap(), 4);
+    assert_eq!(engine.get("flushed_varint").unwrap(), Some(b"3".to_vec()));
+    assert_eq!(engine.get("logged_varint").unwrap(), Some(b"4".to_vec()));
+
+    // Compaction rewrites everything with the current codec
+    engine.compact_now().unwrap();
+    drop(engine);
+    let engine = LsmEngine::new(config(Codec::Varint)).unwrap();
+    assert_eq!(
+        engine.scan().unwrap(),
+        vec![
+            ("flushed_fixint".to_string(), b"1".to_vec()),
+            ("flushed_varint".to_string(), b"3".to_vec()),
+            ("logged_fixint".to_string(), b"2".to_vec()),
+            ("logged_varint".to_string(), b"4".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn stats_all_reports_block_cache_hits_and_misses() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    engine.set("k".to_string(), b"v".to_vec()).unwrap();
+    engine.flush().unwrap();
+
+    for _ in 0..4 {
+        assert_eq!(engine.get("k").unwrap(), Some(b"v".to_vec()));
+    }
+
+    let stats = engine.stats_all().unwrap();
+    assert_eq!((stats.cache_hits, stats.cache_misses), (3, 1));
+    assert_eq!(stats.cache_evictions, 0);
+    assert_eq!(stats.cache_hit_ratio, 0.75);
+}
+
+#[test]
+fn block_cache_is_shared_by_every_sstable() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    for table in 0..3 {
+        engine
+            .set(format!("t{table}"), format!("v{table}").into_bytes())
+            .unwrap();
+        engine.flush().unwrap();
+    }
+    assert_eq!(engine.stats_all().unwrap().sst_files, 3);
+
+    let read_all = || {
+        for table in 0..3 {
+            let value = engine.get(&format!("t{table}")).unwrap();
+            assert_eq!(value, Some(format!("v{table}").into_bytes()));
+        }
+        engine.stats_all().unwrap()
+    };
+
+    // Each table's block is read from disk once, into the one cache ...
+    let first = read_all();
+    assert!(first.cache_misses >= 3);
+    // ... which then serves every lookup of the second pass, whatever table
+    // it is in
+    let second = read_all();
+    assert_eq!(second.cache_misses, first.cache_misses);
+    assert_eq!(second.cache_hits, 2 * first.cache_hits + first.cache_misses);
+}
+
+#[test]
+fn preloaded_blocks_serve_the_first_reads_after_a_restart() {
+    let dir = tempdir().unwrap();
+    {
+        let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+        for table in 0..3 {
+            engine
+                .set(format!("t{table}"), format!("v{table}").into_bytes())
+                .unwrap();
+            engine.flush().unwrap();
+        }
+    }
+
+    let cold = LsmEngine::new(test_config(dir.path())).unwrap();
+    assert_eq!(cold.get("t2").unwrap(), Some(b"v2".to_vec()));
+    assert_eq!(cold.stats_all().unwrap().cache_misses, 1);
+    drop(cold);
+
+    let config = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .preload_blocks(16)
+        .build()
+        .unwrap();
+    let warm = LsmEngine::new(config).unwrap();
+    let preloaded = warm.stats_all().unwrap();
+    assert_eq!(preloaded.cache_misses, 3);
+
+    for table in 0..3 {
+        let value = warm.get(&format!("t{table}")).unwrap();
+        assert_eq!(value, Some(format!("v{table}").into_bytes()));
+    }
+    let stats = warm.stats_all().unwrap();
+    assert_eq!(stats.cache_misses, preloaded.cache_misses);
+    assert!(stats.cache_hits >= 3);
+}
+
+#[test]
+fn negative_cache_answers_repeated_misses_until_the_key_is_written() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .negative_cache_size(64)
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config).unwrap();
+    engine.set("present".to_string(), b"v".to_vec()).unwrap();
+    engine.flush().unwrap();
+
+    for _ in 0..3 {
+        assert_eq!(engine.get("missing").unwrap(), None);
+    }
+    assert_eq!(engine.stats_all().unwrap().negative_cache_hits, 2);
+
+    engine.set("missing".to_string(), b"now".to_vec()).unwrap();
+    assert_eq!(engine.get("missing").unwrap(), Some(b"now".to_vec()));
+
+    // A delete makes the key a miss again, and a batch write undoes that
+    engine.delete("missing".to_string()).unwrap();
+    assert_eq!(engine.get("missing").unwrap(), None);
+    assert_eq!(engine.get("missing").unwrap(), None);
+    let mut batch = WriteBatch::new();
+    batch.set("missing", b"again".to_vec());
+    engine.commit(batch).unwrap();
+    assert_eq!(engine.get("missing").unwrap(), Some(b"again".to_vec()));
+    assert_eq!(engine.stats_all().unwrap().negative_cache_hits, 3);
+
+    // Off by default
+    let other = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(other.path())).unwrap();
+    assert_eq!(engine.get("missing").unwrap(), None);
+    assert_eq!(engine.get("missing").unwrap(), None);
+    assert_eq!(engine.stats_all().unwrap().negative_cache_hits, 0);
+}
+
+#[test]
+fn numeric_suffix_comparator_orders_keys_by_their_number() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .key_comparator(KeyComparator::NumericSuffix)
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config.clone()).unwrap();
+
+    // Spread over two tables and the MemTable
+    for batch in [[1, 10, 100], [2, 20, 3]] {
+        for i in batch {
+            engine
+                .set(format!("key_{i}"), i.to_string().into_bytes())
+                .unwrap();
+        }
+        engine.flush().unwrap();
+    }
+    engine.set("key_9".to_string(), b"9".to_vec()).unwrap();
+
+    let expected = [
+        "key_1", "key_2", "key_3", "key_9", "key_10", "key_20", "key_100",
+    ];
+    let keys = |pairs: Vec<(String, Vec<u8>)>| -> Vec<String> {
+        pairs.into_iter().map(|(key, _)| key).collect()
+    };
+    assert_eq!(keys(engine.scan().unwrap()), expected);
+    let reversed: Vec<String> = engine.iter_rev().unwrap().map(|kv| kv.unwrap().0).collect();
+    assert_eq!(reversed, expected.iter().rev().copied().collect::<Vec<_>>());
+    assert_eq!(
+        keys(
+            engine
+                .range(
+                    Bound::Included("key_3".to_string()),
+                    Bound::Excluded("key_20".to_string())
+                )
+                .unwrap()
+        ),
+        ["key_3", "key_9", "key_10"]
+    );
+    assert_eq!(
+        keys(engine.prefix_scan("key_1", None).unwrap()),
+        ["key_1", "key_10", "key_100"]
+    );
+
+    engine.compact_to_single_file().unwrap();
+    assert_eq!(keys(engine.scan().unwrap()), expected);
+    assert_eq!(engine.get("key_20").unwrap(), Some(b"20".to_vec()));
+    drop(engine);
+
+    // The tables remember their order; a store can't be reopened with another
+    let engine = LsmEngine::new(config).unwrap();
+    assert_eq!(keys(engine.scan().unwrap()), expected);
+    drop(engine);
+    assert!(matches!(
+        LsmEngine::new(test_config(dir.path())),
+        Err(LsmError::ConfigValidation(_))
+    ));
+}
+
+#[test]
+fn stats_report_the_wal_replay_of_the_last_open() {
+    let dir = tempdir().unwrap();
+    let config = test_config(dir.path());
+
+    let engine = LsmEngine::new(config.clone()).unwrap();
+    assert_eq!(engine.stats_all().unwrap().recovered_records, 0);
+    for i in 0..3000 {
+        engine
+            .set(format!("key_{i:05}"), b"value".to_vec())
+            .unwrap();
+    }
+    drop(engine);
+
+    let engine = LsmEngine::new(config).unwrap();
+    let stats = engine.stats_all().unwrap();
+    assert_eq!(stats.recovered_records, 3000);
+    assert_eq!(stats.mem_records, 3000);
+    assert!(
+        stats.last_recovery_ms < 60_000,
+        "{}ms",
+        stats.last_recovery_ms
+    );
+}
+
+#[test]
+fn large_flush_is_split_into_non_overlapping_tables() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .memtable_max_size(16 * 1024 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .verify_after_flush(true)
+        .max_sstable_size(16 * 1024)
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config).unwrap();
+    for i in 0..2000 {
+        engine.set(format!("key_{i:05}"), vec![b'v'; 100]).unwrap();
+    }
+    engine.flush().unwrap();
+
+    let mut tables = engine.sstable_info().unwrap();
+    assert!(tables.len() > 1, "flush wrote a single table");
+    assert!(tables.iter().all(|table| table.level == 0));
+    assert_eq!(
+        tables.iter().map(|table| table.record_count).sum::<u64>(),
+        2000
+    );
+
+    tables.sort_by(|a, b| a.min_key.cmp(&b.min_key));
+    assert_eq!(tables[0].min_key, "key_00000");
+    assert_eq!(tables.last().unwrap().max_key, "key_01999");
+    for pair in tables.windows(2) {
+        // Contiguous: each table picks up right after the previous one
+        let last: u32 = pair[0].max_key["key_".len()..].parse().unwrap();
+        assert_eq!(pair[1].min_key, format!("key_{:05}", last + 1));
+    }
+
+    assert_eq!(engine.get("key_01234").unwrap(), Some(vec![b'v'; 100]));
+    assert_eq!(engine.scan().unwrap().len(), 2000);
+}
+
+#[test]
+fn stats_all_lists_every_sstable() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    for batch in 0..3 {
+        for i in 0..=batch * 10 {
+            engine
+                .set(format!("b{batch}_k{i:02}"), vec![b'v'; 100])
+                .unwrap();
+        }
+        engine.flush().unwrap();
+    }
+
+    let stats = engine.stats_all().unwrap();
+    assert_eq!(stats.per_sstable.len(), 3);
+    assert_eq!(stats.per_sstable.len(), sst_files(dir.path()).len());
+
+    // Newest first
+    let counts: Vec<u64> = stats.per_sstable.iter().map(|s| s.record_count).collect();
+    assert_eq!(counts, vec![21, 11, 1]);
+    assert_eq!(stats.per_sstable[0].min_key, "b2_k00");
+    assert_eq!(stats.per_sstable[0].max_key, "b2_k20");
+    assert!(stats.per_sstable[0].timestamp > stats.per_sstable[2].timestamp);
+
+    let mut reported: Vec<u64> = stats.per_sstable.iter().map(|s| s.byte_size).collect();
+    let mut on_disk: Vec<u64> = sst_files(dir.path())
+        .iter()
+        .map(|path| std::fs::metadata(path).unwrap().len())
+        .collect();
+    reported.sort_unstable();
+    on_disk.sort_unstable();
+    assert_eq!(reported, on_disk);
+    assert_eq!(stats.sst_kb, on_disk.iter().sum::<u64>() / 1024);
+
+    let tiered: usize = stats.size_tiers.iter().map(|tier| tier.tables).sum();
+    assert_eq!(tiered, 3);
+    for tier in &stats.size_tiers {
+        assert!(tier.total_bytes >= tier.min_bytes * tier.tables as u64);
+    }
+}
+
+#[test]
+fn prefix_scan_merges_disk_and_memtable_with_a_limit() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    for i in 0..10 {
+        engine
+            .set(format!("user:{i}"), format!("old{i}").into_bytes())
+            .unwrap();
+    }
+    engine.set("userx".to_string(), b"x".to_vec()).unwrap();
+    engine.set("order:1".to_string(), b"o".to_vec()).unwrap();
+    engine.flush().unwrap();
+    // Newer versions and a tombstone still in the MemTable
+    engine.set("user:3".to_string(), b"new3".to_vec()).unwrap();
+    engine.delete("user:1".to_string()).unwrap();
+
+    let users = engine.prefix_scan("user:", None).unwrap();
+    let keys: Vec<&str> = users.iter().map(|(k, _)| k.as_str()).collect();
+    assert_eq!(
+        keys,
+        [
+            "user:0", "user:2", "user:3", "user:4", "user:5", "user:6", "user:7", "user:8",
+            "user:9"
+        ]
+    );
+    assert_eq!(users[2].1, b"new3");
+
+    let first = engine.prefix_scan("user:", Some(2)).unwrap();
+    assert_eq!(
+        first,
+        [
+            ("user:0".to_string(), b"old0".to_vec()),
+            ("user:2".to_string(), b"old2".to_vec())
+        ]
+    );
+    assert!(engine.prefix_scan("user:", Some(0)).unwrap().is_empty());
+}
+
+#[test]
+fn export_then_import_into_a_fresh_store() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .memtable_max_size(16 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let source = LsmEngine::new(config).unwrap();
+    for i in 0..2500 {
+        source
+            .set(format!("key{i:04}"), format!("value{i}").into_bytes())
+            .unwrap();
+    }
+    source
+        .set("binary".to_string(), vec![0, 159, 255, b'\n'])
+        .unwrap();
+    source.delete("key0007".to_string()).unwrap();
+
+    let mut dump = Vec::new();
+    assert_eq!(source.export_ndjson(&mut dump).unwrap(), 2500);
+
+    let target_dir = tempdir().unwrap();
+    let target = LsmEngine::new(test_config(target_dir.path())).unwrap();
+    assert_eq!(target.import_ndjson(dump.as_slice()).unwrap(), 2500);
+    assert_eq!(target.scan().unwrap(), source.scan().unwrap());
+}
+
+#[test]
+fn import_reports_the_malformed_line() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    let input = "{\"key\":\"a\",\"value_b64\":\"dg==\"}\n\nnot json\n";
+    match engine.import_ndjson(input.as_bytes()) {
+        Err(LsmError::DeserializationFailed(msg)) => assert!(msg.starts_with("line 3:"), "{msg}"),
+        other => panic!("expected DeserializationFailed, got {other:?}"),
+    }
+    let input = "{\"key\":\"a\",\"value_b64\":\"not base64!\"}\n";
+    assert!(engine.import_ndjson(input.as_bytes()).is_err());
+}
+
+#[test]
+fn bulk_load_writes_sstables_without_touching_the_wal() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    engine
+        .set("key000042".to_string(), b"old".to_vec())
+        .unwrap();
+
+    let records = (0..100_000).map(|i| (format!("key{i:06}"), format!("value{i}").into_bytes()));
+    assert_eq!(engine.bulk_load(records).unwrap(), 100_000);
+
+    assert_eq!(engine.count().unwrap(), 100_000);
+    assert!(engine.sstable_info().unwrap().len() > 1);
+    for i in [0, 42, 54_321, 99_999] {
+        assert_eq!(
+            engine.get(&format!("key{i:06}")).unwrap(),
+            Some(format!("value{i}").into_bytes())
+        );
+    }
+
+    // The earlier set and its flush are the only WAL fsyncs
+    let syncs = engine.wal_stats().syncs;
+    let wal_bytes: u64 = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .map(|path| std::fs::metadata(path).unwrap().len())
+        .sum();
+    assert_eq!(wal_bytes, 0);
+    assert_eq!(syncs, 2);
+
+    drop(engine);
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    assert_eq!(engine.count().unwrap(), 100_000);
+}
+
+#[test]
+fn bulk_load_rejects_unsorted_keys() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+
+    let records = ["a", "c", "b"].map(|key| (key.to_string(), b"v".to_vec()));
+    match engine.bulk_load(records) {
+        Err(LsmError::ValidationRejected(msg)) => assert!(msg.contains("'b' after 'c'"), "{msg}"),
+        other => panic!("expected ValidationRejected, got {other:?}"),
+    }
+    let duplicate = ["a", "a"].map(|key| (key.to_string(), b"v".to_vec()));
+    assert!(engine.bulk_load(duplicate).is_err());
+}
+
+#[test]
+fn ingest_sorted_streams_into_size_bounded_tables() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .target_file_size(16 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config).unwrap();
+    engine.set("b0100".to_string(), b"old".to_vec()).unwrap();
+    engine.set("z".to_string(), b"kept".to_vec()).unwrap();
+
+    let records = (0..2000).map(|i| (format!("b{i:04}"), vec![b'v'; 24]));
+    assert_eq!(engine.ingest_sorted(records).unwrap(), 2000);
+
+    // One flushed table plus several ingested ones, each about 16KB
+    let tables = engine.sstable_info().unwrap();
+    assert!(tables.len() > 4, "{} tables", tables.len());
+    assert_eq!(engine.count().unwrap(), 2001);
+    assert_eq!(engine.get("b0100").unwrap(), Some(vec![b'v'; 24]));
+    assert_eq!(engine.get("z").unwrap(), Some(b"kept".to_vec()));
+    let keys: Vec<String> = engine.keys().unwrap();
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn ingest_sorted_stops_at_an_out_of_order_key() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .target_file_size(8 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = LsmEngine::new(config).unwrap();
+
+    let records = (0..1000)
+        .map(|i| (format!("k{i:04}"), vec![b'v'; 24]))
+        .chain([("k0500".to_string(), b"late".to_vec())]);
+    match engine.ingest_sorted(records) {
+        Err(LsmError::ValidationRejected(msg)) => {
+            assert!(msg.contains("'k0500' after 'k0999'"), "{msg}")
+        }
+        other => panic!("expected ValidationRejected, got {other:?}"),
+    }
+
+    // Full tables stay installed; the one in progress is gone from disk too
+    let installed = engine.sstable_info().unwrap().len();
+    assert!(installed > 0);
+    assert_eq!(sst_files(dir.path()).len(), installed);
+    let loaded = engine.count().unwrap();
+    assert!(loaded > 0 && loaded < 1000, "{loaded} records");
+    assert_eq!(engine.get("k0000").unwrap(), Some(vec![b'v'; 24]));
+}
+
+#[test]
+fn typed_values_round_trip_and_report_decode_failures() {
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Profile {
+        name: String,
+        age: u32,
+        tags: Vec<String>,
+    }
+
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    let profile = Profile {
+        name: "ana".to_string(),
+        age: 31,
+        tags: vec!["admin".to_string()],
+    };
+
+    engine.set_typed("user:1".to_string(), &profile).unwrap();
+    assert_eq!(
+        engine.get_typed::<Profile>("user:1").unwrap(),
+        Some(profile)
+    );
+    assert_eq!(engine.get_typed::<Profile>("user:2").unwrap(), None);
+
+    engine.set("user:3".to_string(), b"x".to_vec()).unwrap();
+    assert!(matches!(
+        engine.get_typed::<Profile>("user:3"),
+        Err(LsmError::DeserializationFailed(_))
+    ));
+}
+
+#[test]
+fn secondary_index_follows_inserts_updates_and_deletes() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    let index = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+
+    engine
+        .set_indexed(
+            "user:1".into(),
+            b"ana".to_vec(),
+            &index(&["city:lisbon", "role:admin"]),
+        )
+        .unwrap();
+    engine
+        .set_indexed("user:2".into(), b"bruno".to_vec(), &index(&["city:lisbon"]))
+        .unwrap();
+    engine
+        .set_indexed("user:3".into(), b"carla".to_vec(), &index(&["city:porto"]))
+        .unwrap();
+
+    assert_eq!(
+        engine.find_by_index("city", "lisbon").unwrap(),
+        ["user:1", "user:2"]
+    );
+    assert_eq!(engine.find_by_index("role", "admin").unwrap(), ["user:1"]);
+    assert!(engine.find_by_index("city", "faro").unwrap().is_empty());
+
+    // Moving user:1 drops its old entries
+    engine
+        .set_indexed("user:1".into(), b"ana".to_vec(), &index(&["city:porto"]))
+        .unwrap();
+    assert_eq!(engine.find_by_index("city", "lisbon").unwrap(), ["user:2"]);
+    assert_eq!(
+        engine.find_by_index("city", "porto").unwrap(),
+        ["user:1", "user:3"]
+    );
+    assert!(engine.find_by_index("role", "admin").unwrap().is_empty());
+
+    engine.delete_indexed("user:3".into()).unwrap();
+    assert_eq!(engine.get("user:3").unwrap(), None);
+    assert_eq!(engine.find_by_index("city", "porto").unwrap(), ["user:1"]);
+
+    // Entries survive a flush and reopen like any other key
+    engine.flush().unwrap();
+    drop(engine);
+    let engine = LsmEngine::new(test_config(dir.path())).unwrap();
+    assert_eq!(engine.find_by_index("city", "porto").unwrap(), ["user:1"]);
+    engine.delete_indexed("user:1".into()).unwrap();
+    assert!(engine.find_by_index("city", "porto").unwrap().is_empty());
+
+    for bad in [&["no-separator"][..], &["city:a:b"][..], &[":x"][..]] {
+        assert!(matches!(
+            engine.set_indexed("user:4".into(), Vec::new(), &index(bad)),
+            Err(LsmError::ValidationRejected(_))
+        ));
+    }
+    assert_eq!(engine.get("user:4").unwrap(), None);
+}
+
+fn blob_files(dir: &std::path::Path) -> usize {
+    match std::fs::read_dir(dir.join("blobs")) {
+        Ok(entries) => entries.count(),
+        Err(_) => 0,
+    }
+}
+
+fn blob_config(dir: &std::path::Path) -> LsmConfig {
+    LsmConfig::builder()
+        .dir_path(dir.to_path_buf())
+        .max_value_size(1024 * 1024)
+        .inline_value_max(1024)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn large_values_are_stored_out_of_line() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(blob_config(dir.path())).unwrap();
+    let big: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+
+    engine.set("big".to_string(), big.clone()).unwrap();
+    engine.set("small".to_string(), b"tiny".to_vec()).unwrap();
+    engine.flush().unwrap();
+
+    assert_eq!(blob_files(dir.path()), 1);
+    let table_bytes: u64 = sst_files(dir.path())
+        .iter()
+        .map(|path| std::fs::metadata(path).unwrap().len())
+        .sum();
+    assert!(table_bytes < 10_000, "table holds {table_bytes} bytes");
+
+    assert!(engine.get("big").unwrap() == Some(big.clone()));
+    assert_eq!(engine.get("small").unwrap(), Some(b"tiny".to_vec()));
+    assert!(engine.scan().unwrap()[0] == ("big".to_string(), big.clone()));
+
+    // Compaction carries the pointer forward without copying the blob
+    engine.set("other".to_string(), vec![1; 10]).unwrap();
+    engine.compact_now().unwrap();
+    assert_eq!(blob_files(dir.path()), 1);
+
+    drop(engine);
+    let engine = LsmEngine::new(blob_config(dir.path())).unwrap();
+    assert!(engine.get("big").unwrap() == Some(big));
+}
+
+#[test]
+fn compaction_removes_blobs_no_record_points_at() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(blob_config(dir.path())).unwrap();
+
+    engine.set("a".to_string(), vec![b'1'; 4096]).unwrap();
+    engine.set("b".to_string(), vec![b'1'; 4096]).unwrap();
+    engine.flush().unwrap();
+    // A snapshot still reading the old table keeps its blobs
+    let snapshot = engine.snapshot().unwrap();
+
+    engine.set("a".to_string(), vec![b'2'; 4096]).unwrap();
+    engine.delete("b".to_string()).unwrap();
+    engine.flush().unwrap();
+    assert_eq!(blob_files(dir.path()), 3);
+
+    engine.compact_now().unwrap();
+    assert_eq!(blob_files(dir.path()), 3);
+    assert!(snapshot.get("b").unwrap() == Some(vec![b'1'; 4096]));
+
+    drop(snapshot);
+    assert_eq!(blob_files(dir.path()), 1);
+    assert!(engine.get("a").unwrap() == Some(vec![b'2'; 4096]));
+    assert_eq!(engine.get("b").unwrap(), None);
+}
+
+#[test]
+fn liveness_checks_do_not_read_blobs() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(blob_config(dir.path())).unwrap();
+    engine.set("big".to_string(), vec![7; 4096]).unwrap();
+    engine.flush().unwrap();
+    let seq = engine.last_seq();
+
+    for entry in std::fs::read_dir(dir.path().join("blobs")).unwrap() {
+        std::fs::remove_file(entry.unwrap().path()).unwrap();
+    }
+    assert!(engine.exists("big").unwrap());
+    assert!(engine.exists_at_seq("big", seq).unwrap());
+    assert!(!engine.exists_at_seq("big", seq - 1).unwrap());
+    assert!(matches!(engine.get("big"), Err(LsmError::CorruptedData(_))));
+}
+
+#[test]
+fn iteration_reads_only_the_blobs_of_winning_versions() {
+    let dir = tempdir().unwrap();
+    let engine = LsmEngine::new(blob_config(dir.path())).unwrap();
+    engine.set("a".to_string(), vec![1; 4096]).unwrap();
+    engine.set("b".to_string(), vec![1; 4096]).unwrap();
+    engine.flush().unwrap();
+
+    // Every blob the older table points at is shadowed from here on
+    for entry in std::fs::read_dir(dir.path().join("blobs")).unwrap() {
+        std::fs::remove_file(entry.unwrap().path()).unwrap();
+    }
+    engine.set("a".to_string(), vec![2; 4096]).unwrap();
+    engine.delete("b".to_string()).unwrap();
+    engine.flush().unwrap();
+
+    let expected = vec![("a".to_string(), vec![2; 4096])];
+    assert!(engine.scan().unwrap() == expected);
+    let reversed: Vec<_> = engine.iter_rev().unwrap().map(Result::unwrap).collect();
+    assert!(reversed == expected);
+    let snapshot = engine.snapshot().unwrap();
+    assert!(snapshot.range(Bound::Unbounded, Bound::Unbounded).unwrap() == expected);
+    #[cfg(feature = "parallel")]
+    assert!(engine.scan_parallel().unwrap() == expected);
+}
@@ -0,0 +1,147 @@
+#![cfg(feature = "grpc")]
+
+use lsm_kv_store::grpc::proto::lsm_client::LsmClient;
+use lsm_kv_store::grpc::proto::{
+    change_event, DeleteRequest, GetRequest, ScanRequest, SetRequest, WatchRequest,
+};
+use lsm_kv_store::grpc::GrpcServer;
+use lsm_kv_store::{LsmConfig, LsmEngine};
+use std::sync::Arc;
+use tempfile::tempdir;
+use tonic::transport::Channel;
+
+async fn start_server() -> (LsmClient<Channel>, tempfile::TempDir) {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .memtable_max_size(16 * 1024)
+        .build()
+        .unwrap();
+    let engine = Arc::new(LsmEngine::new(config).unwrap());
+    let server = GrpcServer::bind("127.0.0.1:0", engine).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+
+    let client = LsmClient::connect(format!("http://{addr}")).await.unwrap();
+    (client, dir)
+}
+
+fn set(key: &str, value: &[u8]) -> SetRequest {
+    SetRequest {
+        key: key.to_string(),
+        value: value.to_vec(),
+        ttl_ms: 0,
+    }
+}
+
+#[tokio::test]
+async fn set_get_and_delete() {
+    let (mut client, _dir) = start_server().await;
+
+    let binary = [0u8, 159, 255, b'\n'];
+    client.set(set("k", &binary)).await.unwrap();
+    let reply = client
+        .get(GetRequest {
+            key: "k".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(reply.found);
+    assert_eq!(reply.value, binary);
+
+    client
+        .delete(DeleteRequest {
+            key: "k".to_string(),
+        })
+        .await
+        .unwrap();
+    let reply = client
+        .get(GetRequest {
+            key: "k".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!reply.found);
+}
+
+#[tokio::test]
+async fn scan_streams_a_range_in_key_order() {
+    let (mut client, _dir) = start_server().await;
+    // Enough to spread over several SSTables
+    for i in (0..2000).rev() {
+        client
+            .set(set(&format!("key{i:04}"), format!("v{i}").as_bytes()))
+            .await
+            .unwrap();
+    }
+
+    let collect = |request: ScanRequest| {
+        let mut client = client.clone();
+        async move {
+            let mut stream = client.scan(request).await.unwrap().into_inner();
+            let mut keys = Vec::new();
+            while let Some(record) = stream.message().await.unwrap() {
+                assert_eq!(
+                    record.value,
+                    format!("v{}", record.key[3..].parse::<u32>().unwrap()).into_bytes()
+                );
+                keys.push(record.key);
+            }
+            keys
+        }
+    };
+
+    let all = collect(ScanRequest::default()).await;
+    assert_eq!(all.len(), 2000);
+    assert!(all.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let window = collect(ScanRequest {
+        start_key: "key0100".to_string(),
+        end_key: "key0200".to_string(),
+        limit: 0,
+    })
+    .await;
+    assert_eq!(window.len(), 100);
+    assert_eq!(window.first().unwrap(), "key0100");
+    assert_eq!(window.last().unwrap(), "key0199");
+
+    let limited = collect(ScanRequest {
+        start_key: "key1990".to_string(),
+        limit: 5,
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(
+        limited,
+        ["key1990", "key1991", "key1992", "key1993", "key1994"]
+    );
+}
+
+#[tokio::test]
+async fn watch_streams_matching_writes() {
+    let (mut client, _dir) = start_server().await;
+
+    let mut events = client
+        .watch(WatchRequest {
+            prefix: "user:".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    client.set(set("other", b"x")).await.unwrap();
+    client.set(set("user:1", b"alice")).await.unwrap();
+    client
+        .delete(DeleteRequest {
+            key: "user:1".to_string(),
+        })
+        .await
+        .unwrap();
+
+    let event = events.message().await.unwrap().unwrap();
+    assert_eq!(event.key, "user:1");
+    assert_eq!(event.kind, change_event::Kind::Set as i32);
+    let event = events.message().await.unwrap().unwrap();
+    assert_eq!(event.kind, change_event::Kind::Delete as i32);
+}
@@ -1,11 +1,15 @@
+// Readers took `&mut self` before they were shared across threads
+#![allow(unused_mut)]
+
 use lsm_kv_store::core::log_record::LogRecord;
 use lsm_kv_store::infra::config::StorageConfig;
 use lsm_kv_store::infra::error::Result;
 use lsm_kv_store::storage::builder::SstableBuilder;
 use lsm_kv_store::storage::cache::GlobalBlockCache;
 use lsm_kv_store::storage::reader::SstableReader;
+use lsm_kv_store::{LsmConfig, LsmEngine};
 use std::sync::Arc;
-use tempfile::tempdir;
+use tempfile::{tempdir, TempDir};
 
 fn create_test_record(key: &str, value: &[u8]) -> LogRecord {
     LogRecord::new(key.to_string(), value.to_vec())
@@ -330,3 +334,266 @@ fn test_sstable_v2_unicode_keys() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_sstable_v2_aligned_blocks() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("aligned.sst");
+    let config = StorageConfig {
+        block_size: 512,
+        align_blocks: true,
+        ..Default::default()
+    };
+    let cache = create_test_cache(&config);
+
+    let mut builder = SstableBuilder::new(path.clone(), config.clone(), 777)?;
+    for i in 0..200 {
+        let key = format!("key_{:04}", i);
+        builder.add(key.as_bytes(), &create_test_record(&key, &[b'v'; 40]))?;
+    }
+    builder.finish()?;
+
+    let mut reader = SstableReader::open(path, config, cache)?;
+    let blocks = &reader.metadata().blocks;
+    assert!(blocks.len() > 1, "Should have multiple blocks");
+    for block in blocks {
+        assert_eq!(block.offset % 4096, 0, "Block at {} is not aligned", block.offset);
+        assert_eq!(block.size % 4096, 0, "Block extent should be padded");
+    }
+
+    for i in 0..200 {
+        let key = format!("key_{:04}", i);
+        let record = reader.get(&key)?.expect("Key should exist");
+        assert_eq!(record.value, vec![b'v'; 40]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sstable_v4_lazy_index_roundtrip() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("lazy.sst");
+    let config = StorageConfig {
+        block_size: 256,
+        lazy_block_index: true,
+        ..Default::default()
+    };
+    let cache = create_test_cache(&config);
+
+    let mut builder = SstableBuilder::new(path.clone(), config.clone(), 404)?;
+    for i in 0..500 {
+        let key = format!("key_{:04}", i * 2);
+        builder.add(key.as_bytes(), &create_test_record(&key, &[b'v'; 30]))?;
+    }
+    builder.finish()?;
+
+    let mut reader = SstableReader::open(path, config, cache)?;
+    assert!(reader.has_lazy_index());
+    assert!(
+        reader.metadata().blocks.is_empty(),
+        "V4 index must not be loaded on open"
+    );
+    assert!(reader.block_count() > 10, "Should have many blocks");
+    assert_eq!(reader.metadata().record_count, 500);
+
+    for i in 0..500 {
+        let key = format!("key_{:04}", i * 2);
+        let record = reader.get(&key)?.expect("Key should exist");
+        assert_eq!(record.value, vec![b'v'; 30]);
+
+        let gap = format!("key_{:04}", i * 2 + 1);
+        assert!(reader.get(&gap)?.is_none(), "{} should not exist", gap);
+    }
+    assert!(reader.get("aaa")?.is_none());
+    assert!(reader.get("zzz")?.is_none());
+
+    assert_eq!(reader.scan()?.len(), 500);
+    assert_eq!(
+        reader.warm_range(b"key_0000", b"zzz", usize::MAX)?,
+        reader.block_count()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_sstable_v3_and_v4_readable_side_by_side() -> Result<()> {
+    let dir = tempdir()?;
+    let eager = StorageConfig::default();
+    let lazy = StorageConfig {
+        lazy_block_index: true,
+        ..Default::default()
+    };
+    let cache = create_test_cache(&eager);
+
+    for (name, config) in [("v3.sst", &eager), ("v4.sst", &lazy)] {
+        let mut builder = SstableBuilder::new(dir.path().join(name), config.clone(), 1)?;
+        builder.add(b"k", &create_test_record("k", name.as_bytes()))?;
+        builder.finish()?;
+    }
+
+    // The reader picks the format from the magic, not from the config
+    let mut v3 = SstableReader::open(dir.path().join("v3.sst"), lazy.clone(), Arc::clone(&cache))?;
+    let mut v4 = SstableReader::open(dir.path().join("v4.sst"), eager, cache)?;
+    assert!(!v3.has_lazy_index());
+    assert!(v4.has_lazy_index());
+    assert_eq!(v3.get("k")?.unwrap().value, b"v3.sst");
+    assert_eq!(v4.get("k")?.unwrap().value, b"v4.sst");
+
+    Ok(())
+}
+
+#[test]
+fn test_engine_flush_honors_storage_config() -> Result<()> {
+    // Same records flushed by two engines that differ only in storage tuning
+    let flush_with = |block_size: usize, fp_rate: f64| -> Result<(TempDir, SstableReader)> {
+        let dir = tempdir()?;
+        let config = LsmConfig::builder()
+            .dir_path(dir.path().to_path_buf())
+            .block_size(block_size)
+            .bloom_false_positive_rate(fp_rate)
+            .build()?;
+        let engine = LsmEngine::new(config)?;
+        for i in 0..500 {
+            engine.set(format!("key_{i:04}"), vec![b'v'; 32])?;
+        }
+        engine.flush()?;
+        let path = engine.sstable_info()?.pop().unwrap().path;
+        drop(engine);
+
+        // The tuning is read back from the table itself, not from this config
+        let config = StorageConfig::default();
+        let cache = create_test_cache(&config);
+        Ok((dir, SstableReader::open(path, config, cache)?))
+    };
+
+    let default = StorageConfig::default();
+    let (_baseline_dir, baseline) =
+        flush_with(default.block_size, default.bloom_false_positive_rate)?;
+    let (_tuned_dir, tuned) = flush_with(1024, 0.0001)?;
+
+    assert!(tuned.block_count() > baseline.block_count() * 3);
+    // Bloom bits per key grow with ln(1 / fp_rate)
+    let bloom_len = |reader: &SstableReader| reader.metadata().bloom_filter_data.len();
+    assert!(bloom_len(&tuned) * 2 > bloom_len(&baseline) * 3);
+    Ok(())
+}
+
+#[test]
+fn test_tables_sharing_a_timestamp_get_distinct_files() -> Result<()> {
+    let dir = tempdir()?;
+    let config = StorageConfig::default();
+
+    // Two flushes that read the same clock value
+    let timestamp = 1_700_000_000_000_000_000;
+    let mut paths = Vec::new();
+    for (seq, value) in [(1, "older"), (2, "newer")] {
+        let mut builder = SstableBuilder::create_in(dir.path(), config.clone(), timestamp)?;
+        let mut keys = ["shared", value];
+        keys.sort();
+        for key in keys {
+            let mut record = create_test_record(key, value.as_bytes());
+            record.seq = seq;
+            builder.add(key.as_bytes(), &record)?;
+        }
+        paths.push(builder.finish()?);
+    }
+    assert_eq!(paths[0], dir.path().join(format!("{timestamp}.sst")));
+    assert_eq!(paths[1], dir.path().join(format!("{timestamp}-1.sst")));
+
+    // A taken name is an error for an explicit path, not an overwrite
+    assert!(SstableBuilder::new(paths[0].clone(), config, timestamp).is_err());
+
+    // Both load, and the one holding newer records is read first
+    let engine = LsmEngine::new(
+        LsmConfig::builder()
+            .dir_path(dir.path().to_path_buf())
+            .build()?,
+    )?;
+    assert_eq!(engine.sstable_info()?.len(), 2);
+    assert_eq!(engine.get("shared")?, Some(b"newer".to_vec()));
+    assert_eq!(engine.get("older")?, Some(b"older".to_vec()));
+    assert_eq!(engine.get("newer")?, Some(b"newer".to_vec()));
+    Ok(())
+}
+
+#[test]
+fn test_sstable_v2_iter_streams_every_block() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("iter.sst");
+    let config = StorageConfig {
+        block_size: 512,
+        ..StorageConfig::default()
+    };
+    let cache = create_test_cache(&config);
+
+    let mut builder = SstableBuilder::new(path.clone(), config.clone(), 789)?;
+    for i in 0..1000 {
+        let key = format!("key_{:04}", i);
+        builder.add(
+            key.as_bytes(),
+            &create_test_record(&key, format!("value_{i}").as_bytes()),
+        )?;
+    }
+    builder.finish()?;
+
+    let reader = Arc::new(SstableReader::open(path, config, cache)?);
+    assert!(reader.block_count() > 10);
+
+    let mut iter = reader.iter();
+    let mut count = 0;
+    for entry in iter.by_ref() {
+        let (key, record) = entry?;
+        assert_eq!(key, format!("key_{:04}", count).into_bytes());
+        assert_eq!(record.value, format!("value_{count}").into_bytes());
+        count += 1;
+    }
+    assert_eq!(count, 1000);
+    assert!(iter.next().is_none());
+    Ok(())
+}
+
+#[test]
+fn test_sstable_v2_fixed_bloom_seed_is_reproducible() -> Result<()> {
+    let dir = tempdir()?;
+    let build = |name: &str, config: &StorageConfig| -> Result<std::path::PathBuf> {
+        let path = dir.path().join(name);
+        let mut builder = SstableBuilder::new(path.clone(), config.clone(), 321)?;
+        for i in 0..500 {
+            let key = format!("key_{:04}", i);
+            let mut record = create_test_record(&key, b"value");
+            record.timestamp = i as u128;
+            builder.add(key.as_bytes(), &record)?;
+        }
+        builder.finish()
+    };
+
+    let seeded = StorageConfig {
+        bloom_seed: Some([42; 32]),
+        per_block_bloom: true,
+        ..StorageConfig::default()
+    };
+    let first = build("seeded_a.sst", &seeded)?;
+    let second = build("seeded_b.sst", &seeded)?;
+    assert_eq!(std::fs::read(&first)?, std::fs::read(&second)?);
+
+    let cache = create_test_cache(&seeded);
+    let reader = SstableReader::open(first, StorageConfig::default(), cache)?;
+    assert!(reader.has_bloom());
+    assert!(reader.get("key_0123")?.is_some());
+
+    // Without a seed every filter gets random hash keys
+    let random = StorageConfig::default();
+    let cache = create_test_cache(&random);
+    let blooms: Vec<Vec<u8>> = ["random_a.sst", "random_b.sst"]
+        .into_iter()
+        .map(|name| {
+            let path = build(name, &random)?;
+            let reader = SstableReader::open(path, random.clone(), Arc::clone(&cache))?;
+            Ok(reader.metadata().bloom_filter_data.clone())
+        })
+        .collect::<Result<_>>()?;
+    assert_ne!(blooms[0], blooms[1]);
+    Ok(())
+}
@@ -0,0 +1,126 @@
+#![cfg(feature = "resp")]
+
+use lsm_kv_store::resp::RespServer;
+use lsm_kv_store::{LsmConfig, LsmEngine};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use tempfile::tempdir;
+
+/// Frame `args` as a RESP array of bulk strings
+fn command(args: &[&[u8]]) -> Vec<u8> {
+    let mut frame = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        frame.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        frame.extend_from_slice(arg);
+        frame.extend_from_slice(b"\r\n");
+    }
+    frame
+}
+
+/// Send one command and read exactly `expected.len()` reply bytes
+fn roundtrip(stream: &mut TcpStream, args: &[&[u8]], expected: &[u8]) {
+    stream.write_all(&command(args)).unwrap();
+    let mut reply = vec![0u8; expected.len()];
+    stream.read_exact(&mut reply).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&reply),
+        String::from_utf8_lossy(expected),
+        "reply to {:?}",
+        String::from_utf8_lossy(args[0])
+    );
+}
+
+fn start_server() -> (TcpStream, tempfile::TempDir) {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+    let engine = Arc::new(LsmEngine::new(config).unwrap());
+
+    let server = RespServer::bind("127.0.0.1:0", engine).unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    (TcpStream::connect(addr).unwrap(), dir)
+}
+
+#[test]
+fn set_and_get_binary_values_over_resp() {
+    let (mut stream, _dir) = start_server();
+
+    roundtrip(&mut stream, &[b"PING"], b"+PONG\r\n");
+    roundtrip(&mut stream, &[b"SET", b"greeting", b"hello"], b"+OK\r\n");
+    roundtrip(&mut stream, &[b"GET", b"greeting"], b"$5\r\nhello\r\n");
+    roundtrip(&mut stream, &[b"GET", b"missing"], b"$-1\r\n");
+
+    // CRLF, NUL and non-UTF-8 bytes pass through untouched
+    let binary: &[u8] = b"a\r\nb\x00\xff\xfe";
+    roundtrip(&mut stream, &[b"set", b"blob", binary], b"+OK\r\n");
+    let mut expected = format!("${}\r\n", binary.len()).into_bytes();
+    expected.extend_from_slice(binary);
+    expected.extend_from_slice(b"\r\n");
+    roundtrip(&mut stream, &[b"get", b"blob"], &expected);
+
+    roundtrip(
+        &mut stream,
+        &[b"EXISTS", b"greeting", b"missing", b"blob"],
+        b":2\r\n",
+    );
+    roundtrip(&mut stream, &[b"DEL", b"greeting", b"missing"], b":1\r\n");
+    roundtrip(&mut stream, &[b"GET", b"greeting"], b"$-1\r\n");
+
+    roundtrip(
+        &mut stream,
+        &[b"GET"],
+        b"-ERR wrong number of arguments for 'get' command\r\n",
+    );
+    roundtrip(
+        &mut stream,
+        &[b"FLUSHALL"],
+        b"-ERR unknown command 'flushall'\r\n",
+    );
+}
+
+#[test]
+fn pipelined_commands_are_answered_in_order() {
+    let (mut stream, _dir) = start_server();
+
+    let mut pipeline = command(&[b"SET", b"a", b"1"]);
+    pipeline.extend(command(&[b"SET", b"b", b"2"]));
+    pipeline.extend(command(&[b"GET", b"a"]));
+    pipeline.extend(command(&[b"GET", b"b"]));
+    stream.write_all(&pipeline).unwrap();
+
+    let expected = b"+OK\r\n+OK\r\n$1\r\n1\r\n$1\r\n2\r\n";
+    let mut reply = vec![0u8; expected.len()];
+    stream.read_exact(&mut reply).unwrap();
+    assert_eq!(reply, expected);
+}
+
+#[test]
+fn scan_walks_every_key_with_a_cursor() {
+    let (mut stream, _dir) = start_server();
+    for key in ["user:1", "user:2", "user:3", "order:1", "order:2"] {
+        roundtrip(&mut stream, &[b"SET", key.as_bytes(), b"v"], b"+OK\r\n");
+    }
+
+    // The first page ends at "order:2", whose hex encoding is the cursor
+    let cursor = b"6f726465723a32";
+    let mut page = b"*2\r\n$14\r\n".to_vec();
+    page.extend_from_slice(cursor);
+    page.extend_from_slice(b"\r\n*2\r\n$7\r\norder:1\r\n$7\r\norder:2\r\n");
+    roundtrip(&mut stream, &[b"SCAN", b"0", b"COUNT", b"2"], &page);
+    roundtrip(
+        &mut stream,
+        &[b"SCAN", cursor, b"COUNT", b"10"],
+        b"*2\r\n$1\r\n0\r\n*3\r\n$6\r\nuser:1\r\n$6\r\nuser:2\r\n$6\r\nuser:3\r\n",
+    );
+
+    roundtrip(
+        &mut stream,
+        &[b"SCAN", b"0", b"MATCH", b"user:?"],
+        b"*2\r\n$1\r\n0\r\n*3\r\n$6\r\nuser:1\r\n$6\r\nuser:2\r\n$6\r\nuser:3\r\n",
+    );
+}
@@ -1,4 +1,8 @@
-use lsm_kv_store::{LsmConfig, LsmEngine, LsmError};
+use lsm_kv_store::core::log_record::LogRecord;
+use lsm_kv_store::infra::config::StorageConfig;
+use lsm_kv_store::storage::builder::SstableBuilder;
+use lsm_kv_store::storage::manifest::Manifest;
+use lsm_kv_store::{LsmConfig, LsmEngine, LsmError, WalSyncPolicy, WriteBatch};
 use tempfile::tempdir;
 
 use std::fs::OpenOptions;
@@ -68,7 +72,7 @@ fn tombstone_persists_across_restart() {
 }
 
 #[test]
-fn wal_truncation_is_detected() {
+fn torn_wal_tail_is_discarded() {
     let dir = tempdir().unwrap();
     let dir_path = dir.path().to_path_buf();
     let cfg = LsmConfig::builder()
@@ -79,10 +83,12 @@ fn wal_truncation_is_detected() {
 
     {
         let engine = LsmEngine::new(cfg.clone()).unwrap();
+        engine.set("k0".to_string(), b"v0".to_vec()).unwrap();
         engine.set("k1".to_string(), b"v1".to_vec()).unwrap();
     }
 
-    let wal_path = dir_path.join("wal.log");
+    // A crash in the middle of the last append
+    let wal_path = dir_path.join("wal-000001.log");
     let file = OpenOptions::new()
         .read(true)
         .write(true)
@@ -93,6 +99,45 @@ fn wal_truncation_is_detected() {
     assert!(len > 1);
 
     file.set_len(len - 1).unwrap();
+    drop(file);
+
+    let engine = LsmEngine::new(cfg.clone()).unwrap();
+    assert_eq!(engine.get("k0").unwrap(), Some(b"v0".to_vec()));
+    assert!(engine.get("k1").unwrap().is_none());
+
+    // The torn bytes were cut off, so later appends replay cleanly
+    engine.set("k2".to_string(), b"v2".to_vec()).unwrap();
+    drop(engine);
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(engine.get("k0").unwrap(), Some(b"v0".to_vec()));
+    assert_eq!(engine.get("k2").unwrap(), Some(b"v2".to_vec()));
+}
+
+#[test]
+fn corrupted_wal_record_is_detected() {
+    let dir = tempdir().unwrap();
+    let dir_path = dir.path().to_path_buf();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir_path.clone())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        for i in 0..3 {
+            engine.set(format!("k{i}"), b"value".to_vec()).unwrap();
+        }
+    }
+
+    // Flip the last payload byte of the middle record
+    let wal_path = dir_path.join("wal-000001.log");
+    let mut bytes = std::fs::read(&wal_path).unwrap();
+    let first_len = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize;
+    let second = 8 + first_len;
+    let second_len = u32::from_le_bytes(bytes[second..second + 4].try_into().unwrap()) as usize;
+    bytes[second + 8 + second_len - 1] ^= 0xFF;
+    std::fs::write(&wal_path, bytes).unwrap();
 
     let res = LsmEngine::new(cfg);
     match res {
@@ -101,3 +146,708 @@ fn wal_truncation_is_detected() {
         Ok(_) => panic!("expected WalCorruption, got Ok"),
     }
 }
+
+#[test]
+fn impossible_wal_frame_length_is_detected() {
+    let dir = tempdir().unwrap();
+    let dir_path = dir.path().to_path_buf();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir_path.clone())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        for i in 0..3 {
+            engine.set(format!("k{i}"), b"value".to_vec()).unwrap();
+        }
+    }
+
+    // A middle record whose length now points far past the end of the
+    // segment, which must not pass for a torn tail and cut off what follows
+    let wal_path = dir_path.join("wal-000001.log");
+    let mut bytes = std::fs::read(&wal_path).unwrap();
+    let first_len = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize;
+    let second = 8 + first_len;
+    bytes[second..second + 4].copy_from_slice(&(64u32 << 20).to_le_bytes());
+    std::fs::write(&wal_path, &bytes).unwrap();
+
+    assert!(matches!(LsmEngine::new(cfg), Err(LsmError::WalCorruption)));
+    assert_eq!(std::fs::read(&wal_path).unwrap(), bytes);
+}
+
+#[test]
+fn damaged_interior_wal_frame_length_is_detected() {
+    let dir = tempdir().unwrap();
+    let dir_path = dir.path().to_path_buf();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir_path.clone())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        for i in 0..5 {
+            engine.set(format!("k{i}"), b"value".to_vec()).unwrap();
+        }
+    }
+
+    let wal_path = dir_path.join("wal-000001.log");
+    let original = std::fs::read(&wal_path).unwrap();
+    let first_len = u32::from_le_bytes(original[0..4].try_into().unwrap()) as usize;
+    let second = 8 + first_len;
+    let second_len = u32::from_le_bytes(original[second..second + 4].try_into().unwrap());
+
+    // Lengths that stay plausible: one byte too long, and just past the end
+    // of the segment, as if the append of the second record had been torn
+    let past_end = (original.len() - second - 8) as u32 + 1;
+    for length in [second_len + 1, past_end] {
+        let mut bytes = original.clone();
+        bytes[second..second + 4].copy_from_slice(&length.to_le_bytes());
+        std::fs::write(&wal_path, &bytes).unwrap();
+
+        assert!(
+            matches!(LsmEngine::new(cfg.clone()), Err(LsmError::WalCorruption)),
+            "length {length} was not reported as corruption"
+        );
+        assert_eq!(std::fs::read(&wal_path).unwrap(), bytes);
+    }
+}
+
+#[test]
+fn torn_tail_of_an_older_wal_segment_is_detected() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .wal_segment_size(4096)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        for i in 0..100 {
+            engine.set(format!("k{i:03}"), vec![b'x'; 64]).unwrap();
+        }
+    }
+    let segments = wal_segments(dir.path());
+    assert!(segments.len() >= 2);
+
+    // Older segments were synced before the next one started, so a short one
+    // lost acknowledged records rather than a torn append
+    let file = OpenOptions::new().write(true).open(&segments[0]).unwrap();
+    let len = file.metadata().unwrap().len();
+    file.set_len(len - 1).unwrap();
+    drop(file);
+
+    assert!(matches!(LsmEngine::new(cfg), Err(LsmError::WalCorruption)));
+    assert_eq!(std::fs::metadata(&segments[0]).unwrap().len(), len - 1);
+}
+
+#[test]
+fn unreadable_sstable_is_quarantined() {
+    let dir = tempdir().unwrap();
+    let dir_path = dir.path().to_path_buf();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024)
+        .dir_path(dir_path.clone())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        for i in 0..50 {
+            engine.set(format!("k{i}"), vec![b'x'; 20]).unwrap();
+        }
+    }
+
+    // A live table that no longer reads back: right magic, zeroed MetaBlock
+    let bogus = dir_path.join("1.sst");
+    let mut bytes = b"LSMSST03".to_vec();
+    bytes.extend_from_slice(&[0u8; 8]);
+    bytes.extend_from_slice(&8u64.to_le_bytes());
+    std::fs::write(&bogus, bytes).unwrap();
+    let mut live = Manifest::load(&dir_path).unwrap().unwrap();
+    live.push("1.sst".to_string());
+    Manifest::create(&dir_path, &live, true).unwrap();
+
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert!(!bogus.exists());
+    assert_eq!(
+        engine.quarantined_files().unwrap(),
+        vec![dir_path.join("quarantine").join("1.sst")]
+    );
+    assert!(engine.get("k1").unwrap().is_some());
+}
+
+#[test]
+fn unsupported_sstable_format_fails_open() {
+    let dir = tempdir().unwrap();
+    let dir_path = dir.path().to_path_buf();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir_path.clone())
+        .build()
+        .unwrap();
+    LsmEngine::new(cfg.clone()).unwrap().close().unwrap();
+
+    // Written by a newer release, say
+    let future = dir_path.join("1.sst");
+    std::fs::write(&future, b"LSMSST99 and a layout this build can't read").unwrap();
+    Manifest::create(&dir_path, &["1.sst".to_string()], true).unwrap();
+
+    assert!(matches!(
+        LsmEngine::new(cfg),
+        Err(LsmError::InvalidSstableFormat(_))
+    ));
+    assert!(future.exists());
+    assert!(!dir_path.join("quarantine").exists());
+}
+
+#[test]
+fn partially_written_sstable_is_ignored_on_open() {
+    let dir = tempdir().unwrap();
+    let dir_path = dir.path().to_path_buf();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir_path.clone())
+        .build()
+        .unwrap();
+
+    let table = {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        engine.set("k".to_string(), b"v".to_vec()).unwrap();
+        engine.flush().unwrap();
+        std::fs::read(&engine.sstable_info().unwrap()[0].path).unwrap()
+    };
+
+    // A flush that crashed halfway through writing its table
+    let partial = dir_path.join(format!("{}.sst.tmp", u64::MAX));
+    std::fs::write(&partial, &table[..table.len() / 2]).unwrap();
+
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert!(!partial.exists());
+    assert_eq!(engine.sstable_info().unwrap().len(), 1);
+    assert!(engine.quarantined_files().unwrap().is_empty());
+    assert_eq!(engine.get("k").unwrap(), Some(b"v".to_vec()));
+}
+
+#[test]
+fn manifest_decides_which_tables_are_live() {
+    let dir = tempdir().unwrap();
+    let dir_path = dir.path().to_path_buf();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir_path.clone())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        for round in 0..3 {
+            engine.set(format!("k{round}"), b"old".to_vec()).unwrap();
+            engine.flush().unwrap();
+        }
+        engine.set("k0".to_string(), b"new".to_vec()).unwrap();
+        engine.compact_to_single_file().unwrap();
+        engine.delete("k1".to_string()).unwrap();
+        engine.flush().unwrap();
+    }
+
+    // The compacted table plus the flush after it
+    let live = Manifest::load(&dir_path).unwrap().unwrap();
+    assert_eq!(live.len(), 2);
+
+    let engine = LsmEngine::new(cfg.clone()).unwrap();
+    assert_eq!(engine.sstable_info().unwrap().len(), 2);
+    assert_eq!(engine.get("k0").unwrap(), Some(b"new".to_vec()));
+    assert!(engine.get("k1").unwrap().is_none());
+    assert_eq!(engine.get("k2").unwrap(), Some(b"old".to_vec()));
+    drop(engine);
+
+    // A store written before the manifest existed is adopted from the directory
+    std::fs::remove_file(dir_path.join("MANIFEST")).unwrap();
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(engine.sstable_info().unwrap().len(), 2);
+    assert_eq!(Manifest::load(&dir_path).unwrap().unwrap().len(), 2);
+}
+
+#[test]
+fn tables_missing_from_the_manifest_are_ignored_and_removed() {
+    let dir = tempdir().unwrap();
+    let dir_path = dir.path().to_path_buf();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir_path.clone())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        engine.set("k".to_string(), b"live".to_vec()).unwrap();
+        engine.flush().unwrap();
+    }
+
+    // Output of a compaction that crashed before recording it: newer than
+    // everything else, so it would win every read if it were loaded
+    let orphan = dir_path.join(format!("{}.sst", u64::MAX));
+    let mut builder =
+        SstableBuilder::new(orphan.clone(), StorageConfig::default(), u64::MAX as u128).unwrap();
+    let mut record = LogRecord::new("k".to_string(), b"ghost".to_vec());
+    record.seq = u64::MAX;
+    builder.add(b"k", &record).unwrap();
+    builder.finish().unwrap();
+
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(engine.get("k").unwrap(), Some(b"live".to_vec()));
+    assert_eq!(engine.sstable_info().unwrap().len(), 1);
+    assert!(!orphan.exists());
+}
+
+#[test]
+fn blobs_of_tables_that_never_went_live_are_removed() {
+    let dir = tempdir().unwrap();
+    let dir_path = dir.path().to_path_buf();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .inline_value_max(1024)
+        .dir_path(dir_path.clone())
+        .build()
+        .unwrap();
+    let blobs = |dir: &std::path::Path| std::fs::read_dir(dir.join("blobs")).unwrap().count();
+
+    let live = {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        engine.set("kept".to_string(), vec![1; 4096]).unwrap();
+        engine.flush().unwrap();
+        let live = Manifest::load(&dir_path).unwrap().unwrap();
+
+        engine.set("lost".to_string(), vec![2; 4096]).unwrap();
+        engine.flush().unwrap();
+        live
+    };
+    // A flush that crashed after writing its blob and table, but before the
+    // manifest listed the table; and a blob torn mid-write
+    Manifest::create(&dir_path, &live, true).unwrap();
+    std::fs::write(dir_path.join("blobs").join("9-0.blob"), [3; 100]).unwrap();
+    assert_eq!(blobs(&dir_path), 3);
+
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(blobs(&dir_path), 1);
+    assert_eq!(engine.get("kept").unwrap(), Some(vec![1; 4096]));
+    assert_eq!(engine.get("lost").unwrap(), None);
+}
+
+#[test]
+fn blobs_of_quarantined_tables_are_kept() {
+    let dir = tempdir().unwrap();
+    let dir_path = dir.path().to_path_buf();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .inline_value_max(1024)
+        .dir_path(dir_path.clone())
+        .build()
+        .unwrap();
+    let blobs = |dir: &std::path::Path| std::fs::read_dir(dir.join("blobs")).unwrap().count();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        engine.set("kept".to_string(), vec![1; 4096]).unwrap();
+        engine.flush().unwrap();
+        engine.set("damaged".to_string(), vec![2; 4096]).unwrap();
+        engine.flush().unwrap();
+    }
+    assert_eq!(blobs(&dir_path), 2);
+
+    // The newest table no longer reads back: right magic, zeroed MetaBlock
+    let live = Manifest::load(&dir_path).unwrap().unwrap();
+    let mut bytes = b"LSMSST03".to_vec();
+    bytes.extend_from_slice(&[0u8; 8]);
+    bytes.extend_from_slice(&8u64.to_le_bytes());
+    std::fs::write(dir_path.join(&live[1]), bytes).unwrap();
+
+    // Its blob outlives the open that quarantines it and the ones after
+    for _ in 0..2 {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        assert_eq!(engine.quarantined_files().unwrap().len(), 1);
+        assert_eq!(engine.get("kept").unwrap(), Some(vec![1; 4096]));
+        assert_eq!(blobs(&dir_path), 2);
+    }
+}
+
+#[test]
+fn missing_live_table_is_reported() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    let table = {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        engine.set("k".to_string(), b"v".to_vec()).unwrap();
+        engine.flush().unwrap();
+        engine.sstable_info().unwrap().remove(0).path
+    };
+    std::fs::remove_file(table).unwrap();
+
+    match LsmEngine::new(cfg) {
+        Err(LsmError::CorruptedData(msg)) => assert!(msg.contains("missing"), "{msg}"),
+        Err(other) => panic!("expected CorruptedData, got: {other}"),
+        Ok(_) => panic!("expected CorruptedData, got Ok"),
+    }
+}
+
+fn wal_segments(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let mut segments: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| {
+            let name = p.file_name().unwrap().to_string_lossy();
+            name.starts_with("wal-") && name.ends_with(".log")
+        })
+        .collect();
+    segments.sort();
+    segments
+}
+
+#[test]
+fn close_flushes_memtable_and_clears_wal() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        engine.set("k1".to_string(), b"v1".to_vec()).unwrap();
+        engine.close().unwrap();
+    }
+
+    let wal_len: u64 = wal_segments(dir.path())
+        .iter()
+        .map(|path| std::fs::metadata(path).unwrap().len())
+        .sum();
+    assert_eq!(wal_len, 0, "WAL should be empty after close");
+
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(engine.get("k1").unwrap().unwrap(), b"v1".to_vec());
+    assert_eq!(engine.stats_all().unwrap().mem_records, 0);
+}
+
+#[test]
+fn close_persists_the_memtable_under_any_sync_policy() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .wal_sync_policy(WalSyncPolicy::Never)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        for i in 0..50 {
+            engine.set(format!("k{i:02}"), b"v".to_vec()).unwrap();
+        }
+        engine.delete("k07".to_string()).unwrap();
+        engine.close().unwrap();
+    }
+
+    // Everything is in SSTables: nothing left for the reopen to replay
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(engine.stats_all().unwrap().mem_records, 0);
+    assert_eq!(engine.count().unwrap(), 49);
+    assert!(engine.get("k07").unwrap().is_none());
+    assert_eq!(engine.get("k49").unwrap(), Some(b"v".to_vec()));
+}
+
+#[test]
+fn flush_and_compaction_work_with_and_without_directory_sync() {
+    for sync_directory in [true, false] {
+        let dir = tempdir().unwrap();
+        let cfg = LsmConfig::builder()
+            .memtable_max_size(1024 * 1024)
+            .sync_directory(sync_directory)
+            .dir_path(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+
+        {
+            let engine = LsmEngine::new(cfg.clone()).unwrap();
+            for round in 0..3 {
+                engine.set(format!("k{round}"), b"v".to_vec()).unwrap();
+                engine.flush().unwrap();
+            }
+            // Writes new tables and removes the compacted ones
+            engine.compact_to_single_file().unwrap();
+        }
+
+        let engine = LsmEngine::new(cfg).unwrap();
+        assert_eq!(engine.count().unwrap(), 3);
+    }
+}
+
+#[test]
+fn recovery_replays_every_wal_segment() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .wal_segment_size(4096)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        for i in 0..100 {
+            engine.set(format!("k{i:03}"), vec![b'x'; 64]).unwrap();
+        }
+        // Later segments overwrite earlier ones on replay
+        engine.set("k000".to_string(), b"newest".to_vec()).unwrap();
+        engine.delete("k001".to_string()).unwrap();
+    }
+    assert!(wal_segments(dir.path()).len() >= 3);
+
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(engine.count().unwrap(), 99);
+    assert_eq!(engine.get("k000").unwrap(), Some(b"newest".to_vec()));
+    assert!(engine.get("k001").unwrap().is_none());
+    assert_eq!(engine.get("k099").unwrap(), Some(vec![b'x'; 64]));
+}
+
+#[test]
+fn flush_deletes_only_flushed_wal_segments() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .wal_segment_size(4096)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        for i in 0..100 {
+            engine
+                .set(format!("flushed{i:03}"), vec![b'x'; 64])
+                .unwrap();
+        }
+        let before = wal_segments(dir.path());
+        assert!(before.len() >= 3);
+
+        engine.close().unwrap();
+        let after_flush = wal_segments(dir.path());
+        assert_eq!(after_flush.len(), 1, "only the new active segment remains");
+        assert!(!before.contains(&after_flush[0]));
+
+        engine.set("pending".to_string(), b"p".to_vec()).unwrap();
+        assert_eq!(wal_segments(dir.path()), after_flush);
+    }
+
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(engine.get("pending").unwrap(), Some(b"p".to_vec()));
+    assert_eq!(engine.stats_all().unwrap().mem_records, 1);
+    assert_eq!(engine.count().unwrap(), 101);
+}
+
+#[test]
+fn legacy_single_file_wal_is_replayed() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    // Written by the first release: pre-segment framing ([len u32][payload],
+    // no checksum) around records without sequence numbers
+    std::fs::write(
+        dir.path().join("wal.log"),
+        include_bytes!("fixtures/baseline/wal.log"),
+    )
+    .unwrap();
+
+    let engine = LsmEngine::new(cfg).unwrap();
+    for i in 0..5 {
+        assert_eq!(
+            engine.get(&format!("wal:{i}")).unwrap(),
+            Some(format!("log-{i}").into_bytes())
+        );
+    }
+    assert_eq!(engine.get("sst:01").unwrap(), Some(b"overwritten".to_vec()));
+    assert_eq!(engine.get("sst:02").unwrap(), None);
+    assert_eq!(engine.count().unwrap(), 6);
+
+    // Removed once its records are flushed
+    engine.close().unwrap();
+    assert!(!dir.path().join("wal.log").exists());
+}
+
+/// Copy the checked-in data directory written by the first release
+fn baseline_dir() -> tempfile::TempDir {
+    let fixture = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/baseline");
+    let dir = tempdir().unwrap();
+    for entry in std::fs::read_dir(fixture).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, dir.path().join(path.file_name().unwrap())).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn baseline_data_dir_opens() {
+    let dir = baseline_dir();
+    let cfg = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    let engine = LsmEngine::new(cfg.clone()).unwrap();
+    let check = |engine: &LsmEngine| {
+        for i in 0..5 {
+            assert_eq!(
+                engine.get(&format!("wal:{i}")).unwrap(),
+                Some(format!("log-{i}").into_bytes())
+            );
+        }
+        assert_eq!(engine.get("sst:01").unwrap(), Some(b"overwritten".to_vec()));
+        assert_eq!(engine.get("sst:02").unwrap(), None);
+        assert_eq!(engine.get("sst:05").unwrap(), None);
+        for i in [0, 3, 4, 6, 19] {
+            assert_eq!(
+                engine.get(&format!("sst:{i:02}")).unwrap(),
+                Some(format!("disk-{i}").into_bytes())
+            );
+        }
+        assert_eq!(engine.count().unwrap(), 5 + 18);
+    };
+    check(&engine);
+    assert!(engine.quarantined_files().unwrap().is_empty());
+
+    // Still readable once flushed and compacted into the current layout
+    engine.flush().unwrap();
+    engine.compact_now().unwrap();
+    check(&engine);
+    engine.close().unwrap();
+    check(&LsmEngine::new(cfg).unwrap());
+}
+
+/// Run `threads` concurrent writers against a fresh store, then reopen it and
+/// check every write survived. Returns (elapsed, fsyncs issued).
+fn concurrent_writes_survive_restart(
+    group_commit_us: u64,
+    threads: usize,
+    per_thread: usize,
+) -> (std::time::Duration, u64) {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(16 * 1024 * 1024)
+        .wal_group_commit_us(group_commit_us)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    let (elapsed, syncs) = {
+        let engine = std::sync::Arc::new(LsmEngine::new(cfg.clone()).unwrap());
+        let start = std::time::Instant::now();
+        let writers: Vec<_> = (0..threads)
+            .map(|t| {
+                let engine = std::sync::Arc::clone(&engine);
+                std::thread::spawn(move || {
+                    for i in 0..per_thread {
+                        engine
+                            .set(format!("t{t}_k{i}"), format!("v{i}").into_bytes())
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        (start.elapsed(), engine.wal_stats().syncs)
+        // Dropped without close(): everything must come back from the WAL
+    };
+
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(engine.count().unwrap(), threads * per_thread);
+    for t in 0..threads {
+        for i in 0..per_thread {
+            assert_eq!(
+                engine.get(&format!("t{t}_k{i}")).unwrap(),
+                Some(format!("v{i}").into_bytes())
+            );
+        }
+    }
+    (elapsed, syncs)
+}
+
+#[test]
+fn group_commit_batches_fsyncs_and_survives_restart() {
+    let (threads, per_thread) = (8, 50);
+    let writes = (threads * per_thread) as u64;
+
+    let (per_op_time, per_op_syncs) = concurrent_writes_survive_restart(0, threads, per_thread);
+    let (group_time, group_syncs) = concurrent_writes_survive_restart(2000, threads, per_thread);
+
+    println!(
+        "{writes} writes: per-op fsync {per_op_syncs} syncs in {per_op_time:?}, \
+         group commit {group_syncs} syncs in {group_time:?}"
+    );
+    assert_eq!(per_op_syncs, writes);
+    assert!(
+        group_syncs * 2 < writes,
+        "group commit issued {group_syncs} fsyncs for {writes} writes"
+    );
+}
+
+#[test]
+fn uncommitted_batch_is_discarded_on_recovery() {
+    let dir = tempdir().unwrap();
+    let cfg = LsmConfig::builder()
+        .memtable_max_size(1024 * 1024)
+        .dir_path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    {
+        let engine = LsmEngine::new(cfg.clone()).unwrap();
+        let mut first = WriteBatch::new();
+        first.set("a0", b"1".to_vec()).set("a1", b"1".to_vec());
+        engine.commit(first).unwrap();
+
+        let mut second = WriteBatch::new();
+        second
+            .set("b0", b"2".to_vec())
+            .delete("a0")
+            .set("b1", b"2".to_vec());
+        engine.commit(second).unwrap();
+    }
+
+    // Crash after the batch's records reached the disk but before its
+    // commit marker ([len][crc][u64 count]) did
+    let segment = wal_segments(dir.path()).pop().unwrap();
+    let file = OpenOptions::new().write(true).open(&segment).unwrap();
+    let len = file.metadata().unwrap().len();
+    file.set_len(len - 16).unwrap();
+    drop(file);
+
+    let engine = LsmEngine::new(cfg.clone()).unwrap();
+    assert_eq!(engine.get("a0").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(engine.get("a1").unwrap(), Some(b"1".to_vec()));
+    assert!(engine.get("b0").unwrap().is_none());
+    assert!(engine.get("b1").unwrap().is_none());
+
+    // The partial batch was cut off, so the log keeps working
+    engine.set("c".to_string(), b"3".to_vec()).unwrap();
+    drop(engine);
+    let engine = LsmEngine::new(cfg).unwrap();
+    assert_eq!(engine.count().unwrap(), 3);
+    assert_eq!(engine.get("c").unwrap(), Some(b"3".to_vec()));
+}
@@ -0,0 +1,82 @@
+use lsm_kv_store::core::log_record::LogRecord;
+use lsm_kv_store::storage::builder::SstableBuilder;
+use lsm_kv_store::{LsmConfig, LsmEngine};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tempfile::tempdir;
+
+/// System allocator that tracks live and peak heap bytes
+struct CountingAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const KEYS: usize = 1_000_000;
+const VALUE: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+#[test]
+fn iterating_a_million_keys_keeps_memory_bounded() {
+    let dir = tempdir().unwrap();
+    let config = LsmConfig::builder()
+        .dir_path(dir.path().to_path_buf())
+        .block_cache_size_mb(1)
+        .build()
+        .unwrap();
+
+    // Two interleaved tables so the iterator has to merge
+    for table in 0..2 {
+        let path = dir.path().join(format!("{}.sst", table + 1));
+        let mut builder =
+            SstableBuilder::new(path, config.storage.clone(), table as u128 + 1).unwrap();
+        for i in (table..KEYS).step_by(2) {
+            let key = format!("key{i:08}");
+            builder
+                .add(key.as_bytes(), &LogRecord::new(key.clone(), VALUE.to_vec()))
+                .unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    let engine = LsmEngine::new(config).unwrap();
+
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+
+    let mut count = 0;
+    let mut previous = String::new();
+    for item in engine.iter().unwrap() {
+        let (key, value) = item.unwrap();
+        assert!(key > previous);
+        assert_eq!(value.len(), VALUE.len());
+        previous = key;
+        count += 1;
+    }
+    assert_eq!(count, KEYS);
+
+    // Materializing the dataset would take well over 40MB; the stream should
+    // only hold the block cache plus one decoded block per table.
+    let growth = PEAK.load(Ordering::Relaxed) - baseline;
+    assert!(
+        growth < 8 * 1024 * 1024,
+        "iteration peaked at {growth} bytes above baseline"
+    );
+}